@@ -13,6 +13,7 @@ use serde::Serialize;
 use tracing::debug;
 
 use crate::config::load_config;
+use crate::net::http_client_builder;
 
 /// Umami tracking endpoint.
 const UMAMI_ENDPOINT: &str = "https://track.exquex.com/api/send";
@@ -153,7 +154,7 @@ async fn send_event(event: Event) -> Result<(), String> {
         },
     };
 
-    let client = reqwest::Client::builder()
+    let client = http_client_builder()
         .user_agent(format!("klaas/{}", VERSION))
         .timeout(Duration::from_secs(2))
         .build()
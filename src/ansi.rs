@@ -0,0 +1,101 @@
+//! Streaming removal of terminal escape sequences.
+//!
+//! Session output is meant for a terminal: colors, cursor movement, window
+//! titles and carriage returns are mixed into the text. [`AnsiStripper`]
+//! turns it into plain text that can be grepped or saved. Output arrives in
+//! arbitrary chunks, so an escape sequence can be split across two of them;
+//! the stripper keeps its state between calls to [`AnsiStripper::push`].
+
+/// Where the stripper is within an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+    /// Plain text.
+    #[default]
+    Text,
+    /// After ESC.
+    Escape,
+    /// Inside a control sequence (ESC [), ended by a byte in '@'..='~'.
+    Csi,
+    /// Inside a string (OSC, DCS, ...), ended by BEL or ESC \.
+    String,
+    /// After ESC inside a string.
+    StringEscape,
+    /// After ESC ( and similar, which take one more byte.
+    Charset,
+}
+
+/// Removes escape sequences and control characters from terminal output.
+///
+/// Newlines and tabs are kept; carriage returns and other control
+/// characters are dropped.
+#[derive(Debug, Default)]
+pub struct AnsiStripper {
+    state: State,
+}
+
+impl AnsiStripper {
+    /// Creates a stripper at the start of a stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Strips a chunk of output, returning the plain text in it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity(chunk.len());
+        for &byte in chunk {
+            self.state = match (self.state, byte) {
+                (State::Text, 0x1b) => State::Escape,
+                (State::Text, b'\n' | b'\t') => {
+                    text.push(byte);
+                    State::Text
+                }
+                (State::Text, 0x00..=0x1f | 0x7f) => State::Text,
+                (State::Text, _) => {
+                    text.push(byte);
+                    State::Text
+                }
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::String,
+                (State::Escape, b'(' | b')' | b'*' | b'+') => State::Charset,
+                (State::Escape, _) => State::Text,
+                (State::Csi, 0x40..=0x7e) => State::Text,
+                (State::Csi, _) => State::Csi,
+                (State::String, 0x07) => State::Text,
+                (State::String, 0x1b) => State::StringEscape,
+                (State::String, _) => State::String,
+                (State::StringEscape, b'\\') => State::Text,
+                (State::StringEscape, _) => State::String,
+                (State::Charset, _) => State::Text,
+            };
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(chunks: &[&[u8]]) -> String {
+        let mut stripper = AnsiStripper::new();
+        let text: Vec<u8> = chunks.iter().flat_map(|c| stripper.push(c)).collect();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_strips_colors_cursor_movement_and_titles() {
+        assert_eq!(
+            strip(&[b"\x1b[1;32m\xe2\x9c\x93 done\x1b[0m\r\n"]),
+            "\u{2713} done\n"
+        );
+        assert_eq!(strip(&[b"\x1b]0;klaas\x07title\x1b]8;;x\x1b\\"]), "title");
+        assert_eq!(strip(&[b"\x1b(Bab\x1b[2K\x1b[1Gc\td"]), "abc\td");
+        assert_eq!(strip(&[b"\x1b7saved\x1b8\x08"]), "saved");
+    }
+
+    #[test]
+    fn test_sequences_split_across_chunks() {
+        assert_eq!(strip(&[b"red: \x1b[3", b"1mred\x1b", b"[0m"]), "red: red");
+        assert_eq!(strip(&[b"\x1b]0;ti", b"tle\x1b", b"\\after"]), "after");
+    }
+}
@@ -8,23 +8,27 @@
 //!
 //! ```ignore
 //! use klaas::api_client::ApiClient;
-//! use klaas::config::API_URL;
+//! use klaas::config::get_api_config;
 //!
-//! let client = ApiClient::new(API_URL, "access_token_here");
+//! let client = ApiClient::new(&get_api_config().api_url, "access_token_here");
 //! let sessions = client.get_sessions().await?;
 //! ```
 
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
+use url::Url;
 
+use crate::crypto::WrappedSessionKey;
 use crate::error::{CliError, Result};
+use crate::net::http_client;
+use crate::types::ShareAccess;
 
 /// Session data returned by the API.
 ///
 /// Represents a klaas session with its metadata and current state.
 /// Sessions track CLI connections and allow remote access via the dashboard.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     /// Unique session identifier (ULID format).
     pub session_id: String,
@@ -65,6 +69,81 @@ struct SessionResponse {
     session: Session,
 }
 
+/// A registered device of another user, as returned for sharing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserDevice {
+    /// Device identifier (ULID format).
+    pub device_id: String,
+
+    /// Human-readable name of the device.
+    pub device_name: String,
+
+    /// Device ECDH public key (uncompressed SEC1), base64 encoded.
+    pub public_key: String,
+}
+
+/// API response wrapper for a user's devices.
+#[derive(Debug, Deserialize)]
+struct UserDevicesResponse {
+    /// Devices that have registered a public key.
+    devices: Vec<UserDevice>,
+}
+
+/// A session key wrapped for one specific device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceKeyGrant {
+    /// Recipient device identifier.
+    pub device_id: String,
+
+    /// Session key wrapped to the device's public key.
+    pub wrapped: WrappedSessionKey,
+}
+
+/// A share of one session with another user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareGrant {
+    /// Invitee (username or email).
+    pub user: String,
+
+    /// Access level granted to the invitee.
+    pub access: ShareAccess,
+
+    /// Wrapped session keys, one per invitee device.
+    #[serde(default)]
+    pub keys: Vec<DeviceKeyGrant>,
+}
+
+/// Current shares of a session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionShares {
+    /// Current session key epoch.
+    #[serde(default)]
+    pub key_epoch: u32,
+
+    /// Active shares.
+    pub shares: Vec<ShareGrant>,
+}
+
+/// Request body for rotating a session key after a revocation.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyRotation {
+    /// New session key epoch.
+    pub key_epoch: u32,
+
+    /// Remaining shares, re-wrapped under the new epoch key.
+    pub shares: Vec<ShareGrant>,
+}
+
+/// Session key shared with this device by another user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SharedSessionKey {
+    /// Access level granted to this device's user.
+    pub access: ShareAccess,
+
+    /// Session key wrapped to this device's public key.
+    pub wrapped: WrappedSessionKey,
+}
+
 /// HTTP client for the klaas API.
 ///
 /// Provides methods for interacting with the klaas backend API,
@@ -103,10 +182,24 @@ impl ApiClient {
         Self {
             base_url: base_url.trim_end_matches('/').to_string(),
             access_token: access_token.to_string(),
-            client: Client::new(),
+            client: http_client(),
         }
     }
 
+    /// Builds the URL of an endpoint below the base URL.
+    ///
+    /// Each segment is percent-encoded, so user input such as a name with a
+    /// slash or an email with a plus sign stays a single path segment.
+    fn endpoint(&self, segments: &[&str]) -> Result<String> {
+        let invalid = || CliError::Other(format!("Invalid API URL: {}", self.base_url));
+        let mut url = Url::parse(&self.base_url).map_err(|_| invalid())?;
+        url.path_segments_mut()
+            .map_err(|_| invalid())?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url.into())
+    }
+
     /// Fetches all sessions for the authenticated user.
     ///
     /// Calls `GET /sessions` and returns the list of sessions
@@ -174,7 +267,7 @@ impl ApiClient {
     /// Returns `CliError::NetworkError` if the request fails (except 404)
     /// or the response cannot be parsed.
     pub async fn get_session(&self, identifier: &str) -> Result<Option<Session>> {
-        let url = format!("{}/sessions/{}", self.base_url, identifier);
+        let url = self.endpoint(&["sessions", identifier])?;
 
         debug!(url = %url, identifier = %identifier, "Fetching session");
 
@@ -211,6 +304,208 @@ impl ApiClient {
 
         Ok(Some(data.session))
     }
+
+    /// Registers this device's public key for receiving shared sessions.
+    ///
+    /// Calls `PUT /devices/:device_id/key`. Re-registering the same key is
+    /// a no-op on the server.
+    pub async fn register_device_key(&self, device_id: &str, public_key: &str) -> Result<()> {
+        let url = self.endpoint(&["devices", device_id, "key"])?;
+
+        debug!(url = %url, "Registering device key");
+
+        let response = self
+            .client
+            .put(&url)
+            .bearer_auth(&self.access_token)
+            .json(&serde_json::json!({ "public_key": public_key }))
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to register device key: {}", e)))?;
+
+        error_for_status(response).await?;
+        Ok(())
+    }
+
+    /// Fetches the devices of another user that can receive shared keys.
+    ///
+    /// Calls `GET /users/:user/devices`. Returns `Ok(None)` if the user
+    /// does not exist.
+    pub async fn get_user_devices(&self, user: &str) -> Result<Option<Vec<UserDevice>>> {
+        let url = self.endpoint(&["users", user, "devices"])?;
+
+        debug!(url = %url, "Fetching user devices");
+
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to fetch devices: {}", e)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let data: UserDevicesResponse =
+            error_for_status(response)
+                .await?
+                .json()
+                .await
+                .map_err(|e| {
+                    CliError::NetworkError(format!("Failed to parse devices response: {}", e))
+                })?;
+
+        Ok(Some(data.devices))
+    }
+
+    /// Lists the active shares of a session and its current key epoch.
+    ///
+    /// Calls `GET /sessions/:session_id/shares`.
+    pub async fn get_shares(&self, session_id: &str) -> Result<SessionShares> {
+        let url = self.endpoint(&["sessions", session_id, "shares"])?;
+
+        debug!(url = %url, "Fetching session shares");
+
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to fetch shares: {}", e)))?;
+
+        error_for_status(response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to parse shares response: {}", e)))
+    }
+
+    /// Shares a session with another user.
+    ///
+    /// Calls `POST /sessions/:session_id/shares`. An existing share for the
+    /// same user is replaced.
+    pub async fn create_share(&self, session_id: &str, grant: &ShareGrant) -> Result<()> {
+        let url = self.endpoint(&["sessions", session_id, "shares"])?;
+
+        debug!(url = %url, user = %grant.user, "Creating share");
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(grant)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to create share: {}", e)))?;
+
+        error_for_status(response).await?;
+        Ok(())
+    }
+
+    /// Revokes a user's access to a session.
+    ///
+    /// Calls `DELETE /sessions/:session_id/shares/:user`. The caller is
+    /// expected to have rotated the session key away from the user first.
+    pub async fn revoke_share(&self, session_id: &str, user: &str) -> Result<()> {
+        let url = self.endpoint(&["sessions", session_id, "shares", user])?;
+
+        debug!(url = %url, "Revoking share");
+
+        let response = self
+            .client
+            .delete(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to revoke share: {}", e)))?;
+
+        error_for_status(response).await?;
+        Ok(())
+    }
+
+    /// Moves a session to a new key epoch.
+    ///
+    /// Calls `POST /sessions/:session_id/key-rotation`. The server replaces
+    /// the wrapped keys of the listed shares and notifies the host and
+    /// guests. Shares that aren't listed keep only their old-epoch keys.
+    pub async fn rotate_session_key(&self, session_id: &str, rotation: &KeyRotation) -> Result<()> {
+        let url = self.endpoint(&["sessions", session_id, "key-rotation"])?;
+
+        debug!(url = %url, key_epoch = rotation.key_epoch, "Rotating session key");
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(rotation)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to rotate session key: {}", e)))?;
+
+        error_for_status(response).await?;
+        Ok(())
+    }
+
+    /// Fetches the session key shared with this device, if any.
+    ///
+    /// Calls `GET /sessions/:session_id/shared-key?device_id=...`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(key))` if the session was shared with this device
+    /// - `Ok(None)` if the session is not shared with us (404)
+    pub async fn get_shared_key(
+        &self,
+        session_id: &str,
+        device_id: &str,
+    ) -> Result<Option<SharedSessionKey>> {
+        let url = self.endpoint(&["sessions", session_id, "shared-key"])?;
+
+        debug!(url = %url, "Fetching shared session key");
+
+        let response = self
+            .client
+            .get(&url)
+            .query(&[("device_id", device_id)])
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .map_err(|e| CliError::NetworkError(format!("Failed to fetch shared key: {}", e)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let key = error_for_status(response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| {
+                CliError::NetworkError(format!("Failed to parse shared key response: {}", e))
+            })?;
+
+        Ok(Some(key))
+    }
+}
+
+/// Turns a non-success response into a `CliError::NetworkError`.
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+    Err(CliError::NetworkError(format!(
+        "API request failed ({}): {}",
+        status, body
+    )))
 }
 
 #[cfg(test)]
@@ -233,6 +528,27 @@ mod tests {
         assert_eq!(client.base_url, "https://api.klaas.sh");
     }
 
+    #[test]
+    fn test_endpoint_encodes_segments() {
+        let client = ApiClient::new("https://api.klaas.sh/v1/", "token");
+        assert_eq!(
+            client
+                .endpoint(&["sessions", "01ABC", "shares", "a+b@x.com"])
+                .unwrap(),
+            "https://api.klaas.sh/v1/sessions/01ABC/shares/a+b@x.com"
+        );
+        assert_eq!(
+            client
+                .endpoint(&["users", "team/alice", "devices"])
+                .unwrap(),
+            "https://api.klaas.sh/v1/users/team%2Falice/devices"
+        );
+        assert_eq!(
+            client.endpoint(&["sessions", "my work?"]).unwrap(),
+            "https://api.klaas.sh/v1/sessions/my%20work%3F"
+        );
+    }
+
     #[test]
     fn test_session_deserialization() {
         let json = r#"{
@@ -328,4 +644,42 @@ mod tests {
         assert_eq!(response.session.session_id, "01HQXK7V8G3N5M2R4P6T1W9Y0Z");
         assert_eq!(response.session.name, Some("test-session".to_string()));
     }
+
+    #[test]
+    fn test_session_shares_deserialization() {
+        let json = r#"{
+            "key_epoch": 2,
+            "shares": [
+                { "user": "alice@example.com", "access": "read-write" },
+                { "user": "bob", "access": "read-only", "keys": [] }
+            ]
+        }"#;
+
+        let shares: SessionShares = serde_json::from_str(json).unwrap();
+
+        assert_eq!(shares.key_epoch, 2);
+        assert_eq!(shares.shares.len(), 2);
+        assert_eq!(shares.shares[0].access, ShareAccess::ReadWrite);
+        assert!(shares.shares[1].keys.is_empty());
+    }
+
+    #[test]
+    fn test_shared_key_deserialization() {
+        let json = r#"{
+            "access": "read-only",
+            "wrapped": {
+                "v": 1,
+                "epoch": 1,
+                "ephemeral_public_key": "BA==",
+                "nonce": "dGVzdG5vbmNlMTIz",
+                "ciphertext": "ZW5jcnlwdGVkZGF0YQ==",
+                "tag": "dGFnMTIzNDU2Nzg5MDEy"
+            }
+        }"#;
+
+        let key: SharedSessionKey = serde_json::from_str(json).unwrap();
+
+        assert_eq!(key.access, ShareAccess::ReadOnly);
+        assert_eq!(key.wrapped.epoch, 1);
+    }
 }
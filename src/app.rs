@@ -12,21 +12,21 @@ use tokio::sync::{mpsc, Mutex};
 use tracing::{debug, error, info, warn};
 
 use crate::agents::Agent;
-use crate::auth::{authenticate_with_mek, refresh_token, AuthError};
-use crate::config::{get_api_config, ApiConfig};
+use crate::api_client::ApiClient;
+use crate::auth_manager::{AuthEvent, AuthManager};
+use crate::config::{get_api_config, load_config, ApiConfig, ENV_WS_URL};
 use crate::credentials::CredentialStore;
-use crate::crypto::{get_dev_mek, SecretKey};
+use crate::crypto::{get_dev_mek, KeyRatchet, SecretKey};
 use crate::error::{CliError, Result};
+use crate::guest::keys::{get_or_create_device_key, register_device_key};
 use crate::hook::{ENV_API_URL, ENV_HOOK_TOKEN, ENV_SESSION_ID};
+use crate::profile::{current_profile, DEFAULT_PROFILE, ENV_PROFILE};
 use crate::pty::PtyManager;
+use crate::redact::Redactor;
 use crate::terminal::TerminalManager;
-use crate::types::{ConnectionState, DeviceId, SessionId};
+use crate::types::{ConnectionState, SessionId};
 use crate::ui;
-use crate::websocket::{IncomingMessage, WebSocketClient};
-
-/// Buffer time (seconds) before token expiry to trigger refresh.
-/// We refresh 60 seconds before expiry to avoid race conditions.
-const TOKEN_REFRESH_BUFFER_SECS: i64 = 60;
+use crate::websocket::{IncomingMessage, SharedRatchet, WebSocketClient};
 
 /// Interval for checking WebSocket reconnection (milliseconds).
 const RECONNECT_CHECK_INTERVAL_MS: u64 = 100;
@@ -45,6 +45,9 @@ const WS_RECV_TIMEOUT_MS: u64 = 10;
 /// * `agent_args` - Arguments to pass through to the agent.
 /// * `resume` - If true, resume the previous session instead of starting new.
 /// * `session_name` - Optional human-readable name for the session.
+/// * `headless` - Run without a local terminal: the PTY gets the configured
+///   size, output goes to the remote session (and the configured log), and
+///   remote prompts are the only input.
 ///
 /// # Returns
 /// Exit code from the agent.
@@ -53,6 +56,7 @@ pub async fn run(
     agent_args: Vec<String>,
     resume: bool,
     session_name: Option<String>,
+    headless: bool,
 ) -> Result<i32> {
     // Load configuration from environment
     let config = get_api_config();
@@ -62,38 +66,59 @@ pub async fn run(
     let cred_store = CredentialStore::new();
 
     // Get or generate device ID (persisted across sessions)
-    let device_id = get_or_create_device_id(&cred_store)?;
+    let device_id = cred_store.get_or_create_device_id()?;
     debug!(device_id = %device_id, "Using device ID");
 
+    // Owns tokens and MEK for the rest of the session
+    let auth = AuthManager::new(&config.api_url, CredentialStore::new())?;
+
     // Get device name (needed for authentication and session context)
     let device_name = hostname::get()
         .map(|s| s.to_string_lossy().to_string())
         .unwrap_or_else(|_| "Unknown".to_string());
 
     // Try to authenticate with unified device flow (handles E2EE key exchange)
-    let (access_token, mek) =
-        match try_authenticate_with_mek(&config, &cred_store, &device_name).await {
-            AuthAttemptResultWithMek::Success(token, mek) => {
-                debug!("E2EE enabled with MEK from unified device flow");
-                (Some(token), mek)
-            }
-            AuthAttemptResultWithMek::Cancelled => {
-                // User pressed CTRL+C - exit gracefully
-                return Ok(0);
-            }
-            AuthAttemptResultWithMek::Offline(mek_opt) => {
-                // Offline mode: use existing MEK or generate new one
-                let mek = mek_opt.unwrap_or_else(|| {
-                    let new_mek = SecretKey::random();
-                    if let Err(e) = cred_store.store_mek(new_mek.as_bytes()) {
-                        warn!(error = %e, "Failed to store auto-generated MEK");
-                    }
-                    info!("Generated new MEK for local E2EE (offline mode)");
-                    new_mek
+    let (access_token, mek) = match try_authenticate_with_mek(&auth, &device_name).await {
+        AuthAttemptResultWithMek::Success(token, mek) => {
+            debug!("E2EE enabled with MEK from unified device flow");
+            (Some(token), mek)
+        }
+        AuthAttemptResultWithMek::Cancelled => {
+            // User pressed CTRL+C - exit gracefully
+            return Ok(0);
+        }
+        AuthAttemptResultWithMek::Failed(e) => return Err(e),
+        AuthAttemptResultWithMek::Offline(mek_opt) => {
+            // Offline mode: use existing MEK or generate new one
+            let mek = mek_opt.unwrap_or_else(|| {
+                let new_mek = SecretKey::random();
+                if let Err(e) = cred_store.store_mek(new_mek.as_bytes()) {
+                    warn!(error = %e, "Failed to store auto-generated MEK");
+                }
+                info!("Generated new MEK for local E2EE (offline mode)");
+                new_mek
+            });
+            (None, mek)
+        }
+    };
+
+    // Register this device's sharing key so other users can invite it
+    if let Some(ref token) = access_token {
+        match get_or_create_device_key(&cred_store) {
+            Ok(device_key) => {
+                let api = ApiClient::new(&config.api_url, token);
+                let device_id = device_id.to_string();
+                tokio::spawn(async move {
+                    register_device_key(&api, &device_id, &device_key).await;
                 });
-                (None, mek)
             }
-        };
+            Err(e) => debug!(error = %e, "Could not load device key"),
+        }
+    }
+
+    // Keep the access token fresh for reconnections during long sessions
+    let mut auth_events = auth.subscribe();
+    let refresh_task = access_token.as_ref().map(|_| auth.spawn_refresh_task());
 
     // Get or create session ID (persisted for reconnection)
     let session_id = get_or_create_session_id(&cred_store, resume)?;
@@ -114,12 +139,42 @@ pub async fn run(
         "Session context"
     );
 
-    // Set up terminal (raw mode)
-    let mut terminal = TerminalManager::new()?;
-    terminal.enter_raw_mode()?;
-    // Reserve the bottom row for the status bar so the shell prompt and the
-    // status line never compete for the same row.
-    let _ = terminal.set_status_bar();
+    // Forward-secrecy ratchet, kept across reconnections so guests can follow
+    let klaas_config = load_config();
+    let session_config = &klaas_config.session;
+    let ratchet: Option<SharedRatchet> = session_config
+        .forward_secrecy
+        .then(|| Arc::new(Mutex::new(KeyRatchet::new(session_id.as_str()))));
+    let ratchet_interval = session_config.ratchet_interval();
+    let mut ratchet_timer = tokio::time::interval_at(
+        tokio::time::Instant::now() + ratchet_interval,
+        ratchet_interval,
+    );
+
+    // Secret redaction for the remote stream (the local terminal is untouched)
+    let redaction_config = &klaas_config.redaction;
+    let mut redactor = redaction_config
+        .enabled
+        .then(|| Redactor::new(redaction_config));
+
+    // Set up terminal (raw mode); headless mode has none
+    let mut terminal = if headless {
+        None
+    } else {
+        let mut terminal = TerminalManager::new()?;
+        terminal.enter_raw_mode()?;
+        // Reserve the bottom row for the status bar so the shell prompt and the
+        // status line never compete for the same row.
+        let _ = terminal.set_status_bar();
+        Some(terminal)
+    };
+
+    // Headless output log, in place of the local terminal
+    let headless_config = &klaas_config.headless;
+    let mut output_log = match headless_config.log_file {
+        Some(ref path) if headless => Some(open_output_log(path)?),
+        _ => None,
+    };
 
     // Log agent info
     info!(
@@ -130,22 +185,31 @@ pub async fn run(
     );
 
     // Show notification if agent supports hooks but user hasn't configured them
-    if agent.supports_hooks() && !hooks_configured(&agent) {
-        terminal.exit_raw_mode()?;
-        ui::display_hooks_available_notice(&agent);
-        terminal.enter_raw_mode()?;
-        let _ = terminal.set_status_bar();
+    if let Some(ref mut terminal) = terminal {
+        if agent.supports_hooks() && !hooks_configured(&agent) {
+            terminal.exit_raw_mode()?;
+            ui::display_hooks_available_notice(&agent);
+            terminal.enter_raw_mode()?;
+            let _ = terminal.set_status_bar();
+        }
     }
 
     // Build environment variables for session correlation
     let mut env_vars: HashMap<String, String> = HashMap::new();
     env_vars.insert(ENV_SESSION_ID.to_string(), session_id.to_string());
-    env_vars.insert(ENV_API_URL.to_string(), config.api_url.to_string());
+    env_vars.insert(ENV_API_URL.to_string(), config.api_url.clone());
+    // Nested klaas commands would otherwise derive it from the API URL
+    env_vars.insert(ENV_WS_URL.to_string(), config.ws_url.clone());
     // TODO: Generate a short-lived hook token for authentication
     // For now, we use the access token if available
     if let Some(ref token) = access_token {
         env_vars.insert(ENV_HOOK_TOKEN.to_string(), token.clone());
     }
+    // Keep klaas commands run inside the session on the same profile
+    let profile = current_profile();
+    if profile != DEFAULT_PROFILE {
+        env_vars.insert(ENV_PROFILE.to_string(), profile.to_string());
+    }
 
     // Build full argument list (agent defaults + user args)
     let mut full_args = agent.args.clone();
@@ -159,11 +223,21 @@ pub async fn run(
         None
     };
 
-    // Spawn agent in PTY
-    let pty = match PtyManager::spawn_with_env(&agent.command, &full_args, env_vars) {
+    // Spawn agent in PTY, sized like the local terminal or as configured
+    let spawned = match terminal {
+        Some(_) => PtyManager::spawn_with_env(&agent.command, &full_args, env_vars),
+        None => {
+            let (cols, rows) = headless_config.size();
+            info!(cols, rows, "Running headless");
+            PtyManager::spawn_with_size(&agent.command, &full_args, env_vars, cols, rows)
+        }
+    };
+    let pty = match spawned {
         Ok(pty) => pty,
         Err(e) => {
-            terminal.exit_raw_mode()?;
+            if let Some(ref mut terminal) = terminal {
+                terminal.exit_raw_mode()?;
+            }
             return Err(CliError::SpawnError(format!(
                 "Could not start {}. Is it installed and in your PATH?\n\
                  Error: {}",
@@ -184,6 +258,7 @@ pub async fn run(
                 &device_name,
                 &cwd,
                 &mek,
+                ratchet.as_ref(),
                 session_name.as_deref(),
             )
             .await
@@ -212,6 +287,16 @@ pub async fn run(
     // Channel for WebSocket incoming messages
     let (ws_msg_tx, mut ws_msg_rx) = mpsc::channel::<IncomingMessage>(64);
 
+    // Without a terminal, SIGINT/SIGTERM end the session cleanly
+    if headless {
+        let shutdown_tx_signal = shutdown_tx.clone();
+        tokio::spawn(async move {
+            let code = wait_for_shutdown_signal().await;
+            info!(code, "Received shutdown signal");
+            let _ = shutdown_tx_signal.send(code).await;
+        });
+    }
+
     // Clone handles for reader task
     let pty_for_reader = pty.clone();
     let shutdown_tx_reader = shutdown_tx.clone();
@@ -353,6 +438,9 @@ pub async fn run(
     let mut status_tick: u32 = 0;
     let mut last_status_state = ConnectionState::Detached;
 
+    // Set once the refresh token is rejected; no more reconnection attempts
+    let mut reauth_required = false;
+
     // Reconnection backoff state
     let mut last_reconnect_attempt = std::time::Instant::now();
     let mut reconnect_backoff_secs: u64 = 1;
@@ -363,20 +451,21 @@ pub async fn run(
         tokio::select! {
             // Handle PTY output (display to terminal, stream to WebSocket)
             Some(output) = pty_output_rx.recv() => {
-                // Write to local terminal
-                terminal.write(&output)?;
+                // Write to local terminal or log (never redacted)
+                if let Some(ref mut terminal) = terminal {
+                    terminal.write(&output)?;
+                }
+                write_output_log(&mut output_log, &output);
+
+                // Redact secrets before output leaves the machine
+                let output = match redactor.as_mut() {
+                    Some(redactor) => redactor.push(&output),
+                    None => output,
+                };
 
                 // Stream to WebSocket if connected
-                let state = *connection_state_for_loop.lock().await;
-                if state == ConnectionState::Attached {
-                    let client_guard = ws_client_for_loop.lock().await;
-                    if let Some(ref client) = *client_guard {
-                        if let Err(e) = client.send_output(&output).await {
-                            debug!(error = %e, "Failed to send output to WebSocket");
-                            // Message is queued automatically by websocket module
-                        }
-                    }
-                }
+                send_remote_output(&ws_client_for_loop, &connection_state_for_loop, &output)
+                    .await;
             }
 
             // Handle WebSocket incoming messages
@@ -455,6 +544,60 @@ pub async fn run(
                             "Input rejected"
                         );
                     }
+                    IncomingMessage::KeyRotated { key_epoch, .. } => {
+                        // A share was revoked: encrypt from now on with the new epoch key
+                        let client_guard = ws_client_for_loop.lock().await;
+                        if let Some(ref client) = *client_guard {
+                            client.set_key_epoch(key_epoch).await;
+                        }
+                    }
+                    IncomingMessage::KeyRequest {
+                        request_id,
+                        public_key,
+                        proof,
+                        ..
+                    } => {
+                        // A guest wants the forward-secrecy key
+                        let client_guard = ws_client_for_loop.lock().await;
+                        if let Some(ref client) = *client_guard {
+                            if let Err(e) =
+                                client.handle_key_request(&request_id, &public_key, &proof).await
+                            {
+                                warn!(error = %e, "Rejected forward-secrecy key request");
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Ratchet the forward-secrecy key and drop the old epoch's key
+            _ = ratchet_timer.tick(), if ratchet.is_some() => {
+                if let Some(ref ratchet) = ratchet {
+                    let epoch = ratchet.lock().await.advance();
+                    debug!(epoch, "Ratcheted forward-secrecy key");
+                }
+                let client_guard = ws_client_for_loop.lock().await;
+                if let Some(ref client) = *client_guard {
+                    if let Err(e) = client.announce_key_epoch(false).await {
+                        debug!(error = %e, "Failed to announce key epoch");
+                    }
+                }
+            }
+
+            // Hand refreshed tokens to the WebSocket client for reconnections
+            Ok(event) = auth_events.recv() => {
+                match event {
+                    AuthEvent::TokenRefreshed(token) => {
+                        let client_guard = ws_client_for_loop.lock().await;
+                        if let Some(ref client) = *client_guard {
+                            client.set_token(&token).await;
+                        }
+                    }
+                    AuthEvent::ReauthRequired => {
+                        warn!("Session expired, run 'klaas login' to reconnect");
+                        reauth_required = true;
+                        status_tick = 100;
+                    }
                 }
             }
 
@@ -465,9 +608,9 @@ pub async fn run(
 
             // Poll for keyboard input and handle reconnection
             _ = tokio::time::sleep(Duration::from_millis(10)) => {
-                // Poll for terminal events (non-blocking)
-                while let Ok(Some(event)) =
-                    terminal.poll_event(Duration::from_millis(0))
+                // Poll for terminal events (non-blocking); none when headless
+                while let Some(Ok(Some(event))) =
+                    terminal.as_mut().map(|t| t.poll_event(Duration::from_millis(0)))
                 {
                     match event {
                         Event::Key(key_event) => {
@@ -495,15 +638,28 @@ pub async fn run(
                             let _ = pty.resize(cols, pty_rows).await;
                             // Re-apply the scroll region: some terminals keep
                             // DECSTBM across resize, some don't. Cheap to repeat.
-                            let _ = terminal.set_status_bar();
+                            if let Some(ref mut terminal) = terminal {
+                                let _ = terminal.set_status_bar();
+                            }
                         }
                         _ => {}
                     }
                 }
 
+                // Release output held back by the redactor once output is idle
+                if let Some(redactor) = redactor.as_mut().filter(|r| r.should_flush()) {
+                    let output = redactor.flush();
+                    send_remote_output(&ws_client_for_loop, &connection_state_for_loop, &output)
+                        .await;
+                }
+
                 // Handle reconnection if needed (with exponential backoff)
-                let state = *connection_state_for_loop.lock().await;
-                if state == ConnectionState::Reconnecting {
+                let mut state = *connection_state_for_loop.lock().await;
+                if state == ConnectionState::Reconnecting && reauth_required {
+                    // Can't reconnect without logging in again
+                    state = ConnectionState::Detached;
+                    *connection_state_for_loop.lock().await = state;
+                } else if state == ConnectionState::Reconnecting {
                     let elapsed = last_reconnect_attempt.elapsed();
                     if elapsed.as_secs() >= reconnect_backoff_secs {
                         last_reconnect_attempt = std::time::Instant::now();
@@ -512,12 +668,13 @@ pub async fn run(
                             &ws_client_for_loop,
                             &connection_state_for_loop,
                             &config,
-                            &cred_store,
+                            &auth,
                             session_id.as_str(),
                             device_id.as_str(),
                             &device_name,
                             &cwd,
                             &mek,
+                            ratchet.as_ref(),
                             session_name.as_deref(),
                         )
                         .await;
@@ -552,11 +709,22 @@ pub async fn run(
                         ConnectionState::Connecting | ConnectionState::Reconnecting => {
                             "\x1b[2;33m● klaas reconnecting\x1b[0m"  // dim yellow
                         }
+                        ConnectionState::Detached if reauth_required => {
+                            "\x1b[2;33m● klaas logged out\x1b[0m"  // dim yellow
+                        }
                         ConnectionState::Detached => {
                             "\x1b[2;90m● klaas offline\x1b[0m"  // dim grey
                         }
                     };
-                    let _ = terminal.draw_status_line(status);
+                    match terminal {
+                        Some(ref mut terminal) => {
+                            let _ = terminal.draw_status_line(status);
+                        }
+                        None if state_changed => {
+                            info!(state = ?state, "Connection state changed");
+                        }
+                        None => {}
+                    }
                 }
             }
         }
@@ -576,8 +744,19 @@ pub async fn run(
 
     *connection_state.lock().await = ConnectionState::Detached;
 
-    // Abort WebSocket receiver task
+    // Abort WebSocket receiver and token refresh tasks
     ws_recv_handle.abort();
+    if let Some(refresh_task) = refresh_task {
+        refresh_task.abort();
+    }
+
+    // Stop the agent if it's still running (e.g. after a shutdown signal),
+    // otherwise the reader never sees EOF
+    if let Ok(None) = pty.try_wait().await {
+        if let Err(e) = pty.kill().await {
+            warn!(error = %e, "Failed to stop agent");
+        }
+    }
 
     // Clean up PTY tasks
     drop(pty_input_tx);
@@ -587,23 +766,51 @@ pub async fn run(
     Ok(exit_code)
 }
 
-/// Gets or creates a device ID.
-///
-/// The device ID is persisted in the keychain/credential store and reused
-/// across sessions. If no device ID exists, a new ULID is generated.
-fn get_or_create_device_id(cred_store: &CredentialStore) -> Result<DeviceId> {
-    match cred_store.get_device_id()? {
-        Some(id) => {
-            debug!("Retrieved existing device ID");
-            Ok(DeviceId::from_string(id))
-        }
-        None => {
-            let new_id = DeviceId::new();
-            cred_store.store_device_id(new_id.as_str())?;
-            info!(device_id = %new_id, "Generated new device ID");
-            Ok(new_id)
+/// Opens the headless output log for appending (`-` means stdout).
+fn open_output_log(path: &std::path::Path) -> Result<Box<dyn std::io::Write + Send>> {
+    if path == std::path::Path::new("-") {
+        return Ok(Box::new(std::io::stdout()));
+    }
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| CliError::Other(format!("Cannot open log file {}: {}", path.display(), e)))?;
+    Ok(Box::new(file))
+}
+
+/// Appends output to the headless log, dropping the log after a write error.
+fn write_output_log(log: &mut Option<Box<dyn std::io::Write + Send>>, output: &[u8]) {
+    let Some(writer) = log.as_mut() else {
+        return;
+    };
+    if let Err(e) = writer.write_all(output).and_then(|()| writer.flush()) {
+        warn!(error = %e, "Failed to write output log, disabling it");
+        *log = None;
+    }
+}
+
+/// Waits for SIGINT or SIGTERM and returns the conventional exit code
+/// (128 + signal number).
+async fn wait_for_shutdown_signal() -> i32 {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut sigterm) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return 130,
+                _ = sigterm.recv() => return 143,
+            }
         }
     }
+
+    if tokio::signal::ctrl_c().await.is_err() {
+        // No signal handling available; run until the agent exits
+        std::future::pending::<()>().await;
+    }
+    130
 }
 
 /// Gets or creates a session ID.
@@ -630,181 +837,6 @@ fn get_or_create_session_id(cred_store: &CredentialStore, resume: bool) -> Resul
     Ok(new_id)
 }
 
-/// Ensures the user is authenticated with E2EE key exchange.
-///
-/// Checks for stored credentials and MEK. Refreshes tokens if expired.
-/// Runs unified OAuth Device Flow with ECDH if no valid credentials or MEK exist.
-///
-/// # Arguments
-/// * `config` - API configuration.
-/// * `cred_store` - Credential store for persisting tokens.
-/// * `device_name` - Human-readable device name (used if device flow is needed).
-///
-/// # Returns
-/// A tuple of (access_token, MEK).
-async fn ensure_authenticated_with_mek(
-    config: &ApiConfig,
-    cred_store: &CredentialStore,
-    device_name: &str,
-) -> Result<(String, SecretKey)> {
-    // Check if we have MEK in keychain
-    let mek_from_keychain = cred_store.get_mek()?.map(|mek_bytes| {
-        let mut arr = [0u8; 32];
-        arr.copy_from_slice(&mek_bytes);
-        SecretKey::from_bytes(arr)
-    });
-
-    // Check for stored tokens
-    if let Some((access_token, refresh_token_val)) = cred_store.get_tokens()? {
-        debug!("Found stored tokens");
-
-        // If we have both tokens and MEK, try to use them
-        if let Some(mek) = mek_from_keychain.clone() {
-            match is_token_valid(&access_token) {
-                Some(true) => {
-                    debug!("Access token is still valid, using it directly");
-                    return Ok((access_token, mek));
-                }
-                Some(false) => {
-                    // Token is definitely expired, try to refresh
-                    debug!("Access token is expired, attempting refresh");
-                }
-                None => {
-                    // Couldn't validate token (parsing error), use it anyway
-                    // The API/WebSocket will reject if truly invalid
-                    debug!("Could not validate token format, using it anyway");
-                    return Ok((access_token, mek));
-                }
-            }
-
-            // Try to refresh the expired token
-            match refresh_token(config.api_url, &refresh_token_val).await {
-                Ok(tokens) => {
-                    debug!("Successfully refreshed tokens");
-                    cred_store.store_tokens(&tokens.access_token, &tokens.refresh_token)?;
-                    return Ok((tokens.access_token, mek));
-                }
-                Err(AuthError::InvalidGrant) => {
-                    // Refresh token expired, need to re-authenticate
-                    info!("Refresh token expired, starting new authentication");
-                    cred_store.clear_tokens()?;
-                }
-                Err(e) => {
-                    // Network error or other issue - use existing token anyway
-                    // The API will reject if truly expired, and WebSocket will fail gracefully
-                    warn!(error = %e, "Failed to refresh token, using existing");
-                    return Ok((access_token, mek));
-                }
-            }
-        } else {
-            // Have tokens but no MEK - need to re-authenticate with unified flow
-            info!("No MEK found, starting authentication with E2EE key exchange");
-            cred_store.clear_tokens()?;
-        }
-    }
-
-    // No valid tokens or no MEK, run unified OAuth Device Flow with ECDH
-    info!("Starting authentication with E2EE key exchange");
-    let (tokens, mek) = authenticate_with_mek(config.api_url, device_name)
-        .await
-        .map_err(|e| match e {
-            AuthError::Cancelled | AuthError::Skipped => CliError::AuthError(e.to_string()),
-            _ => CliError::AuthError(e.to_string()),
-        })?;
-
-    // Store the new tokens and MEK
-    cred_store.store_tokens(&tokens.access_token, &tokens.refresh_token)?;
-    cred_store.store_mek(mek.as_bytes())?;
-    info!("Authentication successful with E2EE established");
-
-    Ok((tokens.access_token, mek))
-}
-
-/// Checks if a JWT access token is still valid (not expired).
-///
-/// Decodes the JWT payload (without verifying signature) and checks the `exp` claim.
-///
-/// # Arguments
-/// * `token` - The JWT access token to check.
-///
-/// # Returns
-/// * `Some(true)` if the token is valid and has more than `TOKEN_REFRESH_BUFFER_SECS` until expiry.
-/// * `Some(false)` if the token is definitely expired.
-/// * `None` if the token format couldn't be parsed (caller should use token anyway).
-fn is_token_valid(token: &str) -> Option<bool> {
-    use base64::{
-        engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD},
-        Engine,
-    };
-
-    // JWT format: header.payload.signature
-    let parts: Vec<&str> = token.split('.').collect();
-    if parts.len() != 3 {
-        debug!("Invalid JWT format (expected 3 parts, got {})", parts.len());
-        return None;
-    }
-
-    // Decode the payload (second part)
-    // Try without padding first, then with padding (JWTs can vary)
-    let payload = URL_SAFE_NO_PAD
-        .decode(parts[1])
-        .or_else(|_| URL_SAFE.decode(parts[1]))
-        .or_else(|_| {
-            // Try adding padding manually
-            let mut padded = parts[1].to_string();
-            while !padded.len().is_multiple_of(4) {
-                padded.push('=');
-            }
-            URL_SAFE.decode(&padded)
-        });
-
-    let payload = match payload {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            debug!("Failed to decode JWT payload: {}", e);
-            return None;
-        }
-    };
-
-    // Parse as JSON
-    let payload_str = match std::str::from_utf8(&payload) {
-        Ok(s) => s,
-        Err(e) => {
-            debug!("JWT payload is not valid UTF-8: {}", e);
-            return None;
-        }
-    };
-
-    // Extract the `exp` claim using simple JSON parsing
-    let json_value = match serde_json::from_str::<serde_json::Value>(payload_str) {
-        Ok(v) => v,
-        Err(e) => {
-            debug!("Failed to parse JWT payload as JSON: {}", e);
-            return None;
-        }
-    };
-
-    let exp = match json_value.get("exp").and_then(|e| e.as_i64()) {
-        Some(e) => e,
-        None => {
-            debug!("JWT payload does not contain 'exp' claim");
-            return None;
-        }
-    };
-
-    // Check if token is expired (with buffer)
-    let now = chrono::Utc::now().timestamp();
-    let valid = exp > now + TOKEN_REFRESH_BUFFER_SECS;
-
-    if valid {
-        debug!("Token valid for {} more seconds", exp - now);
-    } else {
-        debug!("Token expired (exp: {}, now: {})", exp, now);
-    }
-
-    Some(valid)
-}
-
 /// Result of authentication attempt with E2EE key exchange.
 pub enum AuthAttemptResultWithMek {
     /// Successfully authenticated with access token and MEK from unified device flow.
@@ -814,40 +846,40 @@ pub enum AuthAttemptResultWithMek {
     /// User skipped (ESC) or offline - continue without sync.
     /// Contains existing MEK from keychain if available.
     Offline(Option<SecretKey>),
+    /// No credentials and no way to ask for them (non-interactive) - should
+    /// exit with the error.
+    Failed(CliError),
 }
 
 /// Tries to authenticate with unified device flow, handling cancellation gracefully.
 ///
-/// This is a non-blocking wrapper around `ensure_authenticated_with_mek` that allows
+/// This is a non-blocking wrapper around `AuthManager::ensure_authenticated_with_mek` that allows
 /// the CLI to start in offline mode when the API server is unavailable.
 /// The user can still use Claude Code normally, just without remote sync.
 async fn try_authenticate_with_mek(
-    config: &ApiConfig,
-    cred_store: &CredentialStore,
+    auth: &AuthManager,
     device_name: &str,
 ) -> AuthAttemptResultWithMek {
     // Check for development test MEK (bypasses authentication for E2EE testing)
     if let Some(dev_mek) = get_dev_mek() {
         warn!("Using development test MEK (KLAAS_DEV_MEK=1)");
         // Still try to get tokens for API access, but use dev MEK for encryption
-        if let Ok(Some((access_token, _refresh))) = cred_store.get_tokens() {
+        if let Some(access_token) = auth.access_token().await {
             return AuthAttemptResultWithMek::Success(access_token, dev_mek);
         }
         // No tokens - still use dev MEK but in offline mode
         return AuthAttemptResultWithMek::Offline(Some(dev_mek));
     }
 
-    match ensure_authenticated_with_mek(config, cred_store, device_name).await {
+    match auth.ensure_authenticated_with_mek(device_name).await {
         Ok((token, mek)) => AuthAttemptResultWithMek::Success(token, mek),
+        // Fail fast rather than silently running without sync in CI
+        Err(e @ CliError::NotAuthenticated(_)) => AuthAttemptResultWithMek::Failed(e),
         Err(e) => {
             let error_str = e.to_string();
 
             // Get existing MEK from keychain for offline use
-            let mek_from_keychain = cred_store.get_mek().ok().flatten().map(|mek_bytes| {
-                let mut arr = [0u8; 32];
-                arr.copy_from_slice(&mek_bytes);
-                SecretKey::from_bytes(arr)
-            });
+            let mek_from_keychain = auth.mek().await;
 
             // Check if this was a user-initiated skip (ESC)
             if error_str.contains("skipped") {
@@ -868,6 +900,25 @@ async fn try_authenticate_with_mek(
     }
 }
 
+/// Streams output to the WebSocket if the session is attached.
+async fn send_remote_output(
+    ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
+    connection_state: &Arc<Mutex<ConnectionState>>,
+    output: &[u8],
+) {
+    if output.is_empty() || *connection_state.lock().await != ConnectionState::Attached {
+        return;
+    }
+
+    let client_guard = ws_client.lock().await;
+    if let Some(ref client) = *client_guard {
+        if let Err(e) = client.send_output(output).await {
+            debug!(error = %e, "Failed to send output to WebSocket");
+            // Message is queued automatically by websocket module
+        }
+    }
+}
+
 /// Connects to the WebSocket server.
 ///
 /// Returns None if connection fails (CLI continues to work locally).
@@ -881,12 +932,13 @@ async fn connect_websocket(
     device_name: &str,
     cwd: &str,
     mek: &SecretKey,
+    ratchet: Option<&SharedRatchet>,
     session_name: Option<&str>,
 ) -> Option<WebSocketClient> {
     debug!(ws_url = %config.ws_url, "Connecting to WebSocket");
 
     match WebSocketClient::connect(
-        config.ws_url,
+        &config.ws_url,
         access_token,
         session_id,
         device_id,
@@ -899,6 +951,11 @@ async fn connect_websocket(
         Ok(client) => {
             // Set MEK for transparent E2EE
             client.set_mek(mek.clone()).await;
+            if let Some(ratchet) = ratchet {
+                if let Err(e) = client.set_ratchet(Arc::clone(ratchet)).await {
+                    debug!(error = %e, "Failed to announce key epoch");
+                }
+            }
             info!("Connected to remote session with E2EE enabled");
             Some(client)
         }
@@ -921,12 +978,13 @@ async fn handle_reconnection_silent(
     ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
     connection_state: &Arc<Mutex<ConnectionState>>,
     config: &ApiConfig,
-    cred_store: &CredentialStore,
+    auth: &AuthManager,
     session_id: &str,
     device_id: &str,
     device_name: &str,
     cwd: &str,
     mek: &SecretKey,
+    ratchet: Option<&SharedRatchet>,
     session_name: Option<&str>,
 ) -> bool {
     debug!("Attempting WebSocket reconnection");
@@ -948,9 +1006,10 @@ async fn handle_reconnection_silent(
         }
     }
 
-    // Try a fresh connection with potentially refreshed token
-    let access_token = match ensure_authenticated_with_mek(config, cred_store, device_name).await {
-        Ok((token, _)) => token,
+    // Try a fresh connection with potentially refreshed token. Never starts
+    // the device flow: the agent owns the terminal by now.
+    let access_token = match auth.valid_token().await {
+        Ok(token) => token,
         Err(_) => {
             // Stay in Reconnecting state, will retry later
             return false;
@@ -965,6 +1024,7 @@ async fn handle_reconnection_silent(
         device_name,
         cwd,
         mek,
+        ratchet,
         session_name,
     )
     .await
@@ -19,6 +19,7 @@ use crate::crypto::{
     decode_base64, decrypt_mek_from_pairing, encode_base64, generate_ecdh_keypair, EncryptedMEK,
     SecretKey,
 };
+use crate::net::http_client;
 use crate::ui::{self, WaitingAnimation};
 
 /// Errors that can occur during authentication.
@@ -265,7 +266,7 @@ pub async fn start_device_flow(api_url: &str) -> AuthResult<DeviceFlowResponse>
     let url = format!("{}/auth/device", api_url.trim_end_matches('/'));
     debug!("Starting device flow at {}", url);
 
-    let client = reqwest::Client::new();
+    let client = http_client();
     let response = client.post(&url).send().await?;
 
     if response.status().is_success() {
@@ -323,7 +324,7 @@ pub async fn start_device_flow_with_ecdh(
         ecdh_public_key: public_key_b64,
     };
 
-    let client = reqwest::Client::new();
+    let client = http_client();
     let response = client.post(&url).json(&request).send().await?;
 
     if response.status().is_success() {
@@ -371,7 +372,7 @@ pub async fn poll_for_token(
     expires_in: u64,
 ) -> AuthResult<TokenResponse> {
     let url = format!("{}/auth/token", api_url.trim_end_matches('/'));
-    let client = reqwest::Client::new();
+    let client = http_client();
 
     let start_time = Instant::now();
     let expiry_duration = Duration::from_secs(expires_in);
@@ -525,7 +526,7 @@ pub async fn poll_for_token_with_mek(
     expires_in: u64,
 ) -> AuthResult<(TokenResponse, SecretKey)> {
     let url = format!("{}/auth/token", api_url.trim_end_matches('/'));
-    let client = reqwest::Client::new();
+    let client = http_client();
 
     let start_time = Instant::now();
     let expiry_duration = Duration::from_secs(expires_in);
@@ -694,7 +695,7 @@ pub async fn refresh_token(api_url: &str, refresh_token: &str) -> AuthResult<Tok
     let url = format!("{}/auth/refresh", api_url.trim_end_matches('/'));
     debug!("Refreshing token at {}", url);
 
-    let client = reqwest::Client::new();
+    let client = http_client();
     let request = RefreshRequest {
         refresh_token: refresh_token.to_string(),
     };
@@ -722,6 +723,108 @@ pub async fn refresh_token(api_url: &str, refresh_token: &str) -> AuthResult<Tok
     }
 }
 
+/// Revokes a refresh token, ending the session of this device.
+///
+/// A token the server no longer knows counts as revoked.
+///
+/// # Arguments
+///
+/// * `api_url` - Base URL of the klaas API
+/// * `refresh_token` - The refresh token to revoke
+pub async fn revoke_token(api_url: &str, refresh_token: &str) -> AuthResult<()> {
+    let url = format!("{}/auth/revoke", api_url.trim_end_matches('/'));
+    debug!("Revoking token at {}", url);
+
+    let client = http_client();
+    let request = RefreshRequest {
+        refresh_token: refresh_token.to_string(),
+    };
+
+    let response = client.post(&url).json(&request).send().await?;
+
+    if response.status().is_success() {
+        info!("Revoked refresh token");
+        return Ok(());
+    }
+
+    let status = response.status();
+    let error_response: OAuthErrorResponse = response
+        .json()
+        .await
+        .map_err(|_| AuthError::ServerError(format!("HTTP {}", status)))?;
+
+    match error_response.error.as_str() {
+        "invalid_grant" => Ok(()),
+        _ => Err(AuthError::ServerError(format!(
+            "{}: {}",
+            error_response.error,
+            error_response.error_description.unwrap_or_default()
+        ))),
+    }
+}
+
+/// Decodes the claims of a JWT without verifying its signature.
+///
+/// Only for displaying and scheduling: the server validates the token.
+/// Returns `None` if the token is not a well-formed JWT.
+pub fn decode_jwt_claims(token: &str) -> Option<serde_json::Value> {
+    use base64::{
+        engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD},
+        Engine,
+    };
+
+    // JWT format: header.payload.signature
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        debug!("Invalid JWT format (expected 3 parts, got {})", parts.len());
+        return None;
+    }
+
+    // Decode the payload (second part)
+    // Try without padding first, then with padding (JWTs can vary)
+    let payload = URL_SAFE_NO_PAD
+        .decode(parts[1])
+        .or_else(|_| URL_SAFE.decode(parts[1]))
+        .or_else(|_| {
+            // Try adding padding manually
+            let mut padded = parts[1].to_string();
+            while !padded.len().is_multiple_of(4) {
+                padded.push('=');
+            }
+            URL_SAFE.decode(&padded)
+        });
+
+    let payload = match payload {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            debug!("Failed to decode JWT payload: {}", e);
+            return None;
+        }
+    };
+
+    match serde_json::from_slice(&payload) {
+        Ok(claims) => Some(claims),
+        Err(e) => {
+            debug!("Failed to parse JWT payload as JSON: {}", e);
+            None
+        }
+    }
+}
+
+/// Returns a human-readable account name from a JWT: the email, username,
+/// name or subject claim, whichever comes first.
+pub fn token_account(token: &str) -> Option<String> {
+    let claims = decode_jwt_claims(token)?;
+    ["email", "username", "name", "sub"]
+        .iter()
+        .find_map(|claim| claims.get(claim)?.as_str().map(str::to_string))
+}
+
+/// Returns the expiry (`exp` claim, Unix seconds) of a JWT.
+pub fn token_expiry(token: &str) -> Option<i64> {
+    decode_jwt_claims(token)?.get("exp")?.as_i64()
+}
+
 /// Displays user-friendly instructions for the device flow.
 ///
 /// # Arguments
@@ -890,7 +993,7 @@ pub async fn start_pairing(
     let keypair = generate_ecdh_keypair();
     let public_key_b64 = encode_base64(&keypair.public_key);
 
-    let client = reqwest::Client::new();
+    let client = http_client();
     let request = PairingRequest {
         device_name: device_name.to_string(),
         cli_public_key: public_key_b64,
@@ -937,7 +1040,7 @@ pub async fn poll_for_pairing(
         api_url.trim_end_matches('/'),
         pairing_code
     );
-    let client = reqwest::Client::new();
+    let client = http_client();
 
     let start_time = Instant::now();
     let expiry_duration = Duration::from_secs(expires_in);
@@ -1212,4 +1315,20 @@ mod tests {
         let err = AuthError::AccessDenied("User cancelled".to_string());
         assert!(err.to_string().contains("User cancelled"));
     }
+
+    #[test]
+    fn test_decode_jwt_claims() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"user_1","exp":1700000000}"#);
+        let token = format!("header.{}.signature", payload);
+
+        let claims = decode_jwt_claims(&token).unwrap();
+        assert_eq!(claims["sub"], "user_1");
+        assert_eq!(token_account(&token).as_deref(), Some("user_1"));
+        assert_eq!(token_expiry(&token), Some(1_700_000_000));
+
+        assert!(decode_jwt_claims("not-a-jwt").is_none());
+        assert!(token_expiry("a.b.c").is_none());
+    }
 }
@@ -0,0 +1,657 @@
+//! Shared authentication state.
+//!
+//! [`AuthManager`] owns the tokens and MEK of the current profile. Every
+//! command gets its access token from here, so expired tokens are refreshed
+//! the same way everywhere. Long-running sessions additionally start a
+//! background task that refreshes the token shortly before it expires and
+//! broadcasts the new token as an [`AuthEvent`], which the main loop hands to
+//! its `WebSocketClient` for the next reconnect.
+//!
+//! For CI and headless servers, a long-lived API token can be provided
+//! through [`ENV_TOKEN`] and the MEK through [`ENV_MEK_FILE`]. Without a
+//! terminal the device flow is never started; missing credentials fail with
+//! [`CliError::NotAuthenticated`] instead.
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use zeroize::Zeroizing;
+
+use crate::api_client::ApiClient;
+use crate::auth::{self, authenticate_with_mek, refresh_token, token_expiry, AuthError};
+use crate::credentials::CredentialStore;
+use crate::crypto::{parse_recovery_key, SecretKey};
+use crate::error::{CliError, Result};
+use crate::ui;
+
+/// Environment variable with a long-lived API token. Takes precedence over
+/// stored tokens and is used as-is, without refreshing.
+pub const ENV_TOKEN: &str = "KLAAS_TOKEN";
+
+/// Environment variable with the path of a file holding the MEK as a
+/// recovery key. Takes precedence over the stored MEK.
+pub const ENV_MEK_FILE: &str = "KLAAS_MEK_FILE";
+
+/// Buffer time (seconds) before token expiry to trigger refresh.
+/// We refresh 60 seconds before expiry to avoid race conditions.
+pub const TOKEN_REFRESH_BUFFER_SECS: i64 = 60;
+
+/// Delay before the background task retries a failed refresh.
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Capacity of the auth event channel.
+const EVENT_CHANNEL_CAPACITY: usize = 16;
+
+/// Authentication changes that long-running tasks need to react to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthEvent {
+    /// The access token was refreshed; use it for the next connection.
+    TokenRefreshed(String),
+    /// The refresh token was rejected. The stored tokens were removed and the
+    /// user has to log in again.
+    ReauthRequired,
+}
+
+/// Owns the tokens and MEK of one profile and keeps the access token fresh.
+///
+/// Cloning is cheap; all clones share the same state.
+#[derive(Clone)]
+pub struct AuthManager {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// API base URL used for refreshing and the device flow.
+    api_url: String,
+    /// Where tokens and MEK are persisted.
+    cred_store: CredentialStore,
+    /// Whether the access token is an API token from [`ENV_TOKEN`].
+    api_token: bool,
+    /// MEK file from [`ENV_MEK_FILE`], if used.
+    mek_file: Option<PathBuf>,
+    /// Cached credentials. Holding the lock serializes refreshes within this
+    /// process; `CredentialStore::lock_refresh` does so across processes.
+    state: Mutex<AuthState>,
+    /// Broadcasts token refreshes and re-authentication requests.
+    events: broadcast::Sender<AuthEvent>,
+}
+
+#[derive(Default)]
+struct AuthState {
+    /// Access and refresh token.
+    tokens: Option<(String, String)>,
+    /// Master Encryption Key.
+    mek: Option<SecretKey>,
+}
+
+/// Credentials provided through the environment instead of the store.
+#[derive(Debug, Default)]
+struct EnvCredentials {
+    /// API token from [`ENV_TOKEN`].
+    token: Option<String>,
+    /// Path from [`ENV_MEK_FILE`].
+    mek_file: Option<PathBuf>,
+}
+
+impl EnvCredentials {
+    /// Reads the credential environment variables, ignoring empty values.
+    fn from_env() -> Self {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            token: var(ENV_TOKEN),
+            mek_file: var(ENV_MEK_FILE).map(PathBuf::from),
+        }
+    }
+}
+
+impl AuthManager {
+    /// Creates a manager for the credentials in `cred_store`.
+    ///
+    /// Reads the stored tokens and MEK once; afterwards the manager keeps
+    /// them up to date itself. [`ENV_TOKEN`] and [`ENV_MEK_FILE`] take
+    /// precedence over the store, which then isn't read at all.
+    pub fn new(api_url: &str, cred_store: CredentialStore) -> Result<Self> {
+        Self::with_env(api_url, cred_store, EnvCredentials::from_env())
+    }
+
+    fn with_env(api_url: &str, cred_store: CredentialStore, env: EnvCredentials) -> Result<Self> {
+        let tokens = match env.token {
+            // API tokens have no refresh token
+            Some(ref token) => Some((token.clone(), String::new())),
+            None => cred_store.get_tokens()?,
+        };
+        let mek = match env.mek_file {
+            Some(ref path) => Some(read_mek_file(path)?),
+            None => cred_store
+                .get_mek()?
+                .and_then(|bytes| mek_from_bytes(&bytes)),
+        };
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                api_url: api_url.to_string(),
+                cred_store,
+                api_token: env.token.is_some(),
+                mek_file: env.mek_file,
+                state: Mutex::new(AuthState { tokens, mek }),
+                events,
+            }),
+        })
+    }
+
+    /// Creates a manager for the current profile.
+    pub fn for_current_profile(api_url: &str) -> Result<Self> {
+        Self::new(api_url, CredentialStore::new())
+    }
+
+    /// Subscribes to [`AuthEvent`]s.
+    pub fn subscribe(&self) -> broadcast::Receiver<AuthEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Returns the credential store backing this manager.
+    pub fn credentials(&self) -> &CredentialStore {
+        &self.inner.cred_store
+    }
+
+    /// Whether the access token is an API token from [`ENV_TOKEN`].
+    pub fn uses_api_token(&self) -> bool {
+        self.inner.api_token
+    }
+
+    /// Returns the MEK file from [`ENV_MEK_FILE`], if the MEK came from there.
+    pub fn mek_file(&self) -> Option<&Path> {
+        self.inner.mek_file.as_deref()
+    }
+
+    /// Returns the current access token without checking its expiry.
+    pub async fn access_token(&self) -> Option<String> {
+        self.inner
+            .state
+            .lock()
+            .await
+            .tokens
+            .as_ref()
+            .map(|(access, _)| access.clone())
+    }
+
+    /// Returns the MEK, if this device has one.
+    pub async fn mek(&self) -> Option<SecretKey> {
+        self.inner.state.lock().await.mek.clone()
+    }
+
+    /// Returns a usable access token, refreshing it if it has expired.
+    ///
+    /// Never starts the device flow, so this is safe to call in the middle of
+    /// a session. Fails if the user isn't logged in or has to log in again.
+    pub async fn valid_token(&self) -> Result<String> {
+        let mut state = self.inner.state.lock().await;
+        self.fresh_token(&mut state).await?.ok_or_else(|| {
+            CliError::NotAuthenticated(format!(
+                "run 'klaas login' or set {} (profile {})",
+                ENV_TOKEN,
+                self.inner.cred_store.profile()
+            ))
+        })
+    }
+
+    /// Returns an API client authenticated with a valid access token.
+    pub async fn api_client(&self) -> Result<ApiClient> {
+        let token = self.valid_token().await?;
+        Ok(ApiClient::new(&self.inner.api_url, &token))
+    }
+
+    /// Ensures the user is authenticated, triggering device flow if needed.
+    ///
+    /// Returns the access token on success.
+    pub async fn ensure_authenticated(&self) -> Result<String> {
+        let mut state = self.inner.state.lock().await;
+        if let Some(token) = self.fresh_token(&mut state).await? {
+            return Ok(token);
+        }
+
+        // No tokens - need to authenticate
+        self.check_device_flow()?;
+        debug!("No tokens found, starting device flow");
+
+        // Display startup banner for auth
+        ui::display_startup_banner();
+
+        match auth::authenticate(&self.inner.api_url).await {
+            Ok(tokens) => {
+                self.inner
+                    .cred_store
+                    .store_tokens(&tokens.access_token, &tokens.refresh_token)?;
+                state.tokens = Some((tokens.access_token.clone(), tokens.refresh_token));
+                Ok(tokens.access_token)
+            }
+            Err(AuthError::Cancelled) => Err(CliError::AuthError("Cancelled".to_string())),
+            Err(AuthError::Skipped) => Err(CliError::AuthError("Skipped".to_string())),
+            Err(e) => Err(CliError::AuthError(e.to_string())),
+        }
+    }
+
+    /// Ensures the user is authenticated with E2EE key exchange.
+    ///
+    /// Uses the stored tokens and MEK, refreshing the tokens if expired.
+    /// Runs unified OAuth Device Flow with ECDH if no valid credentials or
+    /// MEK exist.
+    ///
+    /// # Arguments
+    /// * `device_name` - Human-readable device name (used if device flow is needed).
+    ///
+    /// # Returns
+    /// A tuple of (access_token, MEK).
+    pub async fn ensure_authenticated_with_mek(
+        &self,
+        device_name: &str,
+    ) -> Result<(String, SecretKey)> {
+        let mut state = self.inner.state.lock().await;
+
+        if state.tokens.is_some() {
+            match state.mek.clone() {
+                Some(mek) => {
+                    if let Some(token) = self.fresh_token(&mut state).await? {
+                        return Ok((token, mek));
+                    }
+                }
+                None if self.inner.api_token => {
+                    return Err(CliError::NotAuthenticated(format!(
+                        "{} is set but this device has no encryption key. \
+                         Set {} or run 'klaas login --import-key <FILE>'.",
+                        ENV_TOKEN, ENV_MEK_FILE
+                    )));
+                }
+                None => {
+                    // Have tokens but no MEK - need to re-authenticate with unified flow
+                    info!("No MEK found, starting authentication with E2EE key exchange");
+                    self.inner.cred_store.clear_tokens()?;
+                    state.tokens = None;
+                }
+            }
+        }
+
+        // No valid tokens or no MEK, run unified OAuth Device Flow with ECDH
+        self.check_device_flow()?;
+        info!("Starting authentication with E2EE key exchange");
+        let (tokens, mek) = authenticate_with_mek(&self.inner.api_url, device_name)
+            .await
+            .map_err(|e| CliError::AuthError(e.to_string()))?;
+
+        // Store the new tokens and MEK
+        let cred_store = &self.inner.cred_store;
+        cred_store.store_tokens(&tokens.access_token, &tokens.refresh_token)?;
+        cred_store.store_mek(mek.as_bytes())?;
+        info!("Authentication successful with E2EE established");
+
+        state.tokens = Some((tokens.access_token.clone(), tokens.refresh_token));
+        state.mek = Some(mek.clone());
+        Ok((tokens.access_token, mek))
+    }
+
+    /// Spawns a task that refreshes the access token shortly before it
+    /// expires, for as long as the session runs.
+    ///
+    /// Every refresh is broadcast as [`AuthEvent::TokenRefreshed`]. The task
+    /// stops when the user has to log in again or the token carries no
+    /// expiry; failed refreshes (e.g. while offline) are retried.
+    pub fn spawn_refresh_task(&self) -> JoinHandle<()> {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            if manager.uses_api_token() {
+                debug!("Using an API token, nothing to refresh");
+                return;
+            }
+
+            loop {
+                let Some(token) = manager.access_token().await else {
+                    break;
+                };
+                let Some(exp) = token_expiry(&token) else {
+                    debug!("Access token has no expiry, not scheduling refresh");
+                    break;
+                };
+
+                let now = chrono::Utc::now().timestamp();
+                let wait = (exp - TOKEN_REFRESH_BUFFER_SECS - now).max(0) as u64;
+                debug!(seconds = wait, "Scheduled access token refresh");
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+
+                let mut state = manager.inner.state.lock().await;
+                match &state.tokens {
+                    // Someone refreshed on demand while we were sleeping
+                    Some((current, _)) if *current != token => continue,
+                    Some(_) => {}
+                    None => break,
+                }
+
+                match manager.refresh(&mut state).await {
+                    Ok(Some(_)) => debug!("Refreshed access token in background"),
+                    Ok(None) => break,
+                    Err(e) => {
+                        drop(state);
+                        warn!(error = %e, "Background token refresh failed, retrying");
+                        tokio::time::sleep(REFRESH_RETRY_DELAY).await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Returns the stored access token, refreshed if it has expired.
+    ///
+    /// Returns `None` if there are no tokens or the refresh token was
+    /// rejected. If refreshing fails for another reason (e.g. offline), the
+    /// existing token is returned; the API rejects it if it's truly expired.
+    async fn fresh_token(&self, state: &mut AuthState) -> Result<Option<String>> {
+        let Some((access_token, _)) = state.tokens.clone() else {
+            return Ok(None);
+        };
+        if self.inner.api_token {
+            // API tokens are long-lived; the server rejects them once revoked
+            return Ok(Some(access_token));
+        }
+
+        match is_token_valid(&access_token) {
+            Some(true) => {
+                debug!("Access token is still valid, using it directly");
+                return Ok(Some(access_token));
+            }
+            Some(false) => {
+                // Token is definitely expired, try to refresh
+                debug!("Access token is expired, attempting refresh");
+            }
+            None => {
+                // Couldn't validate token (parsing error), use it anyway
+                // The API/WebSocket will reject if truly invalid
+                debug!("Could not validate token format, using it anyway");
+                return Ok(Some(access_token));
+            }
+        }
+
+        match self.refresh(state).await {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                warn!(error = %e, "Failed to refresh token, using existing");
+                Ok(Some(access_token))
+            }
+        }
+    }
+
+    /// Exchanges the refresh token for new tokens.
+    ///
+    /// Returns `None` (after clearing the tokens and broadcasting
+    /// [`AuthEvent::ReauthRequired`]) if the refresh token was rejected.
+    async fn refresh(&self, state: &mut AuthState) -> Result<Option<String>> {
+        let Some((_, refresh_token_val)) = state.tokens.clone() else {
+            return Ok(None);
+        };
+        let cred_store = &self.inner.cred_store;
+
+        // Refresh tokens rotate on use, so only one klaas process may
+        // refresh at a time; the others pick up its result
+        let _refresh_lock = match cred_store.lock_refresh().await {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                warn!(error = %e, "Refreshing tokens without lock");
+                None
+            }
+        };
+        if let Some((stored_access, stored_refresh)) = cred_store.get_tokens()? {
+            if stored_refresh != refresh_token_val {
+                debug!("Tokens were refreshed by another klaas process");
+                state.tokens = Some((stored_access.clone(), stored_refresh));
+                self.emit(AuthEvent::TokenRefreshed(stored_access.clone()));
+                return Ok(Some(stored_access));
+            }
+        }
+
+        match refresh_token(&self.inner.api_url, &refresh_token_val).await {
+            Ok(tokens) => {
+                debug!("Successfully refreshed tokens");
+                cred_store.store_tokens(&tokens.access_token, &tokens.refresh_token)?;
+                state.tokens = Some((tokens.access_token.clone(), tokens.refresh_token));
+                self.emit(AuthEvent::TokenRefreshed(tokens.access_token.clone()));
+                Ok(Some(tokens.access_token))
+            }
+            Err(AuthError::InvalidGrant) => {
+                // Refresh token expired, need to re-authenticate
+                info!("Refresh token expired, re-authentication required");
+                cred_store.clear_tokens()?;
+                state.tokens = None;
+                self.emit(AuthEvent::ReauthRequired);
+                Ok(None)
+            }
+            Err(e) => Err(CliError::AuthError(e.to_string())),
+        }
+    }
+
+    /// Fails unless the device flow can be shown to a user.
+    fn check_device_flow(&self) -> Result<()> {
+        if is_interactive() {
+            return Ok(());
+        }
+        Err(CliError::NotAuthenticated(format!(
+            "no credentials for profile {} and no terminal for the device flow. \
+             Set {} and {}, or run 'klaas login' in a terminal.",
+            self.inner.cred_store.profile(),
+            ENV_TOKEN,
+            ENV_MEK_FILE
+        )))
+    }
+
+    /// Broadcasts `event`; it's fine if nobody is listening.
+    fn emit(&self, event: AuthEvent) {
+        let _ = self.inner.events.send(event);
+    }
+}
+
+/// Whether a user can take part in the device flow (stdin and stdout are
+/// terminals).
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+/// Reads a recovery key file, e.g. from [`ENV_MEK_FILE`].
+pub fn read_mek_file(path: &Path) -> Result<SecretKey> {
+    let text = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| {
+        CliError::KeychainError(format!(
+            "Cannot read encryption key file {}: {}",
+            path.display(),
+            e
+        ))
+    })?);
+    parse_recovery_key(&text)
+}
+
+/// Converts stored MEK bytes into a key, ignoring malformed entries.
+fn mek_from_bytes(bytes: &[u8]) -> Option<SecretKey> {
+    let arr: [u8; 32] = bytes.try_into().ok()?;
+    Some(SecretKey::from_bytes(arr))
+}
+
+/// Checks if a JWT access token is still valid (not expired).
+///
+/// Decodes the JWT payload (without verifying signature) and checks the `exp` claim.
+///
+/// # Arguments
+/// * `token` - The JWT access token to check.
+///
+/// # Returns
+/// * `Some(true)` if the token is valid and has more than `TOKEN_REFRESH_BUFFER_SECS` until expiry.
+/// * `Some(false)` if the token is definitely expired.
+/// * `None` if the token format couldn't be parsed (caller should use token anyway).
+pub fn is_token_valid(token: &str) -> Option<bool> {
+    let exp = match token_expiry(token) {
+        Some(e) => e,
+        None => {
+            debug!("JWT payload does not contain 'exp' claim");
+            return None;
+        }
+    };
+
+    // Check if token is expired (with buffer)
+    let now = chrono::Utc::now().timestamp();
+    let valid = exp > now + TOKEN_REFRESH_BUFFER_SECS;
+
+    if valid {
+        debug!("Token valid for {} more seconds", exp - now);
+    } else {
+        debug!("Token expired (exp: {}, now: {})", exp, now);
+    }
+
+    Some(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use tempfile::TempDir;
+
+    /// Builds an unsigned JWT expiring at `exp`.
+    fn jwt(exp: i64) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(format!(r#"{{"sub":"user_1","exp":{}}}"#, exp));
+        format!("header.{}.signature", payload)
+    }
+
+    fn test_store(dir: &TempDir) -> CredentialStore {
+        CredentialStore::with_fallback_path(dir.path().join("credentials.json"), "default")
+    }
+
+    /// Creates a manager on `dir` that ignores the environment.
+    fn test_manager(dir: &TempDir) -> AuthManager {
+        AuthManager::with_env(
+            "http://127.0.0.1:9",
+            test_store(dir),
+            EnvCredentials::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_token_valid() {
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(is_token_valid(&jwt(now + 3_600)), Some(true));
+        assert_eq!(is_token_valid(&jwt(now - 10)), Some(false));
+        // Inside the refresh buffer counts as expired
+        assert_eq!(
+            is_token_valid(&jwt(now + TOKEN_REFRESH_BUFFER_SECS / 2)),
+            Some(false)
+        );
+        assert_eq!(is_token_valid("not-a-jwt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_api_token_from_env() {
+        let dir = TempDir::new().unwrap();
+        let key = SecretKey::from_bytes([3u8; 32]);
+        let mek_file = dir.path().join("mek");
+        std::fs::write(&mek_file, format!("{}\n", hex::encode(key.as_bytes()))).unwrap();
+
+        // Expired stored tokens are ignored in favour of the API token
+        let expired = jwt(chrono::Utc::now().timestamp() - 10);
+        test_store(&dir)
+            .store_tokens(&expired, "refresh-1")
+            .unwrap();
+
+        let env = EnvCredentials {
+            token: Some("klaas_api_token".into()),
+            mek_file: Some(mek_file.clone()),
+        };
+        let manager = AuthManager::with_env("http://127.0.0.1:9", test_store(&dir), env).unwrap();
+
+        assert!(manager.uses_api_token());
+        assert_eq!(manager.mek_file(), Some(mek_file.as_path()));
+        assert_eq!(manager.valid_token().await.unwrap(), "klaas_api_token");
+        let (token, mek) = manager.ensure_authenticated_with_mek("ci").await.unwrap();
+        assert_eq!(token, "klaas_api_token");
+        assert_eq!(mek.as_bytes(), key.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_api_token_without_mek_fails() {
+        let dir = TempDir::new().unwrap();
+        let env = EnvCredentials {
+            token: Some("klaas_api_token".into()),
+            mek_file: None,
+        };
+        let manager = AuthManager::with_env("http://127.0.0.1:9", test_store(&dir), env).unwrap();
+
+        let Err(err) = manager.ensure_authenticated_with_mek("ci").await else {
+            panic!("expected missing key to fail");
+        };
+        assert!(matches!(err, CliError::NotAuthenticated(_)));
+        assert_eq!(err.exit_code(), 78);
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_requires_login() {
+        let dir = TempDir::new().unwrap();
+        let manager = test_manager(&dir);
+
+        assert!(matches!(
+            manager.valid_token().await,
+            Err(CliError::NotAuthenticated(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_uses_unexpired_token() {
+        let dir = TempDir::new().unwrap();
+        let token = jwt(chrono::Utc::now().timestamp() + 3_600);
+        test_store(&dir).store_tokens(&token, "refresh-1").unwrap();
+
+        let manager = test_manager(&dir);
+        assert_eq!(manager.valid_token().await.unwrap(), token);
+    }
+
+    #[tokio::test]
+    async fn test_adopts_tokens_refreshed_elsewhere() {
+        let dir = TempDir::new().unwrap();
+        let expired = jwt(chrono::Utc::now().timestamp() - 10);
+        test_store(&dir)
+            .store_tokens(&expired, "refresh-1")
+            .unwrap();
+
+        let manager = test_manager(&dir);
+        let mut events = manager.subscribe();
+
+        // Another process refreshed and rotated the refresh token meanwhile
+        let fresh = jwt(chrono::Utc::now().timestamp() + 3_600);
+        test_store(&dir).store_tokens(&fresh, "refresh-2").unwrap();
+
+        assert_eq!(manager.valid_token().await.unwrap(), fresh);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            AuthEvent::TokenRefreshed(fresh.clone())
+        );
+        assert_eq!(manager.access_token().await, Some(fresh));
+    }
+
+    #[tokio::test]
+    async fn test_keeps_expired_token_when_refresh_fails() {
+        let dir = TempDir::new().unwrap();
+        let expired = jwt(chrono::Utc::now().timestamp() - 10);
+        test_store(&dir)
+            .store_tokens(&expired, "refresh-1")
+            .unwrap();
+
+        // Nothing listens on the discard port, so the refresh request fails
+        let manager = test_manager(&dir);
+        assert_eq!(manager.valid_token().await.unwrap(), expired);
+    }
+}
@@ -6,9 +6,10 @@
 use tracing::debug;
 
 use crate::api_client::ApiClient;
-use crate::config::API_URL;
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
 use crate::error::{CliError, Result};
-use crate::guest;
+use crate::guest::{self, GuestOptions};
 use crate::ui::colors;
 
 use super::sessions;
@@ -20,16 +21,19 @@ use super::sessions;
 /// # Arguments
 ///
 /// * `target` - Optional session ID or name. If None, shows interactive list.
+/// * `options` - Guest options (e.g. line mode)
 ///
 /// # Returns
 ///
 /// * `Ok(())` on successful connection (or cancellation)
 /// * `Err(...)` on authentication, network, or lookup errors
-pub async fn run(target: Option<String>) -> Result<()> {
+pub async fn run(target: Option<String>, options: &GuestOptions) -> Result<()> {
     let (session_id, access_token) = match target {
         Some(identifier) => {
             // User provided a target - look it up (this will authenticate)
-            let token = ensure_authenticated().await?;
+            let token = AuthManager::for_current_profile(&get_api_config().api_url)?
+                .ensure_authenticated()
+                .await?;
             let id = lookup_session_with_token(&identifier, &token).await?;
             (id, token)
         }
@@ -46,7 +50,7 @@ pub async fn run(target: Option<String>) -> Result<()> {
     };
 
     // Connect to the session with the token
-    connect_to_session(&session_id, &access_token).await
+    connect_to_session(&session_id, &access_token, options).await
 }
 
 /// Connects directly to a session without lookup or authentication.
@@ -58,19 +62,28 @@ pub async fn run(target: Option<String>) -> Result<()> {
 ///
 /// * `session_id` - Valid session ID (ULID)
 /// * `access_token` - Valid access token from prior authentication
+/// * `options` - Guest options (e.g. line mode)
 ///
 /// # Returns
 ///
 /// * `Ok(())` on successful connection
 /// * `Err(...)` on connection errors
-pub async fn run_direct(session_id: &str, access_token: &str) -> Result<()> {
-    connect_to_session(session_id, access_token).await
+pub async fn run_direct(
+    session_id: &str,
+    access_token: &str,
+    options: &GuestOptions,
+) -> Result<()> {
+    connect_to_session(session_id, access_token, options).await
 }
 
 /// Internal function to connect to a session as guest.
 ///
 /// Displays connecting message and handles connection result.
-async fn connect_to_session(session_id: &str, access_token: &str) -> Result<()> {
+async fn connect_to_session(
+    session_id: &str,
+    access_token: &str,
+    options: &GuestOptions,
+) -> Result<()> {
     debug!("Connecting to session: {}", session_id);
 
     // Display connecting message
@@ -85,8 +98,8 @@ async fn connect_to_session(session_id: &str, access_token: &str) -> Result<()>
     );
 
     // Connect as guest using the guest module
-    match guest::run_with_token(session_id, access_token).await {
-        Ok(()) => {
+    match guest::run_with_token(session_id, access_token, options).await {
+        Ok(_) => {
             println!();
             println!(
                 "  {}Disconnected from session.{}",
@@ -116,9 +129,12 @@ async fn connect_to_session(session_id: &str, access_token: &str) -> Result<()>
 ///
 /// If the identifier matches ULID format (26 uppercase alphanumeric chars),
 /// looks up by ID directly. Otherwise, looks up by name.
-async fn lookup_session_with_token(identifier: &str, access_token: &str) -> Result<String> {
+pub(crate) async fn lookup_session_with_token(
+    identifier: &str,
+    access_token: &str,
+) -> Result<String> {
     // Create API client
-    let client = ApiClient::new(API_URL, access_token);
+    let client = ApiClient::new(&get_api_config().api_url, access_token);
 
     // Check if it's a valid ULID (both ULID and name use the same endpoint)
     if is_valid_ulid(identifier) {
@@ -167,34 +183,6 @@ fn display_not_found_error(identifier: &str) {
     println!();
 }
 
-/// Ensures the user is authenticated, triggering device flow if needed.
-async fn ensure_authenticated() -> Result<String> {
-    use crate::auth;
-    use crate::credentials;
-
-    // Check for existing tokens
-    if let Some((access_token, _refresh_token)) = credentials::get_tokens()? {
-        debug!("Using existing access token");
-        return Ok(access_token);
-    }
-
-    // No tokens - need to authenticate
-    debug!("No tokens found, starting device flow");
-
-    // Display startup banner for auth
-    crate::ui::display_startup_banner();
-
-    match auth::authenticate(API_URL).await {
-        Ok(token_response) => {
-            credentials::store_tokens(&token_response.access_token, &token_response.refresh_token)?;
-            Ok(token_response.access_token)
-        }
-        Err(auth::AuthError::Cancelled) => Err(CliError::AuthError("Cancelled".to_string())),
-        Err(auth::AuthError::Skipped) => Err(CliError::AuthError("Skipped".to_string())),
-        Err(e) => Err(CliError::AuthError(e.to_string())),
-    }
-}
-
 /// Generates ANSI escape code for 24-bit true color foreground.
 fn fg_color(color: (u8, u8, u8)) -> String {
     format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
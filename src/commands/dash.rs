@@ -0,0 +1,839 @@
+//! Dash command - full-screen overview of all sessions.
+//!
+//! Lists every session with its live status, previews the selected session's
+//! screen and highlights agents that are waiting for input. Attached
+//! sessions are followed with read-only guest connections, whose output is
+//! fed into a terminal emulator so the preview shows the screen as the host
+//! sees it. Enter jumps into a session as a guest; leaving it with Ctrl+Q
+//! returns to the dashboard.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, ExecutableCommand, QueueableCommand};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::debug;
+
+use crate::api_client::{ApiClient, Session};
+use crate::auth_manager::AuthManager;
+use crate::config::{get_api_config, ApiConfig};
+use crate::credentials::CredentialStore;
+use crate::error::{CliError, Result};
+use crate::guest::keys::resolve_session_keys;
+use crate::guest::terminal::{GuestClient, GuestIncomingMessage};
+use crate::guest::{self, GuestOptions};
+use crate::ui::colors;
+
+/// How often the session list is fetched.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the screen is redrawn while output arrives.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Preview size until the server reports the session's terminal size.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// Maximum width of the session list.
+const LIST_WIDTH: u16 = 44;
+
+/// Agent mode the server reports while the agent waits for the user.
+const WAITING_FOR_INPUT_MODE: &str = "waiting_for_input";
+
+/// Update from a preview connection.
+#[derive(Debug)]
+enum PreviewEvent {
+    /// The session's terminal size.
+    Size {
+        session_id: String,
+        cols: u16,
+        rows: u16,
+    },
+    /// Decrypted output.
+    Output { session_id: String, data: Vec<u8> },
+    /// The agent changed mode.
+    Mode { session_id: String, mode: String },
+    /// The connection ended.
+    Closed { session_id: String },
+}
+
+/// Live view of an attached session.
+struct Preview {
+    /// Emulated screen of the session.
+    parser: vt100::Parser,
+    /// Agent mode from the last mode change.
+    mode: Option<String>,
+    /// Connection task, stopped when the preview is dropped.
+    task: JoinHandle<()>,
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// What the user asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    /// Leave the dashboard.
+    Quit,
+    /// Fetch the session list now.
+    Refresh,
+    /// Jump into a session as a guest.
+    Open { session_id: String, read_only: bool },
+}
+
+/// Dashboard state.
+#[derive(Default)]
+struct Dashboard {
+    /// Sessions, attached first, newest first.
+    sessions: Vec<Session>,
+    /// Index of the selected session.
+    selected: usize,
+    /// Previews of attached sessions, by session ID.
+    previews: HashMap<String, Preview>,
+    /// Message shown in the footer, e.g. the last error.
+    notice: Option<String>,
+    /// Whether the screen needs to be redrawn.
+    dirty: bool,
+}
+
+/// Runs the dash command.
+///
+/// # Returns
+///
+/// * `Ok(())` when the user quits
+/// * `Err(...)` on authentication or terminal errors
+pub async fn run() -> Result<()> {
+    let config = get_api_config();
+    let mut access_token = AuthManager::for_current_profile(&config.api_url)?
+        .ensure_authenticated()
+        .await?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut dash = Dashboard::default();
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+
+    let mut screen = FullScreen::enter()?;
+    loop {
+        match next_action(
+            &mut dash,
+            &mut rx,
+            &mut refresh,
+            &config,
+            &mut access_token,
+            &tx,
+        )
+        .await?
+        {
+            Action::Quit => break,
+            Action::Refresh => refresh.reset_immediately(),
+            Action::Open {
+                session_id,
+                read_only,
+            } => {
+                drop(screen);
+                let options = GuestOptions {
+                    read_only,
+                    ..Default::default()
+                };
+                let result = guest::run_with_token(&session_id, &access_token, &options).await;
+                screen = FullScreen::enter()?;
+
+                dash.notice = result.err().map(|e| format!("Could not connect: {}", e));
+                dash.dirty = true;
+            }
+        }
+    }
+    drop(screen);
+
+    Ok(())
+}
+
+/// Runs the dashboard until the user asks for something it cannot do in
+/// place.
+async fn next_action(
+    dash: &mut Dashboard,
+    rx: &mut mpsc::UnboundedReceiver<PreviewEvent>,
+    refresh: &mut Interval,
+    config: &ApiConfig,
+    access_token: &mut String,
+    tx: &mpsc::UnboundedSender<PreviewEvent>,
+) -> Result<Action> {
+    // An interval rather than a sleep, so busy previews cannot starve input
+    let mut frame = tokio::time::interval(FRAME_INTERVAL);
+    frame.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    dash.dirty = true;
+    loop {
+        tokio::select! {
+            Some(event) = rx.recv() => dash.apply(event),
+
+            _ = refresh.tick() => {
+                dash.refresh(config, access_token, tx).await;
+            }
+
+            _ = frame.tick() => {
+                while event::poll(Duration::ZERO)? {
+                    match event::read()? {
+                        Event::Key(key) if key.kind != KeyEventKind::Release => {
+                            if let Some(action) = dash.key(key) {
+                                return Ok(action);
+                            }
+                        }
+                        Event::Resize(..) => dash.dirty = true,
+                        _ => {}
+                    }
+                }
+                if dash.dirty {
+                    dash.draw()?;
+                }
+            }
+        }
+    }
+}
+
+impl Dashboard {
+    /// Fetches the session list and starts or stops previews to match it.
+    async fn refresh(
+        &mut self,
+        config: &ApiConfig,
+        access_token: &mut String,
+        tx: &mpsc::UnboundedSender<PreviewEvent>,
+    ) {
+        let api = ApiClient::new(&config.api_url, access_token);
+        let sessions = match api.get_sessions().await {
+            Ok(sessions) => sessions,
+            Err(CliError::AuthError(_)) => {
+                // The access token expired; the next refresh uses a new one
+                match AuthManager::for_current_profile(&config.api_url) {
+                    Ok(auth) => match auth.valid_token().await {
+                        Ok(token) => *access_token = token,
+                        Err(e) => self.notice = Some(e.to_string()),
+                    },
+                    Err(e) => self.notice = Some(e.to_string()),
+                }
+                return;
+            }
+            Err(e) => {
+                self.notice = Some(format!("Could not fetch sessions: {}", e));
+                self.dirty = true;
+                return;
+            }
+        };
+
+        let selected_id = self
+            .sessions
+            .get(self.selected)
+            .map(|s| s.session_id.clone());
+        self.sessions = sort_sessions(sessions);
+        self.selected = selected_id
+            .and_then(|id| self.sessions.iter().position(|s| s.session_id == id))
+            .unwrap_or(0);
+
+        // Follow attached sessions, forget detached and deleted ones
+        self.previews.retain(|id, _| {
+            self.sessions
+                .iter()
+                .any(|s| &s.session_id == id && s.status == "attached")
+        });
+        for session in &self.sessions {
+            if session.status == "attached" && !self.previews.contains_key(&session.session_id) {
+                let task = tokio::spawn(follow_session(
+                    session.session_id.clone(),
+                    access_token.clone(),
+                    config.clone(),
+                    tx.clone(),
+                ));
+                let (cols, rows) = DEFAULT_SIZE;
+                self.previews.insert(
+                    session.session_id.clone(),
+                    Preview {
+                        parser: vt100::Parser::new(rows, cols, 0),
+                        mode: None,
+                        task,
+                    },
+                );
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Applies an update from a preview connection.
+    fn apply(&mut self, event: PreviewEvent) {
+        match event {
+            PreviewEvent::Size {
+                session_id,
+                cols,
+                rows,
+            } => {
+                if let Some(preview) = self.previews.get_mut(&session_id) {
+                    preview.parser.set_size(rows.max(1), cols.max(1));
+                }
+            }
+            PreviewEvent::Output { session_id, data } => {
+                if let Some(preview) = self.previews.get_mut(&session_id) {
+                    preview.parser.process(&data);
+                }
+            }
+            PreviewEvent::Mode { session_id, mode } => {
+                if let Some(preview) = self.previews.get_mut(&session_id) {
+                    preview.mode = Some(mode);
+                }
+            }
+            PreviewEvent::Closed { session_id } => {
+                // Reconnected by the next refresh if still attached
+                self.previews.remove(&session_id);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Handles a key press, returning an action for the caller, if any.
+    fn key(&mut self, key: KeyEvent) -> Option<Action> {
+        let count = self.sessions.len();
+        let open = |session: &Session, read_only| Action::Open {
+            session_id: session.session_id.clone(),
+            read_only,
+        };
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Action::Quit)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+            KeyCode::Char('r') => Some(Action::Refresh),
+            KeyCode::Up | KeyCode::Char('k') if count > 0 => {
+                self.selected = (self.selected + count - 1) % count;
+                self.dirty = true;
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') if count > 0 => {
+                self.selected = (self.selected + 1) % count;
+                self.dirty = true;
+                None
+            }
+            KeyCode::Enter => self.sessions.get(self.selected).map(|s| open(s, false)),
+            KeyCode::Char('w') => self.sessions.get(self.selected).map(|s| open(s, true)),
+            KeyCode::Char(c @ '1'..='9') => {
+                let index = c as usize - '1' as usize;
+                self.sessions.get(index).map(|s| open(s, false))
+            }
+            _ => None,
+        }
+    }
+
+    /// Agent mode of a session, if it is being previewed.
+    fn mode(&self, session: &Session) -> Option<&str> {
+        self.previews
+            .get(&session.session_id)
+            .and_then(|p| p.mode.as_deref())
+    }
+
+    /// Redraws the whole screen.
+    fn draw(&mut self) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let list_width = LIST_WIDTH.min(width / 2);
+        let body = 1..height.saturating_sub(1);
+
+        let mut stdout = io::stdout();
+        stdout.queue(cursor::MoveTo(0, 0))?;
+        stdout.queue(terminal::Clear(terminal::ClearType::All))?;
+
+        // Header
+        let waiting = self
+            .sessions
+            .iter()
+            .filter(|s| self.mode(s).is_some_and(needs_attention))
+            .count();
+        let mut header = format!(
+            "{}{} klaas dash{}{} · {} sessions",
+            BOLD,
+            fg_color(colors::AMBER),
+            RESET,
+            fg_color(colors::TEXT_MUTED),
+            self.sessions.len()
+        );
+        if waiting > 0 {
+            header.push_str(&format!(
+                " · {}{} waiting for input",
+                fg_color(colors::AMBER),
+                waiting
+            ));
+        }
+        write!(stdout, "{}{}", header, RESET)?;
+
+        // Session list, two rows per session, scrolled to the selection
+        let slots = (body.len() / 2).max(1);
+        let first = (self.selected + 1).saturating_sub(slots);
+        for (i, session) in self.sessions.iter().enumerate().skip(first).take(slots) {
+            let row = body.start + ((i - first) * 2) as u16;
+            let (line1, line2) = self.session_lines(i, session, list_width as usize);
+            stdout.queue(cursor::MoveTo(0, row))?;
+            write!(stdout, "{}", line1)?;
+            stdout.queue(cursor::MoveTo(0, row + 1))?;
+            write!(stdout, "{}", line2)?;
+        }
+        if self.sessions.is_empty() {
+            stdout.queue(cursor::MoveTo(1, body.start))?;
+            write!(
+                stdout,
+                "{}No sessions yet.{}",
+                fg_color(colors::TEXT_MUTED),
+                RESET
+            )?;
+        }
+
+        // Divider and preview
+        for row in body.clone() {
+            stdout.queue(cursor::MoveTo(list_width, row))?;
+            write!(stdout, "{}│{}", fg_color(colors::TEXT_DIM), RESET)?;
+        }
+        let preview_x = list_width + 2;
+        let preview_width = width.saturating_sub(preview_x);
+        self.draw_preview(&mut stdout, preview_x, preview_width, body.clone())?;
+
+        // Footer
+        stdout.queue(cursor::MoveTo(0, height.saturating_sub(1)))?;
+        let footer = match &self.notice {
+            Some(notice) => format!("{} {}", fg_color(colors::AMBER), notice),
+            None => format!(
+                "{} ↑↓ select · Enter connect · w watch · 1-9 jump · r refresh · q quit",
+                fg_color(colors::TEXT_MUTED)
+            ),
+        };
+        write!(stdout, "{}{}", footer, RESET)?;
+
+        stdout.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Formats the two list rows of a session.
+    fn session_lines(&self, index: usize, session: &Session, width: usize) -> (String, String) {
+        let is_selected = index == self.selected;
+        let attached = session.status == "attached";
+        let mode = self.mode(session);
+        let attention = mode.is_some_and(needs_attention);
+
+        let bg = if is_selected {
+            bg_color(BG_SELECTED)
+        } else {
+            String::new()
+        };
+        let hotkey = if index < 9 {
+            (index + 1).to_string()
+        } else {
+            " ".to_string()
+        };
+        let dot = if attention {
+            fg_color(colors::AMBER)
+        } else if attached {
+            fg_color(colors::GREEN)
+        } else {
+            fg_color(colors::TEXT_DIM)
+        };
+        let name_color = if is_selected || attention {
+            colors::AMBER
+        } else {
+            colors::TEXT_PRIMARY
+        };
+
+        let state = match mode {
+            Some(mode) if attached => mode.replace('_', " "),
+            _ => session.status.clone(),
+        };
+        let name = session.name.as_deref().unwrap_or(&session.session_id);
+        // " 1 ● " (5) + name + " " + state (right-aligned) + " "
+        let state_width = state.chars().count().min(width.saturating_sub(8) / 2);
+        let name_width = width.saturating_sub(7 + state_width);
+        let line1 = format!(
+            "{bg}{}{} {}●{} {}{:<name_width$}{} {}{:>state_width$}{} {}",
+            fg_color(colors::TEXT_MUTED),
+            format_args!(" {}", hotkey),
+            dot,
+            fg_color(colors::TEXT_PRIMARY),
+            fg_color(name_color),
+            truncate_str(name, name_width),
+            fg_color(colors::TEXT_MUTED),
+            if attention {
+                fg_color(colors::AMBER)
+            } else {
+                String::new()
+            },
+            truncate_str(&state, state_width),
+            RESET,
+            bg,
+        );
+
+        let details = format!("{} · {}", session.device_name, shorten_path(&session.cwd));
+        let details_width = width.saturating_sub(5);
+        let line2 = format!(
+            "{bg}     {}{:<details_width$}{}",
+            fg_color(colors::TEXT_DIM),
+            truncate_str(&details, details_width),
+            RESET,
+        );
+
+        (format!("{}{}", line1, RESET), line2)
+    }
+
+    /// Draws the selected session's screen into the preview area.
+    fn draw_preview(
+        &self,
+        stdout: &mut io::Stdout,
+        x: u16,
+        width: u16,
+        rows: Range<u16>,
+    ) -> Result<()> {
+        let Some(session) = self.sessions.get(self.selected) else {
+            return Ok(());
+        };
+        let Some(preview) = self.previews.get(&session.session_id) else {
+            let message = if session.status == "attached" {
+                "Connecting…"
+            } else {
+                "Session is detached. Press Enter to view its history."
+            };
+            stdout.queue(cursor::MoveTo(x, rows.start))?;
+            write!(
+                stdout,
+                "{}{}{}",
+                fg_color(colors::TEXT_MUTED),
+                truncate_str(message, width as usize),
+                RESET
+            )?;
+            return Ok(());
+        };
+
+        let screen = preview.parser.screen();
+        let (screen_rows, _) = screen.size();
+        let (cursor_row, _) = screen.cursor_position();
+        let height = rows.len() as u16;
+        for (offset, screen_row) in visible_rows(cursor_row, screen_rows, height).enumerate() {
+            stdout.queue(cursor::MoveTo(x, rows.start + offset as u16))?;
+            write!(stdout, "{}", render_row(screen, screen_row, width))?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows a session with a read-only guest connection, sending its output
+/// and mode changes to the dashboard.
+async fn follow_session(
+    session_id: String,
+    access_token: String,
+    config: ApiConfig,
+    tx: mpsc::UnboundedSender<PreviewEvent>,
+) {
+    if let Err(e) = watch_session(&session_id, &access_token, &config, &tx).await {
+        debug!(error = %e, session_id = %session_id, "Preview connection ended");
+    }
+    let _ = tx.send(PreviewEvent::Closed { session_id });
+}
+
+/// Receives a session's output until it ends or the dashboard goes away.
+async fn watch_session(
+    session_id: &str,
+    access_token: &str,
+    config: &ApiConfig,
+    tx: &mpsc::UnboundedSender<PreviewEvent>,
+) -> Result<()> {
+    let api = ApiClient::new(&config.api_url, access_token);
+    let keys = resolve_session_keys(&api, &CredentialStore::new(), session_id).await?;
+    let client = GuestClient::connect(&config.ws_url, access_token, session_id, keys, true).await?;
+    let session_id = session_id.to_string();
+
+    while let Some(msg) = client.recv().await? {
+        let mut events = Vec::new();
+        match msg {
+            GuestIncomingMessage::SessionInfo(info) => events.push(PreviewEvent::Size {
+                session_id: session_id.clone(),
+                cols: info.cols,
+                rows: info.rows,
+            }),
+            GuestIncomingMessage::History(batch) => {
+                for entry in &batch.entries {
+                    if let Some(data) = client.decrypt_output(&entry.encrypted).await? {
+                        events.push(PreviewEvent::Output {
+                            session_id: session_id.clone(),
+                            data,
+                        });
+                    }
+                }
+            }
+            GuestIncomingMessage::Output { encrypted, .. } => {
+                if let Some(data) = client.decrypt_output(&encrypted).await? {
+                    events.push(PreviewEvent::Output {
+                        session_id: session_id.clone(),
+                        data,
+                    });
+                }
+            }
+            GuestIncomingMessage::ModeChange(change) => events.push(PreviewEvent::Mode {
+                session_id: session_id.clone(),
+                mode: change.mode,
+            }),
+            GuestIncomingMessage::SessionDetached { .. } => break,
+            msg => {
+                client.handle_key_message(&api, &msg).await?;
+            }
+        }
+
+        for event in events {
+            if tx.send(event).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Raw mode on the alternate screen, restored when dropped.
+struct FullScreen;
+
+impl FullScreen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        stdout.execute(EnterAlternateScreen)?;
+        stdout.execute(cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.execute(cursor::Show);
+        let _ = stdout.execute(LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Returns true if the agent mode means it is waiting for the user, e.g. to
+/// answer a question or allow a tool call.
+fn needs_attention(mode: &str) -> bool {
+    mode == WAITING_FOR_INPUT_MODE
+}
+
+/// Sorts sessions for the list: attached first, most recently active first.
+fn sort_sessions(mut sessions: Vec<Session>) -> Vec<Session> {
+    sessions.sort_by(|a, b| {
+        let last_active = |s: &Session| s.attached_at.clone().unwrap_or(s.started_at.clone());
+        (b.status == "attached")
+            .cmp(&(a.status == "attached"))
+            .then_with(|| last_active(b).cmp(&last_active(a)))
+    });
+    sessions
+}
+
+/// Returns the screen rows to show in a preview `height` rows tall. Screens
+/// taller than the preview are cut so that the cursor stays visible.
+fn visible_rows(cursor_row: u16, screen_rows: u16, height: u16) -> Range<u16> {
+    if screen_rows <= height {
+        return 0..screen_rows;
+    }
+    let end = (cursor_row + 1).clamp(height, screen_rows);
+    end - height..end
+}
+
+/// Renders one row of an emulated screen, at most `width` columns wide,
+/// with its colors and attributes.
+fn render_row(screen: &vt100::Screen, row: u16, width: u16) -> String {
+    let (_, cols) = screen.size();
+    let mut line = String::new();
+    let mut used = 0;
+    let mut current = String::new();
+
+    for col in 0..cols {
+        let Some(cell) = screen.cell(row, col) else {
+            break;
+        };
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let cell_width = if cell.is_wide() { 2 } else { 1 };
+        if used + cell_width > width {
+            break;
+        }
+
+        let style = cell_style(cell);
+        if style != current {
+            line.push_str(RESET);
+            line.push_str(&style);
+            current = style;
+        }
+        if cell.has_contents() {
+            line.push_str(&cell.contents());
+        } else {
+            line.push(' ');
+        }
+        used += cell_width;
+    }
+
+    line.push_str(RESET);
+    line
+}
+
+/// SGR escape sequence for a cell's colors and attributes.
+fn cell_style(cell: &vt100::Cell) -> String {
+    let mut codes = Vec::new();
+    if cell.bold() {
+        codes.push("1".to_string());
+    }
+    if cell.italic() {
+        codes.push("3".to_string());
+    }
+    if cell.underline() {
+        codes.push("4".to_string());
+    }
+    if cell.inverse() {
+        codes.push("7".to_string());
+    }
+    for (color, base) in [(cell.fgcolor(), 38), (cell.bgcolor(), 48)] {
+        match color {
+            vt100::Color::Default => {}
+            vt100::Color::Idx(i) => codes.push(format!("{};5;{}", base, i)),
+            vt100::Color::Rgb(r, g, b) => codes.push(format!("{};2;{};{};{}", base, r, g, b)),
+        }
+    }
+
+    if codes.is_empty() {
+        String::new()
+    } else {
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// Truncates a string to max length, adding "…" if truncated.
+fn truncate_str(s: &str, max_len: usize) -> String {
+    if s.chars().count() <= max_len {
+        s.to_string()
+    } else if max_len <= 1 {
+        s.chars().take(max_len).collect()
+    } else {
+        let truncated: String = s.chars().take(max_len - 1).collect();
+        format!("{}…", truncated)
+    }
+}
+
+/// Shortens a path by replacing home directory with ~.
+fn shorten_path(path: &str) -> String {
+    if let Some(home) = dirs::home_dir() {
+        let home_str = home.to_string_lossy();
+        if path.starts_with(home_str.as_ref()) {
+            return format!("~{}", &path[home_str.len()..]);
+        }
+    }
+    path.to_string()
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// Generates ANSI escape code for 24-bit true color background.
+fn bg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[48;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+const RESET: &str = "\x1b[0m";
+
+/// Bold ANSI code.
+const BOLD: &str = "\x1b[1m";
+
+/// Subtle dark background for selected items (very dark amber tint).
+const BG_SELECTED: (u8, u8, u8) = (35, 28, 18);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, status: &str, attached_at: Option<&str>) -> Session {
+        Session {
+            session_id: id.to_string(),
+            device_id: "DEV".to_string(),
+            device_name: "macbook".to_string(),
+            name: None,
+            status: status.to_string(),
+            started_at: "2026-01-01T09:00:00Z".to_string(),
+            attached_at: attached_at.map(str::to_string),
+            cwd: "/work".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sort_sessions_attached_and_recent_first() {
+        let sessions = vec![
+            session("A", "detached", Some("2026-01-01T12:00:00Z")),
+            session("B", "attached", Some("2026-01-01T10:00:00Z")),
+            session("C", "attached", Some("2026-01-01T11:00:00Z")),
+            session("D", "detached", None),
+        ];
+        let ids: Vec<_> = sort_sessions(sessions)
+            .into_iter()
+            .map(|s| s.session_id)
+            .collect();
+        assert_eq!(ids, vec!["C", "B", "A", "D"]);
+    }
+
+    #[test]
+    fn test_visible_rows_keep_cursor_in_view() {
+        assert_eq!(visible_rows(3, 24, 40), 0..24);
+        assert_eq!(visible_rows(3, 24, 10), 0..10);
+        assert_eq!(visible_rows(15, 24, 10), 6..16);
+        assert_eq!(visible_rows(23, 24, 10), 14..24);
+    }
+
+    #[test]
+    fn test_keys_select_and_open_sessions() {
+        let mut dash = Dashboard {
+            sessions: vec![
+                session("A", "attached", None),
+                session("B", "attached", None),
+            ],
+            ..Default::default()
+        };
+        let key = |code| KeyEvent::new(code, KeyModifiers::empty());
+
+        assert_eq!(dash.key(key(KeyCode::Up)), None);
+        assert_eq!(dash.selected, 1);
+        assert_eq!(
+            dash.key(key(KeyCode::Char('w'))),
+            Some(Action::Open {
+                session_id: "B".to_string(),
+                read_only: true
+            })
+        );
+        assert_eq!(
+            dash.key(key(KeyCode::Char('1'))),
+            Some(Action::Open {
+                session_id: "A".to_string(),
+                read_only: false
+            })
+        );
+        assert_eq!(dash.key(key(KeyCode::Char('3'))), None);
+        assert_eq!(dash.key(key(KeyCode::Char('q'))), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_render_row_keeps_colors_and_width() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"ok \x1b[31mred\x1b[0m");
+
+        let row = render_row(parser.screen(), 0, 5);
+        assert_eq!(row, "ok \x1b[0m\x1b[38;5;1mre\x1b[0m");
+        assert!(needs_attention("waiting_for_input"));
+        assert!(!needs_attention("idle"));
+        assert!(!needs_attention("active"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Login command - authenticate this device explicitly.
+//!
+//! Runs the OAuth Device Flow with ECDH key exchange, so the account's
+//! encryption key is transferred to this device, and stores the resulting
+//! credentials in the current profile.
+//!
+//! With `--import-key`, only the encryption key is imported from a recovery
+//! key instead, e.g. on a build box that authenticates with `KLAAS_TOKEN`.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use tracing::debug;
+use zeroize::Zeroizing;
+
+use crate::api_client::ApiClient;
+use crate::auth::{authenticate_with_mek, token_account, AuthError};
+use crate::auth_manager::{is_interactive, read_mek_file, ENV_MEK_FILE, ENV_TOKEN};
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::crypto::{key_fingerprint, parse_recovery_key};
+use crate::error::{CliError, Result};
+use crate::guest::keys::{get_or_create_device_key, register_device_key};
+use crate::ui::{self, colors};
+
+/// Runs the login command.
+///
+/// # Arguments
+///
+/// * `force` - Log in again even if this device already has credentials
+/// * `import_key` - Import the encryption key from this recovery key file
+///   (`-` for stdin) instead of running the device flow
+pub async fn run(force: bool, import_key: Option<&Path>) -> Result<()> {
+    let cred_store = CredentialStore::new();
+
+    if let Some(path) = import_key {
+        return import_recovery_key(&cred_store, path);
+    }
+
+    if !force {
+        if let (Some((access_token, _)), Some(_)) =
+            (cred_store.get_tokens()?, cred_store.get_mek()?)
+        {
+            println!();
+            println!(
+                "  {}Already logged in as {}{}{}{} (profile {}).{}",
+                fg_color(colors::TEXT_SECONDARY),
+                BOLD,
+                token_account(&access_token).unwrap_or_else(|| "unknown".into()),
+                reset(),
+                fg_color(colors::TEXT_SECONDARY),
+                cred_store.profile(),
+                reset()
+            );
+            println!(
+                "  {}Run {}klaas login --force{} to log in again.{}",
+                fg_color(colors::TEXT_MUTED),
+                fg_color(colors::AMBER),
+                fg_color(colors::TEXT_MUTED),
+                reset()
+            );
+            println!();
+            return Ok(());
+        }
+    }
+
+    if !is_interactive() {
+        return Err(CliError::NotAuthenticated(format!(
+            "'klaas login' needs a terminal. Without one, set {} and {} instead.",
+            ENV_TOKEN, ENV_MEK_FILE
+        )));
+    }
+
+    let device_id = cred_store.get_or_create_device_id()?;
+    let device_name = hostname::get()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "Unknown".to_string());
+
+    ui::display_startup_banner();
+
+    let (tokens, mek) = authenticate_with_mek(&get_api_config().api_url, &device_name)
+        .await
+        .map_err(|e| match e {
+            AuthError::Cancelled | AuthError::Skipped => {
+                CliError::AuthError("Login cancelled".into())
+            }
+            _ => CliError::AuthError(e.to_string()),
+        })?;
+
+    cred_store.store_tokens(&tokens.access_token, &tokens.refresh_token)?;
+    cred_store.store_mek(mek.as_bytes())?;
+
+    // Register this device's sharing key so other users can invite it
+    match get_or_create_device_key(&cred_store) {
+        Ok(device_key) => {
+            let api = ApiClient::new(&get_api_config().api_url, &tokens.access_token);
+            register_device_key(&api, &device_id.to_string(), &device_key).await;
+        }
+        Err(e) => debug!(error = %e, "Could not load device key"),
+    }
+
+    println!();
+    println!(
+        "  {}✓{} Logged in as {}{}{} (profile {})",
+        fg_color(colors::GREEN),
+        reset(),
+        BOLD,
+        token_account(&tokens.access_token).unwrap_or_else(|| "unknown".into()),
+        reset(),
+        cred_store.profile()
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Stores the MEK from a recovery key file (or stdin for `-`).
+fn import_recovery_key(cred_store: &CredentialStore, path: &Path) -> Result<()> {
+    let mek = if path == Path::new("-") {
+        let mut text = Zeroizing::new(String::new());
+        io::stdin().read_to_string(&mut text)?;
+        parse_recovery_key(&text)?
+    } else {
+        read_mek_file(path)?
+    };
+    cred_store.store_mek(mek.as_bytes())?;
+
+    println!();
+    println!(
+        "  {}✓{} Imported encryption key {}{}{} (profile {})",
+        fg_color(colors::GREEN),
+        reset(),
+        BOLD,
+        key_fingerprint(&mek),
+        reset(),
+        cred_store.profile()
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
+
+/// Bold ANSI code.
+const BOLD: &str = "\x1b[1m";
@@ -0,0 +1,89 @@
+//! Logout command - end this device's session.
+//!
+//! Revokes the refresh token on the server and removes the tokens of the
+//! current profile. The encryption key (MEK) is kept unless `--forget-key`
+//! is given, so that local history stays readable after logging in again.
+
+use tracing::warn;
+
+use crate::auth::revoke_token;
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::error::Result;
+use crate::ui::colors;
+
+/// Runs the logout command.
+///
+/// # Arguments
+///
+/// * `forget_key` - Also remove the encryption key from this device
+pub async fn run(forget_key: bool) -> Result<()> {
+    let cred_store = CredentialStore::new();
+    let tokens = cred_store.get_tokens()?;
+    let has_mek = cred_store.get_mek()?.is_some();
+
+    println!();
+
+    if tokens.is_none() && !(forget_key && has_mek) {
+        println!(
+            "  {}Not logged in (profile {}).{}",
+            fg_color(colors::TEXT_SECONDARY),
+            cred_store.profile(),
+            reset()
+        );
+        println!();
+        return Ok(());
+    }
+
+    if let Some((_, refresh_token)) = tokens {
+        // Clear local tokens even if the server can't be reached: the
+        // refresh token then simply expires on its own
+        if let Err(e) = revoke_token(&get_api_config().api_url, &refresh_token).await {
+            warn!(error = %e, "Failed to revoke refresh token");
+            println!(
+                "  {}Could not revoke the session on the server: {}{}",
+                fg_color(colors::AMBER),
+                e,
+                reset()
+            );
+        }
+        cred_store.clear_tokens()?;
+    }
+
+    if forget_key {
+        cred_store.clear_mek()?;
+    }
+
+    println!(
+        "  {}✓{} Logged out (profile {})",
+        fg_color(colors::GREEN),
+        reset(),
+        cred_store.profile()
+    );
+    if forget_key {
+        println!(
+            "    {}Removed the encryption key from this device.{}",
+            fg_color(colors::TEXT_MUTED),
+            reset()
+        );
+    } else if has_mek {
+        println!(
+            "    {}The encryption key stays on this device; use --forget-key to remove it.{}",
+            fg_color(colors::TEXT_MUTED),
+            reset()
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
@@ -0,0 +1,252 @@
+//! Logs command - print a session's output without entering raw mode.
+//!
+//! Connects as a read-only guest, decrypts the history the server replays on
+//! connect and writes it to stdout, so session output can be grepped or piped
+//! into other tools. With `--follow`, live output is printed until the
+//! session ends.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::debug;
+
+use crate::ansi::AnsiStripper;
+use crate::api_client::ApiClient;
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::error::{CliError, Result};
+use crate::guest::keys::resolve_session_keys;
+use crate::guest::terminal::{GuestClient, GuestIncomingMessage};
+
+/// Options for the logs command.
+#[derive(Debug, Clone, Default)]
+pub struct LogsOptions {
+    /// Only print output from this long ago or later.
+    pub since: Option<Duration>,
+    /// Keep printing live output until the session ends.
+    pub follow: bool,
+    /// Remove escape sequences and control characters.
+    pub strip_ansi: bool,
+}
+
+/// Runs the logs command.
+///
+/// # Arguments
+///
+/// * `target` - Session ID or name
+/// * `options` - Which output to print and how
+///
+/// # Returns
+///
+/// * `Ok(())` once the output was printed (or stdout was closed)
+/// * `Err(...)` on authentication, network, or lookup errors
+pub async fn run(target: &str, options: &LogsOptions) -> Result<()> {
+    // Never start the device flow: this is meant for scripts
+    let config = get_api_config();
+    let access_token = AuthManager::for_current_profile(&config.api_url)?
+        .valid_token()
+        .await?;
+    let api = ApiClient::new(&config.api_url, &access_token);
+
+    // stdout is for session output, so report a missing session as an error
+    let session = api
+        .get_session(target)
+        .await?
+        .ok_or_else(|| CliError::Other(format!("Session not found: {}", target)))?;
+    let keys = resolve_session_keys(&api, &CredentialStore::new(), &session.session_id).await?;
+    let client = GuestClient::connect(
+        &config.ws_url,
+        &access_token,
+        &session.session_id,
+        keys,
+        true,
+    )
+    .await?;
+
+    let since = options
+        .since
+        .map(|since| {
+            since_cutoff(since).ok_or_else(|| {
+                CliError::Other(format!("--since {}s is too far back", since.as_secs()))
+            })
+        })
+        .transpose()?;
+    let mut output = LogOutput::new(options.strip_ansi);
+    let result = print_logs(&client, &api, options.follow, since, &mut output).await;
+
+    if let Err(e) = client.close().await {
+        debug!(error = %e, "Error closing WebSocket");
+    }
+    result
+}
+
+/// Prints the history and, when following, live output.
+async fn print_logs(
+    client: &GuestClient,
+    api: &ApiClient,
+    follow: bool,
+    since: Option<DateTime<Utc>>,
+    output: &mut LogOutput,
+) -> Result<()> {
+    loop {
+        let Some(msg) = client.recv().await? else {
+            return Err(CliError::WebSocketError("Connection closed".to_string()));
+        };
+
+        match msg {
+            GuestIncomingMessage::History(batch) => {
+                let mut unreadable = 0;
+                for entry in &batch.entries {
+                    if !is_since(&entry.timestamp, since) {
+                        continue;
+                    }
+                    match client.decrypt_output(&entry.encrypted).await? {
+                        Some(data) => {
+                            if !output.write(&data)? {
+                                return Ok(());
+                            }
+                        }
+                        None => unreadable += 1,
+                    }
+                }
+                if unreadable > 0 {
+                    eprintln!(
+                        "Warning: {} output entries could not be decrypted",
+                        unreadable
+                    );
+                }
+                if !follow {
+                    return Ok(());
+                }
+            }
+
+            GuestIncomingMessage::Output { encrypted, .. } => {
+                if let Some(data) = client.decrypt_output(&encrypted).await? {
+                    if !output.write(&data)? {
+                        return Ok(());
+                    }
+                }
+            }
+
+            GuestIncomingMessage::SessionDetached { .. } => return Ok(()),
+
+            GuestIncomingMessage::Error { code, message } => {
+                return Err(CliError::Other(format!(
+                    "Server error [{}]: {}",
+                    code, message
+                )));
+            }
+
+            msg => {
+                client.handle_key_message(api, &msg).await?;
+            }
+        }
+    }
+}
+
+/// Returns true if output with this timestamp should be printed. Output with
+/// a timestamp that cannot be parsed is always printed.
+fn is_since(timestamp: &str, since: Option<DateTime<Utc>>) -> bool {
+    match (since, DateTime::parse_from_rfc3339(timestamp)) {
+        (Some(since), Ok(timestamp)) => timestamp >= since,
+        _ => true,
+    }
+}
+
+/// Writes session output to stdout, optionally as plain text.
+struct LogOutput {
+    /// Strips escape sequences when printing plain text.
+    stripper: Option<AnsiStripper>,
+}
+
+impl LogOutput {
+    /// Creates the output, stripping escape sequences if asked to.
+    fn new(strip_ansi: bool) -> Self {
+        Self {
+            stripper: strip_ansi.then(AnsiStripper::new),
+        }
+    }
+
+    /// Writes output, returning false once stdout was closed (e.g. by
+    /// `| head`).
+    fn write(&mut self, data: &[u8]) -> Result<bool> {
+        let data = match &mut self.stripper {
+            Some(stripper) => stripper.push(data),
+            None => data.to_vec(),
+        };
+
+        let mut stdout = io::stdout().lock();
+        match stdout.write_all(&data).and_then(|()| stdout.flush()) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Returns the time `since` ago, or `None` if that is before the earliest
+/// representable date.
+fn since_cutoff(since: Duration) -> Option<DateTime<Utc>> {
+    let since = chrono::Duration::from_std(since).ok()?;
+    Utc::now().checked_sub_signed(since)
+}
+
+/// Parses a `--since` duration such as `30s`, `10m`, `2h` or `1d`. A bare
+/// number is taken as seconds.
+pub fn parse_since(value: &str) -> std::result::Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}' (expected e.g. 10m)", value))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{}' (use s, m, h or d)", unit)),
+    };
+    let since = number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .filter(|since| since_cutoff(*since).is_some())
+        .ok_or_else(|| format!("duration '{}' is too large", value))?;
+    Ok(since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_since("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_since("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_since("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_since("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_since("").is_err());
+        assert!(parse_since("m").is_err());
+        assert!(parse_since("10w").is_err());
+        assert!(parse_since("100000000000000").is_err());
+        assert!(parse_since("18446744073709551615d").is_err());
+    }
+
+    #[test]
+    fn test_is_since() {
+        let since = DateTime::parse_from_rfc3339("2026-01-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert!(is_since("2026-01-01T10:00:00Z", Some(since)));
+        assert!(is_since("2026-01-01T11:30:00+01:00", Some(since)));
+        assert!(!is_since("2026-01-01T09:59:59Z", Some(since)));
+        assert!(is_since("not a timestamp", Some(since)));
+        assert!(is_since("2020-01-01T00:00:00Z", None));
+    }
+}
@@ -3,6 +3,22 @@
 //! This module contains subcommands for session management:
 //! - `sessions`: List and select sessions interactively
 //! - `connect`: Connect to a session as a guest
+//! - `dash`: Full-screen dashboard of all sessions
+//! - `login`, `logout`, `whoami`: Manage this device's authentication
+//! - `logs`: Print a session's output for scripts
+//! - `profile`: Manage named credential profiles
+//! - `send`: Send input to a session from a script
+//! - `share`: Share a session with another klaas user
+//! - `watch`: View a session read-only
 
 pub mod connect;
+pub mod dash;
+pub mod login;
+pub mod logout;
+pub mod logs;
+pub mod profile;
+pub mod send;
 pub mod sessions;
+pub mod share;
+pub mod watch;
+pub mod whoami;
@@ -0,0 +1,174 @@
+//! Profile command - manage named credential profiles.
+//!
+//! Each profile has its own tokens, device ID and MEK. See
+//! [`crate::profile`] for how the profile of a command is selected.
+
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::config::load_config;
+use crate::credentials::CredentialStore;
+use crate::error::Result;
+use crate::profile::{
+    current_profile, validate_profile_name, ProfileRegistry, DEFAULT_PROFILE, ENV_PROFILE,
+};
+use crate::ui::colors;
+
+/// What the profile command should do.
+#[derive(Debug, Clone)]
+pub enum ProfileAction {
+    /// List known profiles.
+    List,
+    /// Make a profile the active one.
+    Use { name: String },
+    /// Remove a profile and its credentials.
+    Remove { name: String, yes: bool },
+}
+
+/// Runs the profile command.
+pub fn run(action: ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List => list(),
+        ProfileAction::Use { name } => use_profile(&name),
+        ProfileAction::Remove { name, yes } => remove(&name, yes),
+    }
+}
+
+/// Prints all known profiles, marking the current one.
+fn list() -> Result<()> {
+    let registry = ProfileRegistry::load();
+    let current = current_profile();
+
+    let mut names: BTreeSet<String> = registry.profiles;
+    names.insert(DEFAULT_PROFILE.to_string());
+    names.insert(current.to_string());
+
+    println!();
+    for name in &names {
+        let logged_in = matches!(CredentialStore::for_profile(name).get_tokens(), Ok(Some(_)));
+        let (marker, color) = if name == current {
+            ("*", colors::AMBER)
+        } else {
+            (" ", colors::TEXT_PRIMARY)
+        };
+
+        println!(
+            "  {}{} {:<32}{} {}{}{}",
+            fg_color(color),
+            marker,
+            name,
+            reset(),
+            fg_color(colors::TEXT_SECONDARY),
+            if logged_in { "logged in" } else { "-" },
+            reset()
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Makes `name` the active profile.
+fn use_profile(name: &str) -> Result<()> {
+    validate_profile_name(name)?;
+
+    ProfileRegistry::update(|registry| {
+        registry.profiles.insert(name.to_string());
+        registry.active = Some(name.to_string());
+    })?;
+
+    println!();
+    println!(
+        "  {}✓{} Using profile {}{}{}",
+        fg_color(colors::GREEN),
+        reset(),
+        BOLD,
+        name,
+        reset()
+    );
+
+    // The active profile has the lowest precedence, so point out anything
+    // that still overrides it.
+    if let Ok(env) = std::env::var(ENV_PROFILE) {
+        print_override_warning(&format!("{}={}", ENV_PROFILE, env));
+    } else if let Some(configured) = load_config().profile {
+        print_override_warning(&format!("profile = \"{}\" in config.toml", configured));
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Removes `name` and all of its credentials after confirmation.
+fn remove(name: &str, yes: bool) -> Result<()> {
+    validate_profile_name(name)?;
+
+    if !yes {
+        println!();
+        print!(
+            "  {}Remove profile {} and its credentials, including its encryption key? [y/N]{} ",
+            fg_color(colors::TEXT_MUTED),
+            name,
+            reset()
+        );
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let answer = input.trim().to_lowercase();
+        if answer != "y" && answer != "yes" {
+            println!();
+            println!("  Cancelled.");
+            println!();
+            return Ok(());
+        }
+    }
+
+    CredentialStore::for_profile(name).clear_all_servers()?;
+
+    ProfileRegistry::update(|registry| {
+        registry.profiles.remove(name);
+        registry
+            .namespaces
+            .retain(|namespace| namespace.split('@').next() != Some(name));
+        if registry.active.as_deref() == Some(name) {
+            registry.active = None;
+        }
+    })?;
+
+    println!();
+    println!(
+        "  {}✓{} Removed profile {}{}{}",
+        fg_color(colors::GREEN),
+        reset(),
+        BOLD,
+        name,
+        reset()
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Warns that the active profile is overridden by `source`.
+fn print_override_warning(source: &str) {
+    println!(
+        "  {}Note: {} takes precedence over the active profile.{}",
+        fg_color(colors::AMBER),
+        source,
+        reset()
+    );
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
+
+/// Bold ANSI code.
+const BOLD: &str = "\x1b[1m";
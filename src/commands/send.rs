@@ -0,0 +1,261 @@
+//! Send command - type into a running session from a script.
+//!
+//! Connects as a guest, sends the text as input exactly like a guest typing
+//! it, and exits. Meant for cron jobs, git hooks and CI, where there is no
+//! terminal to run `klaas connect` in.
+
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::api_client::ApiClient;
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::error::{CliError, Result};
+use crate::guest::keys::resolve_session_keys;
+use crate::guest::terminal::{
+    GuestClient, GuestIncomingMessage, InputRejected, InputRejectionReason,
+};
+
+/// How long to wait for the server to reject the input before assuming it
+/// was delivered. Accepted input is not acknowledged.
+const REJECTION_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// How long `--wait-idle` waits for the agent without `--timeout`.
+pub const DEFAULT_WAIT_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Agent modes in which the agent waits for the next prompt.
+const IDLE_MODES: &[&str] = &["idle", "waiting_for_input"];
+
+/// Options for the send command.
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Text to send.
+    pub text: Option<String>,
+    /// File to read the text from (`-` for stdin).
+    pub file: Option<PathBuf>,
+    /// Press Enter after the text, submitting it.
+    pub enter: bool,
+    /// Wait until the agent is idle or waiting for input again.
+    pub wait_idle: bool,
+    /// How long to wait for the agent with `wait_idle` (default: 30 minutes).
+    pub timeout: Option<Duration>,
+}
+
+/// Runs the send command.
+///
+/// # Arguments
+///
+/// * `target` - Session ID or name
+/// * `options` - What to send and whether to wait for the agent
+///
+/// # Returns
+///
+/// * `Ok(())` once the input was delivered (and the agent is idle, if asked)
+/// * `Err(...)` if the input was rejected, or on authentication, network, or
+///   lookup errors
+pub async fn run(target: &str, options: &SendOptions) -> Result<()> {
+    let input = read_input(options)?;
+
+    // Never start the device flow: this is meant for scripts
+    let config = get_api_config();
+    let access_token = AuthManager::for_current_profile(&config.api_url)?
+        .valid_token()
+        .await?;
+    let api = ApiClient::new(&config.api_url, &access_token);
+
+    let session = api
+        .get_session(target)
+        .await?
+        .ok_or_else(|| CliError::Other(format!("Session not found: {}", target)))?;
+    let keys = resolve_session_keys(&api, &CredentialStore::new(), &session.session_id).await?;
+    let client = GuestClient::connect(
+        &config.ws_url,
+        &access_token,
+        &session.session_id,
+        keys,
+        false,
+    )
+    .await?;
+
+    let wait_idle = options
+        .wait_idle
+        .then(|| options.timeout.unwrap_or(DEFAULT_WAIT_IDLE_TIMEOUT));
+    let result = deliver(&client, &input, wait_idle).await;
+
+    if let Err(e) = client.close().await {
+        debug!(error = %e, "Error closing WebSocket");
+    }
+    result
+}
+
+/// Sends input to the session and waits until it is delivered.
+///
+/// With `wait_idle`, also waits up to that long for the agent to start
+/// working and then be idle or waiting for input again. An idle mode before
+/// the agent reacted to the input is about the previous prompt, so it
+/// doesn't count.
+pub async fn deliver(
+    client: &GuestClient,
+    input: &[u8],
+    wait_idle: Option<Duration>,
+) -> Result<()> {
+    // Skip the replayed history, so everything after it is about our input
+    loop {
+        match client.recv().await? {
+            Some(GuestIncomingMessage::History(_)) => break,
+            Some(_) => {}
+            None => return Err(connection_closed()),
+        }
+    }
+
+    client.send_input(input).await?;
+    debug!(bytes = input.len(), "Sent input");
+
+    let deadline = Instant::now() + wait_idle.unwrap_or(REJECTION_GRACE_PERIOD);
+    let mut busy = false;
+    loop {
+        let msg = match tokio::time::timeout_at(deadline, client.recv()).await {
+            Ok(msg) => msg?,
+            Err(_) => match wait_idle {
+                Some(timeout) => {
+                    return Err(CliError::Other(format!(
+                        "Timed out after {}s waiting for the agent to be idle",
+                        timeout.as_secs()
+                    )));
+                }
+                // Not rejected, so it reached the host
+                None => return Ok(()),
+            },
+        };
+
+        match msg {
+            Some(GuestIncomingMessage::InputRejected(rejection)) => {
+                return Err(rejection_error(&rejection));
+            }
+            Some(GuestIncomingMessage::ModeChange(change)) => {
+                if !IDLE_MODES.contains(&change.mode.as_str()) {
+                    busy = true;
+                } else if busy {
+                    debug!(mode = %change.mode, "Agent is idle");
+                    return Ok(());
+                }
+            }
+            Some(GuestIncomingMessage::SessionDetached { .. }) => {
+                return Err(CliError::Other(
+                    "Session detached before the agent was idle".to_string(),
+                ));
+            }
+            Some(_) => {}
+            None => return Err(connection_closed()),
+        }
+    }
+}
+
+/// Reads the text to send and appends Enter if asked to.
+///
+/// The host only accepts UTF-8 text, so anything else is refused here
+/// instead of being dropped on the way.
+fn read_input(options: &SendOptions) -> Result<Vec<u8>> {
+    let mut input = match (&options.text, &options.file) {
+        (Some(text), _) => text.clone().into_bytes(),
+        (None, Some(path)) if path.as_os_str() == "-" => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input)?;
+            input
+        }
+        (None, Some(path)) => std::fs::read(path)
+            .map_err(|e| CliError::Other(format!("Failed to read {}: {}", path.display(), e)))?,
+        (None, None) => Vec::new(),
+    };
+    if let Err(e) = std::str::from_utf8(&input) {
+        return Err(CliError::InvalidInput(format!(
+            "input is not valid UTF-8 text ({})",
+            e
+        )));
+    }
+
+    if options.enter {
+        input.push(b'\r');
+    }
+    if input.is_empty() {
+        return Err(CliError::Other("Nothing to send".to_string()));
+    }
+    Ok(input)
+}
+
+/// Describes why the server rejected the input.
+fn rejection_error(rejection: &InputRejected) -> CliError {
+    let reason = match rejection.reason {
+        InputRejectionReason::LockHeld => format!(
+            "{} is typing",
+            rejection.holder_name.as_deref().unwrap_or("someone")
+        ),
+        InputRejectionReason::HostOnly => "the session only accepts input from its host".into(),
+        InputRejectionReason::HostDetached => "the host is not connected".into(),
+        InputRejectionReason::ReadOnly => "this connection is read-only".into(),
+    };
+    CliError::Other(format!("Input rejected: {}", reason))
+}
+
+/// Error for a connection the server closed before we were done.
+fn connection_closed() -> CliError {
+    CliError::WebSocketError("Connection closed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_input_appends_enter() {
+        let options = SendOptions {
+            text: Some("run the tests".to_string()),
+            enter: true,
+            ..Default::default()
+        };
+        assert_eq!(read_input(&options).unwrap(), b"run the tests\r");
+
+        // Enter on its own submits whatever is already typed
+        let options = SendOptions {
+            enter: true,
+            ..Default::default()
+        };
+        assert_eq!(read_input(&options).unwrap(), b"\r");
+
+        assert!(read_input(&SendOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_read_input_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompt.md");
+        std::fs::write(&path, "# Task\nFix the build\n").unwrap();
+
+        let options = SendOptions {
+            file: Some(path),
+            ..Default::default()
+        };
+        assert_eq!(read_input(&options).unwrap(), b"# Task\nFix the build\n");
+
+        let options = SendOptions {
+            file: Some(dir.path().join("missing.md")),
+            ..Default::default()
+        };
+        assert!(read_input(&options).is_err());
+        // Binary data would never reach the agent
+        let binary = dir.path().join("image.png");
+        std::fs::write(&binary, b"\x89PNG\r\n\x1a\n\xff").unwrap();
+        let options = SendOptions {
+            file: Some(binary),
+            ..Default::default()
+        };
+        let err = read_input(&options).unwrap_err();
+        assert!(matches!(err, CliError::InvalidInput(_)), "{}", err);
+        assert_eq!(err.exit_code(), 65);
+    }
+}
@@ -11,10 +11,9 @@ use crossterm::terminal;
 use tracing::debug;
 
 use crate::api_client::{ApiClient, Session};
-use crate::auth;
-use crate::config::API_URL;
-use crate::credentials;
-use crate::error::{CliError, Result};
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
+use crate::error::Result;
 use crate::ui::colors;
 
 /// Result of the sessions command.
@@ -41,7 +40,9 @@ pub enum SessionsResult {
 /// - `Err(...)` on authentication or API errors
 pub async fn run() -> Result<SessionsResult> {
     // Ensure user is authenticated
-    let access_token = ensure_authenticated().await?;
+    let access_token = AuthManager::for_current_profile(&get_api_config().api_url)?
+        .ensure_authenticated()
+        .await?;
 
     // Fetch sessions from API
     let sessions = fetch_sessions(&access_token).await?;
@@ -85,42 +86,11 @@ fn prompt_start_new_session() -> Result<SessionsResult> {
     }
 }
 
-/// Ensures the user is authenticated, triggering device flow if needed.
-///
-/// Returns the access token on success.
-async fn ensure_authenticated() -> Result<String> {
-    // Check for existing tokens
-    if let Some((access_token, _refresh_token)) = credentials::get_tokens()? {
-        // Try to use existing token or refresh it
-        // For now, just use the access token directly
-        // TODO: Add token validation and refresh logic
-        debug!("Using existing access token");
-        return Ok(access_token);
-    }
-
-    // No tokens - need to authenticate
-    debug!("No tokens found, starting device flow");
-
-    // Display startup banner for auth
-    crate::ui::display_startup_banner();
-
-    match auth::authenticate(API_URL).await {
-        Ok(token_response) => {
-            // Store tokens
-            credentials::store_tokens(&token_response.access_token, &token_response.refresh_token)?;
-            Ok(token_response.access_token)
-        }
-        Err(auth::AuthError::Cancelled) => Err(CliError::AuthError("Cancelled".to_string())),
-        Err(auth::AuthError::Skipped) => Err(CliError::AuthError("Skipped".to_string())),
-        Err(e) => Err(CliError::AuthError(e.to_string())),
-    }
-}
-
 /// Fetches sessions from the API using the ApiClient.
 async fn fetch_sessions(access_token: &str) -> Result<Vec<Session>> {
     debug!("Fetching sessions from API");
 
-    let client = ApiClient::new(API_URL, access_token);
+    let client = ApiClient::new(&get_api_config().api_url, access_token);
     client.get_sessions().await
 }
 
@@ -0,0 +1,248 @@
+//! Share command - give another klaas user access to one session.
+//!
+//! The session key is wrapped to each of the invitee's registered device
+//! keys, so the invitee can decrypt the session without ever learning the
+//! owner's MEK. Revoking a share moves the session to a new key epoch and
+//! re-wraps the new key for everyone who still has access.
+
+use tracing::debug;
+
+use crate::api_client::{ApiClient, DeviceKeyGrant, KeyRotation, ShareGrant};
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::crypto::{decode_base64, derive_session_key_for_epoch, wrap_session_key, SecretKey};
+use crate::error::{CliError, Result};
+use crate::types::ShareAccess;
+use crate::ui::colors;
+
+use super::connect;
+
+/// What the share command should do.
+#[derive(Debug, Clone)]
+pub enum ShareAction {
+    /// Share the session with a user.
+    Grant { user: String, access: ShareAccess },
+    /// Revoke a user's access and rotate the session key.
+    Revoke { user: String },
+    /// List current shares.
+    List,
+}
+
+/// Runs the share command.
+///
+/// # Arguments
+///
+/// * `target` - Session ID or name
+/// * `action` - Grant, revoke or list
+pub async fn run(target: &str, action: ShareAction) -> Result<()> {
+    let access_token = AuthManager::for_current_profile(&get_api_config().api_url)?
+        .ensure_authenticated()
+        .await?;
+    let session_id = connect::lookup_session_with_token(target, &access_token).await?;
+    let api = ApiClient::new(&get_api_config().api_url, &access_token);
+
+    match action {
+        ShareAction::Grant { user, access } => {
+            let mek = load_mek()?;
+            grant(&api, &mek, &session_id, &user, access).await
+        }
+        ShareAction::Revoke { user } => {
+            let mek = load_mek()?;
+            revoke(&api, &mek, &session_id, &user).await
+        }
+        ShareAction::List => list(&api, &session_id).await,
+    }
+}
+
+/// Loads the MEK of this account, which is needed to derive session keys.
+fn load_mek() -> Result<SecretKey> {
+    let mek_bytes = CredentialStore::new().get_mek()?.ok_or_else(|| {
+        CliError::AuthError(
+            "No encryption key on this device. Run 'klaas' once to set it up.".into(),
+        )
+    })?;
+
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(&mek_bytes);
+    Ok(SecretKey::from_bytes(arr))
+}
+
+/// Shares the session with `user` at the current key epoch.
+async fn grant(
+    api: &ApiClient,
+    mek: &SecretKey,
+    session_id: &str,
+    user: &str,
+    access: ShareAccess,
+) -> Result<()> {
+    let key_epoch = api.get_shares(session_id).await?.key_epoch;
+    let session_key = derive_session_key_for_epoch(mek, session_id, key_epoch);
+
+    let grant = wrap_for_user(api, &session_key, key_epoch, user, access).await?;
+    let device_count = grant.keys.len();
+    api.create_share(session_id, &grant).await?;
+
+    println!();
+    println!(
+        "  {}✓{} Shared session {}{}{} with {}{}{} ({}, {} device{})",
+        fg_color(colors::GREEN),
+        reset(),
+        fg_color(colors::AMBER),
+        session_id,
+        reset(),
+        BOLD,
+        user,
+        reset(),
+        access.to_wire(),
+        device_count,
+        if device_count == 1 { "" } else { "s" }
+    );
+    println!(
+        "    {}They can join with {}klaas connect {}{}",
+        fg_color(colors::TEXT_SECONDARY),
+        fg_color(colors::AMBER),
+        session_id,
+        reset()
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Revokes `user`'s access and rotates the session key.
+///
+/// The session moves to the next epoch first, with the remaining shares
+/// re-wrapped under its key, and the share is deleted last. If a step
+/// fails, the share is still there and the command can be run again;
+/// until the rotation succeeds, the user keeps the access they had.
+/// The server announces the new epoch to the host and guests with
+/// `key_rotated`.
+pub async fn revoke(api: &ApiClient, mek: &SecretKey, session_id: &str, user: &str) -> Result<()> {
+    let shares = api.get_shares(session_id).await?;
+    if !shares.shares.iter().any(|s| s.user == user) {
+        return Err(CliError::Other(format!(
+            "Session {} is not shared with {}",
+            session_id, user
+        )));
+    }
+
+    let key_epoch = shares.key_epoch + 1;
+    let session_key = derive_session_key_for_epoch(mek, session_id, key_epoch);
+
+    let mut remaining = Vec::with_capacity(shares.shares.len());
+    for share in shares.shares.iter().filter(|s| s.user != user) {
+        remaining
+            .push(wrap_for_user(api, &session_key, key_epoch, &share.user, share.access).await?);
+    }
+
+    debug!(
+        key_epoch,
+        remaining = remaining.len(),
+        "Rotating session key"
+    );
+    api.rotate_session_key(
+        session_id,
+        &KeyRotation {
+            key_epoch,
+            shares: remaining,
+        },
+    )
+    .await?;
+
+    api.revoke_share(session_id, user).await?;
+
+    println!();
+    println!(
+        "  {}✓{} Revoked access for {}{}{}; session key rotated to epoch {}",
+        fg_color(colors::GREEN),
+        reset(),
+        BOLD,
+        user,
+        reset(),
+        key_epoch
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Prints the current shares of the session.
+async fn list(api: &ApiClient, session_id: &str) -> Result<()> {
+    let shares = api.get_shares(session_id).await?;
+
+    println!();
+    if shares.shares.is_empty() {
+        println!(
+            "  {}Session {} is not shared with anyone.{}",
+            fg_color(colors::TEXT_MUTED),
+            session_id,
+            reset()
+        );
+    } else {
+        for share in &shares.shares {
+            println!(
+                "  {}{:<32}{} {}{}{}",
+                fg_color(colors::TEXT_PRIMARY),
+                share.user,
+                reset(),
+                fg_color(colors::TEXT_SECONDARY),
+                share.access.to_wire(),
+                reset()
+            );
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Wraps the session key to every registered device of `user`.
+async fn wrap_for_user(
+    api: &ApiClient,
+    session_key: &SecretKey,
+    key_epoch: u32,
+    user: &str,
+    access: ShareAccess,
+) -> Result<ShareGrant> {
+    let devices = api
+        .get_user_devices(user)
+        .await?
+        .ok_or_else(|| CliError::Other(format!("User not found: {}", user)))?;
+
+    if devices.is_empty() {
+        return Err(CliError::Other(format!(
+            "{} has no devices that can receive shared sessions yet. \
+             Ask them to run 'klaas' once.",
+            user
+        )));
+    }
+
+    let mut keys = Vec::with_capacity(devices.len());
+    for device in devices {
+        let public_key = decode_base64(&device.public_key)?;
+        keys.push(DeviceKeyGrant {
+            device_id: device.device_id,
+            wrapped: wrap_session_key(session_key, key_epoch, &public_key)?,
+        });
+    }
+
+    Ok(ShareGrant {
+        user: user.to_string(),
+        access,
+        keys,
+    })
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
+
+/// Bold ANSI code.
+const BOLD: &str = "\x1b[1m";
@@ -0,0 +1,265 @@
+//! Watch command - view a session read-only.
+//!
+//! Connects to a session as a guest that never sends input. With `--follow`,
+//! watches the newest attached session started on this device or in the
+//! current directory, and moves on to the next one whenever it ends.
+
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+use tracing::debug;
+
+use crate::api_client::{ApiClient, Session};
+use crate::auth_manager::AuthManager;
+use crate::config::get_api_config;
+use crate::credentials::CredentialStore;
+use crate::error::Result;
+use crate::guest::{self, GuestExit, GuestOptions};
+use crate::ui::colors;
+
+use super::connect;
+
+/// How often the session list is checked while waiting for a session to
+/// follow.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Runs the watch command.
+///
+/// # Arguments
+///
+/// * `target` - Session ID or name to watch
+/// * `follow` - Keep watching the newest attached session for this device or
+///   directory
+///
+/// # Returns
+///
+/// * `Ok(())` once the user stops watching or the session ends
+/// * `Err(...)` on authentication, network, or lookup errors
+pub async fn run(target: Option<String>, follow: bool) -> Result<()> {
+    let api_url = get_api_config().api_url;
+    let access_token = AuthManager::for_current_profile(&api_url)?
+        .ensure_authenticated()
+        .await?;
+
+    let options = GuestOptions {
+        read_only: true,
+        ..Default::default()
+    };
+
+    let mut ended = match target {
+        Some(identifier) => {
+            let session_id = connect::lookup_session_with_token(&identifier, &access_token).await?;
+            let exit = watch(&session_id, &access_token, &options).await?;
+            if !follow || exit == GuestExit::Quit {
+                return Ok(());
+            }
+            ApiClient::new(&api_url, &access_token)
+                .get_session(&session_id)
+                .await?
+                .map(|session| ended_marker(&session))
+        }
+        None => None,
+    };
+
+    // Only reached with --follow
+    let device_id = CredentialStore::new().get_device_id()?;
+    let cwd = std::env::current_dir()?.to_string_lossy().to_string();
+    loop {
+        // Waiting may take a while, so use a fresh token for every session
+        let access_token = AuthManager::for_current_profile(&api_url)?
+            .valid_token()
+            .await?;
+        let api = ApiClient::new(&api_url, &access_token);
+
+        let session = wait_for_session(&api, device_id.as_deref(), &cwd, ended.as_ref()).await?;
+        let exit = watch(&session.session_id, &access_token, &options).await?;
+        if exit == GuestExit::Quit {
+            return Ok(());
+        }
+        ended = Some(ended_marker(&session));
+    }
+}
+
+/// Watches one session until the user stops or the session ends.
+async fn watch(session_id: &str, access_token: &str, options: &GuestOptions) -> Result<GuestExit> {
+    debug!("Watching session: {}", session_id);
+
+    println!();
+    println!(
+        "  {}Watching session {}{}{}...{}",
+        fg_color(colors::TEXT_SECONDARY),
+        fg_color(colors::AMBER),
+        session_id,
+        fg_color(colors::TEXT_SECONDARY),
+        reset()
+    );
+
+    let exit = guest::run_with_token(session_id, access_token, options).await?;
+
+    println!();
+    let message = match exit {
+        GuestExit::Quit => "Stopped watching.",
+        GuestExit::SessionEnded => "Session ended.",
+    };
+    println!("  {}{}{}", fg_color(colors::TEXT_MUTED), message, reset());
+    Ok(exit)
+}
+
+/// Polls the session list until there is a session to follow.
+async fn wait_for_session(
+    api: &ApiClient,
+    device_id: Option<&str>,
+    cwd: &str,
+    ended: Option<&(String, Option<String>)>,
+) -> Result<Session> {
+    let mut announced = false;
+    loop {
+        let sessions = api.get_sessions().await?;
+        if let Some(session) = newest_session(sessions, device_id, cwd, ended) {
+            return Ok(session);
+        }
+
+        if !announced {
+            println!();
+            println!(
+                "  {}Waiting for a session on this device or in {}{}{}...{}",
+                fg_color(colors::TEXT_SECONDARY),
+                fg_color(colors::AMBER),
+                cwd,
+                fg_color(colors::TEXT_SECONDARY),
+                reset()
+            );
+            announced = true;
+        }
+        tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+    }
+}
+
+/// Identifies a session attachment that has ended, so it is not picked again
+/// while the session list still shows it as attached. A later attachment of
+/// the same session has a new `attached_at` and is followed again.
+fn ended_marker(session: &Session) -> (String, Option<String>) {
+    (session.session_id.clone(), session.attached_at.clone())
+}
+
+/// Picks the most recently attached session started on this device or in
+/// this directory, skipping the attachment that just ended.
+fn newest_session(
+    sessions: Vec<Session>,
+    device_id: Option<&str>,
+    cwd: &str,
+    ended: Option<&(String, Option<String>)>,
+) -> Option<Session> {
+    sessions
+        .into_iter()
+        .filter(|session| session.status == "attached")
+        .filter(|session| device_id == Some(session.device_id.as_str()) || session.cwd == cwd)
+        .filter(|session| ended != Some(&ended_marker(session)))
+        .max_by_key(|session| {
+            parse_timestamp(
+                session
+                    .attached_at
+                    .as_deref()
+                    .unwrap_or(&session.started_at),
+            )
+        })
+}
+
+/// Parses an RFC 3339 timestamp from the API.
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(timestamp).ok()
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, device_id: &str, cwd: &str, status: &str, attached_at: &str) -> Session {
+        Session {
+            session_id: id.to_string(),
+            device_id: device_id.to_string(),
+            device_name: "macbook".to_string(),
+            name: None,
+            status: status.to_string(),
+            started_at: "2026-01-01T09:00:00Z".to_string(),
+            attached_at: Some(attached_at.to_string()),
+            cwd: cwd.to_string(),
+        }
+    }
+
+    fn id_of(session: Option<Session>) -> Option<String> {
+        session.map(|s| s.session_id)
+    }
+
+    #[test]
+    fn test_newest_session_matches_device_or_cwd() {
+        let sessions = || {
+            vec![
+                session("A", "DEV1", "/work", "attached", "2026-01-01T10:00:00Z"),
+                session(
+                    "B",
+                    "DEV2",
+                    "/work",
+                    "attached",
+                    "2026-01-01T12:30:00+01:00",
+                ),
+                session("C", "DEV2", "/other", "attached", "2026-01-01T12:00:00Z"),
+                session("D", "DEV1", "/work", "detached", "2026-01-01T13:00:00Z"),
+            ]
+        };
+
+        // B attached at 11:30 UTC; C is elsewhere and D is no longer attached
+        assert_eq!(
+            id_of(newest_session(sessions(), None, "/work", None)),
+            Some("B".into())
+        );
+        assert_eq!(
+            id_of(newest_session(sessions(), Some("DEV1"), "/nowhere", None)),
+            Some("A".into())
+        );
+        assert_eq!(
+            id_of(newest_session(sessions(), Some("DEV2"), "/nowhere", None)),
+            Some("C".into())
+        );
+        assert_eq!(
+            id_of(newest_session(sessions(), Some("DEV3"), "/tmp", None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newest_session_skips_ended_attachment() {
+        let sessions = || {
+            vec![session(
+                "A",
+                "DEV1",
+                "/work",
+                "attached",
+                "2026-01-01T10:00:00Z",
+            )]
+        };
+
+        let ended = ("A".to_string(), Some("2026-01-01T10:00:00Z".to_string()));
+        assert_eq!(
+            id_of(newest_session(sessions(), None, "/work", Some(&ended))),
+            None
+        );
+
+        // The same session attached again is followed
+        let ended = ("A".to_string(), Some("2026-01-01T09:00:00Z".to_string()));
+        assert_eq!(
+            id_of(newest_session(sessions(), None, "/work", Some(&ended))),
+            Some("A".into())
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Whoami command - show the account and credentials of this device.
+//!
+//! Everything is read locally: the account comes from the access token's
+//! claims, which are decoded but not verified.
+
+use crate::auth::{decode_jwt_claims, token_account, token_expiry};
+use crate::auth_manager::{AuthManager, ENV_MEK_FILE, ENV_TOKEN};
+use crate::config::get_api_config;
+use crate::crypto::key_fingerprint;
+use crate::error::{CliError, Result};
+use crate::ui::colors;
+
+/// Runs the whoami command.
+pub async fn run() -> Result<()> {
+    let api = get_api_config();
+    let auth = AuthManager::for_current_profile(&api.api_url)?;
+    let cred_store = auth.credentials();
+
+    let Some(access_token) = auth.access_token().await else {
+        return Err(CliError::NotAuthenticated(format!(
+            "run 'klaas login' or set {} (profile {})",
+            ENV_TOKEN,
+            cred_store.profile()
+        )));
+    };
+
+    let user_id = decode_jwt_claims(&access_token)
+        .and_then(|claims| claims.get("sub")?.as_str().map(str::to_string));
+    let expiry = match token_expiry(&access_token) {
+        Some(exp) => format_expiry(exp, chrono::Utc::now().timestamp()),
+        None if auth.uses_api_token() => format!("API token from {}", ENV_TOKEN),
+        None => "unknown".to_string(),
+    };
+    let fingerprint = match auth.mek().await {
+        Some(mek) => key_fingerprint(&mek),
+        None => "not on this device".to_string(),
+    };
+    let storage = match auth.mek_file() {
+        Some(path) => format!("{}={}", ENV_MEK_FILE, path.display()),
+        None if auth.uses_api_token() => {
+            format!("{} (key: {})", ENV_TOKEN, cred_store.storage_description())
+        }
+        None => cred_store.storage_description(),
+    };
+
+    println!();
+    print_row(
+        "Account",
+        &token_account(&access_token).unwrap_or_else(|| "unknown".into()),
+    );
+    if let Some(user_id) = user_id {
+        print_row("User ID", &user_id);
+    }
+    print_row("Profile", cred_store.profile());
+    if !api.is_default_endpoint() {
+        print_row("Server", &api.api_url);
+    }
+    print_row(
+        "Device ID",
+        &cred_store
+            .get_device_id()?
+            .unwrap_or_else(|| "none".to_string()),
+    );
+    print_row("Access token", &expiry);
+    print_row("Encryption key", &fingerprint);
+    print_row("Storage", &storage);
+    println!();
+
+    Ok(())
+}
+
+/// Prints a label/value row.
+fn print_row(label: &str, value: &str) {
+    println!(
+        "  {}{:<16}{}{}{}",
+        fg_color(colors::TEXT_MUTED),
+        label,
+        fg_color(colors::TEXT_PRIMARY),
+        value,
+        reset()
+    );
+}
+
+/// Describes when a token expires (or expired) relative to `now`.
+fn format_expiry(exp: i64, now: i64) -> String {
+    let remaining = exp - now;
+    let abs = remaining.unsigned_abs();
+    let span = if abs >= 86_400 {
+        format!("{}d {}h", abs / 86_400, abs % 86_400 / 3_600)
+    } else if abs >= 3_600 {
+        format!("{}h {}m", abs / 3_600, abs % 3_600 / 60)
+    } else if abs >= 60 {
+        format!("{}m", abs / 60)
+    } else {
+        format!("{}s", abs)
+    };
+
+    if remaining > 0 {
+        format!("expires in {}", span)
+    } else {
+        format!("expired {} ago (refreshed on next use)", span)
+    }
+}
+
+/// Generates ANSI escape code for 24-bit true color foreground.
+fn fg_color(color: (u8, u8, u8)) -> String {
+    format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2)
+}
+
+/// ANSI reset code.
+fn reset() -> &'static str {
+    "\x1b[0m"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_expiry() {
+        assert_eq!(format_expiry(1_030, 1_000), "expires in 30s");
+        assert_eq!(format_expiry(1_000 + 5_400, 1_000), "expires in 1h 30m");
+        assert_eq!(format_expiry(1_000 + 90_000, 1_000), "expires in 1d 1h");
+        assert_eq!(
+            format_expiry(1_000, 1_000 + 120),
+            "expired 2m ago (refreshed on next use)"
+        );
+    }
+}
@@ -6,17 +6,52 @@
 //! - Linux: Secret Service (libsecret)
 //!
 //! When the keychain is unavailable, it falls back to file-based storage
-//! in `~/.config/klaas/`.
+//! in `~/.klaas/credentials.json`. Release builds encrypt that file with a
+//! key derived from a passphrase (see [`ENV_CREDENTIALS_KEY`]), which is
+//! asked for once per process; plaintext files are encrypted on first read.
+//!
+//! Credentials are namespaced by profile (see [`crate::profile`]). The
+//! default profile uses the plain key names; other profiles prefix them
+//! with `<profile>/` in the keychain and live under `profiles` in the
+//! fallback file.
+//!
+//! Credentials for a server other than the built-in one (see
+//! [`crate::config::get_api_config`]) are further namespaced as
+//! `<profile>@<host>`, so tokens of different servers never mix.
 
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, TryLockError};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::{Duration, Instant};
 
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
-
-use crate::config::KEYCHAIN_SERVICE;
+use tracing::{debug, info, warn};
+use zeroize::Zeroizing;
+
+use crate::config::{get_api_config, ApiConfig, KEYCHAIN_SERVICE};
+use crate::crypto::{
+    decode_base64, decrypt_content, derive_kek, encode_base64, encrypt_content, generate_salt,
+    EncryptedContent, SecretKey, SALT_SIZE,
+};
 use crate::error::{CliError, Result};
+use crate::profile::{
+    current_profile, register_profile, ProfileRegistry, DEFAULT_PROFILE, PROFILES_FILE,
+};
+use crate::types::DeviceId;
+use crate::ui;
+
+/// All key names, used when removing a profile.
+const ALL_KEYS: &[&str] = &[
+    ACCESS_TOKEN_KEY,
+    REFRESH_TOKEN_KEY,
+    DEVICE_ID_KEY,
+    SESSION_ID_KEY,
+    MEK_KEY,
+    DEVICE_KEY_KEY,
+];
 
 /// Key names for stored credentials.
 const ACCESS_TOKEN_KEY: &str = "access_token";
@@ -24,6 +59,7 @@ const REFRESH_TOKEN_KEY: &str = "refresh_token";
 const DEVICE_ID_KEY: &str = "device_id";
 const SESSION_ID_KEY: &str = "session_id";
 const MEK_KEY: &str = "encryption_key";
+const DEVICE_KEY_KEY: &str = "device_key";
 
 /// Fallback credentials file name.
 const FALLBACK_CREDENTIALS_FILE: &str = "credentials.json";
@@ -31,6 +67,34 @@ const FALLBACK_CREDENTIALS_FILE: &str = "credentials.json";
 /// Key size in bytes for MEK (256-bit key).
 const MEK_SIZE: usize = 32;
 
+/// Environment variable holding the passphrase of the fallback file.
+pub const ENV_CREDENTIALS_KEY: &str = "KLAAS_CREDENTIALS_KEY";
+
+/// Environment variable naming a file descriptor to read the passphrase
+/// from, so it does not show up in the process environment.
+pub const ENV_CREDENTIALS_KEY_FD: &str = "KLAAS_CREDENTIALS_KEY_FD";
+
+/// Version of the encrypted fallback file format.
+const ENCRYPTED_FILE_VERSION: u8 = 1;
+
+/// Number of times a mistyped passphrase may be re-entered.
+const MAX_PASSPHRASE_ATTEMPTS: u32 = 3;
+
+/// How long to wait for another process to finish refreshing tokens.
+const REFRESH_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to retry a held refresh lock.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Key of the fallback file, unlocked once per process.
+static FILE_KEY: Mutex<Option<FileKey>> = Mutex::new(None);
+
+/// Passphrase from the environment or file descriptor, read once.
+static CONFIGURED_PASSPHRASE: OnceLock<Option<Zeroizing<String>>> = OnceLock::new();
+
+/// Ensures the "stored unencrypted" warning is only shown once.
+static PLAINTEXT_WARNING: Once = Once::new();
+
 /// Fallback credentials structure for file-based storage.
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct FallbackCredentials {
@@ -40,6 +104,80 @@ struct FallbackCredentials {
     session_id: Option<String>,
     /// Master Encryption Key stored as hex string for E2EE.
     mek: Option<String>,
+    /// Device ECDH private key stored as hex string for session sharing.
+    device_key: Option<String>,
+}
+
+/// Layout of the fallback credentials file.
+///
+/// The default profile is stored at the top level, which keeps files
+/// written before profiles existed readable.
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct FallbackFile {
+    /// Credentials of the default profile.
+    #[serde(flatten)]
+    default: FallbackCredentials,
+    /// Credentials of named profiles.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, FallbackCredentials>,
+}
+
+/// Fallback file encrypted with a passphrase-derived key.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFallbackFile {
+    /// Format version.
+    v: u8,
+    /// Argon2id salt, base64 encoded.
+    salt: String,
+    /// The serialized [`FallbackFile`], encrypted with the derived key.
+    content: EncryptedContent,
+}
+
+/// Fallback file as found on disk.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFallbackFile {
+    Encrypted(EncryptedFallbackFile),
+    Plain(FallbackFile),
+}
+
+/// Key the fallback file is encrypted with, and the salt it was derived with.
+#[derive(Clone)]
+struct FileKey {
+    salt: [u8; SALT_SIZE],
+    key: SecretKey,
+}
+
+/// How the fallback file is protected.
+#[derive(Debug, Clone)]
+enum FileProtection {
+    /// Plaintext, protected only by file permissions.
+    Plaintext,
+    /// Encrypted with a key derived from the configured or prompted passphrase.
+    Passphrase,
+    /// Encrypted with a fixed passphrase (for tests).
+    #[cfg(test)]
+    Fixed(String),
+}
+
+/// What a passphrase is asked for.
+#[derive(Clone, Copy)]
+enum PassphrasePrompt {
+    /// Unlock an existing file.
+    Unlock,
+    /// Choose a passphrase for a new file (asked twice).
+    Create,
+}
+
+impl FallbackFile {
+    /// Returns the credentials of a profile, creating them if needed.
+    fn profile_mut(&mut self, profile: &str) -> &mut FallbackCredentials {
+        if profile == DEFAULT_PROFILE {
+            &mut self.default
+        } else {
+            self.profiles.entry(profile.to_string()).or_default()
+        }
+    }
 }
 
 /// Credential storage manager.
@@ -53,6 +191,12 @@ pub struct CredentialStore {
     use_keychain: bool,
     /// Path to fallback credentials file.
     fallback_path: PathBuf,
+    /// Profile whose credentials are accessed.
+    profile: String,
+    /// Storage key of the profile's credentials on the current server.
+    namespace: String,
+    /// How the fallback file is protected.
+    protection: FileProtection,
 }
 
 impl Default for CredentialStore {
@@ -62,7 +206,15 @@ impl Default for CredentialStore {
 }
 
 impl CredentialStore {
-    /// Creates a new credential store.
+    /// Creates a credential store for the current profile.
+    ///
+    /// See [`crate::profile`] for how the profile is selected.
+    pub fn new() -> Self {
+        Self::for_profile(current_profile())
+    }
+
+    /// Creates a credential store for a specific profile on the current
+    /// server.
     ///
     /// Automatically detects whether the keychain is available and sets up
     /// the fallback path if needed.
@@ -70,10 +222,13 @@ impl CredentialStore {
     /// Environment variables:
     /// - `KLAAS_NO_KEYCHAIN=1` - Force file-based storage
     /// - `KLAAS_USE_KEYCHAIN=1` - Force keychain even in debug builds
+    /// - `KLAAS_CREDENTIALS_KEY` / `KLAAS_CREDENTIALS_KEY_FD` - Passphrase
+    ///   of the fallback file, instead of prompting for it
     ///
     /// Note: Debug builds use file storage by default because unsigned
     /// binaries on macOS can't reliably access keychain items across rebuilds.
-    pub fn new() -> Self {
+    /// Their fallback file stays plaintext unless a passphrase is configured.
+    pub fn for_profile(profile: &str) -> Self {
         let fallback_path = get_fallback_path();
 
         // Check if user wants to force file-based storage
@@ -121,12 +276,69 @@ impl CredentialStore {
             available
         };
 
+        let protection = if passphrase_configured() || !is_debug_build {
+            FileProtection::Passphrase
+        } else {
+            FileProtection::Plaintext
+        };
+
         Self {
             use_keychain,
             fallback_path,
+            profile: profile.to_string(),
+            namespace: storage_namespace(profile, &get_api_config()),
+            protection,
         }
     }
 
+    /// Creates a file-backed store at an explicit path (for tests).
+    #[cfg(test)]
+    pub(crate) fn with_fallback_path(fallback_path: PathBuf, profile: &str) -> Self {
+        Self {
+            use_keychain: false,
+            fallback_path,
+            profile: profile.to_string(),
+            namespace: profile.to_string(),
+            protection: FileProtection::Plaintext,
+        }
+    }
+
+    /// Creates a file-backed store encrypted with `passphrase` (for tests).
+    #[cfg(test)]
+    fn with_passphrase(fallback_path: PathBuf, passphrase: &str) -> Self {
+        Self {
+            protection: FileProtection::Fixed(passphrase.to_string()),
+            ..Self::with_fallback_path(fallback_path, DEFAULT_PROFILE)
+        }
+    }
+
+    /// Returns the profile this store reads and writes.
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Returns true if credentials are kept in the OS keychain.
+    pub fn uses_keychain(&self) -> bool {
+        self.use_keychain
+    }
+
+    /// Describes where credentials are stored, for display.
+    pub fn storage_description(&self) -> String {
+        if self.use_keychain {
+            return "system keychain".to_string();
+        }
+
+        let state = match fs::read_to_string(&self.fallback_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<StoredFallbackFile>(&content).ok())
+        {
+            Some(StoredFallbackFile::Encrypted(_)) => "encrypted",
+            Some(StoredFallbackFile::Plain(_)) => "unencrypted",
+            None => "not created yet",
+        };
+        format!("file {} ({})", self.fallback_path.display(), state)
+    }
+
     /// Stores access and refresh tokens.
     ///
     /// # Arguments
@@ -148,6 +360,7 @@ impl CredentialStore {
             })?;
         }
 
+        register_profile(&self.registry_path(), &self.profile, &self.namespace);
         debug!("Stored access and refresh tokens");
         Ok(())
     }
@@ -255,6 +468,25 @@ impl CredentialStore {
         }
     }
 
+    /// Gets the stored device ID, generating and storing a new one if needed.
+    ///
+    /// The device ID is reused across sessions, so the dashboard groups all
+    /// sessions from this machine under one device.
+    pub fn get_or_create_device_id(&self) -> Result<DeviceId> {
+        match self.get_device_id()? {
+            Some(id) => {
+                debug!("Retrieved existing device ID");
+                Ok(DeviceId::from_string(id))
+            }
+            None => {
+                let new_id = DeviceId::new();
+                self.store_device_id(new_id.as_str())?;
+                info!(device_id = %new_id, "Generated new device ID");
+                Ok(new_id)
+            }
+        }
+    }
+
     /// Stores the current session ID.
     ///
     /// The session ID is persisted so the CLI can reconnect to the same session
@@ -355,6 +587,7 @@ impl CredentialStore {
             })?;
         }
 
+        register_profile(&self.registry_path(), &self.profile, &self.namespace);
         debug!("Stored MEK for E2EE");
         Ok(())
     }
@@ -414,8 +647,139 @@ impl CredentialStore {
         Ok(())
     }
 
+    /// Stores the device ECDH private key used to receive shared sessions.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - 32-byte P-256 private scalar
+    ///
+    /// # Errors
+    ///
+    /// Returns `CliError::KeychainError` if storage fails.
+    pub fn store_device_key(&self, key: &[u8]) -> Result<()> {
+        let hex_key = hex::encode(key);
+
+        if self.use_keychain {
+            self.store_keychain_value(DEVICE_KEY_KEY, &hex_key)?;
+        } else {
+            self.update_fallback(|creds| {
+                creds.device_key = Some(hex_key.clone());
+            })?;
+        }
+
+        debug!("Stored device key");
+        Ok(())
+    }
+
+    /// Retrieves the stored device ECDH private key.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(key))` if a device key is stored
+    /// - `Ok(None)` if no device key is stored
+    /// - `Err(...)` if retrieval fails or the key is not valid hex
+    pub fn get_device_key(&self) -> Result<Option<Vec<u8>>> {
+        let hex_key = if self.use_keychain {
+            self.get_keychain_value(DEVICE_KEY_KEY)?
+        } else {
+            self.read_fallback()?.device_key
+        };
+
+        hex_key
+            .map(|hex| {
+                hex::decode(&hex).map_err(|e| {
+                    CliError::KeychainError(format!("Invalid device key encoding: {}", e))
+                })
+            })
+            .transpose()
+    }
+
+    /// Removes all credentials of this profile, including its MEK.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CliError::KeychainError` if deletion fails.
+    pub fn clear_all(&self) -> Result<()> {
+        if self.use_keychain {
+            for key in ALL_KEYS {
+                let _ = self.delete_keychain_value(key);
+            }
+        } else if self.fallback_path.exists() {
+            self.modify_fallback_file(|file| {
+                if self.namespace == DEFAULT_PROFILE {
+                    file.default = FallbackCredentials::default();
+                } else {
+                    file.profiles.remove(&self.namespace);
+                }
+            })?;
+        }
+
+        debug!(namespace = %self.namespace, "Cleared all credentials");
+        Ok(())
+    }
+
+    /// Removes this profile's credentials on every server it was used
+    /// with, including their MEKs.
+    ///
+    /// Keychain entries are found through the namespaces recorded in the
+    /// profile registry; the fallback file is searched directly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CliError::KeychainError` if deletion fails.
+    pub fn clear_all_servers(&self) -> Result<()> {
+        let mut namespaces: BTreeSet<String> =
+            ProfileRegistry::load_from(&self.registry_path()).namespaces;
+        namespaces.insert(self.profile.clone());
+        namespaces.insert(self.namespace.clone());
+        namespaces.retain(|namespace| namespace_of_profile(namespace, &self.profile));
+
+        if self.use_keychain {
+            for namespace in &namespaces {
+                self.in_namespace(namespace).clear_all()?;
+            }
+        } else if self.fallback_path.exists() {
+            self.modify_fallback_file(|file| {
+                if self.profile == DEFAULT_PROFILE {
+                    file.default = FallbackCredentials::default();
+                }
+                file.profiles
+                    .retain(|namespace, _| !namespace_of_profile(namespace, &self.profile));
+            })?;
+        }
+
+        debug!(profile = %self.profile, "Cleared credentials on all servers");
+        Ok(())
+    }
+
+    /// Returns a store for the same profile under another namespace.
+    fn in_namespace(&self, namespace: &str) -> Self {
+        Self {
+            use_keychain: self.use_keychain,
+            fallback_path: self.fallback_path.clone(),
+            profile: self.profile.clone(),
+            namespace: namespace.to_string(),
+            protection: self.protection.clone(),
+        }
+    }
+
+    /// Returns the profile registry path, next to the fallback file.
+    fn registry_path(&self) -> PathBuf {
+        self.fallback_path.with_file_name(PROFILES_FILE)
+    }
+
+    /// Returns the keychain entry name of a key in this profile.
+    fn keychain_key(&self, key: &str) -> String {
+        if self.namespace == DEFAULT_PROFILE {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.namespace, key)
+        }
+    }
+
     /// Stores a value in the keychain.
     fn store_keychain_value(&self, key: &str, value: &str) -> Result<()> {
+        let key = &self.keychain_key(key);
         debug!(
             service = KEYCHAIN_SERVICE,
             key = key,
@@ -457,6 +821,7 @@ impl CredentialStore {
 
     /// Retrieves a value from the keychain.
     fn get_keychain_value(&self, key: &str) -> Result<Option<String>> {
+        let key = &self.keychain_key(key);
         debug!(
             service = KEYCHAIN_SERVICE,
             key = key,
@@ -486,7 +851,7 @@ impl CredentialStore {
 
     /// Deletes a value from the keychain.
     fn delete_keychain_value(&self, key: &str) -> Result<()> {
-        let entry = Entry::new(KEYCHAIN_SERVICE, key)
+        let entry = Entry::new(KEYCHAIN_SERVICE, &self.keychain_key(key))
             .map_err(|e| CliError::KeychainError(e.to_string()))?;
 
         match entry.delete_credential() {
@@ -496,54 +861,330 @@ impl CredentialStore {
         }
     }
 
-    /// Reads fallback credentials from file.
+    /// Reads this profile's fallback credentials from file.
     fn read_fallback(&self) -> Result<FallbackCredentials> {
+        let (mut file, key) = self.read_fallback_file()?;
+
+        // Encrypt a plaintext file in place
+        if key.is_none() && self.fallback_path.exists() && self.new_file_key()?.is_some() {
+            self.modify_fallback_file(|_| {})?;
+            info!(path = ?self.fallback_path, "Encrypted fallback credentials file");
+        }
+
+        Ok(if self.namespace == DEFAULT_PROFILE {
+            file.default
+        } else {
+            file.profiles.remove(&self.namespace).unwrap_or_default()
+        })
+    }
+
+    /// Reads the whole fallback credentials file, decrypting it if needed.
+    ///
+    /// Also returns the key the file is encrypted with. Reading needs no
+    /// lock because the file is only ever replaced atomically.
+    fn read_fallback_file(&self) -> Result<(FallbackFile, Option<FileKey>)> {
         if !self.fallback_path.exists() {
-            return Ok(FallbackCredentials::default());
+            return Ok((FallbackFile::default(), None));
         }
 
         let content = fs::read_to_string(&self.fallback_path).map_err(|e| {
             CliError::KeychainError(format!("Failed to read fallback credentials: {}", e))
         })?;
 
-        serde_json::from_str(&content).map_err(|e| {
+        let stored: StoredFallbackFile = serde_json::from_str(&content).map_err(|e| {
             CliError::KeychainError(format!("Failed to parse fallback credentials: {}", e))
-        })
+        })?;
+
+        match stored {
+            StoredFallbackFile::Encrypted(encrypted) => {
+                let (file, key) = self.decrypt_fallback_file(&encrypted)?;
+                Ok((file, Some(key)))
+            }
+            StoredFallbackFile::Plain(file) => Ok((file, None)),
+        }
+    }
+
+    /// Decrypts the fallback file, unlocking its key if needed.
+    fn decrypt_fallback_file(
+        &self,
+        encrypted: &EncryptedFallbackFile,
+    ) -> Result<(FallbackFile, FileKey)> {
+        if encrypted.v != ENCRYPTED_FILE_VERSION {
+            return Err(CliError::KeychainError(format!(
+                "Unsupported credentials file version: {}",
+                encrypted.v
+            )));
+        }
+
+        let salt: [u8; SALT_SIZE] = decode_base64(&encrypted.salt)?
+            .try_into()
+            .map_err(|_| CliError::KeychainError("Invalid credentials file salt".into()))?;
+
+        // Reuse the key unlocked earlier in this process
+        if self.uses_shared_key() {
+            if let Some(key) = cached_file_key().filter(|k| k.salt == salt) {
+                if let Ok(file) = decrypt_file(&key, &encrypted.content) {
+                    return Ok((file, key));
+                }
+            }
+        }
+
+        for attempt in 1..=MAX_PASSPHRASE_ATTEMPTS {
+            let Some((passphrase, interactive)) = self.passphrase(PassphrasePrompt::Unlock)? else {
+                return Err(CliError::KeychainError(format!(
+                    "{} is encrypted. Set {} or {} to unlock it.",
+                    self.fallback_path.display(),
+                    ENV_CREDENTIALS_KEY,
+                    ENV_CREDENTIALS_KEY_FD
+                )));
+            };
+
+            let key = FileKey {
+                salt,
+                key: derive_kek(&passphrase, &salt)?,
+            };
+
+            match decrypt_file(&key, &encrypted.content) {
+                Ok(file) => {
+                    if self.uses_shared_key() {
+                        cache_file_key(&key);
+                    }
+                    return Ok((file, key));
+                }
+                Err(CliError::CryptoError(_)) if interactive => {
+                    if attempt < MAX_PASSPHRASE_ATTEMPTS {
+                        eprintln!("  Wrong passphrase, try again.");
+                    }
+                }
+                Err(CliError::CryptoError(_)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(CliError::KeychainError(
+            "Wrong passphrase for credentials file".into(),
+        ))
     }
 
-    /// Updates fallback credentials using a closure.
+    /// Returns the key to encrypt a new or plaintext fallback file with.
+    ///
+    /// Returns `None` if the file stays plaintext: in debug builds without a
+    /// configured passphrase, or if no passphrase can be obtained.
+    fn new_file_key(&self) -> Result<Option<FileKey>> {
+        if matches!(self.protection, FileProtection::Plaintext) {
+            return Ok(None);
+        }
+
+        if self.uses_shared_key() {
+            if let Some(key) = cached_file_key() {
+                return Ok(Some(key));
+            }
+        }
+
+        // Don't ask again after the user declined or nothing was available
+        if PLAINTEXT_WARNING.is_completed() && self.uses_shared_key() {
+            return Ok(None);
+        }
+
+        let Some((passphrase, _)) = self.passphrase(PassphrasePrompt::Create)? else {
+            PLAINTEXT_WARNING.call_once(|| {
+                warn!(
+                    "No passphrase available, storing credentials unencrypted. \
+                     Set {} to encrypt them.",
+                    ENV_CREDENTIALS_KEY
+                );
+            });
+            return Ok(None);
+        };
+
+        let salt = generate_salt();
+        let key = FileKey {
+            salt,
+            key: derive_kek(&passphrase, &salt)?,
+        };
+        if self.uses_shared_key() {
+            cache_file_key(&key);
+        }
+
+        Ok(Some(key))
+    }
+
+    /// Returns the passphrase of the fallback file and whether it was typed
+    /// in, or `None` if none is configured and there is no terminal.
+    fn passphrase(&self, prompt: PassphrasePrompt) -> Result<Option<(Zeroizing<String>, bool)>> {
+        #[cfg(test)]
+        if let FileProtection::Fixed(passphrase) = &self.protection {
+            return Ok(Some((Zeroizing::new(passphrase.clone()), false)));
+        }
+
+        if let Some(passphrase) = configured_passphrase() {
+            return Ok(Some((passphrase, false)));
+        }
+
+        if !std::io::stdin().is_terminal() {
+            return Ok(None);
+        }
+
+        let label = self.fallback_path.display();
+        match prompt {
+            PassphrasePrompt::Unlock => {
+                let passphrase = ui::prompt_secret(&format!("Passphrase for {}:", label))?;
+                Ok(passphrase.map(|p| (Zeroizing::new(p), true)))
+            }
+            PassphrasePrompt::Create => {
+                eprintln!();
+                eprintln!("  The system keychain is unavailable, so klaas stores its");
+                eprintln!("  credentials in {}.", label);
+                loop {
+                    let Some(first) = ui::prompt_secret("Choose a passphrase to encrypt them:")?
+                    else {
+                        return Ok(None);
+                    };
+                    let first = Zeroizing::new(first);
+                    if first.is_empty() {
+                        continue;
+                    }
+
+                    let Some(second) = ui::prompt_secret("Repeat the passphrase:")? else {
+                        return Ok(None);
+                    };
+                    if first == Zeroizing::new(second) {
+                        return Ok(Some((first, true)));
+                    }
+                    eprintln!("  Passphrases do not match, try again.");
+                }
+            }
+        }
+    }
+
+    /// Returns true if this store shares the process-wide unlocked key.
+    fn uses_shared_key(&self) -> bool {
+        matches!(
+            self.protection,
+            FileProtection::Plaintext | FileProtection::Passphrase
+        )
+    }
+
+    /// Updates this profile's fallback credentials using a closure.
     fn update_fallback<F>(&self, update: F) -> Result<()>
     where
         F: FnOnce(&mut FallbackCredentials),
     {
-        // Ensure parent directory exists
-        if let Some(parent) = self.fallback_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| {
-                CliError::KeychainError(format!("Failed to create config directory: {}", e))
-            })?;
+        self.modify_fallback_file(|file| update(file.profile_mut(&self.namespace)))
+    }
+
+    /// Applies `modify` to the whole fallback file while holding its lock,
+    /// so concurrent klaas processes never lose each other's updates.
+    fn modify_fallback_file<F>(&self, modify: F) -> Result<()>
+    where
+        F: FnOnce(&mut FallbackFile),
+    {
+        // Unlock the file key first, so a passphrase prompt never holds the
+        // lock while waiting for the user
+        let (_, key) = self.read_fallback_file()?;
+        if key.is_none() {
+            self.new_file_key()?;
         }
 
-        // Read existing credentials
-        let mut creds = self.read_fallback()?;
+        let _lock = self.lock_fallback()?;
 
-        // Apply update
-        update(&mut creds);
+        // Re-read under the lock to pick up other processes' writes
+        let (mut file, key) = self.read_fallback_file()?;
+        modify(&mut file);
 
-        // Write back
-        let content = serde_json::to_string_pretty(&creds).map_err(|e| {
-            CliError::KeychainError(format!("Failed to serialize credentials: {}", e))
+        // A new file is encrypted from the start
+        let key = match key {
+            Some(key) => Some(key),
+            None => self.new_file_key()?,
+        };
+        self.write_fallback_file(&file, key.as_ref())
+    }
+
+    /// Takes the exclusive lock on the fallback file, waiting for other
+    /// processes to release it.
+    fn lock_fallback(&self) -> Result<CredentialLock> {
+        lock_exclusive(&self.fallback_path)
+    }
+
+    /// Takes the token refresh lock of this profile.
+    ///
+    /// Refresh tokens rotate on use, so only one klaas process may refresh
+    /// at a time. After acquiring the lock, re-read the stored tokens: if
+    /// another process refreshed them while we waited, use those instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CliError::KeychainError` if the lock is not released within
+    /// 30 seconds.
+    pub async fn lock_refresh(&self) -> Result<CredentialLock> {
+        let path = self
+            .fallback_path
+            .with_file_name(format!("{}.refresh.lock", self.namespace.replace(':', "_")));
+        let file = open_lock_file(&path)?;
+        let deadline = Instant::now() + REFRESH_LOCK_TIMEOUT;
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(CredentialLock { _file: file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(CliError::KeychainError(
+                        "Timed out waiting for another klaas process to refresh tokens".into(),
+                    ));
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(CliError::KeychainError(format!(
+                        "Failed to lock token refresh: {}",
+                        e
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Writes the whole fallback credentials file, encrypted with `key` if
+    /// given.
+    ///
+    /// The content is written to a temporary file that then replaces the
+    /// old one, so readers never see a partially written file.
+    fn write_fallback_file(&self, file: &FallbackFile, key: Option<&FileKey>) -> Result<()> {
+        // Ensure parent directory exists
+        let parent = self.fallback_path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent).map_err(|e| {
+            CliError::KeychainError(format!("Failed to create config directory: {}", e))
         })?;
 
-        fs::write(&self.fallback_path, content).map_err(|e| {
+        let serialize_error = |e: serde_json::Error| {
+            CliError::KeychainError(format!("Failed to serialize credentials: {}", e))
+        };
+
+        let content = match key {
+            Some(key) => {
+                let plaintext = Zeroizing::new(serde_json::to_vec(file).map_err(serialize_error)?);
+                let encrypted = EncryptedFallbackFile {
+                    v: ENCRYPTED_FILE_VERSION,
+                    salt: encode_base64(&key.salt),
+                    content: encrypt_content(&key.key, &plaintext),
+                };
+                serde_json::to_string_pretty(&encrypted).map_err(serialize_error)?
+            }
+            None => serde_json::to_string_pretty(file).map_err(serialize_error)?,
+        };
+
+        let write_error = |e: std::io::Error| {
             CliError::KeychainError(format!("Failed to write fallback credentials: {}", e))
-        })?;
+        };
+
+        let mut tmp = tempfile::NamedTempFile::new_in(parent).map_err(write_error)?;
 
-        // Set restrictive permissions on Unix systems
+        // Set restrictive permissions on Unix systems before writing
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
             let perms = fs::Permissions::from_mode(0o600);
-            fs::set_permissions(&self.fallback_path, perms).map_err(|e| {
+            tmp.as_file().set_permissions(perms).map_err(|e| {
                 CliError::KeychainError(format!(
                     "Failed to set credentials file permissions: {}",
                     e
@@ -551,10 +1192,169 @@ impl CredentialStore {
             })?;
         }
 
+        tmp.write_all(content.as_bytes()).map_err(write_error)?;
+        tmp.as_file().sync_all().map_err(write_error)?;
+        tmp.persist(&self.fallback_path)
+            .map_err(|e| write_error(e.error))?;
+
         Ok(())
     }
 }
 
+/// Exclusive lock on a credentials `.lock` file, released when dropped.
+#[derive(Debug)]
+pub struct CredentialLock {
+    _file: fs::File,
+}
+
+/// Takes the exclusive lock on `<path>.lock`, waiting for other processes
+/// to release it.
+pub(crate) fn lock_exclusive(path: &Path) -> Result<CredentialLock> {
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(".lock");
+
+    let file = open_lock_file(&path.with_file_name(name))?;
+    file.lock()
+        .map_err(|e| CliError::KeychainError(format!("Failed to lock credentials: {}", e)))?;
+    Ok(CredentialLock { _file: file })
+}
+
+/// Opens (creating if needed) a lock file.
+fn open_lock_file(path: &Path) -> Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            CliError::KeychainError(format!("Failed to create config directory: {}", e))
+        })?;
+    }
+
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| CliError::KeychainError(format!("Failed to open lock file: {}", e)))
+}
+
+/// Decrypts the contents of an encrypted fallback file.
+///
+/// Returns `CliError::CryptoError` if the key is wrong.
+fn decrypt_file(key: &FileKey, content: &EncryptedContent) -> Result<FallbackFile> {
+    let plaintext = Zeroizing::new(decrypt_content(&key.key, content)?);
+    serde_json::from_slice(&plaintext).map_err(|e| {
+        CliError::KeychainError(format!("Failed to parse fallback credentials: {}", e))
+    })
+}
+
+/// Returns the fallback file key unlocked earlier in this process.
+fn cached_file_key() -> Option<FileKey> {
+    FILE_KEY.lock().ok().and_then(|key| key.clone())
+}
+
+/// Remembers the fallback file key for the rest of this process.
+fn cache_file_key(key: &FileKey) {
+    if let Ok(mut cached) = FILE_KEY.lock() {
+        *cached = Some(key.clone());
+    }
+}
+
+/// Returns true if a fallback file passphrase is set in the environment.
+fn passphrase_configured() -> bool {
+    [ENV_CREDENTIALS_KEY, ENV_CREDENTIALS_KEY_FD]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+}
+
+/// Returns the passphrase from `KLAAS_CREDENTIALS_KEY`, or read from the
+/// file descriptor in `KLAAS_CREDENTIALS_KEY_FD`.
+///
+/// The descriptor can only be read once, so the result is kept for the
+/// rest of the process.
+fn configured_passphrase() -> Option<Zeroizing<String>> {
+    CONFIGURED_PASSPHRASE
+        .get_or_init(|| {
+            if let Some(passphrase) = std::env::var(ENV_CREDENTIALS_KEY)
+                .ok()
+                .filter(|p| !p.is_empty())
+            {
+                return Some(Zeroizing::new(passphrase));
+            }
+
+            let fd = std::env::var(ENV_CREDENTIALS_KEY_FD).ok()?;
+            match read_passphrase_fd(&fd) {
+                Ok(passphrase) => Some(passphrase),
+                Err(e) => {
+                    warn!(fd = %fd, error = %e, "Failed to read credentials passphrase");
+                    None
+                }
+            }
+        })
+        .clone()
+}
+
+/// Reads a passphrase from a file descriptor, without the trailing newline.
+#[cfg(unix)]
+fn read_passphrase_fd(fd: &str) -> std::io::Result<Zeroizing<String>> {
+    let fd: u32 = fd
+        .trim()
+        .parse()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a number"))?;
+    let mut passphrase = Zeroizing::new(fs::read_to_string(format!("/dev/fd/{}", fd))?);
+    let len = passphrase.trim_end_matches(['\r', '\n']).len();
+    passphrase.truncate(len);
+    Ok(passphrase)
+}
+
+/// Reading from a file descriptor is only supported on Unix.
+#[cfg(not(unix))]
+fn read_passphrase_fd(_fd: &str) -> std::io::Result<Zeroizing<String>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file descriptors are only supported on Unix",
+    ))
+}
+
+/// Returns the storage key of a profile's credentials on a server.
+///
+/// The built-in server uses the profile name, so existing credentials stay
+/// readable; others append the API server's host and port, plus the
+/// WebSocket server's if it is on a different host.
+fn storage_namespace(profile: &str, api: &ApiConfig) -> String {
+    if api.is_default_endpoint() {
+        return profile.to_string();
+    }
+
+    let api_authority = url_authority(&api.api_url);
+    let ws_authority = url_authority(&api.ws_url);
+    if ws_authority == api_authority {
+        format!("{}@{}", profile, api_authority)
+    } else {
+        format!("{}@{}+{}", profile, api_authority, ws_authority)
+    }
+}
+
+/// Returns the `host[:port]` of a URL, or the URL itself if it has none.
+fn url_authority(raw: &str) -> String {
+    match url::Url::parse(raw) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => raw.to_string(),
+        },
+        Err(_) => raw.to_string(),
+    }
+}
+
+/// Returns true if `namespace` holds credentials of `profile`, on the
+/// built-in server or another one.
+fn namespace_of_profile(namespace: &str, profile: &str) -> bool {
+    namespace
+        .strip_prefix(profile)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('@'))
+}
+
 /// Gets the fallback credentials file path.
 ///
 /// Uses `~/.klaas/credentials.json` for consistency with config location.
@@ -718,6 +1518,7 @@ mod tests {
             device_id: Some("01HQXK7V8G3N5M2R4P6T1W9Y0Z".to_string()),
             session_id: Some("01HQXK8V8G3N5M2R4P6T1W9Y0Z".to_string()),
             mek: Some("0123456789abcdef".repeat(4)),
+            device_key: Some("fedcba9876543210".repeat(4)),
         };
 
         let json = serde_json::to_string(&creds).unwrap();
@@ -728,6 +1529,7 @@ mod tests {
         assert_eq!(parsed.device_id, creds.device_id);
         assert_eq!(parsed.session_id, creds.session_id);
         assert_eq!(parsed.mek, creds.mek);
+        assert_eq!(parsed.device_key, creds.device_key);
     }
 
     /// Tests MEK hex encoding/decoding.
@@ -735,7 +1537,7 @@ mod tests {
     fn test_mek_hex_encoding() {
         // 32 bytes = 64 hex chars
         let mek_bytes = [0xab; 32];
-        let hex = hex::encode(&mek_bytes);
+        let hex = hex::encode(mek_bytes);
         assert_eq!(hex.len(), 64);
 
         let decoded = hex::decode(&hex).unwrap();
@@ -748,7 +1550,7 @@ mod tests {
     fn test_mek_size_validation() {
         // Valid 32-byte MEK should work
         let valid_mek = [0u8; 32];
-        let hex = hex::encode(&valid_mek);
+        let hex = hex::encode(valid_mek);
         let decoded = hex::decode(&hex).unwrap();
         assert_eq!(decoded.len(), MEK_SIZE);
 
@@ -766,4 +1568,315 @@ mod tests {
         let path = get_fallback_path();
         assert!(path.ends_with(".klaas/credentials.json"));
     }
+
+    /// Tests that profiles keep separate credentials in one fallback file.
+    #[test]
+    fn test_profiles_are_isolated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let default = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        let work = CredentialStore::with_fallback_path(path.clone(), "work");
+
+        default
+            .store_tokens("default_access", "default_refresh")
+            .unwrap();
+        work.store_tokens("work_access", "work_refresh").unwrap();
+        work.store_mek(&[7u8; MEK_SIZE]).unwrap();
+
+        assert_eq!(
+            default.get_tokens().unwrap(),
+            Some(("default_access".into(), "default_refresh".into()))
+        );
+        assert_eq!(
+            work.get_tokens().unwrap(),
+            Some(("work_access".into(), "work_refresh".into()))
+        );
+        assert!(default.get_mek().unwrap().is_none());
+        assert_eq!(work.get_mek().unwrap(), Some(vec![7u8; MEK_SIZE]));
+
+        let registry = ProfileRegistry::load_from(&dir.path().join(PROFILES_FILE));
+        assert!(registry.profiles.contains("work"));
+        assert!(registry.profiles.contains(DEFAULT_PROFILE));
+    }
+
+    /// Tests that credentials of other servers get their own namespace.
+    #[test]
+    fn test_storage_namespace() {
+        assert_eq!(
+            storage_namespace("work", &ApiConfig::default()),
+            "work".to_string()
+        );
+
+        let api = ApiConfig {
+            api_url: "https://klaas.corp.example".into(),
+            ws_url: "wss://klaas.corp.example/ws".into(),
+        };
+        assert_eq!(
+            storage_namespace(DEFAULT_PROFILE, &api),
+            "default@klaas.corp.example"
+        );
+
+        let api = ApiConfig {
+            api_url: "http://localhost:9000".into(),
+            ws_url: "ws://localhost:9000/ws".into(),
+        };
+        assert_eq!(storage_namespace("work", &api), "work@localhost:9000");
+    }
+
+    /// Tests that overriding only the WebSocket URL leaves the default
+    /// server's namespace, so its token isn't sent to another host.
+    #[test]
+    fn test_storage_namespace_ws_url_only() {
+        let api = ApiConfig {
+            ws_url: "wss://ws.corp.example/ws".into(),
+            ..ApiConfig::default()
+        };
+        assert!(!api.is_default_endpoint());
+
+        let namespace = storage_namespace(DEFAULT_PROFILE, &api);
+        assert_ne!(namespace, DEFAULT_PROFILE);
+        assert!(namespace.ends_with("+ws.corp.example"));
+    }
+
+    /// Tests that the same profile on two servers keeps separate credentials.
+    #[tokio::test]
+    async fn test_endpoints_are_isolated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let prod = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        let hosted = CredentialStore {
+            namespace: "default@localhost:9000".into(),
+            ..CredentialStore::with_fallback_path(path, DEFAULT_PROFILE)
+        };
+
+        prod.store_tokens("prod_access", "prod_refresh").unwrap();
+        assert!(hosted.get_tokens().unwrap().is_none());
+
+        hosted.store_tokens("own_access", "own_refresh").unwrap();
+        hosted.clear_all().unwrap();
+        assert_eq!(
+            prod.get_tokens().unwrap(),
+            Some(("prod_access".into(), "prod_refresh".into()))
+        );
+
+        // Both can refresh at the same time
+        let _prod_lock = prod.lock_refresh().await.unwrap();
+        drop(hosted.lock_refresh().await.unwrap());
+    }
+
+    /// Tests that a file written before profiles existed is the default profile.
+    #[test]
+    fn test_legacy_fallback_file_is_default_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        fs::write(
+            &path,
+            r#"{"access_token":"a","refresh_token":"r","device_id":"d"}"#,
+        )
+        .unwrap();
+
+        let default = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        assert_eq!(
+            default.get_tokens().unwrap(),
+            Some(("a".into(), "r".into()))
+        );
+        assert_eq!(default.get_device_id().unwrap(), Some("d".into()));
+
+        let work = CredentialStore::with_fallback_path(path, "work");
+        assert!(work.get_tokens().unwrap().is_none());
+    }
+
+    /// Tests that clear_all only removes the credentials of one profile.
+    #[test]
+    fn test_clear_all_only_affects_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let default = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        let work = CredentialStore::with_fallback_path(path, "work");
+
+        default.store_tokens("a", "r").unwrap();
+        work.store_tokens("b", "s").unwrap();
+        work.store_mek(&[1u8; MEK_SIZE]).unwrap();
+
+        work.clear_all().unwrap();
+        assert!(work.get_tokens().unwrap().is_none());
+        assert!(work.get_mek().unwrap().is_none());
+        assert_eq!(
+            default.get_tokens().unwrap(),
+            Some(("a".into(), "r".into()))
+        );
+    }
+
+    /// Tests that removing a profile clears it on every server, and only it.
+    #[test]
+    fn test_clear_all_servers_removes_every_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let work = CredentialStore::with_fallback_path(path.clone(), "work");
+        let work_hosted = work.in_namespace("work@klaas.corp.example");
+        let workshop = CredentialStore::with_fallback_path(path, "workshop");
+
+        for store in [&work, &work_hosted, &workshop] {
+            store.store_tokens("a", "r").unwrap();
+            store.store_mek(&[3u8; MEK_SIZE]).unwrap();
+        }
+        let registry = ProfileRegistry::load_from(&dir.path().join(PROFILES_FILE));
+        assert!(registry.namespaces.contains("work@klaas.corp.example"));
+
+        work.clear_all_servers().unwrap();
+        assert!(work.get_tokens().unwrap().is_none());
+        assert!(work_hosted.get_tokens().unwrap().is_none());
+        assert!(work_hosted.get_mek().unwrap().is_none());
+        assert_eq!(workshop.get_mek().unwrap(), Some(vec![3u8; MEK_SIZE]));
+    }
+
+    /// Tests that an encrypted fallback file round-trips and hides secrets.
+    #[test]
+    fn test_encrypted_fallback_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let store = CredentialStore::with_passphrase(path.clone(), "correct horse");
+
+        store
+            .store_tokens("secret_access", "secret_refresh")
+            .unwrap();
+        store.store_mek(&[9u8; MEK_SIZE]).unwrap();
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret_access"));
+        assert!(!raw.contains(&hex::encode([9u8; MEK_SIZE])));
+
+        let reopened = CredentialStore::with_passphrase(path, "correct horse");
+        assert_eq!(
+            reopened.get_tokens().unwrap(),
+            Some(("secret_access".into(), "secret_refresh".into()))
+        );
+        assert_eq!(reopened.get_mek().unwrap(), Some(vec![9u8; MEK_SIZE]));
+    }
+
+    /// Tests that a wrong passphrase does not unlock the fallback file.
+    #[test]
+    fn test_encrypted_fallback_wrong_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        CredentialStore::with_passphrase(path.clone(), "right")
+            .store_tokens("a", "r")
+            .unwrap();
+
+        let store = CredentialStore::with_passphrase(path, "wrong");
+        assert!(matches!(
+            store.get_tokens(),
+            Err(CliError::KeychainError(_))
+        ));
+    }
+
+    /// Tests that a plaintext fallback file is encrypted on first read.
+    #[test]
+    fn test_plaintext_fallback_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        fs::write(&path, r#"{"access_token":"a","refresh_token":"r"}"#).unwrap();
+
+        let store = CredentialStore::with_passphrase(path.clone(), "pass");
+        assert_eq!(store.get_tokens().unwrap(), Some(("a".into(), "r".into())));
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(serde_json::from_str::<EncryptedFallbackFile>(&raw).is_ok());
+        assert_eq!(store.get_tokens().unwrap(), Some(("a".into(), "r".into())));
+    }
+
+    /// Tests that parallel writers never lose each other's updates.
+    #[test]
+    fn test_parallel_writers_keep_all_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let store = CredentialStore::with_fallback_path(path, &format!("p{}", i));
+                    for round in 0..10 {
+                        store
+                            .store_tokens(&format!("access_{}_{}", i, round), "refresh")
+                            .unwrap();
+                        store.store_session_id(&format!("session_{}", i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        for i in 0..8 {
+            let store = CredentialStore::with_fallback_path(path.clone(), &format!("p{}", i));
+            assert_eq!(
+                store.get_tokens().unwrap(),
+                Some((format!("access_{}_9", i), "refresh".into()))
+            );
+            assert_eq!(
+                store.get_session_id().unwrap(),
+                Some(format!("session_{}", i))
+            );
+        }
+
+        // Atomic writes leave no temporary files behind
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+
+    /// Tests that readers never see a partially written file.
+    #[test]
+    fn test_readers_see_complete_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let store = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        store.store_tokens("a", "r").unwrap();
+
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let store = CredentialStore::with_fallback_path(path, DEFAULT_PROFILE);
+                for i in 0..50 {
+                    store.store_device_id(&"d".repeat(i * 100)).unwrap();
+                }
+            })
+        };
+        for _ in 0..50 {
+            assert!(store.get_tokens().unwrap().is_some());
+        }
+        writer.join().unwrap();
+    }
+
+    /// Tests that only one process at a time holds the refresh lock.
+    #[tokio::test]
+    async fn test_refresh_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        let first = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        let second = CredentialStore::with_fallback_path(path.clone(), DEFAULT_PROFILE);
+        let other_profile = CredentialStore::with_fallback_path(path, "work");
+
+        let lock = first.lock_refresh().await.unwrap();
+
+        // Other profiles refresh independently
+        drop(other_profile.lock_refresh().await.unwrap());
+
+        let waiter = tokio::spawn(async move { second.lock_refresh().await.map(|_| ()) });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!waiter.is_finished());
+
+        drop(lock);
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -5,6 +5,8 @@
 //! - Argon2id for password → KEK derivation
 //! - AES-256-GCM for MEK and content encryption
 //! - HKDF-SHA256 for MEK → session key derivation
+//! - ECDH P-256 for wrapping session keys to another user's device
+//! - HKDF-SHA256 ratchet for forward-secret session keys (optional)
 //!
 //! All keys are 256 bits (32 bytes).
 
@@ -15,7 +17,7 @@ use aes_gcm::{
 use argon2::{Algorithm, Argon2, Params, Version};
 use hkdf::Hkdf;
 use p256::{
-    ecdh::EphemeralSecret,
+    ecdh::{EphemeralSecret, SharedSecret},
     elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint},
     EncodedPoint, PublicKey,
 };
@@ -45,7 +47,7 @@ const KEY_SIZE: usize = 32;
 const NONCE_SIZE: usize = 12;
 
 /// Salt size in bytes for Argon2id (128 bits).
-const SALT_SIZE: usize = 16;
+pub const SALT_SIZE: usize = 16;
 
 /// Auth tag size in bytes for AES-GCM (128 bits).
 const TAG_SIZE: usize = 16;
@@ -56,6 +58,15 @@ const SESSION_KEY_INFO_PREFIX: &str = "klaas-session-v1:";
 /// Domain separation for ECDH pairing key derivation.
 const PAIRING_KEY_INFO: &str = "klaas-pairing-v1";
 
+/// Domain separation for wrapping a session key to a device key.
+const SHARE_KEY_INFO: &str = "klaas-share-v1";
+
+/// Version prefix for ratcheting a forward-secret session key.
+const RATCHET_KEY_INFO_PREFIX: &str = "klaas-ratchet-v1:";
+
+/// Maximum number of ratchet steps taken to catch up with an epoch.
+const MAX_RATCHET_STEPS: u32 = 1024;
+
 // =============================================================================
 // Types
 // =============================================================================
@@ -121,6 +132,19 @@ pub struct EncryptedContent {
     pub ciphertext: String,
     /// 16-byte authentication tag, base64 encoded.
     pub tag: String,
+    /// Session key epoch used for encryption. Omitted for epoch 0, which is
+    /// the original key derived by `derive_session_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u32>,
+    /// Whether the content is encrypted with a forward-secret ratchet key
+    /// instead of a key derived from the MEK.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub ratchet: bool,
+}
+
+/// Serde helper to omit `false` flags.
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 /// Stored MEK format (as received from/sent to server).
@@ -163,6 +187,35 @@ pub struct ECDHKeypair {
     pub public_key: Vec<u8>,
 }
 
+/// Long-lived ECDH P-256 keypair identifying this device.
+///
+/// The public key is registered with the server so that other users can
+/// wrap session keys to this device when sharing a session.
+pub struct DeviceKeypair {
+    /// Static private key (zeroed on drop).
+    secret: p256::SecretKey,
+}
+
+/// A session key wrapped to a recipient device's public key.
+///
+/// The sender generates an ephemeral keypair, performs ECDH against the
+/// recipient's device key and encrypts the session key with the result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedSessionKey {
+    /// Format version (always 1).
+    pub v: u8,
+    /// Session key epoch this key belongs to.
+    pub epoch: u32,
+    /// Sender's ephemeral public key (uncompressed SEC1), base64 encoded.
+    pub ephemeral_public_key: String,
+    /// AES-GCM nonce, 12 bytes, base64 encoded.
+    pub nonce: String,
+    /// Encrypted session key, base64 encoded.
+    pub ciphertext: String,
+    /// Authentication tag, 16 bytes, base64 encoded.
+    pub tag: String,
+}
+
 // =============================================================================
 // Key Derivation Functions
 // =============================================================================
@@ -206,6 +259,41 @@ pub fn derive_session_key(mek: &SecretKey, session_id: &str) -> SecretKey {
     SecretKey::from_bytes(session_key)
 }
 
+/// Derives the session key for a given key epoch.
+///
+/// Epoch 0 is the original key from `derive_session_key`. Later epochs are
+/// produced when a share is revoked, so that a revoked guest cannot decrypt
+/// anything the host sends afterwards.
+pub fn derive_session_key_for_epoch(mek: &SecretKey, session_id: &str, epoch: u32) -> SecretKey {
+    if epoch == 0 {
+        return derive_session_key(mek, session_id);
+    }
+
+    let hk = Hkdf::<Sha256>::new(None, mek.as_bytes());
+    let info = format!("{}{}:{}", SESSION_KEY_INFO_PREFIX, session_id, epoch);
+
+    let mut session_key = [0u8; KEY_SIZE];
+    hk.expand(info.as_bytes(), &mut session_key)
+        .expect("HKDF expand failed");
+
+    SecretKey::from_bytes(session_key)
+}
+
+/// Derives the next key of a forward-secret ratchet chain.
+///
+/// The step is one-way: holding a later key does not reveal earlier ones,
+/// so deleting old keys makes the content they protected unrecoverable.
+pub fn ratchet_session_key(key: &SecretKey, session_id: &str) -> SecretKey {
+    let hk = Hkdf::<Sha256>::new(None, key.as_bytes());
+    let info = format!("{}{}", RATCHET_KEY_INFO_PREFIX, session_id);
+
+    let mut next_key = [0u8; KEY_SIZE];
+    hk.expand(info.as_bytes(), &mut next_key)
+        .expect("HKDF expand failed");
+
+    SecretKey::from_bytes(next_key)
+}
+
 // =============================================================================
 // Encryption Functions
 // =============================================================================
@@ -273,6 +361,50 @@ fn aes_gcm_decrypt(
 // MEK Management
 // =============================================================================
 
+/// Returns a short, non-secret fingerprint of a key (the first 8 bytes of
+/// its SHA-256 hash), e.g. to check that two devices hold the same MEK.
+pub fn key_fingerprint(key: &SecretKey) -> String {
+    use sha2::Digest;
+
+    let digest = Sha256::digest(key.as_bytes());
+    digest[..8]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Parses a MEK exported as a recovery key.
+///
+/// Accepts 64 hex characters (optionally grouped with dashes or spaces) or
+/// the base64 encoding of the 32 key bytes. Surrounding whitespace is
+/// ignored, so the key can be read straight from a file.
+pub fn parse_recovery_key(text: &str) -> Result<SecretKey, CliError> {
+    let compact: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect();
+
+    let bytes = if compact.len() == KEY_SIZE * 2 && compact.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(&compact).map_err(|e| CliError::CryptoError(e.to_string()))?
+    } else {
+        base64_decode(text.trim()).map_err(|_| {
+            CliError::CryptoError(
+                "Invalid encryption key: expected 64 hex characters or base64".into(),
+            )
+        })?
+    };
+
+    let arr: [u8; KEY_SIZE] = bytes.as_slice().try_into().map_err(|_| {
+        CliError::CryptoError(format!(
+            "Invalid encryption key: expected {} bytes, got {}",
+            KEY_SIZE,
+            bytes.len()
+        ))
+    })?;
+    Ok(SecretKey::from_bytes(arr))
+}
+
 /// Generates a new Master Encryption Key.
 pub fn generate_mek() -> SecretKey {
     SecretKey::random()
@@ -363,6 +495,8 @@ pub fn encrypt_content(session_key: &SecretKey, plaintext: &[u8]) -> EncryptedCo
         nonce: base64_encode(&nonce),
         ciphertext: base64_encode(&ciphertext),
         tag: base64_encode(&tag),
+        epoch: None,
+        ratchet: false,
     }
 }
 
@@ -454,20 +588,29 @@ fn compute_ecdh_shared_key(
     their_public_key_bytes: &[u8],
 ) -> Result<SecretKey, CliError> {
     // Parse the other party's public key
-    let encoded_point = EncodedPoint::from_bytes(their_public_key_bytes)
-        .map_err(|e| CliError::CryptoError(format!("Invalid public key format: {}", e)))?;
-
-    let their_public_key = PublicKey::from_encoded_point(&encoded_point);
-    let their_public_key = Option::from(their_public_key)
-        .ok_or_else(|| CliError::CryptoError("Invalid public key point".into()))?;
+    let their_public_key = parse_public_key(their_public_key_bytes)?;
 
     // Compute shared secret
     let shared_secret = private_key.diffie_hellman(&their_public_key);
 
     // Derive key using HKDF with domain separation
+    shared_secret_to_key(&shared_secret, PAIRING_KEY_INFO)
+}
+
+/// Parses an uncompressed or compressed SEC1 P-256 public key.
+fn parse_public_key(bytes: &[u8]) -> Result<PublicKey, CliError> {
+    let encoded_point = EncodedPoint::from_bytes(bytes)
+        .map_err(|e| CliError::CryptoError(format!("Invalid public key format: {}", e)))?;
+
+    Option::from(PublicKey::from_encoded_point(&encoded_point))
+        .ok_or_else(|| CliError::CryptoError("Invalid public key point".into()))
+}
+
+/// Derives a 256-bit key from an ECDH shared secret using HKDF.
+fn shared_secret_to_key(shared_secret: &SharedSecret, info: &str) -> Result<SecretKey, CliError> {
     let hk = Hkdf::<Sha256>::new(None, shared_secret.raw_secret_bytes());
     let mut key = [0u8; KEY_SIZE];
-    hk.expand(PAIRING_KEY_INFO.as_bytes(), &mut key)
+    hk.expand(info.as_bytes(), &mut key)
         .map_err(|_| CliError::CryptoError("HKDF expand failed".into()))?;
 
     Ok(SecretKey::from_bytes(key))
@@ -523,6 +666,216 @@ pub fn decrypt_mek_from_pairing(
     Ok(SecretKey::from_bytes(mek_arr))
 }
 
+// =============================================================================
+// Session Key Sharing
+// =============================================================================
+
+impl DeviceKeypair {
+    /// Generates a new random device keypair.
+    pub fn generate() -> Self {
+        Self {
+            secret: p256::SecretKey::random(&mut rand::thread_rng()),
+        }
+    }
+
+    /// Restores a device keypair from its 32-byte private scalar.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CliError> {
+        let secret = p256::SecretKey::from_slice(bytes)
+            .map_err(|_| CliError::CryptoError("Invalid device private key".into()))?;
+        Ok(Self { secret })
+    }
+
+    /// Returns the 32-byte private scalar for storage.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.secret.to_bytes().to_vec()
+    }
+
+    /// Returns the public key as uncompressed SEC1 bytes (65 bytes).
+    pub fn public_key(&self) -> Vec<u8> {
+        self.secret
+            .public_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec()
+    }
+}
+
+/// Wraps a session key to another device's public key.
+///
+/// Uses a fresh ephemeral keypair per call, so wrapping the same key for
+/// the same device twice yields unrelated ciphertexts.
+pub fn wrap_session_key(
+    session_key: &SecretKey,
+    epoch: u32,
+    recipient_public_key: &[u8],
+) -> Result<WrappedSessionKey, CliError> {
+    let recipient = parse_public_key(recipient_public_key)?;
+
+    let ephemeral = EphemeralSecret::random(&mut rand::thread_rng());
+    let ephemeral_public = ephemeral.public_key().to_encoded_point(false);
+    let shared_secret = ephemeral.diffie_hellman(&recipient);
+    let wrapping_key = shared_secret_to_key(&shared_secret, SHARE_KEY_INFO)?;
+
+    let (ciphertext, nonce, tag) = aes_gcm_encrypt(&wrapping_key, session_key.as_bytes())?;
+
+    Ok(WrappedSessionKey {
+        v: 1,
+        epoch,
+        ephemeral_public_key: base64_encode(ephemeral_public.as_bytes()),
+        nonce: base64_encode(&nonce),
+        ciphertext: base64_encode(&ciphertext),
+        tag: base64_encode(&tag),
+    })
+}
+
+/// Unwraps a session key that was wrapped to this device.
+pub fn unwrap_session_key(
+    device: &DeviceKeypair,
+    wrapped: &WrappedSessionKey,
+) -> Result<SecretKey, CliError> {
+    if wrapped.v != 1 {
+        return Err(CliError::CryptoError(format!(
+            "Unsupported wrapped key version: {}",
+            wrapped.v
+        )));
+    }
+
+    let ephemeral_public = parse_public_key(&base64_decode(&wrapped.ephemeral_public_key)?)?;
+    let shared_secret = p256::ecdh::diffie_hellman(
+        device.secret.to_nonzero_scalar(),
+        ephemeral_public.as_affine(),
+    );
+    let wrapping_key = shared_secret_to_key(&shared_secret, SHARE_KEY_INFO)?;
+
+    let nonce = base64_decode(&wrapped.nonce)?;
+    let ciphertext = base64_decode(&wrapped.ciphertext)?;
+    let tag = base64_decode(&wrapped.tag)?;
+
+    if nonce.len() != NONCE_SIZE {
+        return Err(CliError::CryptoError("Invalid nonce size".into()));
+    }
+    if tag.len() != TAG_SIZE {
+        return Err(CliError::CryptoError("Invalid tag size".into()));
+    }
+
+    let mut nonce_arr = [0u8; NONCE_SIZE];
+    nonce_arr.copy_from_slice(&nonce);
+
+    let mut tag_arr = [0u8; TAG_SIZE];
+    tag_arr.copy_from_slice(&tag);
+
+    let key_bytes = aes_gcm_decrypt(&wrapping_key, &ciphertext, &nonce_arr, &tag_arr)?;
+
+    if key_bytes.len() != KEY_SIZE {
+        return Err(CliError::CryptoError(
+            "Unwrapped session key has wrong size".into(),
+        ));
+    }
+
+    let mut key_arr = [0u8; KEY_SIZE];
+    key_arr.copy_from_slice(&key_bytes);
+    Ok(SecretKey::from_bytes(key_arr))
+}
+
+// =============================================================================
+// Forward Secrecy
+// =============================================================================
+
+/// Forward-secret chain of session keys.
+///
+/// The chain starts from a random key that is never derived from the MEK.
+/// Each epoch's key is ratcheted from the previous one, and only the current
+/// and the previous key are kept, so output from older epochs cannot be
+/// decrypted anymore, not even with the MEK.
+pub struct KeyRatchet {
+    /// Session the chain belongs to (used for domain separation).
+    session_id: String,
+    /// Current epoch.
+    epoch: u32,
+    /// Key of the current epoch.
+    key: SecretKey,
+    /// Key of the previous epoch, kept for content still in flight.
+    previous: Option<SecretKey>,
+}
+
+impl KeyRatchet {
+    /// Starts a new chain from a random key at epoch 0.
+    pub fn new(session_id: &str) -> Self {
+        Self::from_key(session_id, 0, SecretKey::random())
+    }
+
+    /// Continues a chain from a known key, e.g. one granted by the host.
+    pub fn from_key(session_id: &str, epoch: u32, key: SecretKey) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            epoch,
+            key,
+            previous: None,
+        }
+    }
+
+    /// Returns the current epoch.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Returns the key of the current epoch.
+    pub fn current_key(&self) -> &SecretKey {
+        &self.key
+    }
+
+    /// Moves to the next epoch, dropping the key before the previous one.
+    pub fn advance(&mut self) -> u32 {
+        let next = ratchet_session_key(&self.key, &self.session_id);
+        self.previous = Some(std::mem::replace(&mut self.key, next));
+        self.epoch += 1;
+        self.epoch
+    }
+
+    /// Ratchets forward until `epoch` is reached.
+    ///
+    /// Returns false if `epoch` is in the past or too far ahead.
+    pub fn advance_to(&mut self, epoch: u32) -> bool {
+        if epoch < self.epoch || epoch - self.epoch > MAX_RATCHET_STEPS {
+            return false;
+        }
+        while self.epoch < epoch {
+            self.advance();
+        }
+        true
+    }
+
+    /// Replaces the chain with a fresh random key at the next epoch.
+    ///
+    /// Unlike `advance`, holders of earlier keys cannot follow a reseed,
+    /// which is how revoked guests are locked out.
+    pub fn reseed(&mut self) -> u32 {
+        self.key = SecretKey::random();
+        self.previous = None;
+        self.epoch += 1;
+        self.epoch
+    }
+
+    /// Returns the key for the current or the previous epoch.
+    pub fn key_for_epoch(&self, epoch: u32) -> Option<&SecretKey> {
+        if epoch == self.epoch {
+            Some(&self.key)
+        } else if epoch + 1 == self.epoch {
+            self.previous.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Encrypts content with the current key.
+    pub fn encrypt(&self, plaintext: &[u8]) -> EncryptedContent {
+        let mut encrypted = encrypt_content(&self.key, plaintext);
+        encrypted.epoch = Some(self.epoch);
+        encrypted.ratchet = true;
+        encrypted
+    }
+}
+
 // =============================================================================
 // Tests
 // =============================================================================
@@ -649,4 +1002,156 @@ mod tests {
 
         assert_eq!(decrypted, b"Hello from device 1".to_vec());
     }
+
+    #[test]
+    fn test_session_key_epoch_zero_matches_original() {
+        let mek = SecretKey::random();
+        let session_id = "01HQXK7V8G3N5M2R4P6T1W9Y0Z";
+
+        let original = derive_session_key(&mek, session_id);
+        let epoch0 = derive_session_key_for_epoch(&mek, session_id, 0);
+        let epoch1 = derive_session_key_for_epoch(&mek, session_id, 1);
+
+        assert_eq!(original.as_bytes(), epoch0.as_bytes());
+        assert_ne!(original.as_bytes(), epoch1.as_bytes());
+    }
+
+    #[test]
+    fn test_pairing_mek_roundtrip() {
+        let mek = generate_mek();
+        let cli = generate_ecdh_keypair();
+
+        // The Dashboard's side of the exchange
+        let dash = generate_ecdh_keypair();
+        let shared_key = compute_ecdh_shared_key(dash.private_key, &cli.public_key).unwrap();
+        let (ciphertext, nonce, tag) = aes_gcm_encrypt(&shared_key, mek.as_bytes()).unwrap();
+        let encrypted = EncryptedMEK {
+            v: 1,
+            nonce: base64_encode(&nonce),
+            ciphertext: base64_encode(&ciphertext),
+            tag: base64_encode(&tag),
+        };
+        let dash_public_key = dash.public_key;
+
+        let decrypted =
+            decrypt_mek_from_pairing(cli.private_key, &dash_public_key, &encrypted).unwrap();
+        assert_eq!(decrypted.as_bytes(), mek.as_bytes());
+    }
+
+    #[test]
+    fn test_wrap_unwrap_session_key_roundtrip() {
+        let device = DeviceKeypair::generate();
+        let session_key = SecretKey::random();
+
+        let wrapped = wrap_session_key(&session_key, 3, &device.public_key()).unwrap();
+        assert_eq!(wrapped.epoch, 3);
+
+        let restored = DeviceKeypair::from_bytes(&device.to_bytes()).unwrap();
+        let unwrapped = unwrap_session_key(&restored, &wrapped).unwrap();
+
+        assert_eq!(session_key.as_bytes(), unwrapped.as_bytes());
+    }
+
+    #[test]
+    fn test_unwrap_with_other_device_fails() {
+        let device = DeviceKeypair::generate();
+        let other = DeviceKeypair::generate();
+        let session_key = SecretKey::random();
+
+        let wrapped = wrap_session_key(&session_key, 0, &device.public_key()).unwrap();
+
+        assert!(unwrap_session_key(&other, &wrapped).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_content_epoch_omitted_when_none() {
+        let key = SecretKey::random();
+        let mut encrypted = encrypt_content(&key, b"data");
+
+        let json = serde_json::to_string(&encrypted).unwrap();
+        assert!(!json.contains("epoch"));
+
+        encrypted.epoch = Some(2);
+        let json = serde_json::to_string(&encrypted).unwrap();
+        let parsed: EncryptedContent = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.epoch, Some(2));
+    }
+
+    #[test]
+    fn test_ratchet_is_deterministic_and_one_way() {
+        let mut host = KeyRatchet::new("session-1");
+        let mut guest = KeyRatchet::from_key("session-1", 0, host.current_key().clone());
+
+        host.advance();
+        host.advance();
+        assert!(guest.advance_to(2));
+        assert_eq!(
+            host.current_key().as_bytes(),
+            guest.current_key().as_bytes()
+        );
+
+        // Only the current and previous epoch keys are kept
+        assert!(host.key_for_epoch(2).is_some());
+        assert!(host.key_for_epoch(1).is_some());
+        assert!(host.key_for_epoch(0).is_none());
+        assert!(!guest.advance_to(1));
+    }
+
+    #[test]
+    fn test_ratchet_reseed_cannot_be_followed() {
+        let mut host = KeyRatchet::new("session-1");
+        let mut guest = KeyRatchet::from_key("session-1", 0, host.current_key().clone());
+
+        assert_eq!(host.reseed(), 1);
+        assert!(host.key_for_epoch(0).is_none());
+
+        guest.advance_to(1);
+        let encrypted = host.encrypt(b"secret");
+        assert!(decrypt_content(guest.current_key(), &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_ratchet_content_flag() {
+        let ratchet = KeyRatchet::new("session-1");
+        let json = serde_json::to_value(ratchet.encrypt(b"x")).unwrap();
+        assert_eq!(json["ratchet"], true);
+        assert_eq!(json["epoch"], 0);
+
+        let plain = serde_json::to_value(encrypt_content(&SecretKey::random(), b"x")).unwrap();
+        assert!(plain.get("ratchet").is_none());
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let key = SecretKey::from_bytes([7u8; KEY_SIZE]);
+        let fingerprint = key_fingerprint(&key);
+
+        assert_eq!(fingerprint.len(), 19);
+        assert_eq!(fingerprint, key_fingerprint(&key.clone()));
+        assert_ne!(
+            fingerprint,
+            key_fingerprint(&SecretKey::from_bytes([8u8; KEY_SIZE]))
+        );
+    }
+
+    #[test]
+    fn test_parse_recovery_key() {
+        let key = SecretKey::from_bytes([0xab; KEY_SIZE]);
+        let hex_key = hex::encode(key.as_bytes());
+
+        let grouped = hex_key
+            .as_bytes()
+            .chunks(8)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect::<Vec<_>>()
+            .join("-");
+        let base64_key = format!("{}\n", encode_base64(key.as_bytes()));
+
+        for text in [hex_key.as_str(), grouped.as_str(), base64_key.as_str()] {
+            assert_eq!(parse_recovery_key(text).unwrap().as_bytes(), key.as_bytes());
+        }
+
+        assert!(parse_recovery_key("abcd").is_err());
+        assert!(parse_recovery_key(&encode_base64(&[1u8; 16])).is_err());
+    }
 }
@@ -21,6 +21,10 @@ pub enum CliError {
     #[error("Authentication failed: {0}")]
     AuthError(String),
 
+    /// No credentials are available and none can be obtained interactively.
+    #[error("Not logged in: {0}")]
+    NotAuthenticated(String),
+
     /// Keychain/credential storage error.
     #[error("Keychain error: {0}")]
     KeychainError(String),
@@ -37,6 +41,10 @@ pub enum CliError {
     #[error("Crypto error: {0}")]
     CryptoError(String),
 
+    /// Input given by the user cannot be used, e.g. text that is not UTF-8.
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
     /// Generic error.
     #[error("{0}")]
     Other(String),
@@ -44,3 +52,37 @@ pub enum CliError {
 
 /// Convenience type alias for Results using CliError.
 pub type Result<T> = std::result::Result<T, CliError>;
+
+impl CliError {
+    /// Process exit code for this error, following the BSD `sysexits.h`
+    /// conventions so scripts and CI can tell failures apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            // EX_DATAERR: malformed input, e.g. an invalid key file
+            CliError::CryptoError(_) | CliError::InvalidInput(_) => 65,
+            // EX_UNAVAILABLE: the klaas service could not be reached
+            CliError::NetworkError(_) | CliError::WebSocketError(_) => 69,
+            // EX_IOERR: local terminal or credential storage failed
+            CliError::TerminalError(_) | CliError::KeychainError(_) => 74,
+            // EX_NOPERM: credentials were rejected
+            CliError::AuthError(_) => 77,
+            // EX_CONFIG: credentials are missing
+            CliError::NotAuthenticated(_) => 78,
+            CliError::SpawnError(_) | CliError::PtyError(_) | CliError::Other(_) => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(CliError::NotAuthenticated("x".into()).exit_code(), 78);
+        assert_eq!(CliError::AuthError("x".into()).exit_code(), 77);
+        assert_eq!(CliError::NetworkError("x".into()).exit_code(), 69);
+        assert_eq!(CliError::InvalidInput("x".into()).exit_code(), 65);
+        assert_eq!(CliError::Other("x".into()).exit_code(), 1);
+    }
+}
@@ -0,0 +1,457 @@
+//! Session key resolution for guest mode.
+//!
+//! A guest either belongs to the account that owns the session, in which
+//! case it derives the session key from its own MEK, or it was invited with
+//! `klaas share`, in which case the owner wrapped the session key to this
+//! device's public key and the guest unwraps it.
+//!
+//! If the host enabled forward secrecy, output is encrypted with a ratchet
+//! key instead. The guest asks the host for it with a key request, proving
+//! access with the keys above, and then ratchets along with the host.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use crate::api_client::{ApiClient, SharedSessionKey};
+use crate::credentials::CredentialStore;
+use crate::crypto::{
+    decrypt_content, derive_session_key_for_epoch, encode_base64, encrypt_content,
+    unwrap_session_key, DeviceKeypair, EncryptedContent, KeyRatchet, SecretKey, WrappedSessionKey,
+};
+use crate::error::{CliError, Result};
+use crate::types::ShareAccess;
+
+/// How long a forward-secrecy key request may go unanswered before it is
+/// sent again. The grant can be lost, e.g. when the host reconnects.
+const KEY_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the session keys come from.
+enum KeySource {
+    /// Same account as the host: any epoch can be derived from the MEK.
+    Owner(SecretKey),
+    /// Invited by another user: keys arrive wrapped to this device.
+    Shared {
+        device_id: String,
+        device: DeviceKeypair,
+        access: ShareAccess,
+    },
+}
+
+/// Session keys available to a guest, indexed by key epoch.
+pub struct SessionKeys {
+    /// Session these keys belong to.
+    session_id: String,
+    /// How keys for new epochs are obtained.
+    source: KeySource,
+    /// Known session keys by epoch.
+    keys: HashMap<u32, SecretKey>,
+    /// Epoch used for outgoing input.
+    current_epoch: u32,
+    /// Forward-secrecy ratchet, once the host granted us its key.
+    ratchet: Option<KeyRatchet>,
+    /// Outstanding key request: request ID, its ephemeral keypair and when
+    /// it was sent.
+    pending_request: Option<(String, DeviceKeypair, Instant)>,
+}
+
+/// Request for the host's forward-secrecy key.
+pub struct KeyRequest {
+    /// Random ID echoed back in the grant.
+    pub request_id: String,
+    /// Ephemeral public key to wrap the key to, base64 encoded.
+    pub public_key: String,
+    /// The public key encrypted with the current session key.
+    pub proof: EncryptedContent,
+}
+
+impl SessionKeys {
+    /// Creates keys for a session owned by this account.
+    pub fn owner(session_id: &str, mek: SecretKey) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            source: KeySource::Owner(mek),
+            keys: HashMap::new(),
+            current_epoch: 0,
+            ratchet: None,
+            pending_request: None,
+        }
+    }
+
+    /// Creates keys for a session shared with this device.
+    pub fn shared(
+        session_id: &str,
+        device_id: &str,
+        device: DeviceKeypair,
+        shared: &SharedSessionKey,
+    ) -> Result<Self> {
+        let key = unwrap_session_key(&device, &shared.wrapped)?;
+        let epoch = shared.wrapped.epoch;
+
+        Ok(Self {
+            session_id: session_id.to_string(),
+            source: KeySource::Shared {
+                device_id: device_id.to_string(),
+                device,
+                access: shared.access,
+            },
+            keys: HashMap::from([(epoch, key)]),
+            current_epoch: epoch,
+            ratchet: None,
+            pending_request: None,
+        })
+    }
+
+    /// Returns true if this guest may only watch the session.
+    ///
+    /// This only keeps the guest from sending input. Read-only grants are
+    /// enforced by SessionHub, which knows each share's access and answers
+    /// prompts from read-only shares with `input_rejected` (reason
+    /// `read_only`). The host cannot check it itself: every grant holds the
+    /// same session key, and prompts don't say which grant sent them.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self.source,
+            KeySource::Shared {
+                access: ShareAccess::ReadOnly,
+                ..
+            }
+        )
+    }
+
+    /// Returns the device ID the keys were shared with, if shared.
+    pub fn shared_device_id(&self) -> Option<&str> {
+        match &self.source {
+            KeySource::Shared { device_id, .. } => Some(device_id),
+            KeySource::Owner(_) => None,
+        }
+    }
+
+    /// Switches to a new key epoch announced by the server.
+    ///
+    /// Returns false if the key for that epoch is unknown and has to be
+    /// fetched again with `add_shared_key`.
+    pub fn set_epoch(&mut self, epoch: u32) -> bool {
+        self.current_epoch = epoch;
+        self.key_for_epoch(epoch).is_some()
+    }
+
+    /// Adds a re-wrapped key received after a key rotation.
+    pub fn add_shared_key(&mut self, shared: &SharedSessionKey) -> Result<()> {
+        let KeySource::Shared { device, access, .. } = &mut self.source else {
+            return Ok(());
+        };
+
+        let key = unwrap_session_key(device, &shared.wrapped)?;
+        *access = shared.access;
+        self.keys.insert(shared.wrapped.epoch, key);
+        Ok(())
+    }
+
+    /// Encrypts input with the forward-secrecy key if we have one, otherwise
+    /// with the current epoch key.
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<EncryptedContent> {
+        match &self.ratchet {
+            Some(ratchet) => Ok(ratchet.encrypt(plaintext)),
+            None => self.encrypt_with_epoch_key(plaintext),
+        }
+    }
+
+    /// Encrypts with the session key of the current epoch.
+    fn encrypt_with_epoch_key(&mut self, plaintext: &[u8]) -> Result<EncryptedContent> {
+        let epoch = self.current_epoch;
+        let key = self
+            .key_for_epoch(epoch)
+            .ok_or_else(|| CliError::CryptoError(format!("No session key for epoch {}", epoch)))?;
+
+        let mut encrypted = encrypt_content(&key, plaintext);
+        encrypted.epoch = (epoch > 0).then_some(epoch);
+        Ok(encrypted)
+    }
+
+    /// Decrypts content using the key of the epoch it was encrypted with.
+    pub fn decrypt(&mut self, encrypted: &EncryptedContent) -> Result<Vec<u8>> {
+        let epoch = encrypted.epoch.unwrap_or(0);
+
+        if encrypted.ratchet {
+            let key = self.ratchet.as_mut().and_then(|ratchet| {
+                ratchet.advance_to(epoch);
+                ratchet.key_for_epoch(epoch).cloned()
+            });
+            let key = key.ok_or_else(|| {
+                CliError::CryptoError(format!("No forward-secrecy key for epoch {}", epoch))
+            })?;
+            return decrypt_content(&key, encrypted);
+        }
+
+        let key = self
+            .key_for_epoch(epoch)
+            .ok_or_else(|| CliError::CryptoError(format!("No session key for epoch {}", epoch)))?;
+
+        decrypt_content(&key, encrypted)
+    }
+
+    /// Returns true if a forward-secrecy key request is awaiting its grant
+    /// and has not timed out.
+    pub fn has_pending_request(&self) -> bool {
+        self.has_pending_request_at(Instant::now())
+    }
+
+    /// Like [`has_pending_request`](Self::has_pending_request), as of `now`.
+    fn has_pending_request_at(&self, now: Instant) -> bool {
+        self.pending_request
+            .as_ref()
+            .is_some_and(|(_, _, sent)| now.saturating_duration_since(*sent) < KEY_REQUEST_TIMEOUT)
+    }
+
+    /// Forgets the outstanding key request, e.g. because the connection it
+    /// was sent on is gone. Returns true if there was one.
+    pub fn clear_pending_request(&mut self) -> bool {
+        self.pending_request.take().is_some()
+    }
+
+    /// Creates a request for the host's forward-secrecy key.
+    ///
+    /// Each request uses a fresh ephemeral keypair and replaces any
+    /// outstanding one.
+    pub fn key_request(&mut self) -> Result<KeyRequest> {
+        let ephemeral = DeviceKeypair::generate();
+        let public_key = ephemeral.public_key();
+        let proof = self.encrypt_with_epoch_key(&public_key)?;
+        let request_id = ulid::Ulid::new().to_string();
+
+        self.pending_request = Some((request_id.clone(), ephemeral, Instant::now()));
+
+        Ok(KeyRequest {
+            request_id,
+            public_key: encode_base64(&public_key),
+            proof,
+        })
+    }
+
+    /// Accepts a forward-secrecy key granted by the host.
+    ///
+    /// Returns false if the grant answers someone else's request.
+    pub fn accept_key_grant(
+        &mut self,
+        request_id: &str,
+        wrapped: &WrappedSessionKey,
+    ) -> Result<bool> {
+        match &self.pending_request {
+            Some((pending_id, ..)) if pending_id == request_id => {}
+            _ => return Ok(false),
+        }
+
+        let (_, ephemeral, _) = self.pending_request.take().expect("checked above");
+        let key = unwrap_session_key(&ephemeral, wrapped)?;
+        self.ratchet = Some(KeyRatchet::from_key(&self.session_id, wrapped.epoch, key));
+        Ok(true)
+    }
+
+    /// Follows a forward-secrecy epoch announced by the host.
+    ///
+    /// Returns true if the key for the epoch has to be requested, either
+    /// because we have none yet or because the host reseeded the chain.
+    pub fn set_ratchet_epoch(&mut self, epoch: u32, reseeded: bool) -> bool {
+        if reseeded {
+            self.ratchet = None;
+        }
+
+        match self.ratchet.as_mut() {
+            Some(ratchet) => {
+                ratchet.advance_to(epoch);
+                ratchet.key_for_epoch(epoch).is_none()
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the key for an epoch, deriving it from the MEK if possible.
+    fn key_for_epoch(&mut self, epoch: u32) -> Option<SecretKey> {
+        if let Some(key) = self.keys.get(&epoch) {
+            return Some(key.clone());
+        }
+
+        match &self.source {
+            KeySource::Owner(mek) => {
+                let key = derive_session_key_for_epoch(mek, &self.session_id, epoch);
+                self.keys.insert(epoch, key.clone());
+                Some(key)
+            }
+            KeySource::Shared { .. } => None,
+        }
+    }
+}
+
+/// Gets the device keypair, generating and storing one if needed.
+pub fn get_or_create_device_key(cred_store: &CredentialStore) -> Result<DeviceKeypair> {
+    if let Some(bytes) = cred_store.get_device_key()? {
+        return DeviceKeypair::from_bytes(&bytes);
+    }
+
+    let device = DeviceKeypair::generate();
+    cred_store.store_device_key(&device.to_bytes())?;
+    info!("Generated new device key for session sharing");
+    Ok(device)
+}
+
+/// Registers this device's public key so other users can share with it.
+///
+/// Failures are logged and ignored: sharing is optional and older servers
+/// do not implement the endpoint.
+pub async fn register_device_key(api: &ApiClient, device_id: &str, device: &DeviceKeypair) {
+    let public_key = encode_base64(&device.public_key());
+    if let Err(e) = api.register_device_key(device_id, &public_key).await {
+        debug!(error = %e, "Could not register device key");
+    }
+}
+
+/// Resolves the session keys for a guest connection.
+///
+/// Asks the server whether the session was shared with this device. If so,
+/// the wrapped key is unwrapped with the device key; otherwise the keys are
+/// derived from this account's MEK, which is generated on first use.
+pub async fn resolve_session_keys(
+    api: &ApiClient,
+    cred_store: &CredentialStore,
+    session_id: &str,
+) -> Result<SessionKeys> {
+    let device_id = cred_store.get_or_create_device_id()?;
+    let device = get_or_create_device_key(cred_store)?;
+    register_device_key(api, device_id.as_str(), &device).await;
+
+    match api.get_shared_key(session_id, device_id.as_str()).await {
+        Ok(Some(shared)) => {
+            info!(access = shared.access.to_wire(), "Using shared session key");
+            return SessionKeys::shared(session_id, device_id.as_str(), device, &shared);
+        }
+        Ok(None) => debug!("Session not shared with this device, using own MEK"),
+        Err(e) => warn!(error = %e, "Could not check for a shared session key"),
+    }
+
+    let mek = match cred_store.get_mek()? {
+        Some(mek_bytes) => {
+            debug!("Retrieved existing MEK for E2EE");
+            let mut arr = [0u8; 32];
+            arr.copy_from_slice(&mek_bytes);
+            SecretKey::from_bytes(arr)
+        }
+        None => {
+            // Auto-generate MEK if not found (enables E2EE for this device)
+            let mek = SecretKey::random();
+            cred_store.store_mek(mek.as_bytes())?;
+            info!("Generated new MEK for E2EE");
+            mek
+        }
+    };
+
+    // Start at the current epoch in case shares were revoked before
+    let mut keys = SessionKeys::owner(session_id, mek);
+    match api.get_shares(session_id).await {
+        Ok(shares) => {
+            keys.set_epoch(shares.key_epoch);
+        }
+        Err(e) => debug!(error = %e, "Could not fetch current key epoch"),
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{derive_session_key, wrap_session_key};
+
+    #[test]
+    fn test_owner_keys_derive_any_epoch() {
+        let mek = SecretKey::random();
+        let mut keys = SessionKeys::owner("session-1", mek.clone());
+
+        assert!(keys.set_epoch(2));
+        let encrypted = keys.encrypt(b"hello").unwrap();
+        assert_eq!(encrypted.epoch, Some(2));
+
+        let host_key = derive_session_key_for_epoch(&mek, "session-1", 2);
+        assert_eq!(decrypt_content(&host_key, &encrypted).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_shared_keys_unwrap_and_decrypt() {
+        let mek = SecretKey::random();
+        let device = DeviceKeypair::generate();
+        let session_key = derive_session_key(&mek, "session-1");
+
+        let shared = SharedSessionKey {
+            access: ShareAccess::ReadOnly,
+            wrapped: wrap_session_key(&session_key, 0, &device.public_key()).unwrap(),
+        };
+        let mut keys = SessionKeys::shared("session-1", "device-1", device, &shared).unwrap();
+
+        assert_eq!(keys.shared_device_id(), Some("device-1"));
+        assert!(keys.is_read_only());
+
+        let encrypted = encrypt_content(&session_key, b"output");
+        assert_eq!(keys.decrypt(&encrypted).unwrap(), b"output");
+
+        // A rotated epoch is unknown until the re-wrapped key is added
+        assert!(!keys.set_epoch(1));
+    }
+
+    #[test]
+    fn test_key_request_and_grant() {
+        let mek = SecretKey::random();
+        let mut guest = SessionKeys::owner("session-1", mek.clone());
+        let mut host = KeyRatchet::new("session-1");
+        host.advance();
+
+        // Host verifies the proof with the MEK-derived key
+        let request = guest.key_request().unwrap();
+        let public_key = crate::crypto::decode_base64(&request.public_key).unwrap();
+        let session_key = derive_session_key_for_epoch(&mek, "session-1", 0);
+        assert_eq!(
+            decrypt_content(&session_key, &request.proof).unwrap(),
+            public_key
+        );
+
+        let wrapped =
+            crate::crypto::wrap_session_key(host.current_key(), host.epoch(), &public_key).unwrap();
+        assert!(!guest.accept_key_grant("other", &wrapped).unwrap());
+        assert!(guest
+            .accept_key_grant(&request.request_id, &wrapped)
+            .unwrap());
+        assert!(!guest.has_pending_request());
+
+        // Guest follows the ratchet without asking again
+        host.advance();
+        assert!(!guest.set_ratchet_epoch(host.epoch(), false));
+        let output = host.encrypt(b"output");
+        assert_eq!(guest.decrypt(&output).unwrap(), b"output");
+
+        // Input is encrypted with the ratchet key
+        let input = guest.encrypt(b"input").unwrap();
+        assert!(input.ratchet);
+        assert_eq!(
+            decrypt_content(host.current_key(), &input).unwrap(),
+            b"input"
+        );
+
+        // A reseed requires a new request
+        assert!(guest.set_ratchet_epoch(host.reseed(), true));
+    }
+
+    #[test]
+    fn test_unanswered_key_request_times_out() {
+        let mut guest = SessionKeys::owner("session-1", SecretKey::random());
+        guest.key_request().unwrap();
+        assert!(guest.has_pending_request());
+
+        // The grant never arrived, so the key is asked for again
+        let later = Instant::now() + KEY_REQUEST_TIMEOUT;
+        assert!(!guest.has_pending_request_at(later));
+
+        // A reconnect drops the request entirely
+        assert!(guest.clear_pending_request());
+        assert!(!guest.clear_pending_request());
+    }
+}
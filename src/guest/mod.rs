@@ -2,8 +2,16 @@
 //!
 //! This module provides the ability to connect to and view a remote klaas
 //! session as a guest. Guests can observe the terminal output in real-time
-//! and optionally send input (prompts) to the host session.
+//! and optionally send input to the host session, either key by key or a
+//! line at a time.
+//!
+//! Sessions owned by another account can be joined once the owner has
+//! shared them with `klaas share`; see [`keys`].
 
+pub mod keys;
+mod recording;
+mod scrollback;
+mod status;
 pub mod terminal;
 
-pub use terminal::run_with_token;
+pub use terminal::{run_with_token, GuestExit, GuestOptions};
@@ -0,0 +1,226 @@
+//! Recording of guest sessions.
+//!
+//! `klaas connect --record <file>` writes everything the guest shows, the
+//! replayed history as well as live output, to a file. Files ending in
+//! `.cast` are asciicast v2 recordings that `asciinema play` replays with the
+//! original timing, taken from the server's timestamps; any other file gets
+//! the output as plain text.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde_json::json;
+
+use crate::ansi::AnsiStripper;
+use crate::error::{CliError, Result};
+
+/// Terminal size assumed if output arrives before the session's size.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// How a recording is written.
+enum Format {
+    /// asciicast v2: a JSON header line, then one JSON event per line.
+    Asciicast(Asciicast),
+    /// Plain text without escape sequences.
+    Text(AnsiStripper),
+}
+
+/// State of an asciicast recording.
+#[derive(Default)]
+struct Asciicast {
+    /// Title for the header.
+    title: String,
+    /// Size of the session's terminal (columns, rows).
+    size: Option<(u16, u16)>,
+    /// Time of the first output; written in the header, which waits for it.
+    start: Option<DateTime<FixedOffset>>,
+    /// Seconds since the start of the last event, so time never runs
+    /// backwards.
+    elapsed: f64,
+    /// Bytes of a UTF-8 character split across two outputs.
+    partial: Vec<u8>,
+}
+
+/// Writes the session output a guest sees to a file.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    format: Format,
+}
+
+impl Recorder {
+    /// Creates a recording at `path`, as asciicast if it ends in `.cast` and
+    /// as plain text otherwise.
+    pub fn create(path: &Path, title: &str) -> Result<Self> {
+        let file = File::create(path).map_err(|e| {
+            CliError::Other(format!(
+                "Failed to create recording {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let format = if path.extension().is_some_and(|ext| ext == "cast") {
+            Format::Asciicast(Asciicast {
+                title: title.to_string(),
+                ..Default::default()
+            })
+        } else {
+            Format::Text(AnsiStripper::new())
+        };
+        Ok(Self {
+            writer: BufWriter::new(file),
+            format,
+        })
+    }
+
+    /// Records the size of the session's terminal.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        let Format::Asciicast(cast) = &mut self.format else {
+            return Ok(());
+        };
+        let size = (cols, rows);
+        if cast.size == Some(size) {
+            return Ok(());
+        }
+        cast.size = Some(size);
+
+        // Before the header is written, the size simply goes into it
+        if cast.start.is_some() {
+            let event = json!([cast.elapsed, "r", format!("{}x{}", cols, rows)]);
+            writeln!(self.writer, "{}", event)?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records output shown at `timestamp` (RFC 3339, from the server).
+    pub fn output(&mut self, timestamp: &str, data: &[u8]) -> Result<()> {
+        match &mut self.format {
+            Format::Text(stripper) => {
+                self.writer.write_all(&stripper.push(data))?;
+            }
+            Format::Asciicast(cast) => {
+                let time = DateTime::parse_from_rfc3339(timestamp).ok();
+                let start = match cast.start {
+                    Some(start) => start,
+                    None => {
+                        let start = time.unwrap_or_else(|| Utc::now().fixed_offset());
+                        writeln!(self.writer, "{}", cast.header(start))?;
+                        cast.start = Some(start);
+                        start
+                    }
+                };
+                if let Some(time) = time {
+                    let elapsed = (time - start).num_microseconds().unwrap_or(0) as f64 / 1e6;
+                    cast.elapsed = cast.elapsed.max(elapsed);
+                }
+
+                let text = cast.decode(data);
+                if !text.is_empty() {
+                    writeln!(self.writer, "{}", json!([cast.elapsed, "o", text]))?;
+                }
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Asciicast {
+    /// Returns the header line for a recording starting at `start`.
+    fn header(&self, start: DateTime<FixedOffset>) -> serde_json::Value {
+        let (width, height) = self.size.unwrap_or(DEFAULT_SIZE);
+        json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": start.timestamp(),
+            "title": self.title,
+        })
+    }
+
+    /// Decodes output as UTF-8, holding back a character that is split
+    /// across outputs until the rest of it arrives.
+    fn decode(&mut self, data: &[u8]) -> String {
+        self.partial.extend_from_slice(data);
+        let complete = match std::str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Not UTF-8 at all: replace what cannot be decoded
+            Err(_) => self.partial.len(),
+        };
+        let rest = self.partial.split_off(complete);
+        let text = String::from_utf8_lossy(&self.partial).into_owned();
+        self.partial = rest;
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_events(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_asciicast_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.cast");
+        let mut recorder = Recorder::create(&path, "demo").unwrap();
+
+        recorder.resize(120, 40).unwrap();
+        recorder
+            .output("2026-01-01T10:00:00Z", b"\x1b[1mhello\x1b[0m ")
+            .unwrap();
+        // A check mark split across two outputs, 1.5s later
+        recorder
+            .output("2026-01-01T10:00:01.500Z", b"\xe2\x9c")
+            .unwrap();
+        recorder
+            .output("2026-01-01T10:00:01.600Z", b"\x93\r\n")
+            .unwrap();
+        recorder.resize(100, 30).unwrap();
+        // Out of order or unparsable timestamps do not go back in time
+        recorder.output("2026-01-01T09:59:59Z", b"late").unwrap();
+
+        let events = read_events(&path);
+        assert_eq!(
+            events[0],
+            json!({
+                "version": 2,
+                "width": 120,
+                "height": 40,
+                "timestamp": 1767261600,
+                "title": "demo",
+            })
+        );
+        assert_eq!(events[1], json!([0.0, "o", "\u{1b}[1mhello\u{1b}[0m "]));
+        assert_eq!(events[2], json!([1.6, "o", "\u{2713}\r\n"]));
+        assert_eq!(events[3], json!([1.6, "r", "100x30"]));
+        assert_eq!(events[4], json!([1.6, "o", "late"]));
+        assert_eq!(events.len(), 5);
+    }
+
+    #[test]
+    fn test_text_recording_strips_escape_sequences() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.txt");
+        let mut recorder = Recorder::create(&path, "demo").unwrap();
+
+        recorder.resize(120, 40).unwrap();
+        recorder
+            .output("2026-01-01T10:00:00Z", b"\x1b[32mok\x1b[0m\r\n")
+            .unwrap();
+        recorder.output("not a timestamp", b"done\r\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ok\ndone\n");
+        assert!(Recorder::create(&dir.path().join("missing/x.cast"), "demo").is_err());
+    }
+}
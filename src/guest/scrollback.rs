@@ -0,0 +1,559 @@
+//! Local scrollback and copy mode for guest mode.
+//!
+//! Everything the guest shows is also fed into a terminal emulator, which
+//! keeps the decrypted output (history included) long after it scrolled out
+//! of the local terminal. Copy mode shows that buffer in place of the live
+//! session: it can be paged through, searched, jumped through prompt by
+//! prompt and saved to a file. Output that arrives meanwhile still goes into
+//! the buffer, and the live screen is redrawn from it when copy mode ends.
+
+use std::collections::VecDeque;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Number of lines kept above the screen.
+const SCROLLBACK_LINES: usize = 10_000;
+
+/// Number of lines the emulator itself keeps above its screen. Lines are
+/// moved out of it as they scroll off, and the emulator is restarted before
+/// it fills up, so this only needs to exceed what one chunk of output
+/// scrolls.
+const EMULATOR_SCROLLBACK: usize = 1_000;
+
+/// Decrypted session output, emulated so it can be shown again.
+pub struct Scrollback {
+    /// Emulated screen, as the local terminal shows it.
+    parser: vt100::Parser,
+    /// Lines that scrolled off the top of the screen, oldest first.
+    history: VecDeque<String>,
+}
+
+impl Scrollback {
+    /// Creates an empty buffer for a screen of the given size.
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows.max(1), cols.max(1), EMULATOR_SCROLLBACK),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Adds output, exactly as it was written to the terminal.
+    pub fn process(&mut self, data: &[u8]) {
+        // Line by line, so that few lines scroll off between two looks
+        for chunk in data.split_inclusive(|&b| b == b'\n') {
+            let alternate = self.parser.screen().alternate_screen();
+            let before = self.emulator_scrollback();
+            self.parser.process(chunk);
+
+            // The alternate screen has no scrollback; switching screens
+            // changes which scrollback is counted
+            if alternate == self.parser.screen().alternate_screen() {
+                let scrolled = self.emulator_scrollback().saturating_sub(before);
+                self.collect(scrolled);
+            }
+        }
+    }
+
+    /// Follows a resize of the local terminal.
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.set_size(rows.max(1), cols.max(1));
+    }
+
+    /// Returns the escape codes that redraw the live screen, including the
+    /// cursor position.
+    pub fn screen_formatted(&self) -> Vec<u8> {
+        self.parser.screen().contents_formatted()
+    }
+
+    /// Returns every line in the buffer as plain text, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        let (_, cols) = self.parser.screen().size();
+        let mut lines: Vec<String> = self.history.iter().cloned().collect();
+        lines.extend(self.parser.screen().rows(0, cols));
+
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    /// Number of lines in the emulator's own scrollback.
+    fn emulator_scrollback(&mut self) -> usize {
+        self.parser.set_scrollback(usize::MAX);
+        let len = self.parser.screen().scrollback();
+        self.parser.set_scrollback(0);
+        len
+    }
+
+    /// Moves the lines that just scrolled off the screen into the history.
+    fn collect(&mut self, scrolled: usize) {
+        if scrolled == 0 {
+            return;
+        }
+
+        // vt100 can only scroll back up to one screen height, so of a chunk
+        // that scrolled more than a screen, only the last screen is kept
+        let (rows, cols) = self.parser.screen().size();
+        let readable = scrolled.min(usize::from(rows));
+        self.parser.set_scrollback(readable);
+        self.history
+            .extend(self.parser.screen().rows(0, cols).take(readable));
+        self.parser.set_scrollback(0);
+        while self.history.len() > SCROLLBACK_LINES {
+            self.history.pop_front();
+        }
+
+        // Start over with an empty emulator scrollback before it is full
+        // and stops growing, which would hide further scrolling
+        if self.emulator_scrollback() >= EMULATOR_SCROLLBACK / 2 {
+            let mut parser = vt100::Parser::new(rows, cols, EMULATOR_SCROLLBACK);
+            parser.process(&self.parser.screen().state_formatted());
+            self.parser = parser;
+        }
+    }
+}
+
+/// What copy mode asks the guest to do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyAction {
+    /// Stay in copy mode.
+    Continue,
+    /// Go back to the live session.
+    Exit,
+    /// Include output that arrived since copy mode started.
+    Reload,
+    /// Save the lines in view to a file.
+    Save,
+}
+
+/// Copy mode: a scrollable, searchable snapshot of the scrollback.
+#[derive(Debug)]
+pub struct CopyMode {
+    /// Lines of the snapshot, oldest first.
+    lines: Vec<String>,
+    /// Index of the first line shown.
+    top: usize,
+    /// Number of lines shown.
+    height: usize,
+    /// Last search, used by `n` and `N`.
+    query: Option<String>,
+    /// Search being typed after `/`.
+    typing: Option<String>,
+    /// Line of the current match or prompt, highlighted.
+    mark: Option<usize>,
+    /// Whether output arrived since the snapshot was taken.
+    new_output: bool,
+    /// Short message for the status line.
+    notice: Option<String>,
+    /// Whether the view changed since it was last drawn.
+    dirty: bool,
+}
+
+impl CopyMode {
+    /// Starts copy mode at the bottom of the buffer.
+    pub fn new(lines: Vec<String>, height: u16) -> Self {
+        let mut copy = Self {
+            lines,
+            top: 0,
+            height: usize::from(height.max(1)),
+            query: None,
+            typing: None,
+            mark: None,
+            new_output: false,
+            notice: None,
+            dirty: true,
+        };
+        copy.top = copy.max_top();
+        copy
+    }
+
+    /// Replaces the snapshot with a newer one and scrolls to the bottom.
+    pub fn reload(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+        self.top = self.max_top();
+        self.mark = None;
+        self.new_output = false;
+        self.dirty = true;
+    }
+
+    /// Records that output arrived after the snapshot was taken.
+    pub fn set_new_output(&mut self) {
+        if !self.new_output {
+            self.new_output = true;
+            self.dirty = true;
+        }
+    }
+
+    /// Follows a resize of the local terminal.
+    pub fn resize(&mut self, height: u16) {
+        self.height = usize::from(height.max(1));
+        self.top = self.top.min(self.max_top());
+        self.dirty = true;
+    }
+
+    /// Shows a message in the status line.
+    pub fn notify(&mut self, message: &str) {
+        self.notice = Some(message.to_string());
+        self.dirty = true;
+    }
+
+    /// Returns true if the view needs to be drawn, and clears the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Returns the lines in view as plain text, for saving.
+    pub fn visible_text(&self) -> String {
+        let end = (self.top + self.height).min(self.lines.len());
+        let mut text = self.lines[self.top.min(end)..end].join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// Handles a key press.
+    pub fn key(&mut self, event: KeyEvent) -> CopyAction {
+        self.dirty = true;
+        if self.typing.is_some() {
+            self.search_key(event);
+            return CopyAction::Continue;
+        }
+        self.notice = None;
+
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        let page = self.height.saturating_sub(1).max(1);
+        match event.code {
+            KeyCode::Char('q') | KeyCode::Esc => return CopyAction::Exit,
+            KeyCode::Char('s') if !ctrl => return CopyAction::Save,
+            KeyCode::Char('G') | KeyCode::End => return CopyAction::Reload,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_up(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll_down(1),
+            KeyCode::Char('b') if ctrl => self.scroll_up(page),
+            KeyCode::Char('f') if ctrl => self.scroll_down(page),
+            KeyCode::PageUp | KeyCode::Char('b') => self.scroll_up(page),
+            KeyCode::PageDown | KeyCode::Char(' ') | KeyCode::Char('f') => self.scroll_down(page),
+            KeyCode::Char('g') | KeyCode::Home => self.top = 0,
+            KeyCode::Char('/') => self.typing = Some(String::new()),
+            KeyCode::Char('n') => self.search_next(true),
+            KeyCode::Char('N') => self.search_next(false),
+            KeyCode::Char('[') => self.jump_to_prompt(true),
+            KeyCode::Char(']') => self.jump_to_prompt(false),
+            _ => {}
+        }
+        CopyAction::Continue
+    }
+
+    /// Handles a key press while a search is typed.
+    fn search_key(&mut self, event: KeyEvent) {
+        let Some(typing) = &mut self.typing else {
+            return;
+        };
+        match event.code {
+            KeyCode::Esc => self.typing = None,
+            // Backspace on an empty search cancels it
+            KeyCode::Backspace if typing.is_empty() => self.typing = None,
+            KeyCode::Backspace => {
+                typing.pop();
+            }
+            KeyCode::Enter => {
+                let query = std::mem::take(typing);
+                self.typing = None;
+                if !query.is_empty() {
+                    self.query = Some(query);
+                    // Search upwards from the bottom of the view, towards
+                    // older output, like searching a shell's history
+                    self.mark = None;
+                    let from = (self.top + self.height).min(self.lines.len());
+                    self.find(from, true);
+                }
+            }
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => typing.push(c),
+            _ => {}
+        }
+    }
+
+    /// Jumps to the next match of the last search, older or newer.
+    fn search_next(&mut self, older: bool) {
+        if self.query.is_none() {
+            self.notify("No previous search");
+            return;
+        }
+        let from = self.anchor(older);
+        self.find(from, older);
+    }
+
+    /// Moves the mark to the next line matching the search, starting before
+    /// (older) or after (newer) line `from`.
+    fn find(&mut self, from: usize, older: bool) {
+        let Some(query) = self.query.clone() else {
+            return;
+        };
+        match self.next_line(from, older, |line| find_match(line, &query).is_some()) {
+            Some(line) => self.show_mark(line),
+            None => self.notify(&format!("Pattern not found: {}", query)),
+        }
+    }
+
+    /// Moves the mark to the previous or next prompt.
+    fn jump_to_prompt(&mut self, older: bool) {
+        let from = self.anchor(older);
+        match self.next_line(from, older, is_prompt) {
+            Some(line) => self.show_mark(line),
+            None => self.notify(if older {
+                "No earlier prompt"
+            } else {
+                "No later prompt"
+            }),
+        }
+    }
+
+    /// Where a search for the next older or newer line starts: the mark, or
+    /// the edge of the view.
+    fn anchor(&self, older: bool) -> usize {
+        match (self.mark, older) {
+            (Some(mark), true) => mark,
+            (Some(mark), false) => mark + 1,
+            (None, true) => (self.top + self.height).min(self.lines.len()),
+            (None, false) => self.top,
+        }
+    }
+
+    /// Returns the first line before `from` (older) or at or after `from`
+    /// (newer) that matches.
+    fn next_line(&self, from: usize, older: bool, matches: impl Fn(&str) -> bool) -> Option<usize> {
+        if older {
+            (0..from.min(self.lines.len()))
+                .rev()
+                .find(|&i| matches(&self.lines[i]))
+        } else {
+            (from..self.lines.len()).find(|&i| matches(&self.lines[i]))
+        }
+    }
+
+    /// Marks a line and scrolls it into view.
+    fn show_mark(&mut self, line: usize) {
+        self.mark = Some(line);
+        if line < self.top || line >= self.top + self.height {
+            self.top = line.saturating_sub(self.height / 2).min(self.max_top());
+        }
+    }
+
+    fn scroll_up(&mut self, lines: usize) {
+        self.top = self.top.saturating_sub(lines);
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        self.top = (self.top + lines).min(self.max_top());
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    /// Renders the lines in view, at most `width` columns wide, with search
+    /// matches and the mark highlighted.
+    pub fn render(&self, width: u16) -> Vec<String> {
+        let width = usize::from(width);
+        (self.top..self.top + self.height)
+            .map(|i| match self.lines.get(i) {
+                Some(line) => {
+                    let line: String = line.chars().take(width).collect();
+                    self.render_line(&line, Some(i) == self.mark)
+                }
+                None => String::new(),
+            })
+            .collect()
+    }
+
+    /// Highlights search matches in a line, and the whole line if marked.
+    fn render_line(&self, line: &str, marked: bool) -> String {
+        // Dark amber background for the marked line, amber for matches
+        let base = if marked { "\x1b[48;2;35;28;18m" } else { "" };
+        let mut out = String::from(base);
+        let mut rest = line;
+        if let Some(query) = &self.query {
+            while let Some(range) = find_match(rest, query) {
+                out.push_str(&rest[..range.start]);
+                out.push_str("\x1b[30;48;2;245;158;11m");
+                out.push_str(&rest[range.clone()]);
+                out.push_str("\x1b[0m");
+                out.push_str(base);
+                rest = &rest[range.end..];
+            }
+        }
+        out.push_str(rest);
+        if marked {
+            out.push_str("\x1b[K");
+        }
+        out.push_str("\x1b[0m");
+        out
+    }
+
+    /// Renders the status line: the search being typed, a notice, or the
+    /// position and key hints.
+    pub fn status_line(&self) -> String {
+        if let Some(typing) = &self.typing {
+            return format!("\x1b[38;2;245;158;11m/\x1b[0m{}", typing);
+        }
+
+        let mut status = String::from("\x1b[38;2;245;158;11mcopy mode\x1b[0m");
+        if let Some(notice) = &self.notice {
+            status.push_str(&format!(" \x1b[38;2;245;158;11m{}\x1b[0m", notice));
+        }
+        let bottom = (self.top + self.height).min(self.lines.len());
+        let mut segments = vec![format!("{}/{}", bottom, self.lines.len())];
+        if self.new_output {
+            segments.push("new output (G)".to_string());
+        }
+        segments.push("/ search · n/N next · [/] prompt · s save · q back".to_string());
+        status.push_str(&format!("\x1b[2m · {}\x1b[0m", segments.join(" · ")));
+        status
+    }
+}
+
+/// Finds a search query in a line. Lowercase queries match any case.
+fn find_match(line: &str, query: &str) -> Option<std::ops::Range<usize>> {
+    if query.chars().any(char::is_uppercase) {
+        return line.find(query).map(|start| start..start + query.len());
+    }
+    // Lowercasing can change byte lengths, so compare char by char
+    let query: Vec<char> = query.chars().collect();
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    (0..chars.len()).find_map(|i| {
+        let candidate = chars.get(i..i + query.len())?;
+        let matches = candidate
+            .iter()
+            .zip(&query)
+            .all(|((_, c), q)| c.to_lowercase().eq(q.to_lowercase()));
+        matches.then(|| {
+            let start = candidate[0].0;
+            let (last, c) = candidate[candidate.len() - 1];
+            start..last + c.len_utf8()
+        })
+    })
+}
+
+/// Returns true if the line looks like an agent's input prompt, e.g.
+/// `> fix the tests` or `❯ fix the tests`, possibly inside a box.
+fn is_prompt(line: &str) -> bool {
+    let line = line.trim_start_matches(|c: char| c.is_whitespace() || c == '│');
+    line.starts_with("> ") || line.starts_with("❯ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::empty())
+    }
+
+    fn type_search(copy: &mut CopyMode, query: &str) {
+        copy.key(key(KeyCode::Char('/')));
+        for c in query.chars() {
+            copy.key(key(KeyCode::Char(c)));
+        }
+        copy.key(key(KeyCode::Enter));
+    }
+
+    #[test]
+    fn test_lines_include_output_scrolled_off_screen() {
+        let mut scrollback = Scrollback::new(3, 20);
+        for i in 1..=10 {
+            scrollback.process(format!("line {}\r\n", i).as_bytes());
+        }
+        scrollback.process(b"\x1b[1;31mred\x1b[0m");
+
+        let lines = scrollback.lines();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "line 1");
+        assert_eq!(lines[9], "line 10");
+        assert_eq!(lines[10], "red");
+
+        // Reading the scrollback leaves the live screen in view
+        assert_eq!(scrollback.parser.screen().scrollback(), 0);
+    }
+
+    #[test]
+    fn test_lines_survive_emulator_restarts() {
+        let mut scrollback = Scrollback::new(5, 20);
+        let output: String = (0..2_000).map(|i| format!("line {}\r\n", i)).collect();
+        scrollback.process(output.as_bytes());
+        scrollback.process(b"\x1b[32mprompt\x1b[0m");
+
+        let lines = scrollback.lines();
+        assert_eq!(lines.len(), 2_001);
+        assert!(lines[..2_000]
+            .iter()
+            .enumerate()
+            .all(|(i, line)| *line == format!("line {}", i)));
+        assert_eq!(lines[2_000], "prompt");
+        // The restarted emulator carries the screen over
+        assert_eq!(scrollback.parser.screen().cursor_position(), (4, 6));
+    }
+
+    #[test]
+    fn test_search_moves_upwards_and_highlights() {
+        let lines: Vec<String> = (0..100).map(|i| format!("entry {}", i)).collect();
+        let mut copy = CopyMode::new(lines, 10);
+        assert_eq!(copy.top, 90);
+
+        type_search(&mut copy, "ENTRY 4");
+        assert_eq!(copy.notice.as_deref(), Some("Pattern not found: ENTRY 4"));
+
+        type_search(&mut copy, "entry 4");
+        assert_eq!(copy.mark, Some(49));
+        assert_eq!(copy.top, 44);
+        copy.key(key(KeyCode::Char('n')));
+        assert_eq!(copy.mark, Some(48));
+        copy.key(key(KeyCode::Char('N')));
+        assert_eq!(copy.mark, Some(49));
+        copy.key(key(KeyCode::Char('N')));
+        assert_eq!(copy.mark, Some(49));
+        assert_eq!(copy.notice.as_deref(), Some("Pattern not found: entry 4"));
+
+        let rendered = copy.render(80);
+        assert!(rendered[0].contains("\x1b[30;48;2;245;158;11mentry 4\x1b[0m4"));
+    }
+
+    #[test]
+    fn test_visible_text_is_the_lines_in_view() {
+        let lines: Vec<String> = (0..100).map(|i| format!("entry {}", i)).collect();
+        let mut copy = CopyMode::new(lines, 3);
+        assert_eq!(copy.visible_text(), "entry 97\nentry 98\nentry 99\n");
+
+        copy.key(key(KeyCode::Char('g')));
+        assert_eq!(copy.visible_text(), "entry 0\nentry 1\nentry 2\n");
+
+        let copy = CopyMode::new(vec!["only".to_string()], 3);
+        assert_eq!(copy.visible_text(), "only\n");
+    }
+
+    #[test]
+    fn test_jump_to_prompts() {
+        let lines = vec![
+            "> first task".to_string(),
+            "working...".to_string(),
+            "│ ❯ second task │".to_string(),
+            "done".to_string(),
+            ">not a prompt".to_string(),
+        ];
+        let mut copy = CopyMode::new(lines, 2);
+
+        copy.key(key(KeyCode::Char('[')));
+        assert_eq!(copy.mark, Some(2));
+        copy.key(key(KeyCode::Char('[')));
+        assert_eq!(copy.mark, Some(0));
+        assert_eq!(copy.top, 0);
+        copy.key(key(KeyCode::Char('[')));
+        assert_eq!(copy.notice.as_deref(), Some("No earlier prompt"));
+        copy.key(key(KeyCode::Char(']')));
+        assert_eq!(copy.mark, Some(2));
+    }
+
+    #[test]
+    fn test_find_match_ignores_case_for_lowercase_queries() {
+        assert_eq!(find_match("Error: FAILED", "failed"), Some(7..13));
+        assert_eq!(find_match("Error: FAILED", "Failed"), None);
+        assert_eq!(find_match("größe ÄNDERN", "ändern"), Some(8..15));
+    }
+}
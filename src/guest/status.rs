@@ -0,0 +1,234 @@
+//! Status bar for guest mode.
+//!
+//! Guests reserve the bottom row of the terminal, like the host, and show
+//! the session, its host, the agent's mode, the input lock and the measured
+//! latency there. Short-lived notices (e.g. rejected input) are shown in the
+//! same row instead of being written into the session output, where they
+//! would scroll away and corrupt full-screen TUIs.
+
+use std::time::{Duration, Instant};
+
+use crate::terminal::TerminalManager;
+
+/// How long a notice stays in the status bar.
+const NOTICE_DURATION: Duration = Duration::from_secs(5);
+
+/// How often the status bar is redrawn even if nothing changed, in case the
+/// session output drew over it.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State shown in the guest status bar.
+#[derive(Debug)]
+pub struct StatusBar {
+    /// Session name, or ID if it has no name.
+    session: String,
+    /// Name of the host device.
+    host: Option<String>,
+    /// Agent mode from the last mode change (e.g. "waiting_for_input").
+    mode: Option<String>,
+    /// Name of the client holding the input lock.
+    lock_holder: Option<String>,
+    /// Whether this guest only has read-only access.
+    read_only: bool,
+    /// Round-trip time of the last ping.
+    rtt: Option<Duration>,
+    /// Whether the connection is up.
+    connected: bool,
+    /// Short-lived message and when it was shown.
+    notice: Option<(String, Instant)>,
+    /// Whether the state changed since the last draw.
+    dirty: bool,
+    /// When the status bar was last drawn.
+    last_drawn: Option<Instant>,
+}
+
+impl StatusBar {
+    /// Creates the status bar for a session.
+    pub fn new(session: &str, read_only: bool) -> Self {
+        Self {
+            session: session.to_string(),
+            host: None,
+            mode: None,
+            lock_holder: None,
+            read_only,
+            rtt: None,
+            connected: true,
+            notice: None,
+            dirty: true,
+            last_drawn: None,
+        }
+    }
+
+    /// Sets the name of the host device.
+    pub fn set_host(&mut self, host: Option<String>) {
+        self.update(|status| &mut status.host, host);
+    }
+
+    /// Sets the agent mode.
+    pub fn set_mode(&mut self, mode: &str) {
+        self.update(|status| &mut status.mode, Some(mode.to_string()));
+    }
+
+    /// Sets who holds the input lock, or None once it is released.
+    pub fn set_lock_holder(&mut self, holder: Option<String>) {
+        self.update(|status| &mut status.lock_holder, holder);
+    }
+
+    /// Sets the measured round-trip time.
+    pub fn set_rtt(&mut self, rtt: Option<Duration>) {
+        self.update(|status| &mut status.rtt, rtt);
+    }
+
+    /// Sets whether the connection is up.
+    pub fn set_connected(&mut self, connected: bool) {
+        self.update(|status| &mut status.connected, connected);
+    }
+
+    /// Shows a short-lived message.
+    pub fn notify(&mut self, message: &str) {
+        self.notice = Some((message.to_string(), Instant::now()));
+        self.dirty = true;
+    }
+
+    /// Forces a redraw, e.g. after the terminal was resized.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Draws the status bar if it changed, a notice expired, or it was not
+    /// redrawn for a while.
+    pub fn draw(&mut self, terminal: &TerminalManager) {
+        let now = Instant::now();
+        if self
+            .notice
+            .as_ref()
+            .is_some_and(|(_, shown)| now.duration_since(*shown) >= NOTICE_DURATION)
+        {
+            self.notice = None;
+            self.dirty = true;
+        }
+
+        let stale = self
+            .last_drawn
+            .is_none_or(|drawn| now.duration_since(drawn) >= REDRAW_INTERVAL);
+        if !self.dirty && !stale {
+            return;
+        }
+
+        let _ = terminal.draw_status_line(&self.render());
+        self.dirty = false;
+        self.last_drawn = Some(now);
+    }
+
+    /// Renders the status bar with ANSI styling.
+    fn render(&self) -> String {
+        // Use dim/faint colors like the host (ANSI 2 = dim)
+        let dot = if self.connected {
+            "\x1b[2;32m●" // dim green
+        } else {
+            "\x1b[2;33m●" // dim yellow
+        };
+        let mut status = format!("{} klaas\x1b[0m", dot);
+
+        if let Some((notice, _)) = &self.notice {
+            // Amber to match the klaas theme
+            status.push_str(&format!(" \x1b[38;2;245;158;11m{}\x1b[0m", notice));
+        }
+
+        let segments = self.segments();
+        if !segments.is_empty() {
+            status.push_str(&format!("\x1b[2m · {}\x1b[0m", segments.join(" · ")));
+        }
+        status
+    }
+
+    /// Returns the plain-text parts of the status bar.
+    fn segments(&self) -> Vec<String> {
+        let mut segments = vec![self.session.clone()];
+        if let Some(host) = &self.host {
+            segments.push(format!("on {}", host));
+        }
+        if !self.connected {
+            segments.push("reconnecting".to_string());
+            return segments;
+        }
+        if let Some(mode) = &self.mode {
+            segments.push(mode.replace('_', " "));
+        }
+        if let Some(holder) = &self.lock_holder {
+            segments.push(format!("{} is typing", holder));
+        }
+        if self.read_only {
+            segments.push("read-only".to_string());
+        }
+        if let Some(rtt) = self.rtt {
+            segments.push(format!("{} ms", rtt.as_millis()));
+        }
+        segments
+    }
+
+    /// Sets a field, marking the status bar dirty if the value changed.
+    fn update<T: PartialEq>(&mut self, field: impl FnOnce(&mut Self) -> &mut T, value: T) {
+        let slot = field(self);
+        if *slot != value {
+            *slot = value;
+            self.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_show_session_state() {
+        let mut status = StatusBar::new("demo", false);
+        status.set_host(Some("macbook".to_string()));
+        status.set_mode("waiting_for_input");
+        status.set_lock_holder(Some("alice".to_string()));
+        status.set_rtt(Some(Duration::from_millis(42)));
+
+        assert_eq!(
+            status.segments(),
+            vec![
+                "demo",
+                "on macbook",
+                "waiting for input",
+                "alice is typing",
+                "42 ms"
+            ]
+        );
+
+        let mut status = StatusBar::new("demo", true);
+        status.set_lock_holder(Some("alice".to_string()));
+        status.set_lock_holder(None);
+        assert_eq!(status.segments(), vec!["demo", "read-only"]);
+    }
+
+    #[test]
+    fn test_reconnecting_hides_stale_state() {
+        let mut status = StatusBar::new("demo", false);
+        status.set_rtt(Some(Duration::from_millis(42)));
+        status.set_connected(false);
+
+        assert_eq!(status.segments(), vec!["demo", "reconnecting"]);
+    }
+
+    #[test]
+    fn test_updates_mark_dirty_only_on_change() {
+        let mut status = StatusBar::new("demo", false);
+        status.dirty = false;
+
+        status.set_mode("idle");
+        assert!(status.dirty);
+
+        status.dirty = false;
+        status.set_mode("idle");
+        assert!(!status.dirty);
+
+        status.notify("Input blocked: view-only mode");
+        assert!(status.dirty);
+        assert!(status.render().contains("Input blocked: view-only mode"));
+    }
+}
@@ -2,31 +2,42 @@
 //!
 //! Connects to a remote session via WebSocket and displays the terminal
 //! output. Supports receiving history, real-time output, mode changes,
-//! and sending encrypted prompts to the host.
+//! and sending encrypted input to the host, either key by key (the
+//! default) or a line at a time.
 
+use std::cmp::Ordering;
 use std::io::{self, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::tungstenite::http::header::{HeaderValue, AUTHORIZATION};
 use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use tracing::{debug, error, info, warn};
 use url::Url;
 
-use crate::config::get_api_config;
-use crate::credentials::CredentialStore;
-use crate::crypto::{
-    decrypt_content, derive_session_key, encrypt_content, EncryptedContent, SecretKey,
+use super::keys::{resolve_session_keys, SessionKeys};
+use super::recording::Recorder;
+use super::scrollback::{CopyAction, CopyMode, Scrollback};
+use super::status::StatusBar;
+use crate::api_client::ApiClient;
+use crate::auth_manager::AuthManager;
+use crate::config::{
+    get_api_config, ApiConfig, RECONNECT_BASE_DELAY_MS, RECONNECT_JITTER_MS, RECONNECT_MAX_DELAY_MS,
 };
+use crate::credentials::CredentialStore;
+use crate::crypto::{EncryptedContent, WrappedSessionKey};
 use crate::error::{CliError, Result};
+use crate::net::connect_websocket;
 use crate::terminal::TerminalManager;
 
 // ============================================================================
@@ -36,12 +47,34 @@ use crate::terminal::TerminalManager;
 /// Timeout for WebSocket receive operations (milliseconds).
 const WS_RECV_TIMEOUT_MS: u64 = 10;
 
+/// How often the guest pings the server to measure latency and check that
+/// the connection is alive.
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a ping may go unanswered before the connection is considered
+/// half-open and replaced.
+const PONG_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Options for a guest connection.
+#[derive(Debug, Clone, Default)]
+pub struct GuestOptions {
+    /// Buffer typed characters and send them as one prompt on Enter,
+    /// instead of forwarding every key as it is pressed.
+    pub line_mode: bool,
+    /// Watch the session read-only: the server is told that this guest
+    /// never sends input, and keys other than quit are ignored.
+    pub read_only: bool,
+    /// Record the session output to this file (asciicast if it ends in
+    /// `.cast`, plain text otherwise).
+    pub record: Option<PathBuf>,
+}
+
 // ============================================================================
 // Message Types for Guest Mode
 // ============================================================================
 
 /// Session information sent by server on guest connect.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
     /// Session identifier.
     pub session_id: String,
@@ -56,7 +89,7 @@ pub struct SessionInfo {
 }
 
 /// A single history entry from the server.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     /// Encrypted terminal output data.
     pub encrypted: EncryptedContent,
@@ -65,7 +98,7 @@ pub struct HistoryEntry {
 }
 
 /// Batch of session history sent by server.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryBatch {
     /// Session identifier.
     pub session_id: String,
@@ -74,7 +107,7 @@ pub struct HistoryBatch {
 }
 
 /// Mode change notification from server.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModeChange {
     /// Session identifier.
     pub session_id: String,
@@ -85,7 +118,7 @@ pub struct ModeChange {
 }
 
 /// Lock acquired notification.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockAcquired {
     /// Session identifier.
     pub session_id: String,
@@ -96,14 +129,14 @@ pub struct LockAcquired {
 }
 
 /// Lock released notification.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockReleased {
     /// Session identifier.
     pub session_id: String,
 }
 
 /// Input rejection reason.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum InputRejectionReason {
     /// Lock is held by another client.
@@ -112,10 +145,12 @@ pub enum InputRejectionReason {
     HostOnly,
     /// Host is disconnected.
     HostDetached,
+    /// Guest connected read-only or has a read-only share.
+    ReadOnly,
 }
 
 /// Input rejected notification.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputRejected {
     /// Session identifier.
     pub session_id: String,
@@ -126,7 +161,7 @@ pub struct InputRejected {
 }
 
 /// Messages received from server in guest mode.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum GuestIncomingMessage {
     /// Session information on connect.
@@ -152,6 +187,21 @@ pub enum GuestIncomingMessage {
     LockReleased(LockReleased),
     /// Input was rejected.
     InputRejected(InputRejected),
+    /// Session key moved to a new epoch (a share was revoked).
+    KeyRotated { session_id: String, key_epoch: u32 },
+    /// Host moved to a new forward-secrecy key epoch.
+    KeyEpoch {
+        session_id: String,
+        key_epoch: u32,
+        #[serde(default)]
+        reseeded: bool,
+    },
+    /// Host granted a forward-secrecy key request.
+    KeyGrant {
+        session_id: String,
+        request_id: String,
+        wrapped: WrappedSessionKey,
+    },
     /// Heartbeat ping from server.
     Ping,
     /// Error message from server.
@@ -159,7 +209,7 @@ pub enum GuestIncomingMessage {
 }
 
 /// Messages sent from guest to server.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum GuestOutgoingMessage {
     /// Encrypted prompt to send to host (serializes as "prompt" to match API).
@@ -168,6 +218,13 @@ pub enum GuestOutgoingMessage {
         session_id: String,
         encrypted: EncryptedContent,
     },
+    /// Request for the host's forward-secrecy key.
+    KeyRequest {
+        session_id: String,
+        request_id: String,
+        public_key: String,
+        proof: EncryptedContent,
+    },
     /// Heartbeat response.
     Pong,
 }
@@ -183,15 +240,26 @@ type WsSender = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
 type WsReceiver = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
 /// Guest WebSocket client for viewing remote sessions.
-struct GuestClient {
+///
+/// Answers the server's heartbeat pings while receiving, and measures the
+/// round-trip time with pings of its own.
+pub struct GuestClient {
     /// WebSocket sender (write half).
     sender: Arc<Mutex<Option<WsSender>>>,
     /// WebSocket receiver (read half).
     receiver: Arc<Mutex<Option<WsReceiver>>>,
     /// Session ID being viewed.
     session_id: String,
-    /// Session key for E2EE (derived from MEK).
-    session_key: SecretKey,
+    /// WebSocket base URL, kept for reconnecting.
+    ws_url: String,
+    /// Whether the guest connected read-only.
+    read_only: bool,
+    /// Session keys for E2EE (derived from MEK or shared with us).
+    keys: std::sync::Mutex<SessionKeys>,
+    /// When the unanswered WebSocket ping was sent.
+    ping_sent: std::sync::Mutex<Option<Instant>>,
+    /// Round-trip time of the last answered ping.
+    rtt: std::sync::Mutex<Option<Duration>>,
 }
 
 impl GuestClient {
@@ -202,76 +270,79 @@ impl GuestClient {
     /// * `ws_url` - WebSocket base URL
     /// * `access_token` - JWT access token
     /// * `session_id` - Session to connect to
-    /// * `mek` - Master Encryption Key for E2EE
-    async fn connect(
+    /// * `keys` - Session keys for E2EE
+    /// * `read_only` - Connect without permission to send input
+    pub async fn connect(
         ws_url: &str,
         access_token: &str,
         session_id: &str,
-        mek: &SecretKey,
+        keys: SessionKeys,
+        read_only: bool,
     ) -> Result<Self> {
-        // Parse and build URL with guest query parameters
-        let mut parsed_url = Url::parse(ws_url)
-            .map_err(|e| CliError::WebSocketError(format!("Invalid WebSocket URL: {}", e)))?;
-
-        // Add session_id and client=guest query parameters
-        parsed_url
-            .query_pairs_mut()
-            .append_pair("session_id", session_id)
-            .append_pair("client", "guest");
-
-        debug!(url = %parsed_url, "Connecting as guest");
-
-        // Build request with Authorization header
-        let mut request = parsed_url
-            .as_str()
-            .into_client_request()
-            .map_err(|e| CliError::WebSocketError(format!("Failed to build request: {}", e)))?;
-
-        let auth_value = HeaderValue::from_str(&format!("Bearer {}", access_token))
-            .map_err(|e| CliError::WebSocketError(format!("Invalid auth header: {}", e)))?;
-        request.headers_mut().insert(AUTHORIZATION, auth_value);
-
-        // Connect to server
-        let (ws_stream, response) = connect_async(request)
-            .await
-            .map_err(|e| CliError::WebSocketError(format!("Failed to connect: {}", e)))?;
-
-        debug!(
-            status = %response.status(),
-            "Guest WebSocket connection established"
-        );
-
-        // Split into sender and receiver
-        let (sender, receiver) = ws_stream.split();
-
-        // Derive session key from MEK
-        let session_key = derive_session_key(mek, session_id);
+        let (sender, receiver) = open_socket(ws_url, access_token, session_id, read_only).await?;
 
         Ok(Self {
             sender: Arc::new(Mutex::new(Some(sender))),
             receiver: Arc::new(Mutex::new(Some(receiver))),
             session_id: session_id.to_string(),
-            session_key,
+            ws_url: ws_url.to_string(),
+            read_only,
+            keys: std::sync::Mutex::new(keys),
+            ping_sent: std::sync::Mutex::new(None),
+            rtt: std::sync::Mutex::new(None),
         })
     }
 
+    /// Replaces a lost connection with a fresh one.
+    ///
+    /// The session keys are kept. A forward-secrecy key request sent on the
+    /// old connection can no longer be answered, so it is dropped and sent
+    /// again.
+    pub async fn reconnect(&self, access_token: &str) -> Result<()> {
+        let (sender, receiver) =
+            open_socket(&self.ws_url, access_token, &self.session_id, self.read_only).await?;
+        *self.sender.lock().await = Some(sender);
+        *self.receiver.lock().await = Some(receiver);
+        *self.ping_sent.lock().unwrap() = None;
+        *self.rtt.lock().unwrap() = None;
+
+        if self.keys.lock().unwrap().clear_pending_request() {
+            self.request_ratchet_key().await?;
+        }
+        Ok(())
+    }
+
     /// Receives the next message from the server.
-    async fn recv(&self) -> Result<Option<GuestIncomingMessage>> {
+    ///
+    /// Returns None once the connection is closed. Control frames and
+    /// messages this version does not understand are skipped. Heartbeat
+    /// pings are answered before they are returned.
+    pub async fn recv(&self) -> Result<Option<GuestIncomingMessage>> {
         let mut receiver_guard = self.receiver.lock().await;
 
         let receiver = receiver_guard
             .as_mut()
             .ok_or_else(|| CliError::WebSocketError("Not connected".to_string()))?;
 
-        match receiver.next().await {
-            Some(Ok(msg)) => self.handle_raw_message(msg).await,
-            Some(Err(e)) => {
-                debug!(error = %e, "WebSocket receive error");
-                Err(CliError::WebSocketError(format!("Receive error: {}", e)))
-            }
-            None => {
-                info!("WebSocket connection closed by server");
-                Ok(None)
+        loop {
+            match receiver.next().await {
+                Some(Ok(Message::Close(frame))) => {
+                    info!(frame = ?frame, "Received close frame");
+                    return Ok(None);
+                }
+                Some(Ok(msg)) => match self.handle_raw_message(msg).await {
+                    Ok(Some(parsed)) => return Ok(Some(parsed)),
+                    Ok(None) => {}
+                    Err(e) => warn!(error = %e, "Ignoring unreadable message"),
+                },
+                Some(Err(e)) => {
+                    debug!(error = %e, "WebSocket receive error");
+                    return Err(CliError::WebSocketError(format!("Receive error: {}", e)));
+                }
+                None => {
+                    info!("WebSocket connection closed by server");
+                    return Ok(None);
+                }
             }
         }
     }
@@ -286,6 +357,7 @@ impl GuestClient {
                     CliError::WebSocketError(format!("Failed to parse message: {}", e))
                 })?;
 
+                self.answer_ping(&parsed).await;
                 Ok(Some(parsed))
             }
             Message::Binary(data) => {
@@ -297,36 +369,88 @@ impl GuestClient {
                     CliError::WebSocketError(format!("Failed to parse binary message: {}", e))
                 })?;
 
+                self.answer_ping(&parsed).await;
                 Ok(Some(parsed))
             }
-            Message::Ping(data) => {
+            Message::Ping(_) => {
+                // tungstenite queues the pong itself and sends it with the
+                // next read or write
                 debug!("Received WebSocket ping");
-                if let Some(sender) = self.sender.lock().await.as_mut() {
-                    let _ = sender.send(Message::Pong(data)).await;
-                }
                 Ok(None)
             }
             Message::Pong(_) => {
-                debug!("Received WebSocket pong");
+                if let Some(sent) = self.ping_sent.lock().unwrap().take() {
+                    let rtt = sent.elapsed();
+                    debug!(rtt_ms = rtt.as_millis() as u64, "Received WebSocket pong");
+                    *self.rtt.lock().unwrap() = Some(rtt);
+                }
                 Ok(None)
             }
-            Message::Close(frame) => {
-                info!(frame = ?frame, "Received close frame");
-                Ok(None)
+            Message::Close(_) | Message::Frame(_) => Ok(None),
+        }
+    }
+
+    /// Answers a heartbeat ping from the server, so that it keeps the
+    /// connection open.
+    async fn answer_ping(&self, msg: &GuestIncomingMessage) {
+        if matches!(msg, GuestIncomingMessage::Ping) {
+            debug!("Received ping, sending pong");
+            if let Err(e) = self.send_pong().await {
+                warn!(error = %e, "Failed to send pong");
             }
-            Message::Frame(_) => Ok(None),
         }
     }
 
+    /// Sends a WebSocket ping to measure the round-trip time.
+    ///
+    /// Skipped while an earlier ping is unanswered, so the measurement
+    /// always belongs to the oldest outstanding ping.
+    pub async fn send_ping(&self) -> Result<()> {
+        {
+            let mut ping_sent = self.ping_sent.lock().unwrap();
+            if ping_sent.is_some() {
+                return Ok(());
+            }
+            *ping_sent = Some(Instant::now());
+        }
+
+        let mut sender_guard = self.sender.lock().await;
+        if let Some(sender) = sender_guard.as_mut() {
+            sender
+                .send(Message::Ping(Vec::new()))
+                .await
+                .map_err(|e| CliError::WebSocketError(format!("Failed to send ping: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the round-trip time of the last answered ping.
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.rtt.lock().unwrap()
+    }
+
+    /// Returns true if a ping went unanswered for longer than `timeout`,
+    /// which means the connection is half-open: the socket looks fine, but
+    /// nothing reaches the server any more.
+    pub fn is_half_open(&self, timeout: Duration) -> bool {
+        self.ping_sent
+            .lock()
+            .unwrap()
+            .is_some_and(|sent| sent.elapsed() >= timeout)
+    }
+
     /// Sends a pong response to the server.
     async fn send_pong(&self) -> Result<()> {
         let msg = GuestOutgoingMessage::Pong;
         self.send_message(&msg).await
     }
 
-    /// Sends an encrypted prompt to the host.
-    async fn send_prompt(&self, text: &str) -> Result<()> {
-        let encrypted = encrypt_content(&self.session_key, text.as_bytes());
+    /// Sends encrypted input to the host.
+    ///
+    /// Keystrokes and whole lines both travel as prompts, which the host
+    /// writes to the PTY as-is and the server subjects to the input lock.
+    pub async fn send_input(&self, data: &[u8]) -> Result<()> {
+        let encrypted = self.keys.lock().unwrap().encrypt(data)?;
 
         let msg = GuestOutgoingMessage::Prompt {
             session_id: self.session_id.clone(),
@@ -354,13 +478,147 @@ impl GuestClient {
         Ok(())
     }
 
-    /// Decrypts encrypted content using the session key.
+    /// Decrypts encrypted content using the session key of its epoch.
     fn decrypt(&self, encrypted: &EncryptedContent) -> Result<Vec<u8>> {
-        decrypt_content(&self.session_key, encrypted)
+        self.keys.lock().unwrap().decrypt(encrypted)
+    }
+
+    /// Returns true if this guest only has read-only access.
+    ///
+    /// Client-side only; see [`SessionKeys::is_read_only`] for where
+    /// read-only grants are enforced.
+    fn is_read_only(&self) -> bool {
+        self.read_only || self.keys.lock().unwrap().is_read_only()
+    }
+
+    /// Switches to a rotated session key epoch.
+    ///
+    /// For shared sessions the re-wrapped key is fetched from the API.
+    /// Fails if our access was revoked.
+    async fn rotate_key(&self, api: &ApiClient, key_epoch: u32) -> Result<()> {
+        let device_id = {
+            let mut keys = self.keys.lock().unwrap();
+            if keys.set_epoch(key_epoch) {
+                return Ok(());
+            }
+            keys.shared_device_id().map(str::to_string)
+        };
+
+        let Some(device_id) = device_id else {
+            return Ok(());
+        };
+
+        let shared = api
+            .get_shared_key(&self.session_id, &device_id)
+            .await?
+            .ok_or_else(|| CliError::AuthError("Access to this session was revoked".into()))?;
+
+        self.keys.lock().unwrap().add_shared_key(&shared)
+    }
+
+    /// Asks the host for its forward-secrecy key.
+    async fn request_ratchet_key(&self) -> Result<()> {
+        let request = self.keys.lock().unwrap().key_request()?;
+        debug!(request_id = %request.request_id, "Requesting forward-secrecy key");
+
+        let msg = GuestOutgoingMessage::KeyRequest {
+            session_id: self.session_id.clone(),
+            request_id: request.request_id,
+            public_key: request.public_key,
+            proof: request.proof,
+        };
+
+        self.send_message(&msg).await
+    }
+
+    /// Requests the forward-secrecy key unless a request is outstanding.
+    async fn ensure_ratchet_key_requested(&self) -> Result<()> {
+        if self.keys.lock().unwrap().has_pending_request() {
+            return Ok(());
+        }
+        self.request_ratchet_key().await
+    }
+
+    /// Follows a forward-secrecy epoch announced by the host.
+    async fn set_ratchet_epoch(&self, key_epoch: u32, reseeded: bool) -> Result<()> {
+        let needs_key = self
+            .keys
+            .lock()
+            .unwrap()
+            .set_ratchet_epoch(key_epoch, reseeded);
+
+        if needs_key {
+            self.request_ratchet_key().await?;
+        }
+        Ok(())
+    }
+
+    /// Accepts a forward-secrecy key granted by the host.
+    ///
+    /// Returns false if the grant was meant for another guest.
+    fn accept_key_grant(&self, request_id: &str, wrapped: &WrappedSessionKey) -> Result<bool> {
+        self.keys
+            .lock()
+            .unwrap()
+            .accept_key_grant(request_id, wrapped)
+    }
+
+    /// Decrypts session output for callers that only read it.
+    ///
+    /// Returns None if the output cannot be read. Output protected by a
+    /// forward-secrecy key we do not have yet triggers a key request, so
+    /// later output can be read.
+    pub(crate) async fn decrypt_output(
+        &self,
+        encrypted: &EncryptedContent,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.decrypt(encrypted) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if encrypted.ratchet => {
+                debug!(error = %e, "No forward-secrecy key for output");
+                self.ensure_ratchet_key_requested().await?;
+                Ok(None)
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to decrypt output");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Applies a key rotation, forward-secrecy epoch or key grant.
+    ///
+    /// Returns false if the message is not about keys.
+    pub(crate) async fn handle_key_message(
+        &self,
+        api: &ApiClient,
+        msg: &GuestIncomingMessage,
+    ) -> Result<bool> {
+        match msg {
+            GuestIncomingMessage::KeyRotated { key_epoch, .. } => {
+                self.rotate_key(api, *key_epoch).await?;
+            }
+            GuestIncomingMessage::KeyEpoch {
+                key_epoch,
+                reseeded,
+                ..
+            } => self.set_ratchet_epoch(*key_epoch, *reseeded).await?,
+            GuestIncomingMessage::KeyGrant {
+                request_id,
+                wrapped,
+                ..
+            } => {
+                if let Err(e) = self.accept_key_grant(request_id, wrapped) {
+                    warn!(error = %e, "Failed to unwrap forward-secrecy key");
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
     }
 
     /// Gracefully closes the WebSocket connection.
-    async fn close(&self) -> Result<()> {
+    pub(crate) async fn close(&self) -> Result<()> {
         let mut sender_guard = self.sender.lock().await;
         if let Some(sender) = sender_guard.as_mut() {
             let _ = sender.send(Message::Close(None)).await;
@@ -369,6 +627,59 @@ impl GuestClient {
     }
 }
 
+/// Opens a guest WebSocket connection to a session.
+///
+/// # Arguments
+///
+/// * `ws_url` - WebSocket base URL
+/// * `access_token` - JWT access token
+/// * `session_id` - Session to connect to
+/// * `read_only` - Tell the server this guest will not send input
+async fn open_socket(
+    ws_url: &str,
+    access_token: &str,
+    session_id: &str,
+    read_only: bool,
+) -> Result<(WsSender, WsReceiver)> {
+    // Parse and build URL with guest query parameters
+    let mut parsed_url = Url::parse(ws_url)
+        .map_err(|e| CliError::WebSocketError(format!("Invalid WebSocket URL: {}", e)))?;
+
+    // Add session_id and client=guest query parameters
+    parsed_url
+        .query_pairs_mut()
+        .append_pair("session_id", session_id)
+        .append_pair("client", "guest");
+    if read_only {
+        parsed_url
+            .query_pairs_mut()
+            .append_pair("read_only", "true");
+    }
+
+    debug!(url = %parsed_url, "Connecting as guest");
+
+    // Build request with Authorization header
+    let mut request = parsed_url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| CliError::WebSocketError(format!("Failed to build request: {}", e)))?;
+
+    let auth_value = HeaderValue::from_str(&format!("Bearer {}", access_token))
+        .map_err(|e| CliError::WebSocketError(format!("Invalid auth header: {}", e)))?;
+    request.headers_mut().insert(AUTHORIZATION, auth_value);
+
+    // Connect to server
+    let (ws_stream, response) = connect_websocket(request).await?;
+
+    debug!(
+        status = %response.status(),
+        "Guest WebSocket connection established"
+    );
+
+    // Split into sender and receiver
+    Ok(ws_stream.split())
+}
+
 // ============================================================================
 // Terminal Output Helpers
 // ============================================================================
@@ -381,10 +692,10 @@ fn write_to_stdout(data: &[u8]) -> Result<()> {
     Ok(())
 }
 
-/// Displays a notification message in the terminal.
+/// Displays a notification message in the terminal output.
 ///
-/// Uses ANSI escape codes to show a styled notification that appears
-/// on its own line and does not disrupt the terminal content.
+/// Only used for the final message before the guest exits; while connected,
+/// messages go to the status bar.
 fn display_notification(message: &str) -> Result<()> {
     // Save cursor, move to bottom, print notification, restore cursor
     // Using amber color (RGB 245, 158, 11) to match klaas theme
@@ -399,17 +710,23 @@ fn display_notification(message: &str) -> Result<()> {
 fn key_event_to_bytes(event: KeyEvent) -> Vec<u8> {
     match event.code {
         KeyCode::Char(c) => {
-            if event.modifiers.contains(KeyModifiers::CONTROL) {
+            let mut bytes = if event.modifiers.contains(KeyModifiers::CONTROL) {
                 // Ctrl+character (e.g., Ctrl+C = 0x03)
                 let ctrl_char = (c as u8) & 0x1f;
                 vec![ctrl_char]
             } else {
                 c.to_string().into_bytes()
+            };
+            // Alt+character is sent with an ESC prefix (e.g., Alt+b = ESC b)
+            if event.modifiers.contains(KeyModifiers::ALT) {
+                bytes.insert(0, 0x1b);
             }
+            bytes
         }
         KeyCode::Enter => vec![b'\r'],
         KeyCode::Backspace => vec![0x7f],
         KeyCode::Tab => vec![b'\t'],
+        KeyCode::BackTab => vec![0x1b, b'[', b'Z'],
         KeyCode::Esc => vec![0x1b],
         KeyCode::Up => vec![0x1b, b'[', b'A'],
         KeyCode::Down => vec![0x1b, b'[', b'B'],
@@ -440,6 +757,423 @@ fn key_event_to_bytes(event: KeyEvent) -> Vec<u8> {
     }
 }
 
+// ============================================================================
+// Input Handling
+// ============================================================================
+
+/// Turns local key and paste events into input for the host.
+///
+/// In raw mode every key is forwarded as soon as it is pressed, so arrow
+/// keys, Ctrl+C, Esc and Tab reach the agent's TUI. In line mode typed
+/// characters are buffered and sent as one prompt on Enter. When watching
+/// read-only nothing is sent. Ctrl+S opens copy mode to scroll back through
+/// the output; since guests do not capture the mouse or switch to the
+/// alternate screen, the local terminal's own scrollback works too.
+struct GuestInput {
+    /// Whether input is buffered until Enter.
+    line_mode: bool,
+    /// Whether the session is only watched.
+    read_only: bool,
+    /// Characters typed since the last Enter (line mode only).
+    buffer: String,
+}
+
+impl GuestInput {
+    /// Creates the input state for the given options.
+    fn new(options: &GuestOptions) -> Self {
+        Self {
+            line_mode: options.line_mode,
+            read_only: options.read_only,
+            buffer: String::new(),
+        }
+    }
+
+    /// Returns true if the key disconnects: Ctrl+Q, or also q and Ctrl+C
+    /// when watching, since those keys are not sent anywhere.
+    fn is_quit(&self, event: &KeyEvent) -> bool {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        match event.code {
+            KeyCode::Char('q') if ctrl => true,
+            KeyCode::Char('q') => self.read_only && event.modifiers.is_empty(),
+            KeyCode::Char('c') => self.read_only && ctrl,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the key opens copy mode: Ctrl+S, or also Page Up and
+    /// `/` when watching, since those keys are not sent anywhere.
+    fn is_copy_mode(&self, event: &KeyEvent) -> bool {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        match event.code {
+            KeyCode::Char('s') => ctrl,
+            KeyCode::PageUp | KeyCode::Char('/') => self.read_only && !ctrl,
+            _ => false,
+        }
+    }
+
+    /// Handles a key press, returning the bytes to send, if any.
+    fn key(&mut self, event: KeyEvent) -> Option<Vec<u8>> {
+        if self.read_only {
+            return None;
+        }
+        let bytes = key_event_to_bytes(event);
+        if bytes.is_empty() {
+            return None;
+        }
+        if !self.line_mode {
+            return Some(bytes);
+        }
+
+        // For Enter key, send accumulated buffer as prompt
+        if event.code == KeyCode::Enter {
+            if self.buffer.is_empty() {
+                return None;
+            }
+            self.buffer.push('\n');
+            return Some(std::mem::take(&mut self.buffer).into_bytes());
+        }
+
+        // Accumulate input
+        if let Ok(s) = String::from_utf8(bytes) {
+            self.buffer.push_str(&s);
+        }
+        None
+    }
+
+    /// Handles pasted text, returning the bytes to send, if any.
+    fn paste(&mut self, text: &str) -> Option<Vec<u8>> {
+        if self.read_only {
+            None
+        } else if self.line_mode {
+            self.buffer.push_str(text);
+            None
+        } else {
+            Some(text.as_bytes().to_vec())
+        }
+    }
+}
+
+/// Local state of a guest connection, kept across reconnects.
+struct GuestView {
+    /// Terminal in raw mode, with the bottom row reserved for the status bar.
+    terminal: TerminalManager,
+    /// Status bar contents.
+    status: StatusBar,
+    /// Input handling (raw or line mode).
+    input: GuestInput,
+    /// Last output shown, for skipping replayed history.
+    cursor: OutputCursor,
+    /// Everything shown, for copy mode.
+    scrollback: Scrollback,
+    /// Copy mode, while it is open.
+    copy: Option<CopyMode>,
+    /// Session name or ID, for naming saved output.
+    session: String,
+    /// Recording of the output, if asked for.
+    recorder: Option<Recorder>,
+}
+
+impl GuestView {
+    /// Re-reserves the status bar row after the terminal was resized.
+    fn resize(&mut self) {
+        let _ = self.terminal.set_status_bar();
+        self.status.invalidate();
+
+        let (cols, rows) = self.terminal.size().unwrap_or((80, 24));
+        let rows = rows.saturating_sub(1);
+        self.scrollback.resize(rows, cols);
+        if let Some(copy) = &mut self.copy {
+            copy.resize(rows);
+        }
+    }
+
+    /// Shows session output from `timestamp`, or only keeps it while copy
+    /// mode is open.
+    fn show_output(&mut self, data: &[u8], timestamp: &str) -> Result<()> {
+        self.record(|recorder| recorder.output(timestamp, data));
+        self.scrollback.process(data);
+        match &mut self.copy {
+            Some(copy) => {
+                copy.set_new_output();
+                Ok(())
+            }
+            None => write_to_stdout(data),
+        }
+    }
+
+    /// Writes to the recording, stopping it if that fails, e.g. because the
+    /// disk is full. The session itself goes on.
+    fn record(&mut self, write: impl FnOnce(&mut Recorder) -> Result<()>) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        if let Err(e) = write(recorder) {
+            warn!(error = %e, "Failed to write recording");
+            self.status.notify(&format!("Recording stopped: {}", e));
+            self.recorder = None;
+        }
+    }
+
+    /// Opens copy mode on a snapshot of the scrollback.
+    fn enter_copy_mode(&mut self) -> Result<()> {
+        let (_, rows) = self.terminal.size()?;
+        self.copy = Some(CopyMode::new(
+            self.scrollback.lines(),
+            rows.saturating_sub(1),
+        ));
+        // Hide the cursor and stop long lines from wrapping into the next row
+        write_to_stdout(b"\x1b[?25l\x1b[?7l")
+    }
+
+    /// Closes copy mode and redraws the live screen, including output that
+    /// arrived meanwhile.
+    fn exit_copy_mode(&mut self) -> Result<()> {
+        if self.copy.take().is_some() {
+            write_to_stdout(b"\x1b[?7h")?;
+            write_to_stdout(&self.scrollback.screen_formatted())?;
+            self.status.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Handles a key press in copy mode.
+    fn copy_mode_key(&mut self, event: KeyEvent) -> Result<()> {
+        let Some(copy) = &mut self.copy else {
+            return Ok(());
+        };
+        match copy.key(event) {
+            CopyAction::Continue => {}
+            CopyAction::Exit => self.exit_copy_mode()?,
+            CopyAction::Reload => copy.reload(self.scrollback.lines()),
+            CopyAction::Save => {
+                let file = saved_output_path(&self.session, chrono::Local::now());
+                let path = std::env::current_dir()
+                    .map(|dir| dir.join(&file))
+                    .unwrap_or_else(|_| file.into());
+                match std::fs::write(&path, copy.visible_text()) {
+                    Ok(()) => copy.notify(&format!("Saved to {}", path.display())),
+                    Err(e) => copy.notify(&format!("Could not save {}: {}", path.display(), e)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws copy mode in place of the session output, if it changed.
+    fn draw_copy_mode(&mut self) -> Result<()> {
+        let Some(copy) = &mut self.copy else {
+            return Ok(());
+        };
+        if !copy.take_dirty() {
+            return Ok(());
+        }
+
+        let (cols, _) = self.terminal.size()?;
+        let mut frame = String::new();
+        for (row, line) in copy.render(cols).iter().enumerate() {
+            frame.push_str(&format!("\x1b[{};1H\x1b[2K{}", row + 1, line));
+        }
+        write_to_stdout(frame.as_bytes())?;
+        self.terminal.draw_status_line(&copy.status_line())
+    }
+}
+
+/// Returns the file name for saved session output, e.g.
+/// `klaas-my-session-20260101-120000.txt`.
+fn saved_output_path(session: &str, now: chrono::DateTime<chrono::Local>) -> String {
+    let session: String = session
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("klaas-{}-{}.txt", session, now.format("%Y%m%d-%H%M%S"))
+}
+
+// ============================================================================
+// Reconnection
+// ============================================================================
+
+/// Why the event loop stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopExit {
+    /// The user disconnected.
+    Quit,
+    /// The session ended or can no longer be shown.
+    SessionEnded,
+    /// The connection was lost.
+    Disconnected,
+}
+
+/// Returns true if an error means the connection was lost, so the guest
+/// should reconnect instead of exiting.
+///
+/// Only authentication failures and local terminal errors end the guest.
+pub fn is_disconnect(error: &CliError) -> bool {
+    !matches!(
+        error,
+        CliError::AuthError(_) | CliError::NotAuthenticated(_) | CliError::TerminalError(_)
+    )
+}
+
+/// How a guest connection ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestExit {
+    /// The user disconnected.
+    Quit,
+    /// The session ended, e.g. because the host detached.
+    SessionEnded,
+}
+
+/// Position of the last output shown, so that history replayed after a
+/// reconnect does not show the same output twice.
+#[derive(Debug, Default)]
+struct OutputCursor {
+    /// Timestamp of the last output shown.
+    timestamp: Option<String>,
+    /// How many outputs with that timestamp were shown.
+    count: usize,
+}
+
+impl OutputCursor {
+    /// Records that an output with this timestamp was shown.
+    fn advance(&mut self, timestamp: &str) {
+        let same = self
+            .timestamp
+            .as_deref()
+            .is_some_and(|last| compare_timestamps(timestamp, last) == Ordering::Equal);
+        if same {
+            self.count += 1;
+        } else {
+            self.timestamp = Some(timestamp.to_string());
+            self.count = 1;
+        }
+    }
+
+    /// Returns the history entries that have not been shown yet.
+    fn unseen<'a>(&self, entries: &'a [HistoryEntry]) -> &'a [HistoryEntry] {
+        let Some(last) = &self.timestamp else {
+            return entries;
+        };
+
+        let mut seen_at_last = 0;
+        for (i, entry) in entries.iter().enumerate() {
+            match compare_timestamps(&entry.timestamp, last) {
+                Ordering::Less => {}
+                Ordering::Equal if seen_at_last < self.count => seen_at_last += 1,
+                _ => return &entries[i..],
+            }
+        }
+        &[]
+    }
+}
+
+/// Compares two server timestamps, falling back to comparing the strings
+/// if either is not RFC 3339.
+fn compare_timestamps(a: &str, b: &str) -> Ordering {
+    match (
+        chrono::DateTime::parse_from_rfc3339(a),
+        chrono::DateTime::parse_from_rfc3339(b),
+    ) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Returns the delay before reconnection attempt `attempt` (starting at 1):
+/// exponential backoff, capped, plus random jitter so that guests dropped
+/// together do not all reconnect at once.
+fn reconnect_delay(attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let base = (RECONNECT_BASE_DELAY_MS << exponent).min(RECONNECT_MAX_DELAY_MS);
+    let jitter = rand::thread_rng().gen_range(0..RECONNECT_JITTER_MS);
+    Duration::from_millis(base + jitter)
+}
+
+/// Waits for `delay` while watching for the quit key.
+///
+/// Returns true if the user asked to disconnect. Other keys are dropped,
+/// since there is no connection to send them to.
+async fn wait_unless_quit(view: &mut GuestView, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        while let Ok(Some(event)) = view.terminal.poll_event(Duration::from_millis(0)) {
+            match event {
+                Event::Key(key_event) if view.input.is_quit(&key_event) => return true,
+                Event::Resize(..) => view.resize(),
+                _ => {}
+            }
+        }
+        view.status.draw(&view.terminal);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+/// Reconnects a guest after its connection was lost.
+///
+/// Retries with backoff until it succeeds or the user presses Ctrl+Q. If
+/// the server rejects the access token, a refreshed token is tried before
+/// giving up with the authentication error.
+///
+/// # Returns
+///
+/// Ok(true) once reconnected, Ok(false) if the user disconnected.
+async fn reconnect(
+    client: &GuestClient,
+    config: &ApiConfig,
+    view: &mut GuestView,
+    access_token: &mut String,
+) -> Result<bool> {
+    view.exit_copy_mode()?;
+    view.status.set_connected(false);
+    view.status.set_rtt(None);
+    view.status.notify("Connection lost (Ctrl+Q to disconnect)");
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let delay = reconnect_delay(attempt);
+        debug!(
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            "Reconnecting as guest"
+        );
+        if wait_unless_quit(view, delay).await {
+            return Ok(false);
+        }
+
+        match client.reconnect(access_token).await {
+            Ok(()) => {
+                info!(attempt, "Guest reconnected");
+                view.status.set_connected(true);
+                view.status.notify("Reconnected");
+                return Ok(true);
+            }
+            Err(CliError::AuthError(reason)) => {
+                // The access token may have expired while we were away
+                let token = match AuthManager::for_current_profile(&config.api_url) {
+                    Ok(auth) => auth.valid_token().await,
+                    Err(e) => Err(e),
+                };
+                match token {
+                    Ok(token) if token != *access_token => *access_token = token,
+                    Ok(_) => return Err(CliError::AuthError(reason)),
+                    Err(e @ (CliError::AuthError(_) | CliError::NotAuthenticated(_))) => {
+                        return Err(e)
+                    }
+                    Err(e) => debug!(error = %e, "Failed to refresh access token"),
+                }
+            }
+            Err(e) => debug!(error = %e, attempt, "Reconnection attempt failed"),
+        }
+    }
+}
+
 // ============================================================================
 // Main Run Function
 // ============================================================================
@@ -452,21 +1186,20 @@ fn key_event_to_bytes(event: KeyEvent) -> Vec<u8> {
 /// # Arguments
 ///
 /// * `session_id` - The session ID to connect to as a guest
+/// * `options` - How input is sent to the host
 ///
 /// # Returns
 ///
-/// Ok(()) on successful disconnection, or an error if something goes wrong.
-pub async fn run(session_id: &str) -> Result<()> {
-    // Get credentials from keychain
-    let cred_store = CredentialStore::new();
-
-    // Get access token
-    let (access_token, _refresh_token) = cred_store.get_tokens()?.ok_or_else(|| {
-        CliError::AuthError("Not authenticated. Run 'klaas' first to log in.".into())
-    })?;
+/// How the connection ended, or an error if something goes wrong.
+pub async fn run(session_id: &str, options: &GuestOptions) -> Result<GuestExit> {
+    // Get a valid access token (refreshed if it has expired)
+    let config = get_api_config();
+    let access_token = AuthManager::for_current_profile(&config.api_url)?
+        .valid_token()
+        .await?;
 
     // Delegate to run_with_token
-    run_with_token(session_id, &access_token).await
+    run_with_token(session_id, &access_token, options).await
 }
 
 /// Runs the guest terminal mode with a provided access token.
@@ -478,54 +1211,91 @@ pub async fn run(session_id: &str) -> Result<()> {
 ///
 /// * `session_id` - The session ID to connect to as a guest
 /// * `access_token` - Valid access token for authentication
+/// * `options` - How input is sent to the host
 ///
 /// # Returns
 ///
-/// Ok(()) on successful disconnection, or an error if something goes wrong.
-pub async fn run_with_token(session_id: &str, access_token: &str) -> Result<()> {
+/// How the connection ended, or an error if something goes wrong.
+pub async fn run_with_token(
+    session_id: &str,
+    access_token: &str,
+    options: &GuestOptions,
+) -> Result<GuestExit> {
     let config = get_api_config();
     info!(ws_url = %config.ws_url, session_id = %session_id, "Starting guest mode");
 
-    // Get or create MEK for E2EE (auto-generated on first use)
+    // Resolve session keys: shared with this device, or derived from our MEK
     let cred_store = CredentialStore::new();
-    let mek = match cred_store.get_mek()? {
-        Some(mek_bytes) => {
-            debug!("Retrieved existing MEK for E2EE");
-            let mut arr = [0u8; 32];
-            arr.copy_from_slice(&mek_bytes);
-            SecretKey::from_bytes(arr)
-        }
-        None => {
-            // Auto-generate MEK if not found (enables E2EE for this device)
-            let mek = SecretKey::random();
-            cred_store.store_mek(mek.as_bytes())?;
-            info!("Generated new MEK for E2EE");
-            mek
-        }
-    };
+    let mut api = ApiClient::new(&config.api_url, access_token);
+    let keys = resolve_session_keys(&api, &cred_store, session_id).await?;
 
     // Connect to WebSocket as guest
-    let client = GuestClient::connect(config.ws_url, access_token, session_id, &mek).await?;
+    let client = GuestClient::connect(
+        &config.ws_url,
+        access_token,
+        session_id,
+        keys,
+        options.read_only,
+    )
+    .await?;
     info!("Connected to session as guest");
 
-    // Set up terminal in raw mode
+    // Show the session name in the status bar if it has one
+    let session_name = match api.get_session(session_id).await {
+        Ok(Some(session)) => session.name,
+        Ok(None) => None,
+        Err(e) => {
+            debug!(error = %e, "Failed to look up session name");
+            None
+        }
+    };
+    let session = session_name.unwrap_or_else(|| session_id.to_string());
+    let status = StatusBar::new(&session, client.is_read_only());
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, &session)?),
+        None => None,
+    };
+
+    // Set up terminal in raw mode, with the bottom row for the status bar
     let mut terminal = TerminalManager::new()?;
     terminal.enter_raw_mode()?;
-
-    // Display initial notification
-    display_notification(&format!(
-        "Connected to session {}. Press Ctrl+Q to disconnect.",
-        session_id
-    ))?;
-
-    // Input buffer for accumulating typed characters before sending
-    let mut input_buffer = String::new();
-
-    // Main event loop
-    let result = run_event_loop(&client, &mut terminal, &mut input_buffer).await;
+    let _ = terminal.set_status_bar();
+    let (cols, rows) = terminal.size()?;
+
+    let mut view = GuestView {
+        terminal,
+        status,
+        input: GuestInput::new(options),
+        cursor: OutputCursor::default(),
+        scrollback: Scrollback::new(rows.saturating_sub(1), cols),
+        copy: None,
+        session,
+        recorder,
+    };
+    view.status.notify(if options.read_only {
+        "Watching. Press q to stop."
+    } else {
+        "Connected. Press Ctrl+Q to disconnect."
+    });
+
+    // Main event loop, reconnecting whenever the connection is lost
+    let mut access_token = access_token.to_string();
+    let result = loop {
+        match run_event_loop(&client, &api, &mut view).await {
+            Ok(LoopExit::Disconnected) => {}
+            Ok(LoopExit::Quit) => break Ok(GuestExit::Quit),
+            Ok(LoopExit::SessionEnded) => break Ok(GuestExit::SessionEnded),
+            Err(e) => break Err(e),
+        }
+        match reconnect(&client, &config, &mut view, &mut access_token).await {
+            Ok(true) => api = ApiClient::new(&config.api_url, &access_token),
+            Ok(false) => break Ok(GuestExit::Quit),
+            Err(e) => break Err(e),
+        }
+    };
 
     // Clean up
-    terminal.exit_raw_mode()?;
+    view.terminal.exit_raw_mode()?;
 
     // Close WebSocket connection
     if let Err(e) = client.close().await {
@@ -538,11 +1308,15 @@ pub async fn run_with_token(session_id: &str, access_token: &str) -> Result<()>
 }
 
 /// Main event loop for guest mode.
+///
+/// Runs until the user disconnects, the session ends, or the connection is
+/// lost.
 async fn run_event_loop(
     client: &GuestClient,
-    terminal: &mut TerminalManager,
-    input_buffer: &mut String,
-) -> Result<()> {
+    api: &ApiClient,
+    view: &mut GuestView,
+) -> Result<LoopExit> {
+    let mut ping_interval = tokio::time::interval(PING_INTERVAL);
     loop {
         tokio::select! {
             // Try to receive a WebSocket message with timeout
@@ -553,21 +1327,23 @@ async fn run_event_loop(
                 ).await
             } => {
                 match recv_result {
-                    Ok(Ok(Some(msg))) => {
-                        if !handle_incoming_message(client, msg)? {
-                            // Session detached, exit loop
-                            break;
+                    Ok(Ok(Some(msg))) => match handle_incoming_message(client, api, view, msg).await {
+                        Ok(true) => {}
+                        // Session detached, exit loop
+                        Ok(false) => return Ok(LoopExit::SessionEnded),
+                        Err(e) if is_disconnect(&e) => {
+                            warn!(error = %e, "Connection failed");
+                            return Ok(LoopExit::Disconnected);
                         }
-                    }
+                        Err(e) => return Err(e),
+                    },
                     Ok(Ok(None)) => {
                         // Connection closed
-                        display_notification("Connection closed by server")?;
-                        break;
+                        return Ok(LoopExit::Disconnected);
                     }
                     Ok(Err(e)) => {
-                        error!(error = %e, "WebSocket error");
-                        display_notification(&format!("Connection error: {}", e))?;
-                        break;
+                        warn!(error = %e, "WebSocket error");
+                        return Ok(LoopExit::Disconnected);
                     }
                     Err(_) => {
                         // Timeout - continue to check for input
@@ -575,58 +1351,89 @@ async fn run_event_loop(
                 }
             }
 
+            // Measure latency and detect half-open connections
+            _ = ping_interval.tick() => {
+                if client.is_half_open(PONG_TIMEOUT) {
+                    warn!("No pong from server, reconnecting");
+                    return Ok(LoopExit::Disconnected);
+                }
+                if let Err(e) = client.send_ping().await {
+                    debug!(error = %e, "Failed to send ping");
+                }
+            }
+
             // Poll for keyboard input
             _ = tokio::time::sleep(Duration::from_millis(10)) => {
-                while let Ok(Some(event)) = terminal.poll_event(Duration::from_millis(0)) {
+                while let Ok(Some(event)) = view.terminal.poll_event(Duration::from_millis(0)) {
                     match event {
-                        Event::Key(key_event) => {
-                            // Check for Ctrl+Q to disconnect
-                            if key_event.modifiers.contains(KeyModifiers::CONTROL)
-                                && key_event.code == KeyCode::Char('q')
-                            {
-                                display_notification("Disconnecting...")?;
-                                return Ok(());
+                        Event::Key(key_event) if key_event.kind != KeyEventKind::Release => {
+                            // Copy mode takes every key except Ctrl+Q
+                            if view.copy.is_some() {
+                                if is_ctrl_q(&key_event) {
+                                    return Ok(LoopExit::Quit);
+                                }
+                                view.copy_mode_key(key_event)?;
+                                continue;
                             }
 
-                            // Convert key event to bytes
-                            let bytes = key_event_to_bytes(key_event);
-                            if !bytes.is_empty() {
-                                // For Enter key, send accumulated buffer as prompt
-                                if key_event.code == KeyCode::Enter {
-                                    if !input_buffer.is_empty() {
-                                        // Add newline and send
-                                        input_buffer.push('\n');
-                                        if let Err(e) = client.send_prompt(input_buffer).await {
-                                            warn!(error = %e, "Failed to send prompt");
-                                        }
-                                        input_buffer.clear();
-                                    }
-                                } else {
-                                    // Accumulate input
-                                    if let Ok(s) = String::from_utf8(bytes.clone()) {
-                                        input_buffer.push_str(&s);
-                                    }
-                                }
+                            // Check for Ctrl+Q (or q when watching) to disconnect
+                            if view.input.is_quit(&key_event) {
+                                return Ok(LoopExit::Quit);
+                            }
+
+                            if view.input.is_copy_mode(&key_event) {
+                                view.enter_copy_mode()?;
+                            } else if let Some(data) = view.input.key(key_event) {
+                                send_input(client, view, &data).await;
                             }
                         }
+                        Event::Paste(_) if view.copy.is_some() => {}
                         Event::Paste(text) => {
-                            // Add pasted text to buffer
-                            input_buffer.push_str(&text);
+                            if let Some(data) = view.input.paste(&text) {
+                                send_input(client, view, &data).await;
+                            }
                         }
+                        Event::Resize(..) => view.resize(),
                         _ => {}
                     }
                 }
+                view.status.set_rtt(client.rtt());
+                if view.copy.is_some() {
+                    view.draw_copy_mode()?;
+                } else {
+                    view.status.draw(&view.terminal);
+                }
             }
         }
     }
+}
 
-    Ok(())
+/// Returns true for Ctrl+Q, which always disconnects.
+fn is_ctrl_q(event: &KeyEvent) -> bool {
+    event.code == KeyCode::Char('q') && event.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Sends input to the host, unless this guest only has read-only access.
+async fn send_input(client: &GuestClient, view: &mut GuestView, data: &[u8]) {
+    if client.is_read_only() {
+        view.status.notify("Read-only access: input is not sent");
+        return;
+    }
+
+    if let Err(e) = client.send_input(data).await {
+        warn!(error = %e, "Failed to send input");
+    }
 }
 
 /// Handles an incoming message from the server.
 ///
 /// Returns true to continue the event loop, false to exit.
-fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> Result<bool> {
+async fn handle_incoming_message(
+    client: &GuestClient,
+    api: &ApiClient,
+    view: &mut GuestView,
+    msg: GuestIncomingMessage,
+) -> Result<bool> {
     match msg {
         GuestIncomingMessage::SessionInfo(info) => {
             debug!(
@@ -638,19 +1445,8 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
                 "Received session info"
             );
 
-            // Display session info notification
-            let device_info = info
-                .device_name
-                .as_ref()
-                .map(|d| format!(" from {}", d))
-                .unwrap_or_default();
-            display_notification(&format!(
-                "Session {}x{}{} {}",
-                info.cols,
-                info.rows,
-                device_info,
-                info.cwd.as_deref().unwrap_or("")
-            ))?;
+            view.status.set_host(info.device_name);
+            view.record(|recorder| recorder.resize(info.cols, info.rows));
         }
 
         GuestIncomingMessage::History(batch) => {
@@ -660,24 +1456,50 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
                 "Received history batch"
             );
 
-            // Decrypt and display each history entry
-            for entry in &batch.entries {
+            // Decrypt and display each history entry, skipping output
+            // already shown before a reconnect
+            let mut unreadable = 0;
+            for entry in view.cursor.unseen(&batch.entries) {
+                view.cursor.advance(&entry.timestamp);
                 match client.decrypt(&entry.encrypted) {
                     Ok(data) => {
-                        write_to_stdout(&data)?;
+                        view.show_output(&data, &entry.timestamp)?;
+                    }
+                    Err(e) if entry.encrypted.ratchet => {
+                        debug!(error = %e, "History entry is forward-secret");
+                        unreadable += 1;
                     }
                     Err(e) => {
                         warn!(error = %e, "Failed to decrypt history entry");
                     }
                 }
             }
+
+            // Keys of earlier forward-secrecy epochs are gone by design
+            if unreadable > 0 {
+                view.status.notify(&format!(
+                    "{} earlier output entries are protected by forward secrecy",
+                    unreadable
+                ));
+                client.ensure_ratchet_key_requested().await?;
+            }
         }
 
-        GuestIncomingMessage::Output { encrypted, .. } => {
+        GuestIncomingMessage::Output {
+            encrypted,
+            timestamp,
+            ..
+        } => {
             // Decrypt and display output
+            view.cursor.advance(&timestamp);
             match client.decrypt(&encrypted) {
                 Ok(data) => {
-                    write_to_stdout(&data)?;
+                    view.show_output(&data, &timestamp)?;
+                }
+                Err(e) if encrypted.ratchet => {
+                    // Joined a forward-secret session: ask the host for its key
+                    debug!(error = %e, "No forward-secrecy key for output");
+                    client.ensure_ratchet_key_requested().await?;
                 }
                 Err(e) => {
                     warn!(error = %e, "Failed to decrypt output");
@@ -692,12 +1514,12 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
                 holder_name = %lock.holder_name,
                 "Lock acquired"
             );
-            display_notification(&format!("{} is now typing", lock.holder_name))?;
+            view.status.set_lock_holder(Some(lock.holder_name));
         }
 
         GuestIncomingMessage::LockReleased(lock) => {
             debug!(session_id = %lock.session_id, "Lock released");
-            display_notification("Input lock released - ready for input")?;
+            view.status.set_lock_holder(None);
         }
 
         GuestIncomingMessage::InputRejected(rejection) => {
@@ -716,8 +1538,9 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
                 InputRejectionReason::HostDetached => {
                     "Input blocked: host disconnected".to_string()
                 }
+                InputRejectionReason::ReadOnly => "Input blocked: read-only connection".to_string(),
             };
-            display_notification(&message)?;
+            view.status.notify(&message);
         }
 
         GuestIncomingMessage::ModeChange(mode_change) => {
@@ -728,13 +1551,10 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
                 "Mode change"
             );
 
-            // Display mode change notification
-            let message = mode_change
-                .message
-                .as_ref()
-                .map(|m| format!(": {}", m))
-                .unwrap_or_default();
-            display_notification(&format!("Mode: {}{}", mode_change.mode, message))?;
+            view.status.set_mode(&mode_change.mode);
+            if let Some(message) = &mode_change.message {
+                view.status.notify(message);
+            }
         }
 
         GuestIncomingMessage::SessionDetached { reason, .. } => {
@@ -746,21 +1566,41 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
             return Ok(false);
         }
 
+        GuestIncomingMessage::KeyRotated { key_epoch, .. } => {
+            debug!(key_epoch, "Session key rotated");
+            if let Err(e) = client.rotate_key(api, key_epoch).await {
+                display_notification(&format!("Session key rotated: {}", e))?;
+                return Ok(false);
+            }
+        }
+
+        GuestIncomingMessage::KeyEpoch {
+            key_epoch,
+            reseeded,
+            ..
+        } => {
+            debug!(key_epoch, reseeded, "Forward-secrecy key epoch");
+            client.set_ratchet_epoch(key_epoch, reseeded).await?;
+        }
+
+        GuestIncomingMessage::KeyGrant {
+            request_id,
+            wrapped,
+            ..
+        } => match client.accept_key_grant(&request_id, &wrapped) {
+            Ok(true) => debug!(key_epoch = wrapped.epoch, "Received forward-secrecy key"),
+            Ok(false) => debug!(request_id = %request_id, "Ignoring key grant for another guest"),
+            Err(e) => warn!(error = %e, "Failed to unwrap forward-secrecy key"),
+        },
+
         GuestIncomingMessage::Ping => {
-            debug!("Received ping, sending pong");
-            // Fire and forget - we do not want to block on this
-            let client_ref = client;
-            tokio::spawn(async move {
-                // Note: This is a workaround since we cannot easily call
-                // async from sync context. The actual pong is sent via
-                // the WebSocket ping/pong mechanism in handle_raw_message.
-                let _ = client_ref;
-            });
+            // Already answered by the client
         }
 
         GuestIncomingMessage::Error { code, message } => {
             error!(code = %code, message = %message, "Server error");
-            display_notification(&format!("Error [{}]: {}", code, message))?;
+            view.status
+                .notify(&format!("Error [{}]: {}", code, message));
         }
     }
 
@@ -774,6 +1614,7 @@ fn handle_incoming_message(client: &GuestClient, msg: GuestIncomingMessage) -> R
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn test_session_info_deserialization() {
@@ -903,6 +1744,8 @@ mod tests {
             nonce: "dGVzdG5vbmNlMTIz".to_string(),
             ciphertext: "ZW5jcnlwdGVkZGF0YQ==".to_string(),
             tag: "dGFnMTIzNDU2Nzg5MDEy".to_string(),
+            epoch: None,
+            ratchet: false,
         };
 
         let msg = GuestOutgoingMessage::Prompt {
@@ -1052,4 +1895,222 @@ mod tests {
         let left = KeyEvent::new(KeyCode::Left, KeyModifiers::empty());
         assert_eq!(key_event_to_bytes(left), vec![0x1b, b'[', b'D']);
     }
+
+    #[test]
+    fn test_key_event_to_bytes_alt_and_back_tab() {
+        let alt_b = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::ALT);
+        assert_eq!(key_event_to_bytes(alt_b), vec![0x1b, b'b']);
+
+        let back_tab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT);
+        assert_eq!(key_event_to_bytes(back_tab), vec![0x1b, b'[', b'Z']);
+    }
+
+    #[test]
+    fn test_raw_input_forwards_every_key() {
+        let mut input = GuestInput::new(&GuestOptions::default());
+
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(input.key(ctrl_c), Some(vec![0x03]));
+
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::empty());
+        assert_eq!(input.key(esc), Some(vec![0x1b]));
+
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
+        assert_eq!(input.key(enter), Some(vec![b'\r']));
+
+        let unknown = KeyEvent::new(KeyCode::CapsLock, KeyModifiers::empty());
+        assert_eq!(input.key(unknown), None);
+
+        assert_eq!(input.paste("ls -la"), Some(b"ls -la".to_vec()));
+    }
+
+    #[test]
+    fn test_line_input_sends_on_enter() {
+        let mut input = GuestInput::new(&GuestOptions {
+            line_mode: true,
+            ..Default::default()
+        });
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
+
+        // Enter on an empty line sends nothing
+        assert_eq!(input.key(enter), None);
+
+        for c in "hi".chars() {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty());
+            assert_eq!(input.key(key), None);
+        }
+        assert_eq!(input.paste(" there"), None);
+
+        assert_eq!(input.key(enter), Some(b"hi there\n".to_vec()));
+        assert!(input.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_read_only_input_only_quits() {
+        let options = GuestOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let mut input = GuestInput::new(&options);
+
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty());
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        let ctrl_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(input.is_quit(&q));
+        assert!(input.is_quit(&ctrl_c));
+        assert!(input.is_quit(&ctrl_q));
+
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
+        assert!(!input.is_quit(&enter));
+        assert_eq!(input.key(enter), None);
+        assert_eq!(input.paste("rm -rf /"), None);
+
+        // Interactive guests only quit on Ctrl+Q
+        let input = GuestInput::new(&GuestOptions::default());
+        assert!(input.is_quit(&ctrl_q));
+        assert!(!input.is_quit(&q));
+        assert!(!input.is_quit(&ctrl_c));
+    }
+
+    #[test]
+    fn test_copy_mode_keys() {
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let slash = KeyEvent::new(KeyCode::Char('/'), KeyModifiers::empty());
+        let page_up = KeyEvent::new(KeyCode::PageUp, KeyModifiers::empty());
+
+        // Interactive guests keep / and Page Up for the agent
+        let input = GuestInput::new(&GuestOptions::default());
+        assert!(input.is_copy_mode(&ctrl_s));
+        assert!(!input.is_copy_mode(&slash));
+        assert!(!input.is_copy_mode(&page_up));
+
+        let input = GuestInput::new(&GuestOptions {
+            read_only: true,
+            ..Default::default()
+        });
+        assert!(input.is_copy_mode(&ctrl_s));
+        assert!(input.is_copy_mode(&slash));
+        assert!(input.is_copy_mode(&page_up));
+    }
+
+    #[test]
+    fn test_saved_output_path() {
+        let now = chrono::Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        assert_eq!(
+            saved_output_path("fix tests/ci", now),
+            "klaas-fix-tests-ci-20260102-030405.txt"
+        );
+    }
+
+    #[test]
+    fn test_key_rotated_deserialization() {
+        let json = r#"{
+            "type": "key_rotated",
+            "session_id": "01HQXK7V8G3N5M2R4P6T1W9Y0Z",
+            "key_epoch": 3
+        }"#;
+
+        let msg: GuestIncomingMessage = serde_json::from_str(json).unwrap();
+        match msg {
+            GuestIncomingMessage::KeyRotated { key_epoch, .. } => assert_eq!(key_epoch, 3),
+            _ => panic!("Expected KeyRotated message"),
+        }
+    }
+
+    #[test]
+    fn test_key_epoch_deserialization() {
+        let json = r#"{
+            "type": "key_epoch",
+            "session_id": "01HQXK7V8G3N5M2R4P6T1W9Y0Z",
+            "key_epoch": 7
+        }"#;
+
+        let msg: GuestIncomingMessage = serde_json::from_str(json).unwrap();
+        match msg {
+            GuestIncomingMessage::KeyEpoch {
+                key_epoch,
+                reseeded,
+                ..
+            } => {
+                assert_eq!(key_epoch, 7);
+                assert!(!reseeded);
+            }
+            _ => panic!("Expected KeyEpoch message"),
+        }
+    }
+
+    /// Builds history entries with the given timestamps.
+    fn history(timestamps: &[&str]) -> Vec<HistoryEntry> {
+        timestamps
+            .iter()
+            .map(|timestamp| HistoryEntry {
+                encrypted: EncryptedContent {
+                    v: 1,
+                    nonce: String::new(),
+                    ciphertext: String::new(),
+                    tag: String::new(),
+                    epoch: None,
+                    ratchet: false,
+                },
+                timestamp: timestamp.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_output_cursor_skips_replayed_history() {
+        let entries = history(&[
+            "2026-01-01T10:00:00Z",
+            "2026-01-01T10:00:01Z",
+            "2026-01-01T10:00:01Z",
+            "2026-01-01T10:00:02.500Z",
+        ]);
+
+        // Nothing shown yet: everything is new
+        let mut cursor = OutputCursor::default();
+        assert_eq!(cursor.unseen(&entries).len(), 4);
+
+        // Saw the first two entries before the connection dropped
+        cursor.advance("2026-01-01T10:00:00Z");
+        cursor.advance("2026-01-01T10:00:01Z");
+        let unseen = cursor.unseen(&entries);
+        assert_eq!(unseen.len(), 2);
+        assert_eq!(unseen[0].timestamp, "2026-01-01T10:00:01Z");
+
+        // Timestamps are compared as times, not strings
+        cursor.advance("2026-01-01T11:00:01+01:00");
+        assert_eq!(cursor.unseen(&entries).len(), 1);
+
+        cursor.advance("2026-01-01T10:00:02.500Z");
+        assert!(cursor.unseen(&entries).is_empty());
+    }
+
+    #[test]
+    fn test_reconnect_delay_backs_off_with_jitter() {
+        for attempt in 1..=20 {
+            let base =
+                (RECONNECT_BASE_DELAY_MS << (attempt - 1).min(16)).min(RECONNECT_MAX_DELAY_MS);
+            let delay = reconnect_delay(attempt).as_millis() as u64;
+            assert!(delay >= base && delay < base + RECONNECT_JITTER_MS);
+        }
+        assert!(
+            reconnect_delay(30)
+                < Duration::from_millis(RECONNECT_MAX_DELAY_MS + RECONNECT_JITTER_MS)
+        );
+    }
+
+    #[test]
+    fn test_only_auth_and_terminal_errors_end_the_guest() {
+        assert!(is_disconnect(&CliError::WebSocketError(
+            "Failed to send".into()
+        )));
+        assert!(is_disconnect(&CliError::NetworkError("timed out".into())));
+        assert!(!is_disconnect(&CliError::AuthError("revoked".into())));
+        assert!(!is_disconnect(&CliError::NotAuthenticated(
+            "no token".into()
+        )));
+        assert!(!is_disconnect(&CliError::TerminalError(
+            std::io::Error::other("closed")
+        )));
+    }
 }
@@ -15,12 +15,13 @@ use std::io::{self, Read, Write};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error};
 
+use crate::config::get_api_config;
+pub use crate::config::ENV_API_URL;
+use crate::net::http_client;
+
 /// Environment variable for session ID.
 pub const ENV_SESSION_ID: &str = "KLAAS_SESSION_ID";
 
-/// Environment variable for API URL.
-pub const ENV_API_URL: &str = "KLAAS_API_URL";
-
 /// Environment variable for hook authentication token.
 pub const ENV_HOOK_TOKEN: &str = "KLAAS_HOOK_TOKEN";
 
@@ -71,13 +72,8 @@ pub async fn handle_hook(event: &str) -> Result<(), String> {
         "Error: This command must be called by an agent CLI running inside klaas.".to_string()
     })?;
 
-    let api_url = env::var(ENV_API_URL).unwrap_or_else(|_| {
-        if cfg!(debug_assertions) {
-            "http://localhost:8787".to_string()
-        } else {
-            "https://api.klaas.sh".to_string()
-        }
-    });
+    // The host sets KLAAS_API_URL to the server the session runs on
+    let api_url = get_api_config().api_url;
 
     let hook_token = env::var(ENV_HOOK_TOKEN).ok();
 
@@ -183,7 +179,7 @@ async fn send_notification(
     hook_token: Option<&str>,
     payload: &NotificationPayload,
 ) -> Result<(), reqwest::Error> {
-    let client = reqwest::Client::new();
+    let client = http_client();
 
     let url = format!("{}/v1/hooks/notification", api_url);
 
@@ -3,9 +3,11 @@
 //! This module exposes the CLI internals for testing purposes.
 
 pub mod agents;
+pub mod ansi;
 pub mod api_client;
 pub mod app;
 pub mod auth;
+pub mod auth_manager;
 pub mod commands;
 pub mod config;
 pub mod credentials;
@@ -13,8 +15,13 @@ pub mod crypto;
 pub mod error;
 pub mod guest;
 pub mod hook;
+pub mod net;
+pub mod profile;
 pub mod pty;
+pub mod redact;
+pub mod relay;
 pub mod terminal;
+pub mod tls;
 pub mod types;
 pub mod ui;
 pub mod websocket;
@@ -26,9 +26,11 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod agents;
 mod analytics;
+mod ansi;
 mod api_client;
 mod app;
 mod auth;
+mod auth_manager;
 mod commands;
 mod config;
 mod credentials;
@@ -36,8 +38,13 @@ mod crypto;
 mod error;
 mod guest;
 mod hook;
+mod net;
+mod profile;
 mod pty;
+mod redact;
+mod relay;
 mod terminal;
+mod tls;
 mod types;
 mod ui;
 mod update;
@@ -83,6 +90,16 @@ struct Cli {
     #[arg(short = 'q', long = "qr")]
     qr: bool,
 
+    /// Run without a local terminal (e.g. under systemd or in Docker).
+    /// Enabled automatically when stdin or stdout is not a terminal.
+    #[arg(long)]
+    headless: bool,
+
+    /// Credential profile to use (e.g. to switch between accounts).
+    /// Overrides `KLAAS_PROFILE` and the active profile.
+    #[arg(long = "profile", value_name = "NAME", global = true)]
+    profile: Option<String>,
+
     /// Arguments to pass through to the agent.
     /// All unrecognized arguments are forwarded.
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
@@ -100,8 +117,21 @@ enum Commands {
         /// Session ID (ULID) or session name. If omitted, shows interactive list.
         #[arg(value_name = "SESSION")]
         session: Option<String>,
+
+        /// Send input a line at a time on Enter instead of forwarding
+        /// every key as it is pressed.
+        #[arg(long)]
+        line: bool,
+
+        /// Record the session output to a file: asciicast if it ends in
+        /// .cast, plain text otherwise.
+        #[arg(long, value_name = "FILE")]
+        record: Option<std::path::PathBuf>,
     },
 
+    /// Full-screen dashboard of all sessions with live previews.
+    Dash,
+
     /// Handle hook events from agents (internal use).
     /// Called by agent CLIs when hooks fire, not by users directly.
     Hook {
@@ -110,9 +140,125 @@ enum Commands {
         event: String,
     },
 
+    /// Log in to klaas (runs the device flow in your browser).
+    Login {
+        /// Log in again even if this device already has credentials.
+        #[arg(long)]
+        force: bool,
+
+        /// Only import the encryption key from a recovery key file
+        /// (`-` reads from stdin).
+        #[arg(long, value_name = "FILE", conflicts_with = "force")]
+        import_key: Option<std::path::PathBuf>,
+    },
+
+    /// Log out and revoke this device's session.
+    Logout {
+        /// Also remove the encryption key from this device.
+        #[arg(long)]
+        forget_key: bool,
+    },
+
+    /// Print a session's output to stdout, for scripts and pipes.
+    Logs {
+        /// Session ID (ULID) or session name.
+        #[arg(value_name = "SESSION")]
+        session: String,
+
+        /// Only print output from this long ago or later (e.g. 30s, 10m, 2h, 1d).
+        #[arg(long, value_name = "DURATION", value_parser = commands::logs::parse_since)]
+        since: Option<std::time::Duration>,
+
+        /// Keep printing live output until the session ends.
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Print plain text without colors and other escape sequences.
+        #[arg(long)]
+        strip_ansi: bool,
+    },
+
+    /// Manage credential profiles for multiple accounts.
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+
+    /// Run a local relay server, so hosts and guests on this machine or
+    /// network can connect without the klaas cloud.
+    Relay {
+        /// Address to listen on (use 0.0.0.0:7777 to accept LAN connections).
+        #[arg(long, value_name = "ADDR", default_value = relay::DEFAULT_LISTEN)]
+        listen: std::net::SocketAddr,
+
+        /// Token clients must use as their access token
+        /// (default: `KLAAS_RELAY_TOKEN`, or a generated one that is printed).
+        #[arg(long, value_name = "TOKEN")]
+        token: Option<String>,
+    },
+
+    /// Send input to a running session, e.g. from a script or CI job.
+    Send {
+        /// Session ID (ULID) or session name.
+        #[arg(value_name = "SESSION")]
+        session: String,
+
+        /// Text to send.
+        #[arg(value_name = "TEXT", conflicts_with = "file")]
+        text: Option<String>,
+
+        /// Read the text to send from a file (`-` reads from stdin).
+        #[arg(long, value_name = "FILE")]
+        file: Option<std::path::PathBuf>,
+
+        /// Press Enter after the text, submitting the prompt.
+        #[arg(long)]
+        enter: bool,
+
+        /// Wait until the agent is idle or waiting for input again.
+        #[arg(long)]
+        wait_idle: bool,
+
+        /// Give up waiting for the agent after this long, e.g. 90s or 2h
+        /// (default: 30m).
+        #[arg(
+            long,
+            value_name = "DURATION",
+            requires = "wait_idle",
+            value_parser = commands::logs::parse_since
+        )]
+        timeout: Option<std::time::Duration>,
+    },
+
     /// List available sessions with interactive selection.
     Sessions,
 
+    /// Share a session with another klaas user.
+    ///
+    /// The session key is wrapped to the invitee's devices, so they can
+    /// join with `klaas connect` without access to your encryption key.
+    Share {
+        /// Session ID (ULID) or session name.
+        #[arg(value_name = "SESSION")]
+        session: String,
+
+        /// User to share the session with (username or email).
+        #[arg(long, value_name = "USER", conflicts_with_all = ["revoke", "list"])]
+        with: Option<String>,
+
+        /// Grant read-only access (default is read-write).
+        #[arg(long, requires = "with")]
+        read_only: bool,
+
+        /// Revoke a user's access and rotate the session key.
+        #[arg(long, value_name = "USER", conflicts_with = "list")]
+        revoke: Option<String>,
+
+        /// List users the session is shared with.
+        #[arg(long)]
+        list: bool,
+    },
+
     /// Uninstall klaas from this system.
     Uninstall {
         /// Remove all user data (credentials and config) without prompting.
@@ -123,6 +269,46 @@ enum Commands {
     /// Upgrade klaas to the latest version.
     #[command(alias = "update")]
     Upgrade,
+
+    /// Watch a session read-only, without sending any input.
+    Watch {
+        /// Session ID (ULID) or session name. Required unless --follow is given.
+        #[arg(value_name = "SESSION", required_unless_present = "follow")]
+        session: Option<String>,
+
+        /// Keep watching the newest attached session started on this device
+        /// or in the current directory, moving on when it ends.
+        #[arg(long)]
+        follow: bool,
+    },
+
+    /// Show the logged-in account and credential details.
+    Whoami,
+}
+
+/// Profile subcommands.
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List known profiles.
+    List,
+
+    /// Make a profile the active one.
+    Use {
+        /// Profile name.
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+
+    /// Remove a profile and its credentials.
+    Remove {
+        /// Profile name.
+        #[arg(value_name = "NAME")]
+        name: String,
+
+        /// Don't ask for confirmation.
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
 }
 
 #[tokio::main]
@@ -165,12 +351,27 @@ async fn run_cli() -> i32 {
     // might render auth screens runs.
     ui::set_qr_enabled(cli.qr);
 
+    // Select the credential profile before any credentials are read.
+    if let Some(ref name) = cli.profile {
+        if let Err(e) = profile::set_profile_override(name) {
+            eprintln!("Error: {}", e);
+            return 2;
+        }
+    }
+
     // Handle --version flag
     if cli.version {
         println!("klaas {}", VERSION);
         return 0;
     }
 
+    // Fail early on a broken [tls] or [network] section, which would make
+    // every connection fail later
+    if let Err(e) = tls::check_config().and_then(|()| net::check_config()) {
+        eprintln!("Error: {}", e);
+        return e.exit_code();
+    }
+
     // Handle subcommands
     if let Some(ref command) = cli.command {
         return match command {
@@ -178,11 +379,29 @@ async fn run_cli() -> i32 {
                 list_agents();
                 0
             }
-            Commands::Connect { session } => match commands::connect::run(session.clone()).await {
+            Commands::Connect {
+                session,
+                line,
+                record,
+            } => {
+                let options = guest::GuestOptions {
+                    line_mode: *line,
+                    record: record.clone(),
+                    ..Default::default()
+                };
+                match commands::connect::run(session.clone(), &options).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
+            Commands::Dash => match commands::dash::run().await {
                 Ok(()) => 0,
                 Err(e) => {
                     eprintln!("Error: {}", e);
-                    1
+                    e.exit_code()
                 }
             },
             Commands::Hook { event } => match hook::handle_hook(event).await {
@@ -192,14 +411,105 @@ async fn run_cli() -> i32 {
                     1
                 }
             },
+            Commands::Login { force, import_key } => {
+                match commands::login::run(*force, import_key.as_deref()).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
+            Commands::Logout { forget_key } => match commands::logout::run(*forget_key).await {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    e.exit_code()
+                }
+            },
+            Commands::Logs {
+                session,
+                since,
+                follow,
+                strip_ansi,
+            } => {
+                let options = commands::logs::LogsOptions {
+                    since: *since,
+                    follow: *follow,
+                    strip_ansi: *strip_ansi,
+                };
+                match commands::logs::run(session, &options).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
+            Commands::Profile { action } => {
+                use commands::profile::ProfileAction;
+
+                let action = match action {
+                    ProfileCommand::List => ProfileAction::List,
+                    ProfileCommand::Use { name } => ProfileAction::Use { name: name.clone() },
+                    ProfileCommand::Remove { name, yes } => ProfileAction::Remove {
+                        name: name.clone(),
+                        yes: *yes,
+                    },
+                };
+
+                match commands::profile::run(action) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
+            Commands::Relay { listen, token } => match relay::run(*listen, token.clone()).await {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    e.exit_code()
+                }
+            },
+            Commands::Send {
+                session,
+                text,
+                file,
+                enter,
+                wait_idle,
+                timeout,
+            } => {
+                let options = commands::send::SendOptions {
+                    text: text.clone(),
+                    file: file.clone(),
+                    enter: *enter,
+                    wait_idle: *wait_idle,
+                    timeout: *timeout,
+                };
+                match commands::send::run(session, &options).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
             Commands::Sessions => match commands::sessions::run().await {
                 Ok(commands::sessions::SessionsResult::Selected(session_id, access_token)) => {
                     // User selected a session - connect directly (already authed)
-                    match commands::connect::run_direct(&session_id, &access_token).await {
+                    match commands::connect::run_direct(
+                        &session_id,
+                        &access_token,
+                        &guest::GuestOptions::default(),
+                    )
+                    .await
+                    {
                         Ok(()) => 0,
                         Err(e) => {
                             eprintln!("Error: {}", e);
-                            1
+                            e.exit_code()
                         }
                     }
                 }
@@ -210,9 +520,44 @@ async fn run_cli() -> i32 {
                 Ok(commands::sessions::SessionsResult::Cancelled) => 0,
                 Err(e) => {
                     eprintln!("Error: {}", e);
-                    1
+                    e.exit_code()
                 }
             },
+            Commands::Share {
+                session,
+                with,
+                read_only,
+                revoke,
+                list,
+            } => {
+                use commands::share::ShareAction;
+                use types::ShareAccess;
+
+                let action = match (with, revoke, list) {
+                    (Some(user), _, _) => ShareAction::Grant {
+                        user: user.clone(),
+                        access: if *read_only {
+                            ShareAccess::ReadOnly
+                        } else {
+                            ShareAccess::default()
+                        },
+                    },
+                    (None, Some(user), _) => ShareAction::Revoke { user: user.clone() },
+                    (None, None, true) => ShareAction::List,
+                    (None, None, false) => {
+                        eprintln!("Error: specify --with <USER>, --revoke <USER> or --list");
+                        return 2;
+                    }
+                };
+
+                match commands::share::run(session, action).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
             Commands::Uninstall { purge } => match perform_uninstall(*purge).await {
                 Ok(()) => 0,
                 Err(e) => {
@@ -227,6 +572,22 @@ async fn run_cli() -> i32 {
                     1
                 }
             },
+            Commands::Watch { session, follow } => {
+                match commands::watch::run(session.clone(), *follow).await {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        e.exit_code()
+                    }
+                }
+            }
+            Commands::Whoami => match commands::whoami::run().await {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    e.exit_code()
+                }
+            },
         };
     }
 
@@ -236,6 +597,11 @@ async fn run_cli() -> i32 {
 
 /// Runs the main session flow: auto-update, agent selection, and app execution.
 async fn run_main_flow(cli: &Cli) -> i32 {
+    use std::io::IsTerminal;
+
+    let headless =
+        cli.headless || !(std::io::stdin().is_terminal() && std::io::stdout().is_terminal());
+
     // Auto-update if a new version is available
     // This checks the cache first (updated every 24h) and only downloads if needed
     if update::auto_update_if_available().await {
@@ -244,21 +610,22 @@ async fn run_main_flow(cli: &Cli) -> i32 {
     }
 
     // Display startup banner and hide cursor during startup
-    ui::display_startup_banner();
-    ui::hide_cursor();
+    if !headless {
+        ui::display_startup_banner();
+        ui::hide_cursor();
+    }
 
     // Select agent to run
-    let selected_agent = match select_agent(cli) {
-        agents::AgentSelection::Selected(agent) => {
-            ui::show_cursor();
-            agent
-        }
+    let selection = select_agent(cli, headless);
+    if !headless {
+        ui::show_cursor();
+    }
+    let selected_agent = match selection {
+        agents::AgentSelection::Selected(agent) => agent,
         agents::AgentSelection::Cancelled => {
-            ui::show_cursor();
             return 0;
         }
         agents::AgentSelection::NoneInstalled => {
-            ui::show_cursor();
             eprintln!("Error: No shell or AI agents available.");
             eprintln!();
             eprintln!("Set the SHELL environment variable or install an agent:");
@@ -288,13 +655,14 @@ async fn run_main_flow(cli: &Cli) -> i32 {
         cli.agent_args.clone(),
         cli.resume,
         cli.name.clone(),
+        headless,
     )
     .await
     {
         Ok(code) => code,
         Err(e) => {
             eprintln!("Error: {}", e);
-            1
+            e.exit_code()
         }
     }
 }
@@ -310,7 +678,10 @@ fn is_valid_session_name(name: &str) -> bool {
 }
 
 /// Selects an agent based on CLI flags and installed agents.
-fn select_agent(cli: &Cli) -> agents::AgentSelection {
+///
+/// In headless mode there is no interactive selection: with several agents
+/// installed, `--agent` or `default_agent` must pick one.
+fn select_agent(cli: &Cli, headless: bool) -> agents::AgentSelection {
     use agents::{AgentRegistry, AgentSelection};
     use config::{load_config, KlaasConfig};
 
@@ -392,6 +763,14 @@ fn select_agent(cli: &Cli) -> agents::AgentSelection {
                 }
             }
 
+            if headless {
+                eprintln!(
+                    "Error: Several agents are installed and there is no terminal to choose."
+                );
+                eprintln!("Pass --agent or set default_agent in config.toml.");
+                std::process::exit(1);
+            }
+
             // Multiple agents - show interactive selection
             let refs: Vec<&agents::Agent> = installed.iter().collect();
             ui::select_agent(&refs)
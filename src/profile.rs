@@ -0,0 +1,289 @@
+//! Named credential profiles.
+//!
+//! A profile is a separate set of credentials (tokens, device ID, MEK), so
+//! that one machine can be logged in to several klaas accounts, e.g. a
+//! personal and a work account. The `default` profile uses the original,
+//! un-namespaced credential names, so existing installations keep working.
+//!
+//! The profile is selected, in order of precedence, by:
+//! 1. The `--profile` flag
+//! 2. The `KLAAS_PROFILE` environment variable
+//! 3. The `profile` key in the config file
+//! 4. The active profile set with `klaas profile use`
+//! 5. `default`
+//!
+//! Keychains cannot be enumerated, so known profiles, the active one and the
+//! storage namespaces each profile has credentials under are tracked in
+//! `~/.klaas/profiles.json`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::config::load_config;
+use crate::credentials::lock_exclusive;
+use crate::error::{CliError, Result};
+
+/// Name of the default profile.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Environment variable selecting the profile.
+pub const ENV_PROFILE: &str = "KLAAS_PROFILE";
+
+/// Maximum length of a profile name.
+const MAX_PROFILE_NAME_LEN: usize = 32;
+
+/// Profile registry file name.
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Profile selected with `--profile`, set once at startup.
+static PROFILE_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Profile resolved for this process.
+static CURRENT_PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Known profiles and the active one, stored in `~/.klaas/profiles.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProfileRegistry {
+    /// Profile set with `klaas profile use`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// Profiles that have been used on this machine.
+    #[serde(default)]
+    pub profiles: BTreeSet<String>,
+    /// Storage namespaces with credentials, e.g. `work@klaas.corp.example`
+    /// for a profile used with a self-hosted server.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub namespaces: BTreeSet<String>,
+}
+
+impl ProfileRegistry {
+    /// Loads the registry, returning an empty one if it does not exist.
+    pub fn load() -> Self {
+        match registry_path() {
+            Some(path) => Self::load_from(&path),
+            None => Self::default(),
+        }
+    }
+
+    /// Loads the registry from a specific path.
+    pub fn load_from(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "Failed to parse profiles file");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Applies `modify` to the registry while holding its lock.
+    pub fn update<F>(modify: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self),
+    {
+        let path = registry_path()
+            .ok_or_else(|| CliError::Other("Could not determine home directory".into()))?;
+        Self::update_at(&path, modify)
+    }
+
+    /// Applies `modify` to the registry at `path` while holding its lock,
+    /// so concurrent klaas processes never lose each other's updates.
+    pub fn update_at<F>(path: &Path, modify: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self),
+    {
+        let _lock = lock_exclusive(path)?;
+        let mut registry = Self::load_from(path);
+        modify(&mut registry);
+        registry.save_to(path)
+    }
+
+    /// Writes the registry to a specific path.
+    ///
+    /// The file is replaced atomically, so readers that don't take the lock
+    /// never see a partially written file.
+    fn save_to(&self, path: &Path) -> Result<()> {
+        let parent = path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| CliError::Other(format!("Failed to serialize profiles: {}", e)))?;
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        tmp.write_all(content.as_bytes())?;
+        tmp.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+/// Sets the profile selected with `--profile`.
+///
+/// Must be called before the first credential access.
+pub fn set_profile_override(name: &str) -> Result<()> {
+    validate_profile_name(name)?;
+    if PROFILE_OVERRIDE.set(name.to_string()).is_err() {
+        warn!("Profile override set twice, keeping the first one");
+    }
+    Ok(())
+}
+
+/// Returns the profile used by this process.
+pub fn current_profile() -> &'static str {
+    CURRENT_PROFILE.get_or_init(|| {
+        let profile = resolve_profile(
+            PROFILE_OVERRIDE.get().cloned(),
+            std::env::var(ENV_PROFILE).ok(),
+            load_config().profile,
+            ProfileRegistry::load().active,
+        );
+        debug!(profile = %profile, "Using credential profile");
+        profile
+    })
+}
+
+/// Picks the profile from the sources in order of precedence.
+///
+/// Invalid names are skipped with a warning.
+fn resolve_profile(
+    flag: Option<String>,
+    env: Option<String>,
+    config: Option<String>,
+    active: Option<String>,
+) -> String {
+    [flag, env, config, active]
+        .into_iter()
+        .flatten()
+        .find(|name| match validate_profile_name(name) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(profile = %name, error = %e, "Ignoring invalid profile");
+                false
+            }
+        })
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// Checks that a profile name is non-empty, short and only uses
+/// alphanumerics, `-` and `_`.
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_PROFILE_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(CliError::Other(format!(
+            "Invalid profile name '{}': use up to {} letters, digits, '-' or '_'",
+            name, MAX_PROFILE_NAME_LEN
+        )))
+    }
+}
+
+/// Records in the registry at `path` that a profile has credentials under
+/// `namespace`.
+pub fn register_profile(path: &Path, name: &str, namespace: &str) {
+    let registry = ProfileRegistry::load_from(path);
+    if registry.profiles.contains(name) && registry.namespaces.contains(namespace) {
+        return;
+    }
+
+    let result = ProfileRegistry::update_at(path, |registry| {
+        registry.profiles.insert(name.to_string());
+        registry.namespaces.insert(namespace.to_string());
+    });
+    if let Err(e) = result {
+        debug!(error = %e, "Failed to register profile");
+    }
+}
+
+/// Returns the profile registry path (`~/.klaas/profiles.json`).
+fn registry_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".klaas").join(PROFILES_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn test_resolve_profile_precedence() {
+        assert_eq!(
+            resolve_profile(some("flag"), some("env"), some("cfg"), some("act")),
+            "flag"
+        );
+        assert_eq!(
+            resolve_profile(None, some("env"), some("cfg"), some("act")),
+            "env"
+        );
+        assert_eq!(resolve_profile(None, None, some("cfg"), some("act")), "cfg");
+        assert_eq!(resolve_profile(None, None, None, some("act")), "act");
+        assert_eq!(resolve_profile(None, None, None, None), DEFAULT_PROFILE);
+    }
+
+    #[test]
+    fn test_resolve_profile_skips_invalid_names() {
+        assert_eq!(
+            resolve_profile(None, some("../etc"), some("work"), None),
+            "work"
+        );
+    }
+
+    #[test]
+    fn test_validate_profile_name() {
+        assert!(validate_profile_name("work").is_ok());
+        assert!(validate_profile_name("my_work-2").is_ok());
+        assert!(validate_profile_name("").is_err());
+        assert!(validate_profile_name("a/b").is_err());
+        assert!(validate_profile_name(&"x".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn test_registry_serialization() {
+        let registry: ProfileRegistry =
+            serde_json::from_str(r#"{"active":"work","profiles":["work","default"]}"#).unwrap();
+        assert_eq!(registry.active.as_deref(), Some("work"));
+        assert!(registry.profiles.contains("default"));
+
+        let empty: ProfileRegistry = serde_json::from_str("{}").unwrap();
+        assert!(empty.active.is_none());
+        assert!(empty.namespaces.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), r#"{"profiles":[]}"#);
+    }
+
+    #[test]
+    fn test_parallel_registrations_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PROFILES_FILE);
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let name = format!("p{}", i);
+                    register_profile(&path, &name, &format!("{}@host", name));
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let registry = ProfileRegistry::load_from(&path);
+        for i in 0..8 {
+            assert!(registry.profiles.contains(&format!("p{}", i)));
+            assert!(registry.namespaces.contains(&format!("p{}@host", i)));
+        }
+    }
+}
@@ -51,10 +51,48 @@ impl PtyManager {
         args: &[String],
         env_vars: HashMap<String, String>,
     ) -> Result<Self> {
-        let pty_system = native_pty_system();
-
         // Get terminal size or use defaults
-        let size = get_terminal_size();
+        Self::spawn_with_pty_size(command, args, env_vars, get_terminal_size())
+    }
+
+    /// Spawns a command in a new PTY of a fixed size.
+    ///
+    /// Used when there is no local terminal to take the size from
+    /// (headless mode).
+    ///
+    /// # Arguments
+    /// * `command` - The command to execute (e.g., "claude", "gemini")
+    /// * `args` - Arguments to pass to the command
+    /// * `env_vars` - Additional environment variables to set
+    /// * `cols` - PTY width
+    /// * `rows` - PTY height
+    ///
+    /// # Returns
+    /// A PtyManager instance managing the spawned process.
+    pub fn spawn_with_size(
+        command: &str,
+        args: &[String],
+        env_vars: HashMap<String, String>,
+        cols: u16,
+        rows: u16,
+    ) -> Result<Self> {
+        let size = PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        Self::spawn_with_pty_size(command, args, env_vars, size)
+    }
+
+    /// Spawns a command in a new PTY of the given size.
+    fn spawn_with_pty_size(
+        command: &str,
+        args: &[String],
+        env_vars: HashMap<String, String>,
+        size: PtySize,
+    ) -> Result<Self> {
+        let pty_system = native_pty_system();
 
         // Create PTY pair
         let pair = pty_system
@@ -130,6 +168,15 @@ impl PtyManager {
             .map_err(|e| CliError::PtyError(format!("Read failed: {}", e)))
     }
 
+    /// Kills the child process.
+    pub async fn kill(&self) -> Result<()> {
+        self.child
+            .lock()
+            .await
+            .kill()
+            .map_err(|e| CliError::PtyError(format!("Kill failed: {}", e)))
+    }
+
     /// Resizes the PTY to new dimensions.
     pub async fn resize(&self, cols: u16, rows: u16) -> Result<()> {
         let master = self.master.lock().await;
@@ -190,6 +237,20 @@ mod tests {
         assert!(output.contains("hello"));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_with_size() {
+        let pty =
+            PtyManager::spawn_with_size("stty", &["size".to_string()], HashMap::new(), 100, 30)
+                .expect("Failed to spawn PTY");
+
+        let mut buf = [0u8; 1024];
+        let n = pty.read_blocking(&mut buf).expect("Failed to read");
+
+        let output = String::from_utf8_lossy(&buf[..n]);
+        assert!(output.contains("30 100"), "unexpected output: {:?}", output);
+    }
+
     #[test]
     fn test_terminal_size() {
         let size = get_terminal_size();
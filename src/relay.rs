@@ -0,0 +1,1437 @@
+//! Self-contained relay server (`klaas relay`).
+//!
+//! Runs a minimal SessionHub-compatible server for hosts and guests on the
+//! same machine or network, with no cloud involved:
+//! - WebSocket fan-out of host output to guests, with in-memory history
+//! - Input lock arbitration according to the host's `InputMode`
+//! - Just enough of the REST API (`/sessions`) for `klaas sessions` and
+//!   `klaas connect`
+//!
+//! WebSocket and REST requests share one port: requests for `/ws` are
+//! upgraded, everything else is answered as plain HTTP.
+//!
+//! Output and input are end-to-end encrypted with the MEK before they reach
+//! the relay, so it only ever handles ciphertext. There are no accounts:
+//! clients authenticate with the relay token as their access token. Without
+//! a configured token, the relay generates one and prints it. A session
+//! belongs to the device that created it: another host can't take it over,
+//! and device IDs are left out of the REST API so they can't be learned
+//! from it.
+//!
+//! Every connection has a bounded outgoing queue; a client that doesn't
+//! keep up is disconnected instead of buffering without limit.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+use crate::api_client::Session;
+use crate::auth_manager::ENV_TOKEN;
+use crate::config::{DEFAULT_TERMINAL_COLS, DEFAULT_TERMINAL_ROWS, ENV_API_URL};
+use crate::error::{CliError, Result};
+use crate::guest::terminal::{
+    GuestIncomingMessage, GuestOutgoingMessage, HistoryBatch, HistoryEntry, InputRejected,
+    InputRejectionReason, LockAcquired, LockReleased, SessionInfo,
+};
+use crate::types::{InputConfig, InputMode};
+use crate::websocket::{IncomingMessage, OutgoingMessage};
+
+/// Default listen address: this machine only.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7777";
+
+/// Environment variable with the token clients must present.
+pub const ENV_RELAY_TOKEN: &str = "KLAAS_RELAY_TOKEN";
+
+/// Output messages kept per session for guests that join later.
+const MAX_HISTORY_ENTRIES: usize = 1000;
+
+/// Messages queued per connection before the client counts as too slow.
+const CONNECTION_QUEUE_SIZE: usize = 256;
+
+/// Length in bytes of a generated relay token.
+const GENERATED_TOKEN_LEN: usize = 16;
+
+/// Largest WebSocket message accepted from a client.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Largest HTTP request head or body accepted.
+const MAX_HTTP_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Time a client gets to send its request line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often expired locks and abandoned sessions are cleaned up.
+const SWEEP_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often clients are pinged to keep idle connections open.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a detached session without guests is kept for its history.
+const DETACHED_SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+// ============================================================================
+// State
+// ============================================================================
+
+/// Role of a WebSocket connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Host,
+    Guest,
+}
+
+/// An open WebSocket connection.
+struct Connection {
+    role: Role,
+    session_id: String,
+    /// Shown to other clients as the lock holder.
+    name: String,
+    /// Whether a guest connected read-only and may not send input.
+    read_only: bool,
+    /// Messages to send, written to the socket by the connection task.
+    tx: mpsc::Sender<Message>,
+    /// Makes the connection task close the connection.
+    kick: Arc<Notify>,
+}
+
+/// Input lock held by a guest in auto-lock mode.
+struct InputLock {
+    holder: u64,
+    name: String,
+    last_input: Instant,
+}
+
+/// Outcome of arbitrating a guest's input.
+#[derive(Debug, PartialEq)]
+enum InputDecision {
+    /// Forward the input to the host; `acquired` if the guest just took the
+    /// lock.
+    Forward { acquired: bool },
+    /// Reject the input, with the lock holder's name for `LockHeld`.
+    Reject(InputRejectionReason, Option<String>),
+}
+
+/// Why a WebSocket connection was refused.
+#[derive(Debug, PartialEq)]
+enum RegisterError {
+    /// A guest asked for a session the relay doesn't know.
+    SessionNotFound(String),
+    /// A host may not attach to the session.
+    Forbidden(String),
+}
+
+impl RegisterError {
+    /// Error code and message sent to the client.
+    fn code_and_message(self) -> (&'static str, String) {
+        match self {
+            RegisterError::SessionNotFound(message) => ("session_not_found", message),
+            RegisterError::Forbidden(message) => ("forbidden", message),
+        }
+    }
+}
+
+/// A session as known to the relay.
+struct RelaySession {
+    /// Device ID of the host that created the session. Only that device may
+    /// attach as its host.
+    device_id: String,
+    device_name: String,
+    cwd: String,
+    name: Option<String>,
+    started_at: String,
+    attached_at: Option<String>,
+    /// Connection of the attached host.
+    host: Option<u64>,
+    /// When the host went away, for expiring the session.
+    detached_since: Option<Instant>,
+    input_config: InputConfig,
+    history: VecDeque<HistoryEntry>,
+    lock: Option<InputLock>,
+}
+
+impl RelaySession {
+    /// Decides whether input from a guest reaches the host.
+    fn arbitrate(&mut self, conn_id: u64, name: &str, now: Instant) -> InputDecision {
+        if self.host.is_none() {
+            return InputDecision::Reject(InputRejectionReason::HostDetached, None);
+        }
+
+        match self.input_config.mode {
+            InputMode::HostOnly => InputDecision::Reject(InputRejectionReason::HostOnly, None),
+            InputMode::FreeForAll => InputDecision::Forward { acquired: false },
+            InputMode::AutoLock => {
+                let idle_timeout = Duration::from_millis(self.input_config.idle_timeout_ms);
+                match &mut self.lock {
+                    Some(lock) if lock.holder == conn_id => {
+                        lock.last_input = now;
+                        InputDecision::Forward { acquired: false }
+                    }
+                    Some(lock) if now.duration_since(lock.last_input) < idle_timeout => {
+                        InputDecision::Reject(
+                            InputRejectionReason::LockHeld,
+                            Some(lock.name.clone()),
+                        )
+                    }
+                    _ => {
+                        self.lock = Some(InputLock {
+                            holder: conn_id,
+                            name: name.to_string(),
+                            last_input: now,
+                        });
+                        InputDecision::Forward { acquired: true }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Releases the lock once its holder has been idle for the timeout.
+    ///
+    /// Returns true if a lock was released.
+    fn expire_lock(&mut self, now: Instant) -> bool {
+        let idle_timeout = Duration::from_millis(self.input_config.idle_timeout_ms);
+        let expired = self
+            .lock
+            .as_ref()
+            .is_some_and(|lock| now.duration_since(lock.last_input) >= idle_timeout);
+        if expired {
+            self.lock = None;
+        }
+        expired
+    }
+
+    /// Returns the session in the REST API format.
+    ///
+    /// The device ID is left out, as it is what a host attaches with.
+    fn to_api(&self, session_id: &str) -> Session {
+        Session {
+            session_id: session_id.to_string(),
+            device_id: String::new(),
+            device_name: self.device_name.clone(),
+            name: self.name.clone(),
+            status: if self.host.is_some() {
+                "attached"
+            } else {
+                "detached"
+            }
+            .to_string(),
+            started_at: self.started_at.clone(),
+            attached_at: self.attached_at.clone(),
+            cwd: self.cwd.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RelayState {
+    next_id: u64,
+    sessions: HashMap<String, RelaySession>,
+    connections: HashMap<u64, Connection>,
+    /// Forward-secrecy key requests: request ID -> guest connection.
+    key_requests: HashMap<String, u64>,
+}
+
+impl RelayState {
+    fn send<T: Serialize>(&self, conn_id: u64, msg: &T) {
+        let Some(conn) = self.connections.get(&conn_id) else {
+            return;
+        };
+        let json = match serde_json::to_string(msg) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!(error = %e, "Failed to serialize relay message");
+                return;
+            }
+        };
+        match conn.tx.try_send(Message::Text(json)) {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(conn_id, "Client is not keeping up, disconnecting");
+                conn.kick.notify_one();
+            }
+        }
+    }
+
+    fn send_to_guests(&self, session_id: &str, msg: &GuestIncomingMessage) {
+        for (id, conn) in &self.connections {
+            if conn.role == Role::Guest && conn.session_id == session_id {
+                self.send(*id, msg);
+            }
+        }
+    }
+
+    fn has_guests(&self, session_id: &str) -> bool {
+        self.connections
+            .values()
+            .any(|conn| conn.role == Role::Guest && conn.session_id == session_id)
+    }
+
+    /// Tells the host and guests that the lock changed hands.
+    fn announce_lock(&self, session_id: &str, acquired: Option<(u64, &str)>) {
+        let host = self.sessions.get(session_id).and_then(|s| s.host);
+        match acquired {
+            Some((holder, name)) => {
+                let lock = LockAcquired {
+                    session_id: session_id.to_string(),
+                    holder_id: holder.to_string(),
+                    holder_name: name.to_string(),
+                };
+                if let Some(host) = host {
+                    let msg = IncomingMessage::LockAcquired {
+                        session_id: lock.session_id.clone(),
+                        holder_id: lock.holder_id.clone(),
+                        holder_name: lock.holder_name.clone(),
+                    };
+                    self.send(host, &msg);
+                }
+                self.send_to_guests(session_id, &GuestIncomingMessage::LockAcquired(lock));
+            }
+            None => {
+                if let Some(host) = host {
+                    let msg = IncomingMessage::LockReleased {
+                        session_id: session_id.to_string(),
+                    };
+                    self.send(host, &msg);
+                }
+                let msg = GuestIncomingMessage::LockReleased(LockReleased {
+                    session_id: session_id.to_string(),
+                });
+                self.send_to_guests(session_id, &msg);
+            }
+        }
+    }
+
+    /// Removes a closed connection. A host that disappears without
+    /// detaching may reconnect, so guests are not told.
+    fn disconnect(&mut self, conn_id: u64) {
+        let Some(conn) = self.connections.remove(&conn_id) else {
+            return;
+        };
+        self.key_requests.retain(|_, id| *id != conn_id);
+
+        let Some(session) = self.sessions.get_mut(&conn.session_id) else {
+            return;
+        };
+        if session.host == Some(conn_id) {
+            info!(session_id = %conn.session_id, "Host disconnected");
+            session.host = None;
+            session.detached_since = Some(Instant::now());
+        }
+        if session.lock.as_ref().map(|lock| lock.holder) == Some(conn_id) {
+            session.lock = None;
+            self.announce_lock(&conn.session_id, None);
+        }
+    }
+}
+
+/// The relay: configuration and shared state.
+pub struct Relay {
+    /// Token clients must present.
+    token: String,
+    state: Mutex<RelayState>,
+}
+
+impl Relay {
+    /// Creates a relay that requires `token` from clients.
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            state: Mutex::new(RelayState::default()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, RelayState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Checks an `Authorization` header against the relay token.
+    fn is_authorized(&self, header: Option<&str>) -> bool {
+        let presented = header
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        !self.token.is_empty() && token_matches(&self.token, presented)
+    }
+
+    /// Registers a WebSocket connection.
+    ///
+    /// Hosts create their session or attach to it again; guests get the
+    /// session info and history. Fails for guests of unknown sessions and
+    /// for hosts on another device than the one that created the session.
+    fn register(
+        &self,
+        role: Role,
+        session_id: &str,
+        query: &HashMap<String, String>,
+        peer: SocketAddr,
+        tx: mpsc::Sender<Message>,
+        kick: Arc<Notify>,
+    ) -> std::result::Result<u64, RegisterError> {
+        let mut state = self.state();
+        let param = |key: &str| query.get(key).cloned().unwrap_or_default();
+        match (role, state.sessions.get(session_id)) {
+            (Role::Guest, None) => {
+                return Err(RegisterError::SessionNotFound(format!(
+                    "Session {} is not on this relay",
+                    session_id
+                )));
+            }
+            (Role::Host, _) if param("device_id").is_empty() => {
+                return Err(RegisterError::Forbidden("Missing device_id".to_string()));
+            }
+            (Role::Host, Some(session))
+                if !token_matches(&session.device_id, &param("device_id")) =>
+            {
+                warn!(session_id, peer = %peer, "Rejected host of another device");
+                return Err(RegisterError::Forbidden(format!(
+                    "Session {} belongs to another device",
+                    session_id
+                )));
+            }
+            _ => {}
+        }
+
+        state.next_id += 1;
+        let conn_id = state.next_id;
+        let name = match role {
+            Role::Host => param("device_name"),
+            Role::Guest => format!("guest@{}", peer.ip()),
+        };
+        state.connections.insert(
+            conn_id,
+            Connection {
+                role,
+                session_id: session_id.to_string(),
+                name,
+                read_only: role == Role::Guest && param("read_only") == "true",
+                tx,
+                kick,
+            },
+        );
+
+        match role {
+            Role::Host => {
+                let now = Utc::now().to_rfc3339();
+                let session = state
+                    .sessions
+                    .entry(session_id.to_string())
+                    .or_insert_with(|| RelaySession {
+                        device_id: param("device_id"),
+                        device_name: param("device_name"),
+                        cwd: param("cwd"),
+                        name: None,
+                        started_at: now.clone(),
+                        attached_at: None,
+                        host: None,
+                        detached_since: None,
+                        input_config: InputConfig::default(),
+                        history: VecDeque::new(),
+                        lock: None,
+                    });
+                let previous = session.host.replace(conn_id);
+                session.detached_since = None;
+                session.attached_at = Some(now);
+
+                // A reconnecting host replaces its half-open connection
+                if let Some(conn) = previous.and_then(|id| state.connections.get(&id)) {
+                    conn.kick.notify_one();
+                }
+                info!(session_id, "Host connected");
+            }
+            Role::Guest => {
+                let session = &state.sessions[session_id];
+                let info_msg = GuestIncomingMessage::SessionInfo(SessionInfo {
+                    session_id: session_id.to_string(),
+                    cols: DEFAULT_TERMINAL_COLS,
+                    rows: DEFAULT_TERMINAL_ROWS,
+                    device_name: Some(session.device_name.clone()),
+                    cwd: Some(session.cwd.clone()),
+                });
+                let history = GuestIncomingMessage::History(HistoryBatch {
+                    session_id: session_id.to_string(),
+                    entries: session.history.iter().cloned().collect(),
+                });
+                state.send(conn_id, &info_msg);
+                state.send(conn_id, &history);
+                info!(session_id, peer = %peer, "Guest connected");
+            }
+        }
+
+        Ok(conn_id)
+    }
+
+    /// Handles a text message from a connection.
+    fn handle_text(&self, conn_id: u64, text: &str) {
+        let mut state = self.state();
+        let Some(conn) = state.connections.get(&conn_id) else {
+            return;
+        };
+        let (role, session_id, name) = (conn.role, conn.session_id.clone(), conn.name.clone());
+
+        match role {
+            Role::Host => match serde_json::from_str::<OutgoingMessage>(text) {
+                Ok(msg) => handle_host_message(&mut state, conn_id, &session_id, msg),
+                Err(e) => debug!(error = %e, "Ignoring unknown host message"),
+            },
+            Role::Guest => match serde_json::from_str::<GuestOutgoingMessage>(text) {
+                Ok(msg) => handle_guest_message(&mut state, conn_id, &session_id, &name, msg),
+                Err(e) => debug!(error = %e, "Ignoring unknown guest message"),
+            },
+        }
+    }
+
+    /// Releases idle locks, forgets abandoned sessions and optionally pings
+    /// all clients.
+    fn sweep(&self, now: Instant, ping: bool) {
+        let mut state = self.state();
+
+        let mut released = Vec::new();
+        for (id, session) in state.sessions.iter_mut() {
+            if session.expire_lock(now) {
+                released.push(id.clone());
+            }
+        }
+        for session_id in released {
+            state.announce_lock(&session_id, None);
+        }
+
+        let expired: Vec<String> = state
+            .sessions
+            .iter()
+            .filter(|(id, session)| {
+                session
+                    .detached_since
+                    .is_some_and(|since| now.duration_since(since) >= DETACHED_SESSION_TTL)
+                    && !state.has_guests(id)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for session_id in expired {
+            debug!(session_id = %session_id, "Forgetting detached session");
+            state.sessions.remove(&session_id);
+        }
+
+        if ping {
+            for (id, conn) in &state.connections {
+                match conn.role {
+                    Role::Host => state.send(*id, &IncomingMessage::Ping),
+                    Role::Guest => state.send(*id, &GuestIncomingMessage::Ping),
+                }
+            }
+        }
+    }
+}
+
+/// Handles a message from a session's host.
+///
+/// Session IDs inside messages are ignored: a host only speaks for the
+/// session it connected to.
+fn handle_host_message(
+    state: &mut RelayState,
+    conn_id: u64,
+    session_id: &str,
+    msg: OutgoingMessage,
+) {
+    match msg {
+        OutgoingMessage::SessionAttach {
+            device_name,
+            cwd,
+            name,
+            input_config,
+            ..
+        } => {
+            let Some(session) = state.sessions.get_mut(session_id) else {
+                return;
+            };
+            session.device_name = device_name;
+            session.cwd = cwd;
+            session.name = name;
+            if let Some(wire) = input_config {
+                session.input_config = InputConfig {
+                    mode: InputMode::from_wire(&wire.mode).unwrap_or_default(),
+                    idle_timeout_ms: wire.idle_timeout_ms,
+                };
+            }
+            debug!(
+                session_id,
+                mode = session.input_config.mode.to_wire(),
+                "Session attached"
+            );
+        }
+        OutgoingMessage::EncryptedOutput {
+            encrypted,
+            timestamp,
+            ..
+        } => {
+            if let Some(session) = state.sessions.get_mut(session_id) {
+                session.history.push_back(HistoryEntry {
+                    encrypted: encrypted.clone(),
+                    timestamp: timestamp.clone(),
+                });
+                while session.history.len() > MAX_HISTORY_ENTRIES {
+                    session.history.pop_front();
+                }
+            }
+            let msg = GuestIncomingMessage::Output {
+                session_id: session_id.to_string(),
+                encrypted,
+                timestamp,
+            };
+            state.send_to_guests(session_id, &msg);
+        }
+        OutgoingMessage::SessionDetach { .. } => {
+            let mut released = false;
+            if let Some(session) = state.sessions.get_mut(session_id) {
+                if session.host == Some(conn_id) {
+                    session.host = None;
+                    released = session.lock.take().is_some();
+                    session.detached_since = Some(Instant::now());
+                }
+            }
+            if released {
+                state.announce_lock(session_id, None);
+            }
+            info!(session_id, "Session detached");
+            let msg = GuestIncomingMessage::SessionDetached {
+                session_id: session_id.to_string(),
+                reason: Some("host ended the session".to_string()),
+            };
+            state.send_to_guests(session_id, &msg);
+        }
+        OutgoingMessage::KeyEpoch {
+            key_epoch,
+            reseeded,
+            ..
+        } => {
+            let msg = GuestIncomingMessage::KeyEpoch {
+                session_id: session_id.to_string(),
+                key_epoch,
+                reseeded,
+            };
+            state.send_to_guests(session_id, &msg);
+        }
+        OutgoingMessage::KeyGrant {
+            request_id,
+            wrapped,
+            ..
+        } => {
+            if let Some(guest) = state.key_requests.remove(&request_id) {
+                let msg = GuestIncomingMessage::KeyGrant {
+                    session_id: session_id.to_string(),
+                    request_id,
+                    wrapped,
+                };
+                state.send(guest, &msg);
+            }
+        }
+        OutgoingMessage::Pong | OutgoingMessage::Output { .. } => {}
+    }
+}
+
+/// Handles a message from a guest.
+fn handle_guest_message(
+    state: &mut RelayState,
+    conn_id: u64,
+    session_id: &str,
+    name: &str,
+    msg: GuestOutgoingMessage,
+) {
+    let read_only = state
+        .connections
+        .get(&conn_id)
+        .is_some_and(|conn| conn.read_only);
+    let Some(session) = state.sessions.get_mut(session_id) else {
+        return;
+    };
+
+    match msg {
+        GuestOutgoingMessage::Prompt { encrypted, .. } => {
+            let decision = if read_only {
+                InputDecision::Reject(InputRejectionReason::ReadOnly, None)
+            } else {
+                session.arbitrate(conn_id, name, Instant::now())
+            };
+            match decision {
+                InputDecision::Reject(reason, holder_name) => {
+                    let msg = GuestIncomingMessage::InputRejected(InputRejected {
+                        session_id: session_id.to_string(),
+                        reason,
+                        holder_name,
+                    });
+                    state.send(conn_id, &msg);
+                }
+                InputDecision::Forward { acquired } => {
+                    let host = session.host;
+                    if acquired {
+                        state.announce_lock(session_id, Some((conn_id, name)));
+                    }
+                    if let Some(host) = host {
+                        let msg = IncomingMessage::Prompt {
+                            session_id: session_id.to_string(),
+                            encrypted,
+                            source: "guest".to_string(),
+                            timestamp: Utc::now().to_rfc3339(),
+                        };
+                        state.send(host, &msg);
+                    }
+                }
+            }
+        }
+        GuestOutgoingMessage::KeyRequest {
+            request_id,
+            public_key,
+            proof,
+            ..
+        } => {
+            let Some(host) = session.host else {
+                return;
+            };
+            state.key_requests.insert(request_id.clone(), conn_id);
+            let msg = IncomingMessage::KeyRequest {
+                session_id: session_id.to_string(),
+                request_id,
+                public_key,
+                proof,
+            };
+            state.send(host, &msg);
+        }
+        GuestOutgoingMessage::Pong => {}
+    }
+}
+
+/// Compares tokens without leaking the position of the first difference.
+fn token_matches(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+/// Runs the relay until Ctrl+C.
+///
+/// # Arguments
+///
+/// * `listen` - Address to listen on
+/// * `token` - Token clients must present; `KLAAS_RELAY_TOKEN` if not
+///   given, or a random one that is printed
+pub async fn run(listen: SocketAddr, token: Option<String>) -> Result<()> {
+    let token = token
+        .or_else(|| std::env::var(ENV_RELAY_TOKEN).ok())
+        .filter(|token| !token.is_empty())
+        .unwrap_or_else(generate_token);
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| CliError::NetworkError(format!("Failed to listen on {}: {}", listen, e)))?;
+    let addr = listener.local_addr()?;
+
+    display_relay_instructions(addr, &token);
+    let relay = Arc::new(Relay::new(token));
+
+    tokio::select! {
+        _ = serve(listener, relay) => {}
+        _ = tokio::signal::ctrl_c() => {
+            println!();
+            println!("  Relay stopped.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Accepts connections and runs the background sweep until the listener
+/// fails.
+pub async fn serve(listener: TcpListener, relay: Arc<Relay>) {
+    let sweeper = relay.clone();
+    let sweep_task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        let mut last_ping = Instant::now();
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let ping = now.duration_since(last_ping) >= PING_INTERVAL;
+            if ping {
+                last_ping = now;
+            }
+            sweeper.sweep(now, ping);
+        }
+    });
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let relay = relay.clone();
+                tokio::spawn(async move {
+                    handle_connection(relay, stream, peer).await;
+                });
+            }
+            Err(e) => {
+                warn!(error = %e, "Relay stopped accepting connections");
+                break;
+            }
+        }
+    }
+
+    sweep_task.abort();
+}
+
+/// Dispatches a connection to the WebSocket or REST handler by path.
+async fn handle_connection(relay: Arc<Relay>, stream: TcpStream, peer: SocketAddr) {
+    let is_websocket = match tokio::time::timeout(REQUEST_TIMEOUT, peek_path(&stream)).await {
+        Ok(Some(path)) => path == "/ws" || path.starts_with("/ws?"),
+        _ => return,
+    };
+
+    if is_websocket {
+        serve_websocket(relay, stream, peer).await;
+    } else if let Err(e) = serve_http(&relay, stream).await {
+        debug!(error = %e, "HTTP request failed");
+    }
+}
+
+/// Reads the request path without consuming the request.
+async fn peek_path(stream: &TcpStream) -> Option<String> {
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = stream.peek(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        let line_end = buf[..n].windows(2).position(|w| w == b"\r\n");
+        match line_end {
+            Some(end) => {
+                let line = std::str::from_utf8(&buf[..end]).ok()?;
+                return line.split_whitespace().nth(1).map(str::to_string);
+            }
+            None if n == buf.len() => return None,
+            None => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    }
+}
+
+/// Handles one WebSocket connection from handshake to disconnect.
+#[allow(clippy::result_large_err)] // The handshake callback's error type is tungstenite's
+async fn serve_websocket(relay: Arc<Relay>, stream: TcpStream, peer: SocketAddr) {
+    let mut query = HashMap::new();
+    let callback = |request: &Request, response: Response| {
+        let header = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if !relay.is_authorized(header) {
+            let mut error = ErrorResponse::new(Some("Invalid token".to_string()));
+            *error.status_mut() = StatusCode::UNAUTHORIZED;
+            return Err(error);
+        }
+
+        let raw_query = request.uri().query().unwrap_or_default();
+        query = url::form_urlencoded::parse(raw_query.as_bytes())
+            .into_owned()
+            .collect();
+        Ok(response)
+    };
+
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    };
+    let mut ws =
+        match tokio_tungstenite::accept_hdr_async_with_config(stream, callback, Some(config)).await
+        {
+            Ok(ws) => ws,
+            Err(e) => {
+                debug!(peer = %peer, error = %e, "WebSocket handshake failed");
+                return;
+            }
+        };
+
+    let role = match query.get("client").map(String::as_str) {
+        Some("guest") => Role::Guest,
+        _ => Role::Host,
+    };
+    let session_id = query.get("session_id").cloned().unwrap_or_default();
+
+    let (tx, mut rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+    let kick = Arc::new(Notify::new());
+    let registered = if session_id.is_empty() {
+        Err(RegisterError::SessionNotFound(
+            "Missing session_id".to_string(),
+        ))
+    } else {
+        relay.register(role, &session_id, &query, peer, tx, kick.clone())
+    };
+    let conn_id = match registered {
+        Ok(id) => id,
+        Err(e) => {
+            let (code, message) = e.code_and_message();
+            let error = GuestIncomingMessage::Error {
+                code: code.to_string(),
+                message,
+            };
+            if let Ok(json) = serde_json::to_string(&error) {
+                let _ = ws.send(Message::Text(json)).await;
+            }
+            let _ = ws.close(None).await;
+            return;
+        }
+    };
+
+    let (mut sink, mut stream) = ws.split();
+    let writer = async {
+        while let Some(msg) = rx.recv().await {
+            if sink.send(msg).await.is_err() {
+                break;
+            }
+        }
+    };
+    let reader = async {
+        while let Some(msg) = stream.next().await {
+            let Ok(msg) = msg else { break };
+            match msg {
+                Message::Text(text) => relay.handle_text(conn_id, &text),
+                Message::Binary(data) => {
+                    if let Ok(text) = String::from_utf8(data) {
+                        relay.handle_text(conn_id, &text);
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+    };
+    tokio::select! {
+        _ = writer => {}
+        _ = reader => {}
+        _ = kick.notified() => debug!(conn_id, "Closing connection"),
+    }
+
+    relay.state().disconnect(conn_id);
+}
+
+/// Answers a single REST request and closes the connection.
+async fn serve_http(relay: &Relay, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_HTTP_REQUEST_SIZE {
+            return write_response(&mut stream, StatusCode::PAYLOAD_TOO_LARGE, "{}").await;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                "authorization" => authorization = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    // Read and discard the body so the client sees the response
+    let mut remaining = (head_end + content_length).saturating_sub(buf.len());
+    if content_length > MAX_HTTP_REQUEST_SIZE {
+        return write_response(&mut stream, StatusCode::PAYLOAD_TOO_LARGE, "{}").await;
+    }
+    while remaining > 0 {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        remaining = remaining.saturating_sub(n);
+    }
+
+    let (status, body) = if relay.is_authorized(authorization.as_deref()) {
+        route(relay, method, path)
+    } else {
+        (StatusCode::UNAUTHORIZED, error_body("Invalid token"))
+    };
+    write_response(&mut stream, status, &body).await
+}
+
+/// Handles a REST request and returns the status and JSON body.
+fn route(relay: &Relay, method: &str, path: &str) -> (StatusCode, String) {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let state = relay.state();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["sessions"]) => {
+            let mut sessions: Vec<Session> = state
+                .sessions
+                .iter()
+                .map(|(id, session)| session.to_api(id))
+                .collect();
+            sessions.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+            let body = serde_json::json!({ "sessions": sessions });
+            (StatusCode::OK, body.to_string())
+        }
+        ("GET", ["sessions", identifier]) => {
+            let found = state.sessions.iter().find(|(id, session)| {
+                id.as_str() == *identifier || session.name.as_deref() == Some(*identifier)
+            });
+            match found {
+                Some((id, session)) => {
+                    let body = serde_json::json!({ "session": session.to_api(id) });
+                    (StatusCode::OK, body.to_string())
+                }
+                None => (StatusCode::NOT_FOUND, error_body("Session not found")),
+            }
+        }
+        _ => (
+            StatusCode::NOT_FOUND,
+            error_body("Not supported by klaas relay"),
+        ),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: StatusCode,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default(),
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Generates a random relay token.
+fn generate_token() -> String {
+    let mut bytes = [0u8; GENERATED_TOKEN_LEN];
+    rand::Rng::fill(&mut rand::thread_rng(), &mut bytes);
+    hex::encode(bytes)
+}
+
+/// Prints the address and how to point hosts and guests at the relay.
+fn display_relay_instructions(addr: SocketAddr, token: &str) {
+    let host = if addr.ip().is_unspecified() {
+        hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "localhost".to_string())
+    } else {
+        addr.ip().to_string()
+    };
+
+    println!();
+    println!("  klaas relay listening on {}", addr);
+    println!();
+    println!("  On each host and guest:");
+    println!("    export {}=http://{}:{}", ENV_API_URL, host, addr.port());
+    println!("    export {}={}", ENV_TOKEN, token);
+    println!("    klaas login --import-key <recovery key file>");
+    println!();
+    println!("  All devices need the same encryption key. Press Ctrl+C to stop.");
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::ApiClient;
+    use crate::crypto::{decrypt_content, derive_session_key, encrypt_content, SecretKey};
+    use crate::websocket::WebSocketClient;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    fn test_session(mode: InputMode) -> RelaySession {
+        RelaySession {
+            device_id: String::new(),
+            device_name: String::new(),
+            cwd: String::new(),
+            name: None,
+            started_at: String::new(),
+            attached_at: None,
+            host: Some(1),
+            detached_since: None,
+            input_config: InputConfig {
+                mode,
+                idle_timeout_ms: 1000,
+            },
+            history: VecDeque::new(),
+            lock: None,
+        }
+    }
+
+    #[test]
+    fn test_auto_lock_arbitration() {
+        let mut session = test_session(InputMode::AutoLock);
+        let now = Instant::now();
+
+        assert_eq!(
+            session.arbitrate(2, "alice", now),
+            InputDecision::Forward { acquired: true }
+        );
+        assert_eq!(
+            session.arbitrate(2, "alice", now + Duration::from_millis(500)),
+            InputDecision::Forward { acquired: false }
+        );
+        assert_eq!(
+            session.arbitrate(3, "bob", now + Duration::from_millis(1000)),
+            InputDecision::Reject(InputRejectionReason::LockHeld, Some("alice".to_string()))
+        );
+
+        // Alice's last input was at +500ms, so the lock expires at +1500ms
+        assert!(!session.expire_lock(now + Duration::from_millis(1499)));
+        assert!(session.expire_lock(now + Duration::from_millis(1500)));
+        assert_eq!(
+            session.arbitrate(3, "bob", now + Duration::from_millis(1600)),
+            InputDecision::Forward { acquired: true }
+        );
+    }
+
+    #[test]
+    fn test_other_input_modes() {
+        let now = Instant::now();
+
+        let mut session = test_session(InputMode::HostOnly);
+        assert_eq!(
+            session.arbitrate(2, "alice", now),
+            InputDecision::Reject(InputRejectionReason::HostOnly, None)
+        );
+
+        let mut session = test_session(InputMode::FreeForAll);
+        assert_eq!(
+            session.arbitrate(2, "alice", now),
+            InputDecision::Forward { acquired: false }
+        );
+        assert_eq!(
+            session.arbitrate(3, "bob", now),
+            InputDecision::Forward { acquired: false }
+        );
+
+        session.host = None;
+        assert_eq!(
+            session.arbitrate(2, "alice", now),
+            InputDecision::Reject(InputRejectionReason::HostDetached, None)
+        );
+    }
+
+    #[test]
+    fn test_input_mode_wire_roundtrip() {
+        for mode in [
+            InputMode::HostOnly,
+            InputMode::AutoLock,
+            InputMode::FreeForAll,
+        ] {
+            assert_eq!(InputMode::from_wire(mode.to_wire()), Some(mode));
+        }
+        assert_eq!(InputMode::from_wire("everyone"), None);
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret2"));
+        assert!(!token_matches("secret", ""));
+
+        let relay = Relay::new("secret".to_string());
+        assert!(relay.is_authorized(Some("Bearer secret")));
+        assert!(!relay.is_authorized(Some("Bearer other")));
+        assert!(!relay.is_authorized(None));
+
+        let relay = Relay::new(String::new());
+        assert!(!relay.is_authorized(None));
+        assert!(!relay.is_authorized(Some("Bearer ")));
+    }
+
+    #[test]
+    fn test_generated_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), GENERATED_TOKEN_LEN * 2);
+        assert_ne!(token, generate_token());
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_is_kicked() {
+        let relay = Relay::new("secret".to_string());
+        let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let query = HashMap::from([("device_id".to_string(), "device".to_string())]);
+        let (tx, _rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+        let kick = Arc::new(Notify::new());
+        let conn_id = relay
+            .register(Role::Host, "session", &query, peer, tx, kick.clone())
+            .unwrap();
+
+        {
+            let state = relay.state();
+            for _ in 0..=CONNECTION_QUEUE_SIZE {
+                state.send(conn_id, &"output");
+            }
+        }
+        tokio::time::timeout(Duration::from_secs(1), kick.notified())
+            .await
+            .expect("slow client was not kicked");
+    }
+
+    #[test]
+    fn test_host_of_another_device_is_refused() {
+        let relay = Relay::new("secret".to_string());
+        let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let register = |device_id: &str| {
+            let query = HashMap::from([("device_id".to_string(), device_id.to_string())]);
+            let (tx, _rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+            relay.register(
+                Role::Host,
+                "session",
+                &query,
+                peer,
+                tx,
+                Arc::new(Notify::new()),
+            )
+        };
+
+        assert!(matches!(register(""), Err(RegisterError::Forbidden(_))));
+        assert!(register("device").is_ok());
+        assert!(matches!(
+            register("intruder"),
+            Err(RegisterError::Forbidden(_))
+        ));
+        assert!(register("device").is_ok());
+    }
+
+    #[test]
+    fn test_detach_releases_lock_for_guests() {
+        let relay = Relay::new("secret".to_string());
+        let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let host_query = HashMap::from([("device_id".to_string(), "device".to_string())]);
+        let (host_tx, _host_rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+        let host = relay
+            .register(
+                Role::Host,
+                "session",
+                &host_query,
+                peer,
+                host_tx,
+                Arc::new(Notify::new()),
+            )
+            .unwrap();
+        let (guest_tx, mut guest_rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+        let guest = relay
+            .register(
+                Role::Guest,
+                "session",
+                &HashMap::new(),
+                peer,
+                guest_tx,
+                Arc::new(Notify::new()),
+            )
+            .unwrap();
+
+        let mut state = relay.state();
+        state.sessions.get_mut("session").unwrap().lock = Some(InputLock {
+            holder: guest,
+            name: "alice".to_string(),
+            last_input: Instant::now(),
+        });
+        let detach = OutgoingMessage::SessionDetach {
+            session_id: "session".to_string(),
+        };
+        handle_host_message(&mut state, host, "session", detach);
+        drop(state);
+
+        let mut messages = Vec::new();
+        while let Ok(Message::Text(text)) = guest_rx.try_recv() {
+            messages.push(serde_json::from_str::<GuestIncomingMessage>(&text).unwrap());
+        }
+        let released = messages
+            .iter()
+            .position(|msg| matches!(msg, GuestIncomingMessage::LockReleased(_)))
+            .expect("guests should hear that the lock is gone");
+        let detached = messages
+            .iter()
+            .position(|msg| matches!(msg, GuestIncomingMessage::SessionDetached { .. }))
+            .unwrap();
+        assert!(released < detached);
+    }
+
+    async fn start_relay(token: &str) -> (SocketAddr, Arc<Relay>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Arc::new(Relay::new(token.to_string()));
+        tokio::spawn(serve(listener, relay.clone()));
+        (addr, relay)
+    }
+
+    async fn wait_for_history(relay: &Relay, session_id: &str, len: usize) {
+        for _ in 0..500 {
+            let current = relay
+                .state()
+                .sessions
+                .get(session_id)
+                .map_or(0, |s| s.history.len());
+            if current >= len {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("history of {} never reached {} entries", session_id, len);
+    }
+
+    async fn next_guest_message(
+        ws: &mut tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>,
+    ) -> GuestIncomingMessage {
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), ws.next())
+                .await
+                .expect("timed out")
+                .expect("closed")
+                .expect("failed");
+            if let Message::Text(text) = msg {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_between_host_and_guest() {
+        let (addr, relay) = start_relay("secret").await;
+        let mek = SecretKey::random();
+        let session_id = "01HQXK7V8G3N5M2R4P6T1W9Y0Z";
+        let session_key = derive_session_key(&mek, session_id);
+
+        let host = WebSocketClient::connect(
+            &format!("ws://{}/ws", addr),
+            "secret",
+            session_id,
+            "01HQXK7V8G3N5M2R4P6T1W9Y10",
+            "relay-host",
+            "/tmp",
+            Some("demo"),
+        )
+        .await
+        .unwrap();
+        host.set_mek(mek.clone()).await;
+        host.send_output(b"history").await.unwrap();
+        wait_for_history(&relay, session_id, 1).await;
+
+        // The REST API lists the session once the host has attached
+        let api = ApiClient::new(&format!("http://{}", addr), "secret");
+        let mut found = None;
+        for _ in 0..100 {
+            found = api.get_session("demo").await.unwrap();
+            if found.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(found.unwrap().session_id, session_id);
+        assert!(ApiClient::new(&format!("http://{}", addr), "wrong")
+            .get_sessions()
+            .await
+            .is_err());
+
+        let mut request = format!("ws://{}/ws?session_id={}&client=guest", addr, session_id)
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let (mut guest, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        assert!(matches!(
+            next_guest_message(&mut guest).await,
+            GuestIncomingMessage::SessionInfo(_)
+        ));
+        match next_guest_message(&mut guest).await {
+            GuestIncomingMessage::History(batch) => {
+                assert_eq!(batch.entries.len(), 1);
+                let plaintext = decrypt_content(&session_key, &batch.entries[0].encrypted);
+                assert_eq!(plaintext.unwrap(), b"history");
+            }
+            other => panic!("expected history, got {:?}", other),
+        }
+
+        host.send_output(b"live").await.unwrap();
+        match next_guest_message(&mut guest).await {
+            GuestIncomingMessage::Output { encrypted, .. } => {
+                assert_eq!(decrypt_content(&session_key, &encrypted).unwrap(), b"live");
+            }
+            other => panic!("expected output, got {:?}", other),
+        }
+
+        let prompt = GuestOutgoingMessage::Prompt {
+            session_id: session_id.to_string(),
+            encrypted: encrypt_content(&session_key, b"echo hi\r"),
+        };
+        guest
+            .send(Message::Text(serde_json::to_string(&prompt).unwrap()))
+            .await
+            .unwrap();
+
+        let decrypted = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(IncomingMessage::Prompt { encrypted, .. }) = host.recv().await.unwrap()
+                {
+                    return host.decrypt_prompt(&encrypted).await.unwrap();
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(decrypted, "echo hi\r");
+    }
+
+    #[tokio::test]
+    async fn test_guest_of_unknown_session_is_refused() {
+        let (addr, _relay) = start_relay("secret").await;
+
+        let mut request = format!("ws://{}/ws?session_id=missing&client=guest", addr)
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let (mut guest, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        assert!(matches!(
+            next_guest_message(&mut guest).await,
+            GuestIncomingMessage::Error { .. }
+        ));
+    }
+}
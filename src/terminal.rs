@@ -154,15 +154,14 @@ impl TerminalManager {
         // Clear the line
         stdout.execute(Clear(ClearType::CurrentLine))?;
 
-        // Truncate status if too long
-        let display_status = if status.len() > cols as usize {
-            &status[..cols as usize]
-        } else {
-            status
-        };
+        // Truncate status if too long, keeping its styling intact
+        let display_status = truncate_visible(status, cols as usize);
 
         // Write status
         stdout.execute(Print(display_status))?;
+        if display_status.len() < status.len() {
+            stdout.execute(Print("\x1b[0m"))?;
+        }
 
         // Restore cursor position
         stdout.execute(RestorePosition)?;
@@ -172,6 +171,31 @@ impl TerminalManager {
     }
 }
 
+/// Truncates `text` to at most `width` visible characters.
+///
+/// ANSI escape sequences (e.g. colors) take up no space, and the cut always
+/// falls on a character boundary.
+fn truncate_visible(text: &str, width: usize) -> &str {
+    let mut visible = 0;
+    let mut in_escape = false;
+    for (i, c) in text.char_indices() {
+        if in_escape {
+            // CSI sequences end with a byte in '@'..='~'
+            if c != '[' && ('@'..='~').contains(&c) {
+                in_escape = false;
+            }
+        } else if c == '\x1b' {
+            in_escape = true;
+        } else {
+            if visible == width {
+                return &text[..i];
+            }
+            visible += 1;
+        }
+    }
+    text
+}
+
 impl Drop for TerminalManager {
     fn drop(&mut self) {
         // Always restore terminal state on drop
@@ -189,6 +213,17 @@ mod tests {
         assert!(!manager.is_raw());
     }
 
+    #[test]
+    fn truncate_visible_skips_escapes_and_respects_char_boundaries() {
+        assert_eq!(truncate_visible("klaas", 10), "klaas");
+        assert_eq!(truncate_visible("klaas", 3), "kla");
+        assert_eq!(
+            truncate_visible("\x1b[2;32m● klaas\x1b[0m", 3),
+            "\x1b[2;32m● k"
+        );
+        assert_eq!(truncate_visible("●●●", 2), "●●");
+    }
+
     #[test]
     fn terminal_size_returns_valid_dimensions() {
         let manager = TerminalManager::new().unwrap();
@@ -0,0 +1,404 @@
+//! TLS configuration shared by HTTP and WebSocket clients.
+//!
+//! By default reqwest and tokio-tungstenite use their own rustls setup. The
+//! `[tls]` config section replaces it with one built here:
+//! - `ca_file` adds CA certificates to the system roots, e.g. for a
+//!   TLS-intercepting gateway
+//! - `pinned_spki` requires the API and WebSocket servers to present a
+//!   certificate whose public key hash matches one of the pins
+//!
+//! A broken `[tls]` section never falls back to the defaults: connections
+//! fail instead, and [`check_config`] reports why.
+
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, OnceLock};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::config::{get_api_config, load_config, TlsConfig};
+use crate::crypto::{decode_base64, encode_base64};
+use crate::error::{CliError, Result};
+
+/// Prefix of pins in `tls.pinned_spki`.
+const PIN_PREFIX: &str = "sha256/";
+
+/// Result of building the TLS configuration, cached for the process.
+static CLIENT_CONFIG: OnceLock<std::result::Result<Option<Arc<ClientConfig>>, String>> =
+    OnceLock::new();
+
+/// Returns the configuration built from `[tls]`, or `None` to use the
+/// library defaults.
+///
+/// If the section is invalid, returns a configuration that trusts no
+/// certificates, so that connections fail rather than skip the pins.
+pub fn client_config() -> Option<Arc<ClientConfig>> {
+    match cached_config() {
+        Ok(config) => config.clone(),
+        Err(_) => Some(Arc::new(untrusting_config())),
+    }
+}
+
+/// Checks that the `[tls]` config section is usable.
+///
+/// # Errors
+///
+/// Returns `CliError::NetworkError` if the CA file can't be read or a pin
+/// is malformed.
+pub fn check_config() -> Result<()> {
+    cached_config()
+        .as_ref()
+        .map(|_| ())
+        .map_err(|e| CliError::NetworkError(e.clone()))
+}
+
+/// Builds the TLS configuration once.
+fn cached_config() -> &'static std::result::Result<Option<Arc<ClientConfig>>, String> {
+    CLIENT_CONFIG.get_or_init(|| {
+        let tls = load_config().tls;
+        if tls.is_default() {
+            return Ok(None);
+        }
+
+        let api = get_api_config();
+        let pinned_hosts = [api.api_url, api.ws_url]
+            .iter()
+            .filter_map(|url| Url::parse(url).ok()?.host_str().map(str::to_string))
+            .collect();
+
+        build_client_config(&tls, pinned_hosts)
+            .map(|config| Some(Arc::new(config)))
+            .map_err(|e| match e {
+                CliError::NetworkError(message) => message,
+                other => other.to_string(),
+            })
+    })
+}
+
+/// Builds a client configuration trusting the system roots and `ca_file`,
+/// with `pinned_spki` enforced for `pinned_hosts`.
+fn build_client_config(tls: &TlsConfig, pinned_hosts: Vec<String>) -> Result<ClientConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let verifier = build_verifier(tls, pinned_hosts, provider.clone())?;
+
+    Ok(ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| CliError::NetworkError(format!("TLS setup failed: {}", e)))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth())
+}
+
+/// Builds the certificate verifier for [`build_client_config`].
+fn build_verifier(
+    tls: &TlsConfig,
+    pinned_hosts: Vec<String>,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn ServerCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for e in &native.errors {
+        warn!(error = %e, "Failed to load a system CA certificate");
+    }
+    let (added, _) = roots.add_parsable_certificates(native.certs);
+    debug!(count = added, "Loaded system CA certificates");
+
+    if let Some(path) = &tls.ca_file {
+        let pem = fs::read(path).map_err(|e| {
+            CliError::NetworkError(format!("Failed to read tls.ca_file {:?}: {}", path, e))
+        })?;
+        let certs = CertificateDer::pem_slice_iter(&pem)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| {
+                CliError::NetworkError(format!("Invalid PEM in tls.ca_file {:?}: {}", path, e))
+            })?;
+        if certs.is_empty() {
+            return Err(CliError::NetworkError(format!(
+                "No certificates in tls.ca_file {:?}",
+                path
+            )));
+        }
+        for cert in certs {
+            roots.add(cert).map_err(|e| {
+                CliError::NetworkError(format!("Invalid certificate in {:?}: {}", path, e))
+            })?;
+        }
+    }
+
+    if roots.is_empty() {
+        return Err(CliError::NetworkError(
+            "No trusted CA certificates found, set tls.ca_file".to_string(),
+        ));
+    }
+
+    let pins = tls
+        .pinned_spki
+        .iter()
+        .map(|pin| parse_pin(pin))
+        .collect::<Result<Vec<_>>>()?;
+
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| CliError::NetworkError(format!("TLS setup failed: {}", e)))?;
+    if pins.is_empty() {
+        return Ok(inner);
+    }
+
+    Ok(Arc::new(PinningVerifier {
+        inner,
+        pins,
+        hosts: pinned_hosts,
+    }))
+}
+
+/// Returns a configuration that rejects every server certificate.
+pub(crate) fn untrusting_config() -> ClientConfig {
+    ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth()
+}
+
+/// Parses a `sha256/<base64>` pin (the prefix is optional).
+fn parse_pin(pin: &str) -> Result<[u8; 32]> {
+    let encoded = pin.trim();
+    let encoded = encoded.strip_prefix(PIN_PREFIX).unwrap_or(encoded);
+
+    decode_base64(encoded)
+        .ok()
+        .and_then(|hash| <[u8; 32]>::try_from(hash.as_slice()).ok())
+        .ok_or_else(|| {
+            CliError::NetworkError(format!(
+                "Invalid tls.pinned_spki entry '{}', expected {}<base64 SHA-256>",
+                pin, PIN_PREFIX
+            ))
+        })
+}
+
+/// Returns the `sha256/<base64>` pin of a certificate's public key.
+pub fn spki_pin(cert: &CertificateDer<'_>) -> Option<String> {
+    spki_hash(cert).map(|hash| format!("{}{}", PIN_PREFIX, encode_base64(&hash)))
+}
+
+/// Hashes a certificate's SubjectPublicKeyInfo.
+fn spki_hash(cert: &CertificateDer<'_>) -> Option<[u8; 32]> {
+    let parsed = webpki::EndEntityCert::try_from(cert).ok()?;
+    Some(Sha256::digest(parsed.subject_public_key_info().as_ref()).into())
+}
+
+/// Verifies certificates as usual, then checks the pins for klaas hosts.
+struct PinningVerifier {
+    /// Standard chain verification.
+    inner: Arc<WebPkiServerVerifier>,
+    /// Accepted SPKI hashes.
+    pins: Vec<[u8; 32]>,
+    /// Hosts the pins apply to.
+    hosts: Vec<String>,
+}
+
+impl fmt::Debug for PinningVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinningVerifier")
+            .field("pins", &self.pins.len())
+            .field("hosts", &self.hosts)
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let host = server_name.to_str();
+        if !self
+            .hosts
+            .iter()
+            .any(|pinned| pinned.eq_ignore_ascii_case(&host))
+        {
+            return Ok(verified);
+        }
+
+        let matches = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(spki_hash)
+            .any(|hash| self.pins.contains(&hash));
+        if matches {
+            Ok(verified)
+        } else {
+            warn!(
+                host = %host,
+                pin = spki_pin(end_entity).unwrap_or_default(),
+                "Server certificate does not match tls.pinned_spki"
+            );
+            Err(rustls::Error::General(format!(
+                "certificate of {} does not match tls.pinned_spki",
+                host
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Test CA, valid until 2126.
+    const CA_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBlzCCAT2gAwIBAgIUTewIlybr9q1wpYnqg4moPnVh3Z0wCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNa2xhYXMgdGVzdCBDQTAgFw0yNjEwMTgxODE0MTFaGA8yMTI2
+MDkyNDE4MTQxMVowGDEWMBQGA1UEAwwNa2xhYXMgdGVzdCBDQTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABI4XgpPR4I8F/8fQ0tHA2Q6nil02ulPIVKEuUTpK3H1s
+0Z3n5G3zeF1BbKTINbLcnDTP/9DrJarFXZvHuNFAihGjYzBhMB0GA1UdDgQWBBR6
+apqEdPzt230SyWuI2jDUu92V0TAfBgNVHSMEGDAWgBR6apqEdPzt230SyWuI2jDU
+u92V0TAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwICBDAKBggqhkjOPQQD
+AgNIADBFAiBx/S43Zbgqa5tx/vogSSaPzG7a9y9xsDSTajMrVlro7QIhALR1Dwcg
+22aykBZ9ftGdDEiayvzLXQsZYDEu2xeuOoIY
+-----END CERTIFICATE-----
+";
+
+    /// Certificate for `localhost`, issued by [`CA_PEM`].
+    const LEAF_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBtzCCAV2gAwIBAgIUegieMc3w9SHHP51BKx5vF0ELAhUwCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNa2xhYXMgdGVzdCBDQTAgFw0yNjEwMTgxODE0MTFaGA8yMTI2
+MDkyNDE4MTQxMVowFDESMBAGA1UEAwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYI
+KoZIzj0DAQcDQgAEiFS5pWjl5X39HkRaHo1Wk7hbQ7jU+7nuD20czm1s29V2jlY9
+HGK7cMa8DVa34iw3HnJlYMoYdL+xYpQ9Tffw0aOBhjCBgzAUBgNVHREEDTALggls
+b2NhbGhvc3QwCQYDVR0TBAIwADATBgNVHSUEDDAKBggrBgEFBQcDATALBgNVHQ8E
+BAMCB4AwHQYDVR0OBBYEFIVRiAE7Cn+m/n/KapWSagdUpLeBMB8GA1UdIwQYMBaA
+FHpqmoR0/O3bfRLJa4jaMNS73ZXRMAoGCCqGSM49BAMCA0gAMEUCIFD3u+JKIN4l
+LUcBUiIB8ovCZnGHjo90c3N5UpwN1nbMAiEA/gOV0y29PTJehonNBDenVneBPI6S
+K2Ar3OfO73Egemc=
+-----END CERTIFICATE-----
+";
+
+    /// Pin of [`LEAF_PEM`], as printed by
+    /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der |
+    /// openssl dgst -sha256 -binary | base64`.
+    const LEAF_PIN: &str = "sha256//VHO6mWNKurKrxfygauR1pxcIeYncPp1cNT5wtf7nRc=";
+
+    fn leaf() -> CertificateDer<'static> {
+        CertificateDer::from_pem_slice(LEAF_PEM.as_bytes()).unwrap()
+    }
+
+    /// Builds a verifier trusting the test CA.
+    fn verifier(pins: &[&str]) -> Arc<dyn ServerCertVerifier> {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        fs::write(&ca_file, CA_PEM).unwrap();
+
+        let tls = TlsConfig {
+            ca_file: Some(ca_file),
+            pinned_spki: pins.iter().map(|pin| pin.to_string()).collect(),
+        };
+        build_verifier(
+            &tls,
+            vec!["localhost".into()],
+            Arc::new(ring::default_provider()),
+        )
+        .unwrap()
+    }
+
+    fn verify(verifier: &Arc<dyn ServerCertVerifier>, host: &str) -> bool {
+        let now = UnixTime::since_unix_epoch(Duration::from_secs(1_900_000_000));
+        verifier
+            .verify_server_cert(
+                &leaf(),
+                &[],
+                &ServerName::try_from(host.to_string()).unwrap(),
+                &[],
+                now,
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn test_spki_pin() {
+        assert_eq!(spki_pin(&leaf()).as_deref(), Some(LEAF_PIN));
+        assert_eq!(
+            parse_pin(LEAF_PIN).unwrap(),
+            parse_pin(LEAF_PIN.trim_start_matches(PIN_PREFIX)).unwrap()
+        );
+        assert!(parse_pin("sha256/bm90IGEgaGFzaA==").is_err());
+    }
+
+    #[test]
+    fn test_ca_file_is_trusted() {
+        assert!(verify(&verifier(&[]), "localhost"));
+    }
+
+    #[test]
+    fn test_pinned_spki() {
+        assert!(verify(&verifier(&[LEAF_PIN]), "localhost"));
+        assert!(build_client_config(
+            &TlsConfig {
+                ca_file: None,
+                pinned_spki: vec![LEAF_PIN.into()],
+            },
+            Vec::new()
+        )
+        .is_ok());
+
+        let other_pin = format!("{}{}", PIN_PREFIX, encode_base64(&[7u8; 32]));
+        assert!(!verify(&verifier(&[&other_pin]), "localhost"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let tls = TlsConfig {
+            ca_file: Some("/nonexistent/ca.pem".into()),
+            pinned_spki: Vec::new(),
+        };
+        assert!(build_client_config(&tls, Vec::new()).is_err());
+
+        let tls = TlsConfig {
+            ca_file: None,
+            pinned_spki: vec!["not a pin".into()],
+        };
+        assert!(build_client_config(&tls, Vec::new()).is_err());
+    }
+}
@@ -117,6 +117,16 @@ impl InputMode {
             InputMode::FreeForAll => "free-for-all",
         }
     }
+
+    /// Parses the API wire format.
+    pub fn from_wire(value: &str) -> Option<Self> {
+        match value {
+            "host-only" => Some(InputMode::HostOnly),
+            "auto-lock" => Some(InputMode::AutoLock),
+            "free-for-all" => Some(InputMode::FreeForAll),
+            _ => None,
+        }
+    }
 }
 
 /// Default idle timeout in milliseconds.
@@ -144,6 +154,29 @@ impl Default for InputConfig {
     }
 }
 
+/// Access level granted when sharing a session with another user.
+///
+/// The default is what `klaas share --with` grants without `--read-only`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShareAccess {
+    /// The invitee can watch output but not send input.
+    ReadOnly,
+    /// The invitee can watch output and send input.
+    #[default]
+    ReadWrite,
+}
+
+impl ShareAccess {
+    /// Converts to the API wire format.
+    pub fn to_wire(self) -> &'static str {
+        match self {
+            ShareAccess::ReadOnly => "read-only",
+            ShareAccess::ReadWrite => "read-write",
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,4 +277,14 @@ mod tests {
         assert_eq!(config.mode, InputMode::HostOnly);
         assert_eq!(config.idle_timeout_ms, 1500);
     }
+
+    #[test]
+    fn share_access_serde_kebab_case() {
+        let parsed: ShareAccess = serde_json::from_str(r#""read-write""#).unwrap();
+        assert_eq!(parsed, ShareAccess::ReadWrite);
+        assert_eq!(
+            serde_json::to_string(&ShareAccess::ReadOnly).unwrap(),
+            format!("\"{}\"", ShareAccess::ReadOnly.to_wire())
+        );
+    }
 }
@@ -829,6 +829,55 @@ fn clear_agent_menu(stdout: &mut io::Stdout, agent_count: usize) {
     let _ = stdout.flush();
 }
 
+/// Prompts for a secret on the terminal without echoing it.
+///
+/// The prompt is written to stderr so it never mixes with piped output.
+/// Returns `None` if the user cancels with Esc or Ctrl+C.
+pub fn prompt_secret(prompt: &str) -> io::Result<Option<String>> {
+    use crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        terminal,
+    };
+
+    let (mr, mg, mb) = colors::TEXT_MUTED;
+    let mut stderr = io::stderr();
+    write!(stderr, "  {}{}{} ", fg_color(mr, mg, mb), prompt, RESET)?;
+    stderr.flush()?;
+
+    // Raw mode disables echo; keep it on if the caller already enabled it
+    let was_raw = terminal::is_raw_mode_enabled().unwrap_or(false);
+    if !was_raw {
+        terminal::enable_raw_mode()?;
+    }
+
+    let mut input = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => match key.code {
+                KeyCode::Enter => break Ok(Some(std::mem::take(&mut input))),
+                KeyCode::Esc => break Ok(None),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Ok(None)
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+
+    if !was_raw {
+        let _ = terminal::disable_raw_mode();
+    }
+    write!(stderr, "\r\n")?;
+
+    result
+}
+
 /// Displays a notification that no AI coding agents were found.
 ///
 /// This is shown when the user has no supported AI agents installed,
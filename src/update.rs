@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
 use crate::analytics;
+use crate::net::http_client_builder;
 
 /// GitHub repository for klaas releases.
 const GITHUB_REPO: &str = "klaas-sh/cli";
@@ -212,7 +213,7 @@ async fn fetch_latest_version() -> Option<String> {
         GITHUB_REPO
     );
 
-    let client = reqwest::Client::builder()
+    let client = http_client_builder()
         .user_agent(format!("klaas/{}", CURRENT_VERSION))
         .timeout(Duration::from_secs(5))
         .build()
@@ -287,7 +288,7 @@ async fn perform_update_quiet() -> Result<(), String> {
         GITHUB_REPO
     );
 
-    let client = reqwest::Client::builder()
+    let client = http_client_builder()
         .user_agent(format!("klaas/{}", CURRENT_VERSION))
         .timeout(Duration::from_secs(30))
         .build()
@@ -464,7 +465,7 @@ pub async fn perform_update() -> Result<(), String> {
         GITHUB_REPO
     );
 
-    let client = reqwest::Client::builder()
+    let client = http_client_builder()
         .user_agent(format!("klaas/{}", CURRENT_VERSION))
         .timeout(Duration::from_secs(30))
         .build()
@@ -7,6 +7,7 @@
 //! - Receiving prompts, resize commands, and pings from the server
 //! - Automatic reconnection with exponential backoff
 //! - Transparent end-to-end encryption (always enabled, no user interaction)
+//! - Optional forward-secret key ratchet, with keys granted to guests on request
 
 use std::collections::VecDeque;
 use std::sync::Arc;
@@ -22,15 +23,17 @@ use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::tungstenite::http::header::{HeaderValue, AUTHORIZATION};
 use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use tracing::{debug, error, info, warn};
 use url::Url;
 
 use crate::config::get_input_config;
 use crate::crypto::{
-    decrypt_content, derive_session_key, encrypt_content, EncryptedContent, SecretKey,
+    decode_base64, decrypt_content, derive_session_key_for_epoch, encrypt_content,
+    wrap_session_key, EncryptedContent, KeyRatchet, SecretKey, WrappedSessionKey,
 };
 use crate::error::{CliError, Result};
+use crate::net::connect_websocket;
 use crate::types::InputConfig;
 
 /// Maximum number of reconnection attempts before giving up.
@@ -53,7 +56,7 @@ const MAX_QUEUE_AGE: Duration = Duration::from_secs(5 * 60);
 // ============================================================================
 
 /// Wire format for input config (matches API types).
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputConfigWire {
     /// Input mode as a string (e.g., "auto-lock").
     pub mode: String,
@@ -75,7 +78,7 @@ impl From<&InputConfig> for InputConfigWire {
 // ============================================================================
 
 /// Messages sent from CLI to server.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum OutgoingMessage {
     /// Attach a session to the server.
@@ -95,6 +98,7 @@ pub enum OutgoingMessage {
     /// Kept for backward compatibility but no longer used - all output is now
     /// encrypted using EncryptedOutput.
     #[allow(dead_code)]
+    #[serde(skip_deserializing)]
     Output {
         session_id: String,
         data: String,
@@ -113,10 +117,24 @@ pub enum OutgoingMessage {
     SessionDetach { session_id: String },
     /// Heartbeat response.
     Pong,
+    /// Announces the current forward-secrecy key epoch to guests.
+    /// `reseeded` means the chain was restarted and guests must request the
+    /// new key instead of ratcheting their own.
+    KeyEpoch {
+        session_id: String,
+        key_epoch: u32,
+        reseeded: bool,
+    },
+    /// Forward-secrecy key wrapped to the ephemeral key of a guest request.
+    KeyGrant {
+        session_id: String,
+        request_id: String,
+        wrapped: WrappedSessionKey,
+    },
 }
 
 /// Messages received from server to CLI.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum IncomingMessage {
     /// Prompt from a web client (E2EE encrypted).
@@ -152,8 +170,27 @@ pub enum IncomingMessage {
         #[serde(default)]
         holder_name: Option<String>,
     },
+    /// Session key moved to a new epoch after a share was revoked.
+    /// Also sent after session_attach when the session is past epoch 0.
+    KeyRotated { session_id: String, key_epoch: u32 },
+    /// Guest asks for the current forward-secrecy key.
+    ///
+    /// `proof` is the guest's ephemeral public key encrypted with the
+    /// MEK-derived session key of the current epoch, which shows the guest
+    /// owns the session or has an active share.
+    KeyRequest {
+        session_id: String,
+        request_id: String,
+        public_key: String,
+        proof: EncryptedContent,
+    },
 }
 
+/// Forward-secrecy ratchet shared between the host and its connections.
+///
+/// Lives outside the client so that the chain survives fresh connections.
+pub type SharedRatchet = Arc<Mutex<KeyRatchet>>;
+
 /// Queued message with timestamp for expiration.
 #[derive(Debug)]
 struct QueuedMessage {
@@ -186,8 +223,8 @@ pub struct WebSocketClient {
     receiver: Arc<Mutex<Option<WsReceiver>>>,
     /// Connection URL.
     url: Url,
-    /// JWT token for authentication.
-    token: String,
+    /// JWT token for authentication (replaced when the token is refreshed).
+    token: Arc<Mutex<String>>,
     /// Session ID for this connection.
     session_id: String,
     /// Device ID for this device.
@@ -208,6 +245,11 @@ pub struct WebSocketClient {
     mek: Arc<Mutex<Option<SecretKey>>>,
     /// Cached session key derived from MEK (derived lazily).
     session_key: Arc<Mutex<Option<SecretKey>>>,
+    /// Current session key epoch (bumped when a share is revoked).
+    key_epoch: Arc<Mutex<u32>>,
+    /// Forward-secrecy ratchet, if enabled. Replaces the MEK-derived key
+    /// for output.
+    ratchet: Arc<Mutex<Option<SharedRatchet>>>,
 }
 
 impl WebSocketClient {
@@ -254,7 +296,7 @@ impl WebSocketClient {
             sender: Arc::new(Mutex::new(None)),
             receiver: Arc::new(Mutex::new(None)),
             url: parsed_url,
-            token: token.to_string(),
+            token: Arc::new(Mutex::new(token.to_string())),
             session_id: session_id.to_string(),
             device_id: device_id.to_string(),
             device_name: device_name.to_string(),
@@ -265,6 +307,8 @@ impl WebSocketClient {
             reconnect_attempt: Arc::new(Mutex::new(0)),
             mek: Arc::new(Mutex::new(None)),
             session_key: Arc::new(Mutex::new(None)),
+            key_epoch: Arc::new(Mutex::new(0)),
+            ratchet: Arc::new(Mutex::new(None)),
         };
 
         // Perform initial connection
@@ -288,14 +332,13 @@ impl WebSocketClient {
             .map_err(|e| CliError::WebSocketError(format!("Failed to build request: {}", e)))?;
 
         // Add authorization header
-        let auth_value = HeaderValue::from_str(&format!("Bearer {}", self.token))
+        let token = self.token.lock().await.clone();
+        let auth_value = HeaderValue::from_str(&format!("Bearer {}", token))
             .map_err(|e| CliError::WebSocketError(format!("Invalid auth header: {}", e)))?;
         request.headers_mut().insert(AUTHORIZATION, auth_value);
 
         // Connect to server
-        let (ws_stream, response) = connect_async(request)
-            .await
-            .map_err(|e| CliError::WebSocketError(format!("Failed to connect: {}", e)))?;
+        let (ws_stream, response) = connect_websocket(request).await?;
 
         debug!(
             status = %response.status(),
@@ -352,6 +395,18 @@ impl WebSocketClient {
     pub async fn send_output(&self, data: &[u8]) -> Result<()> {
         let timestamp = Utc::now().to_rfc3339();
 
+        // Forward-secrecy mode: encrypt with the current ratchet key
+        let ratchet = self.ratchet.lock().await.clone();
+        if let Some(ratchet) = ratchet {
+            let encrypted = ratchet.lock().await.encrypt(data);
+            let msg = OutgoingMessage::EncryptedOutput {
+                session_id: self.session_id.clone(),
+                encrypted,
+                timestamp,
+            };
+            return self.send_message(&msg).await;
+        }
+
         // Get session key (always available since MEK is auto-generated)
         let session_key = self
             .get_or_derive_session_key()
@@ -359,7 +414,9 @@ impl WebSocketClient {
             .expect("MEK should always be available for E2EE");
 
         // Always encrypt - MEK is always available
-        let encrypted = encrypt_content(&session_key, data);
+        let mut encrypted = encrypt_content(&session_key, data);
+        let epoch = *self.key_epoch.lock().await;
+        encrypted.epoch = (epoch > 0).then_some(epoch);
         let msg = OutgoingMessage::EncryptedOutput {
             session_id: self.session_id.clone(),
             encrypted,
@@ -379,10 +436,11 @@ impl WebSocketClient {
         drop(session_key_guard);
 
         // Check if MEK is available
+        let epoch = *self.key_epoch.lock().await;
         let mek_guard = self.mek.lock().await;
         if let Some(ref mek) = *mek_guard {
-            // Derive session key from MEK
-            let derived_key = derive_session_key(mek, &self.session_id);
+            // Derive session key for the current epoch from MEK
+            let derived_key = derive_session_key_for_epoch(mek, &self.session_id, epoch);
             drop(mek_guard);
 
             // Cache the derived key
@@ -459,13 +517,10 @@ impl WebSocketClient {
 
                 Ok(Some(parsed))
             }
-            Message::Ping(data) => {
+            Message::Ping(_) => {
+                // tungstenite queues the pong itself and sends it with the
+                // next read or write
                 debug!("Received WebSocket ping");
-                // Send pong with same data
-                if let Some(sender) = self.sender.lock().await.as_mut() {
-                    let _ = sender.send(Message::Pong(data)).await;
-                }
-                // Continue waiting for next message (don't return ping)
                 Ok(None)
             }
             Message::Pong(_) => {
@@ -574,6 +629,11 @@ impl WebSocketClient {
         Ok(())
     }
 
+    /// Replaces the access token used for subsequent reconnections.
+    pub async fn set_token(&self, token: &str) {
+        *self.token.lock().await = token.to_string();
+    }
+
     /// Attempts to reconnect with exponential backoff.
     ///
     /// # Returns
@@ -617,6 +677,7 @@ impl WebSocketClient {
                     error!(error = %e, "Failed to send session_attach");
                     return Err(e);
                 }
+                self.announce_key_epoch(false).await?;
                 info!("Reconnected successfully");
                 Ok(true)
             }
@@ -694,6 +755,111 @@ impl WebSocketClient {
         *self.mek.lock().await = None;
     }
 
+    /// Switches to a new session key epoch announced by the server.
+    ///
+    /// Output sent afterwards is encrypted with the new epoch key, so guests
+    /// whose share was revoked can no longer read it.
+    pub async fn set_key_epoch(&self, epoch: u32) {
+        let mut current = self.key_epoch.lock().await;
+        if *current == epoch {
+            return;
+        }
+
+        info!(key_epoch = epoch, "Session key rotated");
+        *current = epoch;
+        drop(current);
+        *self.session_key.lock().await = None;
+
+        // Revoked guests could keep ratcheting the old chain, so restart it
+        let ratchet = self.ratchet.lock().await.clone();
+        if let Some(ratchet) = ratchet {
+            let fs_epoch = ratchet.lock().await.reseed();
+            debug!(fs_epoch, "Reseeded forward-secrecy ratchet");
+            if let Err(e) = self.announce_key_epoch(true).await {
+                debug!(error = %e, "Failed to announce reseeded key epoch");
+            }
+        }
+    }
+
+    /// Enables forward secrecy with the given ratchet and announces its epoch.
+    pub async fn set_ratchet(&self, ratchet: SharedRatchet) -> Result<()> {
+        info!("Forward secrecy enabled");
+        *self.ratchet.lock().await = Some(ratchet);
+        self.announce_key_epoch(false).await
+    }
+
+    /// Announces the current forward-secrecy epoch to guests.
+    ///
+    /// Does nothing if forward secrecy is disabled.
+    pub async fn announce_key_epoch(&self, reseeded: bool) -> Result<()> {
+        let ratchet = self.ratchet.lock().await.clone();
+        let Some(ratchet) = ratchet else {
+            return Ok(());
+        };
+
+        let key_epoch = ratchet.lock().await.epoch();
+        let msg = OutgoingMessage::KeyEpoch {
+            session_id: self.session_id.clone(),
+            key_epoch,
+            reseeded,
+        };
+
+        self.send_message(&msg).await
+    }
+
+    /// Answers a guest's request for the current forward-secrecy key.
+    ///
+    /// The key is only granted if the proof decrypts to the guest's public
+    /// key under the session key of the current share epoch.
+    pub async fn handle_key_request(
+        &self,
+        request_id: &str,
+        public_key: &str,
+        proof: &EncryptedContent,
+    ) -> Result<()> {
+        let ratchet = self.ratchet.lock().await.clone();
+        let Some(ratchet) = ratchet else {
+            debug!("Ignoring key request: forward secrecy is disabled");
+            return Ok(());
+        };
+
+        let share_epoch = *self.key_epoch.lock().await;
+        if proof.ratchet || proof.epoch.unwrap_or(0) != share_epoch {
+            return Err(CliError::CryptoError(
+                "Key request proof uses a stale session key".into(),
+            ));
+        }
+
+        let session_key = self
+            .get_or_derive_session_key()
+            .await
+            .ok_or_else(|| CliError::CryptoError("Cannot verify key request: no MEK set".into()))?;
+        let public_key = decode_base64(public_key)?;
+        if decrypt_content(&session_key, proof)? != public_key {
+            return Err(CliError::CryptoError(
+                "Key request proof does not match its public key".into(),
+            ));
+        }
+
+        let wrapped = {
+            let ratchet = ratchet.lock().await;
+            wrap_session_key(ratchet.current_key(), ratchet.epoch(), &public_key)?
+        };
+        debug!(
+            request_id,
+            key_epoch = wrapped.epoch,
+            "Granting forward-secrecy key"
+        );
+
+        let msg = OutgoingMessage::KeyGrant {
+            session_id: self.session_id.clone(),
+            request_id: request_id.to_string(),
+            wrapped,
+        };
+
+        self.send_message(&msg).await
+    }
+
     /// Returns whether E2EE is currently enabled (MEK is set).
     pub async fn is_e2ee_enabled(&self) -> bool {
         self.mek.lock().await.is_some()
@@ -701,12 +867,44 @@ impl WebSocketClient {
 
     /// Decrypts an incoming encrypted prompt message.
     ///
+    /// Prompts must use the current session key epoch, or the one just
+    /// before it for prompts sent while a rotation was under way. Older
+    /// epochs are rejected, so that a guest whose share was revoked cannot
+    /// keep typing with the key it still holds.
+    ///
     /// Returns the decrypted text or an error if decryption fails
     /// (e.g., wrong key or corrupted data).
     pub async fn decrypt_prompt(&self, encrypted: &EncryptedContent) -> Result<String> {
-        let session_key = self.get_or_derive_session_key().await.ok_or_else(|| {
-            CliError::CryptoError("Cannot decrypt: E2EE not enabled (no MEK set)".into())
-        })?;
+        let epoch = encrypted.epoch.unwrap_or(0);
+        let current_epoch = *self.key_epoch.lock().await;
+        let session_key = if encrypted.ratchet {
+            // Input from a guest holding the forward-secrecy key
+            let ratchet = self.ratchet.lock().await.clone();
+            match ratchet {
+                Some(ratchet) => ratchet.lock().await.key_for_epoch(epoch).cloned(),
+                None => None,
+            }
+            .ok_or_else(|| {
+                CliError::CryptoError(format!("No forward-secrecy key for epoch {}", epoch))
+            })?
+        } else if epoch == current_epoch {
+            self.get_or_derive_session_key()
+                .await
+                .ok_or_else(no_mek_error)?
+        } else if epoch.checked_add(1) == Some(current_epoch) {
+            // Prompt encrypted just before the rotation
+            self.mek
+                .lock()
+                .await
+                .as_ref()
+                .map(|mek| derive_session_key_for_epoch(mek, &self.session_id, epoch))
+                .ok_or_else(no_mek_error)?
+        } else {
+            return Err(CliError::CryptoError(format!(
+                "Prompt uses key epoch {}, but the session is at epoch {}",
+                epoch, current_epoch
+            )));
+        };
 
         let plaintext = decrypt_content(&session_key, encrypted)?;
 
@@ -716,6 +914,11 @@ impl WebSocketClient {
     }
 }
 
+/// Error returned when decrypting without a MEK.
+fn no_mek_error() -> CliError {
+    CliError::CryptoError("Cannot decrypt: E2EE not enabled (no MEK set)".into())
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -923,6 +1126,8 @@ mod tests {
                 nonce: "dGVzdG5vbmNlMTIz".to_string(),
                 ciphertext: "ZW5jcnlwdGVkZGF0YQ==".to_string(),
                 tag: "dGFnMTIzNDU2Nzg5MDEy".to_string(),
+                epoch: None,
+                ratchet: false,
             },
             timestamp: "2025-01-13T10:00:00Z".to_string(),
         };
@@ -1053,4 +1258,66 @@ mod tests {
             _ => panic!("Expected InputRejected message"),
         }
     }
+
+    #[test]
+    fn test_incoming_key_rotated_deserialization() {
+        let json = r#"{
+            "type": "key_rotated",
+            "session_id": "01HQXK7V8G3N5M2R4P6T1W9Y0Z",
+            "key_epoch": 1
+        }"#;
+
+        let msg: IncomingMessage = serde_json::from_str(json).unwrap();
+        match msg {
+            IncomingMessage::KeyRotated {
+                session_id,
+                key_epoch,
+            } => {
+                assert_eq!(session_id, "01HQXK7V8G3N5M2R4P6T1W9Y0Z");
+                assert_eq!(key_epoch, 1);
+            }
+            _ => panic!("Expected KeyRotated message"),
+        }
+    }
+
+    #[test]
+    fn test_key_epoch_serialization() {
+        let msg = OutgoingMessage::KeyEpoch {
+            session_id: "01HQXK7V8G3N5M2R4P6T1W9Y0Z".to_string(),
+            key_epoch: 4,
+            reseeded: false,
+        };
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""type":"key_epoch""#));
+        assert!(json.contains(r#""key_epoch":4"#));
+        assert!(json.contains(r#""reseeded":false"#));
+    }
+
+    #[test]
+    fn test_incoming_key_request_deserialization() {
+        let json = r#"{
+            "type": "key_request",
+            "session_id": "01HQXK7V8G3N5M2R4P6T1W9Y0Z",
+            "request_id": "01HQXK8V8G3N5M2R4P6T1W9Y0Z",
+            "public_key": "BAAA",
+            "proof": {
+                "v": 1,
+                "nonce": "dGVzdG5vbmNlMTIz",
+                "ciphertext": "ZW5jcnlwdGVkZGF0YQ==",
+                "tag": "dGFnMTIzNDU2Nzg5MDEy"
+            }
+        }"#;
+
+        let msg: IncomingMessage = serde_json::from_str(json).unwrap();
+        match msg {
+            IncomingMessage::KeyRequest {
+                request_id, proof, ..
+            } => {
+                assert_eq!(request_id, "01HQXK8V8G3N5M2R4P6T1W9Y0Z");
+                assert!(!proof.ratchet);
+            }
+            _ => panic!("Expected KeyRequest message"),
+        }
+    }
 }
@@ -0,0 +1,800 @@
+//! End-to-end tests against the in-process mock hub in `support`.
+//!
+//! A host (`WebSocketClient`, optionally fed by a real PTY) and guests
+//! (raw WebSocket clients using the guest wire types and `SessionKeys`)
+//! talk through the mock, so these tests cover the protocol, E2EE and
+//! reconnects together rather than message serialization alone.
+
+mod support;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::{HeaderValue, AUTHORIZATION};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use klaas::api_client::{ApiClient, ShareGrant};
+use klaas::auth;
+use klaas::commands::{send, share};
+use klaas::crypto::{derive_session_key_for_epoch, encrypt_content, DeviceKeypair, KeyRatchet};
+use klaas::error::CliError;
+use klaas::guest::keys::SessionKeys;
+use klaas::guest::terminal::{
+    is_disconnect, GuestClient, GuestIncomingMessage, GuestOutgoingMessage, InputRejectionReason,
+};
+use klaas::pty::PtyManager;
+use klaas::types::{DeviceId, SessionId, ShareAccess};
+use klaas::websocket::{IncomingMessage, WebSocketClient};
+
+use support::MockHub;
+
+/// How long to wait for a single message before failing the test.
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects a host for a new session and enables E2EE with the hub's MEK.
+async fn connect_host(hub: &MockHub, name: Option<&str>) -> WebSocketClient {
+    let session_id = SessionId::new();
+    let device_id = DeviceId::new();
+    let host = WebSocketClient::connect(
+        &hub.ws_url,
+        &hub.token,
+        session_id.as_str(),
+        device_id.as_str(),
+        "test-host",
+        "/tmp",
+        name,
+    )
+    .await
+    .expect("host should connect");
+    host.set_mek(hub.mek.clone()).await;
+
+    let id = host.session_id().to_string();
+    hub.wait_until("session_attach", |hub| hub.attach_count(&id) == 1)
+        .await;
+    host
+}
+
+/// Receives the next message for the host, skipping WebSocket control frames.
+async fn host_recv(host: &WebSocketClient) -> IncomingMessage {
+    tokio::time::timeout(RECV_TIMEOUT, async {
+        loop {
+            match host.recv().await.expect("host connection failed") {
+                Some(msg) => return msg,
+                None => assert!(host.is_connected().await, "host connection closed"),
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for a host message")
+}
+
+/// A guest connection speaking the guest wire protocol.
+struct Guest {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    session_id: String,
+    keys: SessionKeys,
+}
+
+impl Guest {
+    /// Connects as the session owner (keys derived from the MEK).
+    async fn connect(hub: &MockHub, session_id: &str, name: &str) -> Self {
+        Self::connect_with_query(hub, session_id, &format!("device_name={}", name)).await
+    }
+
+    /// Connects as the session owner with extra query parameters.
+    async fn connect_with_query(hub: &MockHub, session_id: &str, query: &str) -> Self {
+        let url = format!(
+            "{}?session_id={}&client=guest&{}",
+            hub.ws_url, session_id, query
+        );
+        let mut request = url.into_client_request().unwrap();
+        let auth = HeaderValue::from_str(&format!("Bearer {}", hub.token)).unwrap();
+        request.headers_mut().insert(AUTHORIZATION, auth);
+
+        let (ws, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .expect("guest should connect");
+        Self {
+            ws,
+            session_id: session_id.to_string(),
+            keys: SessionKeys::owner(session_id, hub.mek.clone()),
+        }
+    }
+
+    async fn send(&mut self, msg: &GuestOutgoingMessage) {
+        let json = serde_json::to_string(msg).unwrap();
+        self.ws.send(Message::Text(json)).await.unwrap();
+    }
+
+    async fn recv(&mut self) -> GuestIncomingMessage {
+        loop {
+            let msg = tokio::time::timeout(RECV_TIMEOUT, self.ws.next())
+                .await
+                .expect("timed out waiting for a guest message")
+                .expect("guest connection closed")
+                .expect("guest connection failed");
+            if let Message::Text(text) = msg {
+                return serde_json::from_str(&text).expect("unknown message for guest");
+            }
+        }
+    }
+
+    /// Receives messages until the next one of the expected type.
+    async fn recv_until<T>(
+        &mut self,
+        mut pick: impl FnMut(GuestIncomingMessage) -> Option<T>,
+    ) -> T {
+        loop {
+            if let Some(value) = pick(self.recv().await) {
+                return value;
+            }
+        }
+    }
+
+    /// Receives the next output message and decrypts it.
+    async fn recv_output(&mut self) -> String {
+        let encrypted = self
+            .recv_until(|msg| match msg {
+                GuestIncomingMessage::Output { encrypted, .. } => Some(encrypted),
+                _ => None,
+            })
+            .await;
+        let plaintext = self
+            .keys
+            .decrypt(&encrypted)
+            .expect("guest should decrypt output");
+        String::from_utf8(plaintext).unwrap()
+    }
+
+    /// Receives output until the accumulated plaintext contains `needle`.
+    async fn recv_output_containing(&mut self, needle: &str) -> String {
+        let mut seen = String::new();
+        while !seen.contains(needle) {
+            seen.push_str(&self.recv_output().await);
+        }
+        seen
+    }
+
+    /// Reads session_info and history, returning the decrypted history.
+    async fn recv_history(&mut self) -> Vec<String> {
+        let info = match self.recv().await {
+            GuestIncomingMessage::SessionInfo(info) => info,
+            other => panic!("expected session_info, got {:?}", other),
+        };
+        assert_eq!(info.session_id, self.session_id);
+
+        let batch = match self.recv().await {
+            GuestIncomingMessage::History(batch) => batch,
+            other => panic!("expected history, got {:?}", other),
+        };
+        batch
+            .entries
+            .iter()
+            .map(|entry| String::from_utf8(self.keys.decrypt(&entry.encrypted).unwrap()).unwrap())
+            .collect()
+    }
+
+    async fn send_prompt(&mut self, text: &str) {
+        let encrypted = self.keys.encrypt(text.as_bytes()).unwrap();
+        let msg = GuestOutgoingMessage::Prompt {
+            session_id: self.session_id.clone(),
+            encrypted,
+        };
+        self.send(&msg).await;
+    }
+
+    async fn recv_rejection(&mut self) -> (InputRejectionReason, Option<String>) {
+        self.recv_until(|msg| match msg {
+            GuestIncomingMessage::InputRejected(rejected) => {
+                Some((rejected.reason, rejected.holder_name))
+            }
+            _ => None,
+        })
+        .await
+    }
+}
+
+#[tokio::test]
+async fn test_output_reaches_guest_with_history() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+
+    host.send_output(b"before the guest joined").await.unwrap();
+    hub.wait_until("history", |hub| hub.history_len(&session_id) == 1)
+        .await;
+
+    let mut guest = Guest::connect(&hub, &session_id, "viewer").await;
+    assert_eq!(guest.recv_history().await, ["before the guest joined"]);
+
+    host.send_output(b"live output").await.unwrap();
+    assert_eq!(guest.recv_output().await, "live output");
+}
+
+#[tokio::test]
+async fn test_guest_prompt_reaches_host() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    assert_eq!(hub.input_mode(&session_id).as_deref(), Some("auto-lock"));
+
+    let mut guest = Guest::connect(&hub, &session_id, "alice").await;
+    guest.recv_history().await;
+    guest.send_prompt("ls -la\r").await;
+
+    let holder = guest
+        .recv_until(|msg| match msg {
+            GuestIncomingMessage::LockAcquired(lock) => Some(lock.holder_name),
+            _ => None,
+        })
+        .await;
+    assert_eq!(holder, "alice");
+
+    loop {
+        match host_recv(&host).await {
+            IncomingMessage::Prompt {
+                encrypted, source, ..
+            } => {
+                assert_eq!(source, "guest");
+                assert_eq!(host.decrypt_prompt(&encrypted).await.unwrap(), "ls -la\r");
+                break;
+            }
+            IncomingMessage::LockAcquired { holder_name, .. } => assert_eq!(holder_name, "alice"),
+            other => panic!("unexpected message for host: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_input_lock_and_rejections() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+
+    let mut alice = Guest::connect(&hub, &session_id, "alice").await;
+    let mut bob = Guest::connect(&hub, &session_id, "bob").await;
+    alice.recv_history().await;
+    bob.recv_history().await;
+
+    // Alice takes the lock, so Bob's input is rejected while she types
+    alice.send_prompt("a").await;
+    alice
+        .recv_until(|msg| matches!(msg, GuestIncomingMessage::LockAcquired(_)).then_some(()))
+        .await;
+    bob.send_prompt("b").await;
+    let (reason, holder) = bob.recv_rejection().await;
+    assert!(matches!(reason, InputRejectionReason::LockHeld));
+    assert_eq!(holder.as_deref(), Some("alice"));
+
+    // Guests that connected read-only (klaas watch) may never send input
+    let mut carol =
+        Guest::connect_with_query(&hub, &session_id, "device_name=carol&read_only=true").await;
+    carol.recv_history().await;
+    carol.send_prompt("c").await;
+    let (reason, _) = carol.recv_rejection().await;
+    assert!(matches!(reason, InputRejectionReason::ReadOnly));
+
+    hub.set_input_mode(&session_id, "host-only");
+    alice.send_prompt("a").await;
+    let (reason, _) = alice.recv_rejection().await;
+    assert!(matches!(reason, InputRejectionReason::HostOnly));
+
+    // Once the host detaches, guests are told and input has nowhere to go
+    host.close().await.unwrap();
+    bob.recv_until(|msg| matches!(msg, GuestIncomingMessage::SessionDetached { .. }).then_some(()))
+        .await;
+    bob.send_prompt("b").await;
+    let (reason, _) = bob.recv_rejection().await;
+    assert!(matches!(reason, InputRejectionReason::HostDetached));
+}
+
+#[tokio::test]
+async fn test_ping_pong() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let mut guest = Guest::connect(&hub, &session_id, "viewer").await;
+    guest.recv_history().await;
+
+    hub.ping_all();
+
+    assert!(matches!(host_recv(&host).await, IncomingMessage::Ping));
+    host.send_pong().await.unwrap();
+    assert!(matches!(guest.recv().await, GuestIncomingMessage::Ping));
+    guest.send(&GuestOutgoingMessage::Pong).await;
+
+    hub.wait_until("pongs", |hub| hub.pongs() == 2).await;
+}
+
+/// Keeps a guest client receiving until `condition` holds, failing the test
+/// after a few seconds.
+async fn guest_client_recv_until(guest: &GuestClient, condition: impl Fn(&GuestClient) -> bool) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !condition(guest) {
+        assert!(tokio::time::Instant::now() < deadline, "timed out");
+        if let Ok(result) = tokio::time::timeout(Duration::from_millis(20), guest.recv()).await {
+            assert!(result.unwrap().is_some(), "connection closed");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_guest_client_keepalive() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let keys = SessionKeys::owner(&session_id, hub.mek.clone());
+    let guest = GuestClient::connect(&hub.ws_url, &hub.token, &session_id, keys, false)
+        .await
+        .unwrap();
+
+    // Heartbeat pings from the server are answered while receiving
+    hub.ping_all();
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), guest.recv()).await {
+            Ok(Ok(Some(GuestIncomingMessage::Ping))) => break,
+            Ok(Ok(Some(_))) => {}
+            other => panic!("expected ping, got {:?}", other),
+        }
+    }
+    hub.wait_until("guest pong", |hub| hub.pongs() == 1).await;
+
+    // WebSocket ping frames are answered by tungstenite, once
+    hub.ping_guest_frames();
+    let pinged_at = tokio::time::Instant::now();
+    while pinged_at.elapsed() < Duration::from_millis(300) {
+        let _ = tokio::time::timeout(Duration::from_millis(20), guest.recv()).await;
+    }
+    assert_eq!(hub.pong_frames(), 1);
+
+    // The guest's own pings measure the round-trip time
+    guest.send_ping().await.unwrap();
+    guest_client_recv_until(&guest, |guest| guest.rtt().is_some()).await;
+    assert!(!guest.is_half_open(Duration::ZERO));
+
+    // A server that stops answering is detected as half-open
+    hub.stall_connections();
+    guest.send_ping().await.unwrap();
+    let stalled_at = tokio::time::Instant::now();
+    while stalled_at.elapsed() < Duration::from_millis(300) {
+        let _ = tokio::time::timeout(Duration::from_millis(20), guest.recv()).await;
+    }
+    assert!(guest.is_half_open(Duration::from_millis(200)));
+}
+
+#[tokio::test]
+async fn test_guest_client_reconnects_after_dropped_socket() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let keys = SessionKeys::owner(&session_id, hub.mek.clone());
+    let guest = GuestClient::connect(&hub.ws_url, &hub.token, &session_id, keys, false)
+        .await
+        .unwrap();
+    hub.wait_until("guest", |hub| hub.guest_count(&session_id) == 1)
+        .await;
+
+    // The server goes away mid-session: reads end and sends fail, but both
+    // only call for a reconnect
+    hub.drop_connections();
+    tokio::time::timeout(RECV_TIMEOUT, async {
+        while let Ok(Some(_)) = guest.recv().await {}
+    })
+    .await
+    .expect("guest should notice the dropped connection");
+    let err = tokio::time::timeout(RECV_TIMEOUT, async {
+        loop {
+            if let Err(e) = guest.send_input(b"lost").await {
+                return e;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("sending on a dropped socket should fail");
+    assert!(is_disconnect(&err), "{} should not end the guest", err);
+
+    assert!(host.reconnect().await.unwrap());
+    guest.reconnect(&hub.token).await.unwrap();
+    hub.wait_until("guest back", |hub| hub.guest_count(&session_id) == 1)
+        .await;
+
+    guest.send_input(b"after reconnect\r").await.unwrap();
+    let prompt = loop {
+        if let IncomingMessage::Prompt { encrypted, .. } = host_recv(&host).await {
+            break host.decrypt_prompt(&encrypted).await.unwrap();
+        }
+    };
+    assert_eq!(prompt, "after reconnect\r");
+
+    host.send_output(b"still here").await.unwrap();
+    tokio::time::timeout(RECV_TIMEOUT, async {
+        loop {
+            if let Some(GuestIncomingMessage::Output { .. }) = guest.recv().await.unwrap() {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("output should reach the reconnected guest");
+}
+
+#[tokio::test]
+async fn test_send_delivers_input_and_waits_for_idle() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let connect = || async {
+        let keys = SessionKeys::owner(&session_id, hub.mek.clone());
+        GuestClient::connect(&hub.ws_url, &hub.token, &session_id, keys, false)
+            .await
+            .unwrap()
+    };
+
+    let guest = connect().await;
+    send::deliver(&guest, b"run the tests\r", None)
+        .await
+        .unwrap();
+    let prompt = loop {
+        if let IncomingMessage::Prompt { encrypted, .. } = host_recv(&host).await {
+            break host.decrypt_prompt(&encrypted).await.unwrap();
+        }
+    };
+    assert_eq!(prompt, "run the tests\r");
+
+    // --wait-idle returns once the agent is done with the prompt
+    drop(guest);
+    hub.wait_until("guest gone", |hub| hub.guest_count(&session_id) == 0)
+        .await;
+    let guest = connect().await;
+    let agent = async {
+        loop {
+            if let IncomingMessage::Prompt { .. } = host_recv(&host).await {
+                break;
+            }
+        }
+        hub.set_agent_mode(&session_id, "active");
+        hub.set_agent_mode(&session_id, "waiting_for_input");
+    };
+    let (result, ()) = tokio::join!(send::deliver(&guest, b"\r", Some(RECV_TIMEOUT)), agent);
+    result.unwrap();
+
+    // An idle mode before the agent started working doesn't count
+    drop(guest);
+    hub.wait_until("guest gone", |hub| hub.guest_count(&session_id) == 0)
+        .await;
+    let guest = connect().await;
+    let agent = async {
+        loop {
+            if let IncomingMessage::Prompt { .. } = host_recv(&host).await {
+                break;
+            }
+        }
+        hub.set_agent_mode(&session_id, "idle");
+    };
+    let wait = send::deliver(&guest, b"\r", Some(Duration::from_millis(500)));
+    let (result, ()) = tokio::join!(wait, agent);
+    assert!(result.unwrap_err().to_string().contains("Timed out"));
+
+    // Rejected input is an error, so scripts notice
+    hub.set_input_mode(&session_id, "host-only");
+    let guest = connect().await;
+    let err = send::deliver(&guest, b"x", None).await.unwrap_err();
+    assert!(err.to_string().contains("only accepts input from its host"));
+}
+
+#[tokio::test]
+async fn test_resize_reaches_host() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+
+    hub.resize(host.session_id(), 120, 40);
+
+    match host_recv(&host).await {
+        IncomingMessage::Resize { cols, rows, .. } => assert_eq!((cols, rows), (120, 40)),
+        other => panic!("expected resize, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_host_reconnects_and_flushes_queued_output() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+
+    hub.drop_connections();
+    assert!(!hub.is_attached(&session_id));
+
+    // The host notices the dropped socket on its next read
+    tokio::time::timeout(RECV_TIMEOUT, async {
+        while host.is_connected().await {
+            let _ = host.recv().await;
+        }
+    })
+    .await
+    .expect("host should notice the dropped connection");
+
+    host.send_output(b"written while offline").await.unwrap();
+    assert!(host.reconnect().await.unwrap());
+
+    hub.wait_until("re-attach", |hub| hub.attach_count(&session_id) == 2)
+        .await;
+    assert!(hub.is_attached(&session_id));
+
+    let mut guest = Guest::connect(&hub, &session_id, "viewer").await;
+    assert_eq!(guest.recv_history().await, ["written while offline"]);
+}
+
+#[tokio::test]
+async fn test_forward_secrecy_key_exchange() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let mut guest = Guest::connect(&hub, &session_id, "viewer").await;
+    guest.recv_history().await;
+
+    let ratchet = Arc::new(Mutex::new(KeyRatchet::new(&session_id)));
+    host.set_ratchet(ratchet).await.unwrap();
+
+    // The guest follows the announced epoch and asks for the key
+    let (key_epoch, reseeded) = guest
+        .recv_until(|msg| match msg {
+            GuestIncomingMessage::KeyEpoch {
+                key_epoch,
+                reseeded,
+                ..
+            } => Some((key_epoch, reseeded)),
+            _ => None,
+        })
+        .await;
+    assert!(guest.keys.set_ratchet_epoch(key_epoch, reseeded));
+    let request = guest.keys.key_request().unwrap();
+    let msg = GuestOutgoingMessage::KeyRequest {
+        session_id: session_id.clone(),
+        request_id: request.request_id,
+        public_key: request.public_key,
+        proof: request.proof,
+    };
+    guest.send(&msg).await;
+
+    match host_recv(&host).await {
+        IncomingMessage::KeyRequest {
+            request_id,
+            public_key,
+            proof,
+            ..
+        } => host
+            .handle_key_request(&request_id, &public_key, &proof)
+            .await
+            .unwrap(),
+        other => panic!("expected key_request, got {:?}", other),
+    }
+
+    let (request_id, wrapped) = guest
+        .recv_until(|msg| match msg {
+            GuestIncomingMessage::KeyGrant {
+                request_id,
+                wrapped,
+                ..
+            } => Some((request_id, wrapped)),
+            _ => None,
+        })
+        .await;
+    assert!(guest.keys.accept_key_grant(&request_id, &wrapped).unwrap());
+
+    host.send_output(b"forward secret").await.unwrap();
+    assert_eq!(guest.recv_output().await, "forward secret");
+
+    // Guest input is now encrypted with the ratchet key, which the host knows
+    guest.send_prompt("from ratchet").await;
+    loop {
+        if let IncomingMessage::Prompt { encrypted, .. } = host_recv(&host).await {
+            assert!(encrypted.ratchet);
+            assert_eq!(
+                host.decrypt_prompt(&encrypted).await.unwrap(),
+                "from ratchet"
+            );
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_pty_round_trip() {
+    let hub = MockHub::start().await;
+    let host = Arc::new(connect_host(&hub, None).await);
+    let session_id = host.session_id().to_string();
+    let mut guest = Guest::connect(&hub, &session_id, "viewer").await;
+    guest.recv_history().await;
+
+    let pty = PtyManager::spawn_with_size("cat", &[], HashMap::new(), 80, 24).unwrap();
+
+    // Forward PTY output to the hub like the host's main loop does
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let reader = pty.clone();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = reader.read_blocking(&mut buf) {
+            if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    let forwarder = host.clone();
+    let forward = tokio::spawn(async move {
+        while let Some(data) = rx.recv().await {
+            forwarder.send_output(&data).await.unwrap();
+        }
+    });
+
+    // Guest input goes through the hub into the PTY, and cat echoes it back
+    guest.send_prompt("hello through the hub\r").await;
+    let prompt = loop {
+        if let IncomingMessage::Prompt { encrypted, .. } = host_recv(&host).await {
+            break host.decrypt_prompt(&encrypted).await.unwrap();
+        }
+    };
+    pty.write_blocking(prompt.as_bytes()).unwrap();
+
+    let seen = guest.recv_output_containing("hello through the hub").await;
+    assert!(seen.contains("hello through the hub"), "{:?}", seen);
+
+    pty.kill().await.unwrap();
+    forward.abort();
+}
+
+#[tokio::test]
+async fn test_sessions_api() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, Some("e2e")).await;
+
+    let api = ApiClient::new(&hub.api_url, &hub.token);
+    let sessions = api.get_sessions().await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].session_id, host.session_id());
+    assert_eq!(sessions[0].status, "attached");
+    assert_eq!(sessions[0].device_name, "test-host");
+
+    let by_name = api.get_session("e2e").await.unwrap().unwrap();
+    assert_eq!(by_name.session_id, host.session_id());
+    assert!(api.get_session("missing").await.unwrap().is_none());
+
+    host.close().await.unwrap();
+    let id = host.session_id().to_string();
+    hub.wait_until("detach", |hub| !hub.is_attached(&id)).await;
+    let session = api.get_session(&id).await.unwrap().unwrap();
+    assert_eq!(session.status, "detached");
+
+    let unauthorized = ApiClient::new(&hub.api_url, "not-a-token");
+    assert!(unauthorized.get_sessions().await.is_err());
+}
+
+#[tokio::test]
+async fn test_revoke_keeps_share_until_key_rotated() {
+    let hub = MockHub::start().await;
+    let session_id = SessionId::new().to_string();
+    let api = ApiClient::new(&hub.api_url, &hub.token);
+    for user in ["alice", "bob"] {
+        let device = DeviceKeypair::generate();
+        hub.add_user_device(user, &format!("{}-device", user), &device.public_key());
+        let grant = ShareGrant {
+            user: user.to_string(),
+            access: ShareAccess::ReadWrite,
+            keys: Vec::new(),
+        };
+        api.create_share(&session_id, &grant).await.unwrap();
+    }
+
+    // A failed rotation leaves the share in place, so revoking can be retried
+    hub.set_fail_key_rotation(true);
+    let result = share::revoke(&api, &hub.mek, &session_id, "bob").await;
+    assert!(result.is_err());
+    assert_eq!(hub.shared_users(&session_id), vec!["alice", "bob"]);
+    assert_eq!(hub.key_epoch(&session_id), 0);
+
+    hub.set_fail_key_rotation(false);
+    share::revoke(&api, &hub.mek, &session_id, "bob")
+        .await
+        .unwrap();
+    assert_eq!(hub.shared_users(&session_id), vec!["alice"]);
+    assert_eq!(hub.key_epoch(&session_id), 1);
+
+    let err = share::revoke(&api, &hub.mek, &session_id, "bob")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not shared with bob"));
+}
+
+#[tokio::test]
+async fn test_host_rejects_prompts_under_old_key_epochs() {
+    let hub = MockHub::start().await;
+    let host = connect_host(&hub, None).await;
+    let session_id = host.session_id().to_string();
+    let prompt = |epoch: u32| {
+        let key = derive_session_key_for_epoch(&hub.mek, &session_id, epoch);
+        let mut encrypted = encrypt_content(&key, b"rm -rf /\r");
+        encrypted.epoch = (epoch > 0).then_some(epoch);
+        encrypted
+    };
+
+    host.set_key_epoch(1).await;
+    host.set_key_epoch(2).await;
+
+    // A guest revoked two rotations ago still holds the epoch 0 key
+    let err = host.decrypt_prompt(&prompt(0)).await.unwrap_err();
+    assert!(matches!(err, CliError::CryptoError(_)), "{}", err);
+    assert_eq!(host.decrypt_prompt(&prompt(1)).await.unwrap(), "rm -rf /\r");
+    assert_eq!(host.decrypt_prompt(&prompt(2)).await.unwrap(), "rm -rf /\r");
+    assert!(host.decrypt_prompt(&prompt(3)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_rejects_unknown_token() {
+    let hub = MockHub::start().await;
+    let result = WebSocketClient::connect(
+        &hub.ws_url,
+        "not-a-token",
+        SessionId::new().as_str(),
+        DeviceId::new().as_str(),
+        "test-host",
+        "/tmp",
+        None,
+    )
+    .await;
+    assert!(matches!(result, Err(CliError::AuthError(_))));
+}
+
+#[tokio::test]
+async fn test_device_flow_delivers_mek() {
+    let hub = MockHub::start().await;
+
+    let (flow, secret) = auth::start_device_flow_with_ecdh(&hub.api_url, "test-device")
+        .await
+        .unwrap();
+    hub.approve_all();
+    let (tokens, mek) =
+        auth::poll_for_token_with_mek(&hub.api_url, &flow.device_code, secret, flow.interval, 30)
+            .await
+            .unwrap();
+
+    assert_eq!(mek.as_bytes(), hub.mek.as_bytes());
+    assert!(hub.is_valid_token(&tokens.access_token));
+    assert!(auth::token_expiry(&tokens.access_token).is_some());
+
+    // Refreshing replaces the access token; revoking ends the session
+    let refreshed = auth::refresh_token(&hub.api_url, &tokens.refresh_token)
+        .await
+        .unwrap();
+    assert!(!hub.is_valid_token(&tokens.access_token));
+    assert!(hub.is_valid_token(&refreshed.access_token));
+    assert!(matches!(
+        auth::refresh_token(&hub.api_url, &tokens.refresh_token).await,
+        Err(auth::AuthError::InvalidGrant)
+    ));
+
+    auth::revoke_token(&hub.api_url, &refreshed.refresh_token)
+        .await
+        .unwrap();
+    assert!(!hub.is_valid_token(&refreshed.access_token));
+}
+
+#[tokio::test]
+async fn test_pairing_delivers_mek() {
+    let hub = MockHub::start().await;
+
+    let (pairing, secret) = auth::start_pairing(&hub.api_url, "test-device")
+        .await
+        .unwrap();
+    hub.approve_all();
+    let mek = auth::poll_for_pairing(&hub.api_url, &pairing.pairing_code, secret, 30)
+        .await
+        .unwrap();
+
+    assert_eq!(mek.as_bytes(), hub.mek.as_bytes());
+}
@@ -0,0 +1,1098 @@
+//! In-process stand-in for the klaas API and the SessionHub Durable Object.
+//!
+//! [`MockHub`] listens on two local ports:
+//! - a WebSocket endpoint that speaks the SessionHub protocol for hosts and
+//!   guests (session_attach, output fan-out, history, input locks,
+//!   input_rejected, ping, key requests/grants)
+//! - a minimal HTTP/1.1 server with the REST endpoints the CLI calls
+//!   (device flow, token refresh/revoke, /sessions, shares and key
+//!   rotation, dashboard pairing)
+//!
+//! The hub never sees plaintext: it stores and forwards the encrypted
+//! payloads as-is, like the real server.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use hkdf::Hkdf;
+use klaas::crypto::{
+    decode_base64, encode_base64, encrypt_content, generate_ecdh_keypair, EncryptedMEK, SecretKey,
+};
+use sha2::Sha256;
+
+/// Maximum number of output messages kept per session for history.
+const MAX_HISTORY: usize = 1000;
+
+/// Default terminal size reported to guests.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// HKDF info for the pairing key, as used by the CLI and the dashboard.
+const PAIRING_KEY_INFO: &str = "klaas-pairing-v1";
+
+/// How long [`MockHub::wait_until`] waits before failing the test.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Role of a WebSocket connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Host,
+    Guest,
+}
+
+/// An open WebSocket connection.
+struct Connection {
+    role: Role,
+    session_id: String,
+    /// Display name used as lock holder.
+    name: String,
+    /// Guest connected with `read_only=true` and may not send input.
+    read_only: bool,
+    /// Outgoing messages, written by the connection task.
+    tx: mpsc::UnboundedSender<Message>,
+    /// Aborting the task drops the socket without a close frame.
+    abort: AbortHandle,
+}
+
+/// Input lock held by a guest (auto-lock mode).
+struct Lock {
+    conn_id: u64,
+    name: String,
+    last_input: Instant,
+}
+
+/// Server-side state of one session.
+struct SessionRecord {
+    device_id: String,
+    device_name: String,
+    cwd: String,
+    name: Option<String>,
+    started_at: String,
+    attached_at: Option<String>,
+    host: Option<u64>,
+    input_mode: String,
+    idle_timeout: Duration,
+    history: Vec<Value>,
+    lock: Option<Lock>,
+    attach_count: usize,
+}
+
+impl SessionRecord {
+    fn to_json(&self, session_id: &str) -> Value {
+        json!({
+            "session_id": session_id,
+            "device_id": self.device_id,
+            "device_name": self.device_name,
+            "name": self.name,
+            "status": if self.host.is_some() { "attached" } else { "detached" },
+            "started_at": self.started_at,
+            "attached_at": self.attached_at,
+            "cwd": self.cwd,
+        })
+    }
+}
+
+/// Pending device flow or dashboard pairing.
+struct Approval {
+    /// Public key of the CLI's ephemeral ECDH keypair.
+    cli_public_key: Option<Vec<u8>>,
+    approved: bool,
+}
+
+#[derive(Default)]
+struct HubState {
+    next_id: u64,
+    /// Valid access tokens.
+    access_tokens: Vec<String>,
+    /// Refresh token -> access token it was issued with.
+    refresh_tokens: HashMap<String, String>,
+    sessions: HashMap<String, SessionRecord>,
+    connections: HashMap<u64, Connection>,
+    /// Key request ID -> guest connection that asked.
+    key_requests: HashMap<String, u64>,
+    /// Device code -> device flow.
+    device_flows: HashMap<String, Approval>,
+    /// Pairing code -> pairing request.
+    pairings: HashMap<String, Approval>,
+    /// Session ID -> key epoch and share grants.
+    shares: HashMap<String, (u32, Vec<Value>)>,
+    /// User -> registered devices with public keys.
+    user_devices: HashMap<String, Vec<Value>>,
+    /// Fail key rotations, see [`MockHub::set_fail_key_rotation`].
+    fail_key_rotation: bool,
+    pongs: usize,
+    /// WebSocket pong frames received, see [`MockHub::ping_guest_frames`].
+    pong_frames: usize,
+    /// Set once connections stop reading, see [`MockHub::stall_connections`].
+    stalled: watch::Sender<bool>,
+}
+
+impl HubState {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn issue_tokens(&mut self) -> (String, String) {
+        let access = fake_jwt(self.next_id());
+        let refresh = format!("refresh-{}", self.next_id());
+        self.access_tokens.push(access.clone());
+        self.refresh_tokens.insert(refresh.clone(), access.clone());
+        (access, refresh)
+    }
+
+    fn send(&self, conn_id: u64, msg: &Value) {
+        if let Some(conn) = self.connections.get(&conn_id) {
+            let _ = conn.tx.send(Message::Text(msg.to_string()));
+        }
+    }
+
+    fn guests_of(&self, session_id: &str) -> Vec<u64> {
+        self.connections
+            .iter()
+            .filter(|(_, c)| c.role == Role::Guest && c.session_id == session_id)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn send_to_guests(&self, session_id: &str, msg: &Value) {
+        for id in self.guests_of(session_id) {
+            self.send(id, msg);
+        }
+    }
+
+    /// Removes a connection; a host that goes away leaves the session
+    /// detached but keeps its history.
+    fn disconnect(&mut self, conn_id: u64) {
+        let Some(conn) = self.connections.remove(&conn_id) else {
+            return;
+        };
+        if let Some(session) = self.sessions.get_mut(&conn.session_id) {
+            if session.host == Some(conn_id) {
+                session.host = None;
+            }
+            if session.lock.as_ref().map(|l| l.conn_id) == Some(conn_id) {
+                session.lock = None;
+            }
+        }
+        self.key_requests.retain(|_, id| *id != conn_id);
+    }
+}
+
+/// Shared hub state.
+type Shared = Arc<Mutex<HubState>>;
+
+/// Mock klaas server for end-to-end tests.
+///
+/// Dropping the hub stops both listeners and closes all connections.
+pub struct MockHub {
+    /// Base URL of the REST API (no trailing slash).
+    pub api_url: String,
+    /// WebSocket URL.
+    pub ws_url: String,
+    /// Access token accepted by the hub.
+    pub token: String,
+    /// Refresh token belonging to `token`.
+    pub refresh_token: String,
+    /// The account's MEK, handed out by the device flow and pairing.
+    pub mek: SecretKey,
+    state: Shared,
+    listeners: Vec<JoinHandle<()>>,
+}
+
+impl MockHub {
+    /// Starts the hub on two ephemeral local ports.
+    pub async fn start() -> Self {
+        let state: Shared = Arc::default();
+        let (token, refresh_token) = state.lock().unwrap().issue_tokens();
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_url = format!("ws://{}/ws", ws_listener.local_addr().unwrap());
+        let api_url = format!("http://{}", api_listener.local_addr().unwrap());
+
+        let ws_state = state.clone();
+        let ws_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = ws_listener.accept().await {
+                let state = ws_state.clone();
+                let (abort_tx, abort_rx) = tokio::sync::oneshot::channel();
+                let task = tokio::spawn(async move {
+                    if let Ok(abort) = abort_rx.await {
+                        serve_websocket(state, stream, abort).await;
+                    }
+                });
+                let _ = abort_tx.send(task.abort_handle());
+            }
+        });
+
+        let mek = SecretKey::random();
+        let api_state = state.clone();
+        let api_mek = mek.clone();
+        let api_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = api_listener.accept().await {
+                let state = api_state.clone();
+                let mek = api_mek.clone();
+                tokio::spawn(async move {
+                    let _ = serve_http(state, mek, stream).await;
+                });
+            }
+        });
+
+        Self {
+            api_url,
+            ws_url,
+            token,
+            refresh_token,
+            mek,
+            state,
+            listeners: vec![ws_task, api_task],
+        }
+    }
+
+    /// Waits until `condition` holds for the hub state, failing the test
+    /// after a few seconds.
+    pub async fn wait_until(&self, what: &str, condition: impl Fn(&MockHub) -> bool) {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        while !condition(self) {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Returns true if a host is attached to the session.
+    pub fn is_attached(&self, session_id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .sessions
+            .get(session_id)
+            .is_some_and(|s| s.host.is_some())
+    }
+
+    /// Number of session_attach messages received for the session.
+    pub fn attach_count(&self, session_id: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.sessions.get(session_id).map_or(0, |s| s.attach_count)
+    }
+
+    /// Number of output messages stored for the session.
+    pub fn history_len(&self, session_id: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .sessions
+            .get(session_id)
+            .map_or(0, |s| s.history.len())
+    }
+
+    /// Number of guests connected to the session.
+    pub fn guest_count(&self, session_id: &str) -> usize {
+        self.state.lock().unwrap().guests_of(session_id).len()
+    }
+
+    /// Input mode announced by the host in session_attach.
+    pub fn input_mode(&self, session_id: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.sessions.get(session_id).map(|s| s.input_mode.clone())
+    }
+
+    /// Overrides the input mode of a session (as a dashboard user could).
+    pub fn set_input_mode(&self, session_id: &str, mode: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(session) = state.sessions.get_mut(session_id) {
+            session.input_mode = mode.to_string();
+            session.lock = None;
+        }
+    }
+
+    /// Tells the guests of a session that the agent changed mode, as the
+    /// server does when it receives a hook notification.
+    pub fn set_agent_mode(&self, session_id: &str, mode: &str) {
+        let msg = json!({
+            "type": "mode_change",
+            "session_id": session_id,
+            "mode": mode,
+        });
+        self.state.lock().unwrap().send_to_guests(session_id, &msg);
+    }
+
+    /// Number of pong messages received from hosts and guests.
+    pub fn pongs(&self) -> usize {
+        self.state.lock().unwrap().pongs
+    }
+
+    /// Sends a heartbeat ping to every connection.
+    pub fn ping_all(&self) {
+        let state = self.state.lock().unwrap();
+        for id in state.connections.keys() {
+            state.send(*id, &json!({ "type": "ping" }));
+        }
+    }
+
+    /// Number of WebSocket pong frames received from hosts and guests.
+    pub fn pong_frames(&self) -> usize {
+        self.state.lock().unwrap().pong_frames
+    }
+
+    /// Sends a WebSocket ping frame to every guest.
+    pub fn ping_guest_frames(&self) {
+        let state = self.state.lock().unwrap();
+        for conn in state.connections.values().filter(|c| c.role == Role::Guest) {
+            let _ = conn.tx.send(Message::Ping(b"mock".to_vec()));
+        }
+    }
+
+    /// Forwards a resize request to the host of a session.
+    pub fn resize(&self, session_id: &str, cols: u16, rows: u16) {
+        let state = self.state.lock().unwrap();
+        if let Some(host) = state.sessions.get(session_id).and_then(|s| s.host) {
+            let msg = json!({
+                "type": "resize",
+                "session_id": session_id,
+                "cols": cols,
+                "rows": rows,
+            });
+            state.send(host, &msg);
+        }
+    }
+
+    /// Drops every WebSocket connection without a close frame, as a network
+    /// failure or server restart would.
+    pub fn drop_connections(&self) {
+        let mut state = self.state.lock().unwrap();
+        let ids: Vec<u64> = state.connections.keys().copied().collect();
+        for id in ids {
+            if let Some(conn) = state.connections.get(&id) {
+                conn.abort.abort();
+            }
+            state.disconnect(id);
+        }
+    }
+
+    /// Stops reading from every WebSocket connection while keeping the
+    /// sockets open, as a half-open connection would: nothing sent by the
+    /// clients is answered any more, not even pings.
+    pub fn stall_connections(&self) {
+        self.state.lock().unwrap().stalled.send_replace(true);
+    }
+
+    /// Approves all pending device flows and pairing requests, as the user
+    /// would in the dashboard.
+    pub fn approve_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        for flow in state.device_flows.values_mut() {
+            flow.approved = true;
+        }
+        for pairing in state.pairings.values_mut() {
+            pairing.approved = true;
+        }
+    }
+
+    /// Registers a device of another user that sessions can be shared with.
+    pub fn add_user_device(&self, user: &str, device_id: &str, public_key: &[u8]) {
+        let device = json!({
+            "device_id": device_id,
+            "device_name": format!("{}'s device", user),
+            "public_key": encode_base64(public_key),
+        });
+        let mut state = self.state.lock().unwrap();
+        state
+            .user_devices
+            .entry(user.to_string())
+            .or_default()
+            .push(device);
+    }
+
+    /// Users a session is currently shared with.
+    pub fn shared_users(&self, session_id: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .shares
+            .get(session_id)
+            .map_or(Vec::new(), |(_, shares)| {
+                shares
+                    .iter()
+                    .filter_map(|s| s["user"].as_str().map(String::from))
+                    .collect()
+            })
+    }
+
+    /// Current key epoch of a session.
+    pub fn key_epoch(&self, session_id: &str) -> u32 {
+        let state = self.state.lock().unwrap();
+        state.shares.get(session_id).map_or(0, |(epoch, _)| *epoch)
+    }
+
+    /// Makes key rotation requests fail with a server error.
+    pub fn set_fail_key_rotation(&self, fail: bool) {
+        self.state.lock().unwrap().fail_key_rotation = fail;
+    }
+
+    /// Returns true if the access token is currently valid.
+    pub fn is_valid_token(&self, token: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.access_tokens.iter().any(|t| t == token)
+    }
+}
+
+impl Drop for MockHub {
+    fn drop(&mut self) {
+        for task in &self.listeners {
+            task.abort();
+        }
+        self.drop_connections();
+    }
+}
+
+/// Builds an unsigned JWT with the claims the CLI reads (`sub`, `exp`).
+fn fake_jwt(id: u64) -> String {
+    let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"none","typ":"JWT"}"#);
+    let claims = json!({
+        "sub": format!("user-{}", id),
+        "email": "test@example.com",
+        "exp": Utc::now().timestamp() + 3600,
+    });
+    let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+    format!("{}.{}.signature", header, claims)
+}
+
+// ============================================================================
+// WebSocket
+// ============================================================================
+
+/// Handles one WebSocket connection from handshake to disconnect.
+#[allow(clippy::result_large_err)] // The handshake callback's error type is tungstenite's
+async fn serve_websocket(state: Shared, stream: TcpStream, abort: AbortHandle) {
+    let mut query = HashMap::new();
+    let callback = |request: &Request, response: Response| {
+        let token = request
+            .headers()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !state
+            .lock()
+            .unwrap()
+            .access_tokens
+            .iter()
+            .any(|t| t == token)
+        {
+            let mut error = ErrorResponse::new(Some("invalid token".to_string()));
+            *error.status_mut() = StatusCode::UNAUTHORIZED;
+            return Err(error);
+        }
+
+        query = url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+        Ok(response)
+    };
+
+    let Ok(ws) = tokio_tungstenite::accept_hdr_async(stream, callback).await else {
+        return;
+    };
+    let Some(session_id) = query.get("session_id").cloned() else {
+        return;
+    };
+    let role = match query.get("client").map(String::as_str) {
+        Some("guest") => Role::Guest,
+        _ => Role::Host,
+    };
+
+    let (mut sink, mut stream) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let conn_id = register(&state, role, &session_id, &query, tx, abort);
+    let mut stalled = state.lock().unwrap().stalled.subscribe();
+
+    let writer = async {
+        while let Some(msg) = rx.recv().await {
+            if sink.send(msg).await.is_err() {
+                break;
+            }
+        }
+    };
+    let reader = async {
+        loop {
+            let msg = tokio::select! {
+                msg = stream.next() => msg,
+                _ = async { stalled.wait_for(|stalled| *stalled).await.is_ok() } => {
+                    std::future::pending().await
+                }
+            };
+            match msg {
+                Some(Ok(Message::Text(text))) => handle_message(&state, conn_id, &text),
+                Some(Ok(Message::Pong(_))) => state.lock().unwrap().pong_frames += 1,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            }
+        }
+    };
+    tokio::select! {
+        _ = writer => {}
+        _ = reader => {}
+    }
+
+    state.lock().unwrap().disconnect(conn_id);
+}
+
+/// Registers a new connection. Hosts create the session record from their
+/// query parameters; guests receive session info and history.
+fn register(
+    state: &Shared,
+    role: Role,
+    session_id: &str,
+    query: &HashMap<String, String>,
+    tx: mpsc::UnboundedSender<Message>,
+    abort: AbortHandle,
+) -> u64 {
+    let mut state = state.lock().unwrap();
+    let conn_id = state.next_id();
+    let param = |key: &str| query.get(key).cloned().unwrap_or_default();
+
+    let name = match role {
+        Role::Host => param("device_name"),
+        Role::Guest => query
+            .get("device_name")
+            .cloned()
+            .unwrap_or_else(|| format!("guest-{}", conn_id)),
+    };
+    state.connections.insert(
+        conn_id,
+        Connection {
+            role,
+            session_id: session_id.to_string(),
+            name,
+            read_only: role == Role::Guest && param("read_only") == "true",
+            tx,
+            abort,
+        },
+    );
+
+    match role {
+        Role::Host => {
+            let now = Utc::now().to_rfc3339();
+            let session = state
+                .sessions
+                .entry(session_id.to_string())
+                .or_insert_with(|| SessionRecord {
+                    device_id: param("device_id"),
+                    device_name: param("device_name"),
+                    cwd: param("cwd"),
+                    name: None,
+                    started_at: now.clone(),
+                    attached_at: None,
+                    host: None,
+                    input_mode: "auto-lock".to_string(),
+                    idle_timeout: Duration::from_millis(1500),
+                    history: Vec::new(),
+                    lock: None,
+                    attach_count: 0,
+                });
+            session.host = Some(conn_id);
+            session.attached_at = Some(now);
+        }
+        Role::Guest => {
+            let (cols, rows) = DEFAULT_SIZE;
+            let (info, history) = match state.sessions.get(session_id) {
+                Some(session) => (
+                    json!({
+                        "type": "session_info",
+                        "session_id": session_id,
+                        "cols": cols,
+                        "rows": rows,
+                        "device_name": session.device_name,
+                        "cwd": session.cwd,
+                    }),
+                    session.history.clone(),
+                ),
+                None => (
+                    json!({
+                        "type": "session_info",
+                        "session_id": session_id,
+                        "cols": cols,
+                        "rows": rows,
+                    }),
+                    Vec::new(),
+                ),
+            };
+            state.send(conn_id, &info);
+            state.send(
+                conn_id,
+                &json!({ "type": "history", "session_id": session_id, "entries": history }),
+            );
+        }
+    }
+
+    conn_id
+}
+
+/// Dispatches a text message from a host or guest.
+fn handle_message(state: &Shared, conn_id: u64, text: &str) {
+    let Ok(msg) = serde_json::from_str::<Value>(text) else {
+        return;
+    };
+    let mut state = state.lock().unwrap();
+    let Some(conn) = state.connections.get(&conn_id) else {
+        return;
+    };
+    let (role, session_id) = (conn.role, conn.session_id.clone());
+    let kind = msg["type"].as_str().unwrap_or_default().to_string();
+
+    if kind == "pong" {
+        state.pongs += 1;
+        return;
+    }
+
+    match role {
+        Role::Host => handle_host_message(&mut state, &session_id, &kind, msg),
+        Role::Guest => handle_guest_message(&mut state, conn_id, &session_id, &kind, msg),
+    }
+}
+
+fn handle_host_message(state: &mut HubState, session_id: &str, kind: &str, msg: Value) {
+    match kind {
+        "session_attach" => {
+            if let Some(session) = state.sessions.get_mut(session_id) {
+                session.attach_count += 1;
+                session.name = msg["name"].as_str().map(str::to_string);
+                if let Some(mode) = msg["input_config"]["mode"].as_str() {
+                    session.input_mode = mode.to_string();
+                }
+                if let Some(ms) = msg["input_config"]["idle_timeout_ms"].as_u64() {
+                    session.idle_timeout = Duration::from_millis(ms);
+                }
+            }
+        }
+        "output" => {
+            let entry = json!({ "encrypted": msg["encrypted"], "timestamp": msg["timestamp"] });
+            if let Some(session) = state.sessions.get_mut(session_id) {
+                session.history.push(entry);
+                if session.history.len() > MAX_HISTORY {
+                    session.history.remove(0);
+                }
+            }
+            state.send_to_guests(session_id, &msg);
+        }
+        "session_detach" => {
+            if let Some(session) = state.sessions.get_mut(session_id) {
+                session.host = None;
+                session.lock = None;
+            }
+            let detached = json!({
+                "type": "session_detached",
+                "session_id": session_id,
+                "reason": "host_detached",
+            });
+            state.send_to_guests(session_id, &detached);
+        }
+        "key_epoch" => state.send_to_guests(session_id, &msg),
+        "key_grant" => {
+            let request_id = msg["request_id"].as_str().unwrap_or_default();
+            if let Some(guest) = state.key_requests.remove(request_id) {
+                state.send(guest, &msg);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_guest_message(
+    state: &mut HubState,
+    conn_id: u64,
+    session_id: &str,
+    kind: &str,
+    msg: Value,
+) {
+    let Some(session) = state.sessions.get_mut(session_id) else {
+        return;
+    };
+    let Some(host) = session.host else {
+        let rejected = json!({
+            "type": "input_rejected",
+            "session_id": session_id,
+            "reason": "host_detached",
+        });
+        state.send(conn_id, &rejected);
+        return;
+    };
+
+    match kind {
+        "prompt" => {
+            if state.connections[&conn_id].read_only {
+                let rejected = json!({
+                    "type": "input_rejected",
+                    "session_id": session_id,
+                    "reason": "read_only",
+                });
+                state.send(conn_id, &rejected);
+                return;
+            }
+            let mut acquired = None;
+            match session.input_mode.as_str() {
+                "host-only" => {
+                    let rejected = json!({
+                        "type": "input_rejected",
+                        "session_id": session_id,
+                        "reason": "host_only",
+                    });
+                    state.send(conn_id, &rejected);
+                    return;
+                }
+                "auto-lock" => {
+                    let idle_timeout = session.idle_timeout;
+                    match &mut session.lock {
+                        Some(lock) if lock.conn_id == conn_id => lock.last_input = Instant::now(),
+                        Some(lock) if lock.last_input.elapsed() < idle_timeout => {
+                            let rejected = json!({
+                                "type": "input_rejected",
+                                "session_id": session_id,
+                                "reason": "lock_held",
+                                "holder_name": lock.name,
+                            });
+                            state.send(conn_id, &rejected);
+                            return;
+                        }
+                        _ => {
+                            let name = state.connections[&conn_id].name.clone();
+                            acquired = Some(name.clone());
+                            let session = state.sessions.get_mut(session_id).unwrap();
+                            session.lock = Some(Lock {
+                                conn_id,
+                                name,
+                                last_input: Instant::now(),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(holder_name) = acquired {
+                let lock_acquired = json!({
+                    "type": "lock_acquired",
+                    "session_id": session_id,
+                    "holder_id": conn_id.to_string(),
+                    "holder_name": holder_name,
+                });
+                state.send(host, &lock_acquired);
+                state.send_to_guests(session_id, &lock_acquired);
+            }
+
+            let prompt = json!({
+                "type": "prompt",
+                "session_id": session_id,
+                "encrypted": msg["encrypted"],
+                "source": "guest",
+                "timestamp": Utc::now().to_rfc3339(),
+            });
+            state.send(host, &prompt);
+        }
+        "key_request" => {
+            let request_id = msg["request_id"].as_str().unwrap_or_default().to_string();
+            state.key_requests.insert(request_id, conn_id);
+            state.send(host, &msg);
+        }
+        _ => {}
+    }
+}
+
+// ============================================================================
+// REST API
+// ============================================================================
+
+/// Serves a single HTTP request and closes the connection.
+async fn serve_http(state: Shared, mek: SecretKey, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut token = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "authorization" => token = value.strip_prefix("Bearer ").map(str::to_string),
+            _ => {}
+        }
+    }
+
+    while buf.len() < head_end + content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body: Value = serde_json::from_slice(&buf[head_end..]).unwrap_or(Value::Null);
+
+    let (status, response) = route(&state, &mek, &method, &path, token.as_deref(), &body);
+    let response = response.to_string();
+    let reply = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default(),
+        response.len(),
+        response
+    );
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// OAuth-style error body.
+fn oauth_error(error: &str) -> (StatusCode, Value) {
+    (StatusCode::BAD_REQUEST, json!({ "error": error }))
+}
+
+/// Handles a REST request and returns status and JSON body.
+fn route(
+    state: &Shared,
+    mek: &SecretKey,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &Value,
+) -> (StatusCode, Value) {
+    let mut state = state.lock().unwrap();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        ("POST", ["auth", "device"]) => {
+            let device_code = format!("device-{}", state.next_id());
+            let user_code = format!("ABCD-{:04}", state.next_id);
+            let cli_public_key = body["ecdh_public_key"]
+                .as_str()
+                .and_then(|key| decode_base64(key).ok());
+            state.device_flows.insert(
+                device_code.clone(),
+                Approval {
+                    cli_public_key,
+                    approved: false,
+                },
+            );
+            let response = json!({
+                "device_code": device_code,
+                "user_code": user_code,
+                "verification_uri": "http://localhost/device",
+                "expires_in": 60,
+                "interval": 0,
+            });
+            (StatusCode::OK, response)
+        }
+        ("POST", ["auth", "token"]) => {
+            let device_code = body["device_code"].as_str().unwrap_or_default();
+            let Some(flow) = state.device_flows.get(device_code) else {
+                return oauth_error("expired_token");
+            };
+            if !flow.approved {
+                return oauth_error("authorization_pending");
+            }
+            let cli_public_key = flow.cli_public_key.clone();
+            state.device_flows.remove(device_code);
+
+            let (access, refresh) = state.issue_tokens();
+            let mut response = json!({
+                "access_token": access,
+                "token_type": "Bearer",
+                "expires_in": 3600,
+                "refresh_token": refresh,
+            });
+            if let Some(cli_public_key) = cli_public_key {
+                let (dash_public_key, encrypted_mek) =
+                    encrypt_mek_for_pairing(mek, &cli_public_key);
+                response["dash_public_key"] = json!(encode_base64(&dash_public_key));
+                response["encrypted_mek"] = json!(encrypted_mek);
+            }
+            (StatusCode::OK, response)
+        }
+        ("POST", ["auth", "refresh"]) => {
+            let refresh = body["refresh_token"].as_str().unwrap_or_default();
+            let Some(old_access) = state.refresh_tokens.remove(refresh) else {
+                return oauth_error("invalid_grant");
+            };
+            state.access_tokens.retain(|t| *t != old_access);
+            let (access, refresh) = state.issue_tokens();
+            let response = json!({
+                "access_token": access,
+                "token_type": "Bearer",
+                "expires_in": 3600,
+                "refresh_token": refresh,
+            });
+            (StatusCode::OK, response)
+        }
+        ("POST", ["auth", "revoke"]) => {
+            let refresh = body["refresh_token"].as_str().unwrap_or_default();
+            let Some(access) = state.refresh_tokens.remove(refresh) else {
+                return oauth_error("invalid_grant");
+            };
+            state.access_tokens.retain(|t| *t != access);
+            (StatusCode::OK, json!({}))
+        }
+        ("POST", ["dashboard", "auth", "device"]) => {
+            let id = state.next_id();
+            let pairing_code = format!("PAIR-{:04}", id);
+            let cli_public_key = body["cli_public_key"]
+                .as_str()
+                .and_then(|key| decode_base64(key).ok());
+            state.pairings.insert(
+                pairing_code.clone(),
+                Approval {
+                    cli_public_key,
+                    approved: false,
+                },
+            );
+            let response = json!({
+                "success": true,
+                "data": {
+                    "id": id.to_string(),
+                    "pairing_code": pairing_code,
+                    "verification_uri": "http://localhost/pair",
+                    "expires_in": 60,
+                },
+            });
+            (StatusCode::OK, response)
+        }
+        ("GET", ["dashboard", "auth", "device", code, "status"]) => {
+            let Some(pairing) = state.pairings.get(*code) else {
+                let data = json!({ "status": "expired" });
+                return (StatusCode::OK, json!({ "success": true, "data": data }));
+            };
+            let data = match (&pairing.cli_public_key, pairing.approved) {
+                (Some(cli_public_key), true) => {
+                    let (dash_public_key, encrypted_mek) =
+                        encrypt_mek_for_pairing(mek, cli_public_key);
+                    json!({
+                        "status": "completed",
+                        "dashPublicKey": encode_base64(&dash_public_key),
+                        "encryptedMek": encrypted_mek,
+                    })
+                }
+                _ => json!({ "status": "pending" }),
+            };
+            (StatusCode::OK, json!({ "success": true, "data": data }))
+        }
+        (_, ["sessions", ..] | ["users", ..])
+            if !token.is_some_and(|t| state.access_tokens.iter().any(|a| a == t)) =>
+        {
+            (StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" }))
+        }
+        ("GET", ["sessions"]) => {
+            let mut sessions: Vec<(&String, &SessionRecord)> = state.sessions.iter().collect();
+            sessions.sort_by(|a, b| a.1.started_at.cmp(&b.1.started_at));
+            let sessions: Vec<Value> = sessions.iter().map(|(id, s)| s.to_json(id)).collect();
+            (StatusCode::OK, json!({ "sessions": sessions }))
+        }
+        ("GET", ["sessions", identifier]) => {
+            let found = state
+                .sessions
+                .iter()
+                .find(|(id, s)| id == identifier || s.name.as_deref() == Some(*identifier));
+            match found {
+                Some((id, session)) => (StatusCode::OK, json!({ "session": session.to_json(id) })),
+                None => (StatusCode::NOT_FOUND, json!({ "error": "not_found" })),
+            }
+        }
+        ("GET", ["users", user, "devices"]) => match state.user_devices.get(*user) {
+            Some(devices) => (StatusCode::OK, json!({ "devices": devices })),
+            None => (StatusCode::NOT_FOUND, json!({ "error": "not_found" })),
+        },
+        ("GET", ["sessions", session_id, "shares"]) => {
+            let (key_epoch, shares) = state.shares.get(*session_id).cloned().unwrap_or_default();
+            (
+                StatusCode::OK,
+                json!({ "key_epoch": key_epoch, "shares": shares }),
+            )
+        }
+        ("POST", ["sessions", session_id, "shares"]) => {
+            let (_, shares) = state.shares.entry(session_id.to_string()).or_default();
+            shares.retain(|s| s["user"] != body["user"]);
+            shares.push(body.clone());
+            (StatusCode::OK, json!({}))
+        }
+        ("DELETE", ["sessions", session_id, "shares", user]) => {
+            let Some((_, shares)) = state.shares.get_mut(*session_id) else {
+                return (StatusCode::NOT_FOUND, json!({ "error": "not_found" }));
+            };
+            let before = shares.len();
+            shares.retain(|s| s["user"] != *user);
+            if shares.len() == before {
+                return (StatusCode::NOT_FOUND, json!({ "error": "not_found" }));
+            }
+            (StatusCode::OK, json!({}))
+        }
+        ("POST", ["sessions", session_id, "key-rotation"]) => {
+            if state.fail_key_rotation {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({ "error": "internal_error" }),
+                );
+            }
+            let (key_epoch, shares) = state.shares.entry(session_id.to_string()).or_default();
+            *key_epoch = body["key_epoch"].as_u64().unwrap_or_default() as u32;
+            for grant in body["shares"].as_array().into_iter().flatten() {
+                if let Some(share) = shares.iter_mut().find(|s| s["user"] == grant["user"]) {
+                    share["keys"] = grant["keys"].clone();
+                }
+            }
+            (StatusCode::OK, json!({}))
+        }
+        _ => (StatusCode::NOT_FOUND, json!({ "error": "not_found" })),
+    }
+}
+
+/// Encrypts a MEK for a CLI that is pairing, as the dashboard does.
+///
+/// Returns the dashboard's ephemeral public key and the encrypted MEK.
+fn encrypt_mek_for_pairing(mek: &SecretKey, cli_public_key: &[u8]) -> (Vec<u8>, EncryptedMEK) {
+    let dash = generate_ecdh_keypair();
+    let cli_public_key =
+        p256::PublicKey::from_sec1_bytes(cli_public_key).expect("CLI public key is valid");
+    let shared_secret = dash.private_key.diffie_hellman(&cli_public_key);
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared_secret.raw_secret_bytes())
+        .expand(PAIRING_KEY_INFO.as_bytes(), &mut key)
+        .expect("HKDF output length is valid");
+    let encrypted = encrypt_content(&SecretKey::from_bytes(key), mek.as_bytes());
+
+    let encrypted_mek = EncryptedMEK {
+        v: 1,
+        nonce: encrypted.nonce,
+        ciphertext: encrypted.ciphertext,
+        tag: encrypted.tag,
+    };
+    (dash.public_key, encrypted_mek)
+}